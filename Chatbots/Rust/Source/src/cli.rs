@@ -1,124 +1,510 @@
-use anyhow::{anyhow, Context, Result};
+use anyhow::{Context, Result, anyhow};
 use clap::{Arg, ArgAction, Command};
-use std::collections::HashMap;
-use std::io::{self, Write};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::sync::{Arc, Condvar, Mutex, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::config::{load_parser_config, load_stopwords, ParserConfig};
-use crate::data_model::{
-    build_guid_index, deck_path_to_string, AnswerHit, Card, DeckPath, LogRecord, StageTimings,
+use rulebot_rust::compare::{ComparisonReport, compare_rankings};
+use rulebot_rust::config::{
+    KeywordCountMode, ParserConfig, load_configured_stopwords, load_parser_config, load_synonyms,
 };
-use crate::io_decks::load_decks;
-use crate::logging_io::{log_benchmark, log_invalid_records};
-use crate::normalise::normalise_for_display;
-use crate::timing::Stopwatch;
-use crate::topics::{
-    build_topic_index, collect_subtree_candidates, list_available_topics, resolve_topic_string,
+use rulebot_rust::data_model::{
+    AnswerHit, Card, DeckPath, InvalidRecord, LogRecord, StageTimings, build_guid_index,
+    count_invalid_records_by_reason, deck_path_to_string, short_preview,
+    top_entries_from_hits_with_decimals,
 };
-use crate::scoring::keyword::{prepare_keyword_index, score_keyword_overlap, PreparedQuestion};
-use crate::scoring::tfidf::{build_tfidf_index, score_tfidf, TfidfIndex};
+use rulebot_rust::evaluate::{EvalReport, evaluate_self_retrieval};
+use rulebot_rust::io_decks::{list_deck_files, load_decks_multi};
+use rulebot_rust::logging_io::{
+    ensure_parent_directory_exists, log_benchmark, log_benchmark_csv, log_invalid_records,
+    log_invalid_records_json,
+};
+use rulebot_rust::memory::current_rss_kilobytes;
+use rulebot_rust::normalise::{normalise_for_display, normalise_for_display_plain};
+use rulebot_rust::result_cache::{ResultCache, ResultCacheKey};
+use rulebot_rust::scoring::{ScoreExplanation, Scorer, ScorerRegistry, normalise_hit_scores};
+use rulebot_rust::snippet::extract_snippet;
+use rulebot_rust::spelling::correct_tokens;
+use rulebot_rust::timing::{RepeatStatistics, Stopwatch, TimingAggregator};
+use rulebot_rust::tokenise::{stem_token, tokenise, tokenise_to_set, tokenise_with_trace};
+use rulebot_rust::topics::{
+    CorpusStats, build_topic_index, build_topic_index_with_depth, collect_prefix_candidates,
+    collect_subtree_candidates, corpus_stats, list_available_topics, list_top_level_topics,
+    resolve_topic_string, truncate_deck_path,
+};
+use rulebot_rust::{ChatbotEngine, ChatbotError};
 
 const DEFAULT_DATA_PATH: &str = "Data/Decks";
 const DEFAULT_PARSER_CONFIG_PATH: &str = "Data/Configs/Parser.json";
+/// `--algo` value that runs every registered scorer on each query, one labelled section each.
+const ALL_ALGORITHMS_NAME: &str = "all";
+const AUTOCORRECT_MAX_EDIT_DISTANCE: usize = 2;
+const INTERACTIVE_HELP: &str = "Commands:
+  :topic <deck path>  Search only this topic (rebuilds the index)
+  :topic              Search all cards again
+  :history            List this session's queries (and any loaded from --history-file)
+  :N                  Run history entry N again
+  :help               Show this list
+  exit                Leave interactive mode";
 
 /// Command-line entry point. Mirrors the Python CLI behaviour.
+/// Without a subcommand the flags behave like `query`, so existing scripts keep working.
 pub fn run() -> Result<()> {
+    let scorer_registry = ScorerRegistry::builtin();
     let argument_matches = Command::new("rulebot-rust")
         .about("Rule-based chatbot over Anki decks (Rust)")
-        // parity with Python: data and parser-config are fixed defaults, not CLI args
-        .arg(
-            Arg::new("topic")
-                .long("topic")
-                .required(false)
-                .help("Deck path (e.g., \"Launch into Computing::Unit 03 - Principles of Computer Science\"). If omitted, all topics are searched."),
-        )
-        .arg(
-            Arg::new("algo")
-                .long("algo")
-                .required(true)
-                .value_parser(["keyword", "tfidf"])
-                .help("Retrieval algorithm."),
-        )
-        .arg(
-            Arg::new("k")
-                .long("k")
-                .value_parser(clap::value_parser!(usize))
-                .default_value("1")
-                .help("Number of answers to return (default: 1)."),
-        )
-        .arg(
-            Arg::new("interactive")
-                .long("interactive")
-                .action(ArgAction::SetTrue)
-                .help("Interactive session."),
-        )
-        .arg(
-            Arg::new("query")
-                .long("query")
-                .help("Answer a single query and exit."),
-        )
-        .arg(
-            Arg::new("log")
-                .long("log")
-                .help("Append per-query JSON line logs to this file."),
-        )
-        .arg(
-            Arg::new("invalid_log")
-                .long("invalid-log")
-                .default_value("Logs/errors-rs.log")
-                .help("Path to invalid record log file."),
-        )
-        .arg(
-            Arg::new("warmup")
-                .long("warmup")
-                .value_parser(clap::value_parser!(usize))
-                .default_value("0")
-                .help("Number of warm-up queries before timing."),
-        )
-        .arg(
-            Arg::new("include_subtree")
-                .long("include-subtree")
-                .value_parser(["true", "false"])
-                .help("Override config include_subtree."),
+        .args(query_arguments(&scorer_registry))
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true)
+        .subcommand(
+            Command::new("query")
+                .about("Answer a query, a queries file, or serve over HTTP (the default).")
+                .args(query_arguments(&scorer_registry)),
         )
-        .arg(
-            Arg::new("show_cards")
-                .long("show-cards")
-                .action(ArgAction::SetTrue)
-                .help("Print GUIDs and scores for returned results."),
+        .subcommand(
+            Command::new("interactive")
+                .about("Interactive session; same flags as `query`.")
+                .args(query_arguments(&scorer_registry)),
         )
+        .subcommand(bench_command(&scorer_registry))
         .get_matches();
 
-    // Load configuration and stopwords from fixed paths
-    let parser_config =
-        load_parser_config(DEFAULT_PARSER_CONFIG_PATH).with_context(|| "Failed to load parser configuration")?;
+    match argument_matches.subcommand() {
+        Some(("bench", bench_matches)) => run_bench(bench_matches, &scorer_registry),
+        Some(("interactive", interactive_matches)) => {
+            run_query_command(interactive_matches, &scorer_registry, true)
+        }
+        Some(("query", query_matches)) => run_query_command(query_matches, &scorer_registry, false),
+        _ => run_query_command(&argument_matches, &scorer_registry, false),
+    }
+}
 
-    let stopword_set = if parser_config.remove_stopwords {
-        let stopwords_path = parser_config
-            .stopwords_path
-            .as_ref()
-            .expect("validated by load_parser_config");
-        load_stopwords(stopwords_path)?
-    } else {
-        Default::default()
-    };
+/// Flags of the `query` and `interactive` subcommands, also accepted with no subcommand.
+/// `--algo` and `--compare` accept the names in `scorer_registry`.
+fn query_arguments(scorer_registry: &ScorerRegistry) -> Vec<Arg> {
+    vec![
+        Arg::new("config")
+            .long("config")
+            .value_name("PATH")
+            .default_value(DEFAULT_PARSER_CONFIG_PATH)
+            .help("Parser config JSON to load (default: Data/Configs/Parser.json)."),
+        Arg::new("data")
+            .long("data")
+            .value_name("PATH")
+            .action(ArgAction::Append)
+            .help("Deck directory or file to load; repeat to combine several (default: Data/Decks)."),
+        Arg::new("topic")
+            .long("topic")
+            .required(false)
+            .help("Deck path (e.g., \"Launch into Computing::Unit 03 - Principles of Computer Science\"). If omitted, all topics are searched."),
+        Arg::new("topic_prefix")
+            .long("topic-prefix")
+            .value_name("PARTIAL PATH")
+            .conflicts_with("topic")
+            .help("Search every card whose deck path starts with these segments (e.g. \"Launch into Computing::Unit 03\"), even if that is not itself a deck."),
+        Arg::new("algo")
+            .long("algo")
+            .required_unless_present_any([
+                "compare",
+                "count_only",
+                "list_topics",
+                "serve",
+                "show_guid",
+                "tokenise",
+                "validate",
+            ])
+            .value_parser(clap::builder::PossibleValuesParser::new(
+                scorer_registry
+                    .names()
+                    .into_iter()
+                    .chain([ALL_ALGORITHMS_NAME]),
+            ))
+            .help("Retrieval algorithm, or \"all\" to compare every algorithm in turn."),
+        Arg::new("compare")
+            .long("compare")
+            .num_args(2)
+            .value_names(["ALGO_A", "ALGO_B"])
+            .value_parser(scorer_registry.names())
+            .conflicts_with("algo")
+            .requires("query")
+            .help("Rank --query with two algorithms and print where their top k agree and differ."),
+        Arg::new("eval")
+            .long("eval")
+            .action(ArgAction::SetTrue)
+            .conflicts_with_all(["query", "queries_file", "interactive", "serve", "compare"])
+            .help("Query each candidate card's own question and print recall@k and mean reciprocal rank for --algo (\"all\" evaluates each)."),
+        Arg::new("similar_to")
+            .long("similar-to")
+            .value_name("GUID")
+            .conflicts_with_all([
+                "query",
+                "queries_file",
+                "interactive",
+                "serve",
+                "compare",
+                "eval",
+                "log",
+                "explain",
+                "snippet",
+                "highlight",
+                "stats",
+            ])
+            .help("Rank the candidate cards by similarity to this card's question, leaving the card itself out."),
+        Arg::new("k")
+            .long("k")
+            .value_parser(clap::value_parser!(usize))
+            .default_value("1")
+            .help("Number of answers to return (default: 1)."),
+        Arg::new("min_score")
+            .long("min-score")
+            .value_parser(clap::value_parser!(f32))
+            .default_value("0.0")
+            .help("Drop results scoring below this threshold (default: 0.0)."),
+        Arg::new("serve")
+            .long("serve")
+            .value_name("ADDR")
+            .help("Serve the engine over HTTP on ADDR (e.g. 127.0.0.1:8080); requires the `server` feature."),
+        Arg::new("interactive")
+            .long("interactive")
+            .action(ArgAction::SetTrue)
+            .help("Interactive session."),
+        Arg::new("query")
+            .long("query")
+            .help("Answer a single query and exit."),
+        Arg::new("queries_file")
+            .long("queries-file")
+            .help("Answer each query in this file (one per line; blank lines and '#' comments skipped) and exit."),
+        Arg::new("log")
+            .long("log")
+            .help("Append per-query JSON line logs to this file."),
+        Arg::new("log_format")
+            .long("log-format")
+            .value_parser(["jsonl", "csv"])
+            .default_value("jsonl")
+            .help("Benchmark log format for --log: JSON Lines (default) or CSV."),
+        Arg::new("invalid_log")
+            .long("invalid-log")
+            .default_value("Logs/errors-rs.log")
+            .help("Path to invalid record log file."),
+        Arg::new("invalid_log_format")
+            .long("invalid-log-format")
+            .value_parser(["text", "json"])
+            .default_value("text")
+            .help("Invalid record log format: two lines per record (default) or JSON Lines."),
+        Arg::new("history_file")
+            .long("history-file")
+            .value_name("PATH")
+            .help("Load interactive query history from this file at startup and append each new query."),
+        Arg::new("warmup")
+            .long("warmup")
+            .value_parser(clap::value_parser!(usize))
+            .default_value("0")
+            .help("Number of warm-up queries before timing."),
+        Arg::new("profile")
+            .long("profile")
+            .action(ArgAction::SetTrue)
+            .help("After all queries, print min/median/p95/max/mean wall and rank times."),
+        Arg::new("result_cache")
+            .long("result-cache")
+            .value_name("N")
+            .value_parser(clap::value_parser!(usize))
+            .default_value("0")
+            .help("Reuse the ranked hits of up to N recent identical queries (0 disables)."),
+        Arg::new("include_subtree")
+            .long("include-subtree")
+            .value_parser(["true", "false"])
+            .help("Override config include_subtree."),
+        Arg::new("list_topics")
+            .long("list-topics")
+            .action(ArgAction::SetTrue)
+            .help("Print every deck path with its card count, then exit."),
+        Arg::new("count_only")
+            .long("count-only")
+            .action(ArgAction::SetTrue)
+            .help("Print card, invalid-record, topic, depth and question-token totals, then exit."),
+        Arg::new("validate")
+            .long("validate")
+            .action(ArgAction::SetTrue)
+            .help("Load decks, log invalid records, print a summary, and fail if any record was invalid."),
+        Arg::new("answer_render")
+            .long("answer-render")
+            .value_parser(["escaped", "plain"])
+            .default_value("escaped")
+            .help("Show answers with HTML escaped (default) or stripped to plain text."),
+        Arg::new("preview")
+            .long("preview")
+            .value_parser(["raw", "normalised"])
+            .default_value("raw")
+            .help("Show questions as written (default) or in the lowercased form they are matched in."),
+        Arg::new("answer_max_chars")
+            .long("answer-max-chars")
+            .value_name("N")
+            .value_parser(clap::value_parser!(usize))
+            .default_value("0")
+            .help("Truncate displayed answers to N characters with an ellipsis (0 = unlimited)."),
+        Arg::new("snippet")
+            .long("snippet")
+            .action(ArgAction::SetTrue)
+            .conflicts_with("answer_max_chars")
+            .help("Show a ~200-character excerpt of each answer around the query words instead of the whole answer."),
+        Arg::new("show_guid")
+            .long("show-guid")
+            .value_name("GUID")
+            .help("Print the card with this GUID (deck path, question, answer), then exit."),
+        Arg::new("tokenise")
+            .long("tokenise")
+            .value_name("TEXT")
+            .help("Print the tokens TEXT produces and why each dropped word was dropped, then exit."),
+        Arg::new("autocorrect")
+            .long("autocorrect")
+            .action(ArgAction::SetTrue)
+            .help("Replace query words missing from the corpus with their nearest corpus term."),
+        Arg::new("highlight")
+            .long("highlight")
+            .action(ArgAction::SetTrue)
+            .help("Wrap matched query words in the displayed question with *markers*."),
+        Arg::new("stats")
+            .long("stats")
+            .action(ArgAction::SetTrue)
+            .help("After each result list, print how many hits were shown and how many candidates matched."),
+        Arg::new("timeout_ms")
+            .long("timeout-ms")
+            .value_name("N")
+            .value_parser(clap::value_parser!(u64))
+            .default_value("0")
+            .help("Give up on a query whose scoring takes longer than N milliseconds (0 = no limit)."),
+        Arg::new("precision")
+            .long("precision")
+            .value_name("N")
+            .value_parser(clap::builder::RangedU64ValueParser::<usize>::new().range(0..=9))
+            .default_value("6")
+            .help("Decimal places (0-9) of scores in results, --show-cards lines and logs (default: 6)."),
+        Arg::new("page")
+            .long("page")
+            .value_name("N")
+            .value_parser(clap::value_parser!(usize))
+            .default_value("0")
+            .help("In interactive mode, show results N at a time, waiting for Enter (q stops) (default: 0, no paging)."),
+        max_invalid_ratio_argument(),
+        Arg::new("normalise_scores")
+            .long("normalise-scores")
+            .action(ArgAction::SetTrue)
+            .help("Rescale keyword scores to 0..1 (overlap / query token count) for comparison with other algorithms."),
+        Arg::new("per_topic")
+            .long("per-topic")
+            .action(ArgAction::SetTrue)
+            .conflicts_with("diversity")
+            .help("Return the best hit from each deck path, for the top k deck paths."),
+        Arg::new("topic_depth")
+            .long("topic-depth")
+            .value_name("N")
+            .value_parser(clap::value_parser!(usize))
+            .default_value("0")
+            .help("Roll deck paths up to their first N segments for --list-topics and --per-topic (0 = full paths)."),
+        Arg::new("diversity")
+            .long("diversity")
+            .action(ArgAction::SetTrue)
+            .help("Rerank results (MMR) so near-duplicate questions do not crowd the top k."),
+        Arg::new("show_cards")
+            .long("show-cards")
+            .action(ArgAction::SetTrue)
+            .help("Print GUIDs and scores for returned results."),
+        Arg::new("explain")
+            .long("explain")
+            .action(ArgAction::SetTrue)
+            .help("Print each result's matched terms and their score contributions."),
+        Arg::new("format")
+            .long("format")
+            .value_parser(["text", "json"])
+            .default_value("text")
+            .help("Result output format. `json` prints one JSON array per query; status lines go to stderr."),
+        Arg::new("output_file")
+            .long("output-file")
+            .value_name("PATH")
+            .help("Write each query's results to this file instead of stdout (replaced at startup, then appended per query)."),
+        Arg::new("template")
+            .long("template")
+            .value_name("TEMPLATE")
+            .help("Text layout of each result, with {rank}, {guid}, {score}, {topic}, {question} and {answer} placeholders (default: the three-line layout)."),
+        Arg::new("seed")
+            .long("seed")
+            .value_parser(clap::value_parser!(u64))
+            .help("Accepted for reproducibility scripts; ranking uses no randomness, so it has no effect."),
+    ]
+    .into_iter()
+    .chain(engine_arguments())
+    .chain(config_override_arguments())
+    .collect()
+}
+
+/// Flags applied to every engine the CLI builds, shared by queries and `bench`
+/// (see `EngineSettings`).
+fn engine_arguments() -> [Arg; 4] {
+    [
+        Arg::new("min_overlap")
+            .long("min-overlap")
+            .value_parser(clap::value_parser!(usize))
+            .default_value("1")
+            .help("Keyword candidates must share at least this many query tokens (default: 1)."),
+        Arg::new("cache_queries")
+            .long("cache-queries")
+            .value_name("N")
+            .value_parser(clap::value_parser!(usize))
+            .default_value("0")
+            .help("Cache the tokens of up to N recent queries (0 disables)."),
+        Arg::new("expand")
+            .long("expand")
+            .action(ArgAction::SetTrue)
+            .help("Add synonyms from the config's synonyms_path to keyword and tfidf queries, at synonym_weight."),
+        Arg::new("progress")
+            .long("progress")
+            .action(ArgAction::SetTrue)
+            .help("Show a progress bar while indices are built; requires the `progress` feature."),
+    ]
+}
+
+/// Flags that override the loaded parser config, shared by queries and `bench`
+/// (see `load_parser_settings`).
+fn config_override_arguments() -> [Arg; 5] {
+    [
+        Arg::new("hybrid_alpha")
+            .long("hybrid-alpha")
+            .value_parser(clap::value_parser!(f32))
+            .help("Keyword weight in --algo hybrid; TF–IDF gets 1 minus this (default 0.5)."),
+        Arg::new("query_boost_short")
+            .long("query-boost-short")
+            .value_name("N")
+            .value_parser(clap::value_parser!(usize))
+            .help("Score TF–IDF queries with fewer than N tokens by IDF sum instead of cosine (default 0, off)."),
+        Arg::new("keyword_count_mode")
+            .long("keyword-count-mode")
+            .value_parser(["set", "multiset"])
+            .help("Count a keyword match once (set) or as often as the term repeats in both query and question (multiset). Overrides config keyword_count_mode."),
+        Arg::new("no_header_skip")
+            .long("no-header-skip")
+            .action(ArgAction::SetTrue)
+            .help("Load every deck line, including lines starting with the comment prefix."),
+        Arg::new("deterministic")
+            .long("deterministic")
+            .action(ArgAction::SetTrue)
+            .help("Enable all reproducibility options: candidates sorted by deck path then GUID before indexing, tfidf, hybrid, tfidf-soft and proximity scores rounded to 6 decimals before ranking (keyword, jaccard, dice and ngram scores are not rounded), and no parse/index build timing summary on stdout."),
+    ]
+}
+
+/// Answer queries in the mode the flags select. `interactive_subcommand` (the `interactive`
+/// subcommand) acts like `--interactive`.
+fn run_query_command(
+    argument_matches: &clap::ArgMatches,
+    scorer_registry: &ScorerRegistry,
+    interactive_subcommand: bool,
+) -> Result<()> {
+    // Mode: interactive, batch file, or single query
+    let is_interactive = interactive_subcommand || argument_matches.get_flag("interactive");
+    let single_query_option = argument_matches.get_one::<String>("query");
+    let queries_file_option = argument_matches.get_one::<String>("queries_file");
+
+    let serve_address_option = argument_matches.get_one::<String>("serve");
+
+    let selected_mode_count = [
+        is_interactive,
+        single_query_option.is_some(),
+        queries_file_option.is_some(),
+        serve_address_option.is_some(),
+    ]
+    .into_iter()
+    .filter(|is_selected| *is_selected)
+    .count();
+    if selected_mode_count > 1 {
+        return Err(anyhow!(
+            "--query, --interactive, --queries-file and --serve are mutually exclusive; choose one"
+        ));
+    }
+
+    let (parser_config, stopword_set) = load_parser_settings(argument_matches)?;
+
+    if let Some(tokenise_text) = argument_matches.get_one::<String>("tokenise") {
+        print!(
+            "{}",
+            format_token_trace(tokenise_text, &stopword_set, &parser_config)
+        );
+        return Ok(());
+    }
 
-    // Load decks with timing from the fixed data path
     let mut stopwatch_parse = Stopwatch::new();
     stopwatch_parse.start();
-    let (all_cards, invalid_records) = load_decks(DEFAULT_DATA_PATH)?;
+    let (all_cards, invalid_records) = load_corpus(argument_matches, &parser_config)?;
     let parse_milliseconds = stopwatch_parse.stop();
 
-    if !invalid_records.is_empty() {
-        let invalid_log_path = argument_matches
-            .get_one::<String>("invalid_log")
-            .expect("has default");
-        let _ = log_invalid_records(&invalid_records, invalid_log_path);
+    if argument_matches.get_flag("validate") {
+        let mut deck_file_count = 0;
+        for data_path in &deck_data_paths(argument_matches) {
+            deck_file_count += list_deck_files(data_path, parser_config.deck_format)?.len();
+        }
+        print!(
+            "{}",
+            format_validation_summary(deck_file_count, all_cards.len(), &invalid_records)
+        );
+        check_invalid_ratio(all_cards.len(), &invalid_records, argument_matches)?;
+        if !invalid_records.is_empty() {
+            return Err(anyhow!(
+                "{} invalid records (details in {})",
+                invalid_records.len(),
+                argument_matches
+                    .get_one::<String>("invalid_log")
+                    .expect("has default")
+            ));
+        }
+        return Ok(());
+    }
+
+    check_invalid_ratio(all_cards.len(), &invalid_records, argument_matches)?;
+
+    if argument_matches.get_flag("count_only") {
+        let stats = corpus_stats(&all_cards, &stopword_set, &parser_config);
+        print!("{}", format_corpus_stats(&stats, invalid_records.len()));
+        return Ok(());
     }
 
     if all_cards.is_empty() {
-        return Err(anyhow!(
-            "No valid cards were loaded. Check your data path and data contract."
-        ));
+        return Err(ChatbotError::NoValidCards.into());
+    }
+
+    if argument_matches.get_flag("list_topics") {
+        let topic_depth = *argument_matches
+            .get_one::<usize>("topic_depth")
+            .expect("defaulted by clap");
+        print!(
+            "{}",
+            format_topic_listing(&all_cards, &parser_config.topic_separator, topic_depth)
+        );
+        return Ok(());
+    }
+
+    if let Some(requested_guid) = argument_matches.get_one::<String>("show_guid") {
+        let guid_index_map = build_guid_index(&all_cards);
+        let card = guid_index_map.get(requested_guid).ok_or_else(|| {
+            anyhow!(
+                "GUID {requested_guid:?} not found among the {} loaded cards",
+                all_cards.len()
+            )
+        })?;
+        print!("{}", format_card_details(card));
+        return Ok(());
+    }
+
+    if let Some(serve_address) = serve_address_option {
+        return serve_engine(
+            serve_address,
+            scorer_registry,
+            all_cards,
+            parser_config,
+            stopword_set,
+            argument_matches,
+        );
     }
 
     // Determine candidate pool: topic subtree if provided, otherwise all cards
@@ -127,88 +513,318 @@ pub fn run() -> Result<()> {
         None => parser_config.include_subtree,
     };
 
-    let (candidate_cards, topic_label_for_logs): (Vec<Card>, String) = if let Some(requested_topic_text) =
-        argument_matches.get_one::<String>("topic")
-    {
-        let known_topics = list_available_topics(&all_cards);
-        let resolved_root_topic: DeckPath = resolve_topic_string(
-            requested_topic_text,
-            &parser_config.topic_separator,
-            &known_topics,
-        )
-        .map_err(|message| anyhow!(message))?;
-
-        let topic_index = build_topic_index(&all_cards);
-        let candidates =
-            collect_subtree_candidates(&topic_index, &resolved_root_topic, include_subtree_value);
+    let output_format = argument_matches
+        .get_one::<String>("format")
+        .expect("defaulted by clap")
+        .to_string();
+    let json_output = output_format == "json";
 
-        if candidates.is_empty() {
-            return Err(anyhow!(
-                "No candidate cards found for the requested topic."
-            ));
-        }
-        (candidates, requested_topic_text.to_string())
-    } else {
-        (all_cards.clone(), "<ALL>".to_string())
-    };
+    let (candidate_cards, topic_label_for_logs) =
+        match argument_matches.get_one::<String>("topic_prefix") {
+            Some(topic_prefix_text) => select_prefix_candidate_cards(
+                &all_cards,
+                topic_prefix_text,
+                &parser_config.topic_separator,
+            )?,
+            None => select_candidate_cards(
+                &all_cards,
+                argument_matches
+                    .get_one::<String>("topic")
+                    .map(String::as_str),
+                &parser_config.topic_separator,
+                include_subtree_value,
+                json_output,
+            )?,
+        };
 
-    println!(
-        "Loaded {} cards; {} candidates in topic '{}'.",
-        all_cards.len(),
-        candidate_cards.len(),
-        topic_label_for_logs
+    print_status(
+        json_output,
+        &format!(
+            "Loaded {} cards; {} candidates in topic '{}'.",
+            all_cards.len(),
+            candidate_cards.len(),
+            topic_label_for_logs
+        ),
     );
 
-    // Build indices with timing
-    let mut stopwatch_index = Stopwatch::new();
-    stopwatch_index.start();
+    if let Some(compare_names) = argument_matches.get_many::<String>("compare") {
+        let compared_pair: Vec<Arc<dyn Scorer>> = compare_names
+            .map(|scorer_name| {
+                scorer_registry
+                    .get(scorer_name)
+                    .expect("restricted by clap value_parser")
+            })
+            .collect();
+        let query_text = single_query_option.ok_or(ChatbotError::MissingQuery)?;
+        let top_k = *argument_matches
+            .get_one::<usize>("k")
+            .expect("defaulted by clap");
+        let min_score = *argument_matches
+            .get_one::<f32>("min_score")
+            .expect("defaulted by clap");
+        let engine_settings = EngineSettings::from_matches(argument_matches, &parser_config)?;
+        let engine = build_engine(
+            candidate_cards,
+            parser_config,
+            stopword_set,
+            &compared_pair,
+            &engine_settings,
+        )?;
+        let [first_guids, second_guids] = [&compared_pair[0], &compared_pair[1]].map(|scorer| {
+            engine
+                .query_above(query_text, scorer.name(), top_k, min_score)
+                .into_iter()
+                .map(|answer_hit| answer_hit.guid)
+                .collect::<Vec<String>>()
+        });
+        let comparison_report = compare_rankings(&first_guids, &second_guids, top_k);
+        print!(
+            "{}",
+            format_comparison_report(
+                &comparison_report,
+                compared_pair[0].name(),
+                compared_pair[1].name()
+            )
+        );
+        return Ok(());
+    }
 
+    // Build indices with timing
     let algorithm_name = argument_matches
         .get_one::<String>("algo")
-        .expect("required by clap")
-        .to_string();
-
-    let guid_index_map: HashMap<String, Card> = build_guid_index(&candidate_cards);
-    let mut prepared_keyword_index: Option<Vec<PreparedQuestion>> = None;
-    let mut tfidf_index: Option<TfidfIndex> = None;
-
-    let index_milliseconds = if algorithm_name == "keyword" {
-        prepared_keyword_index =
-            Some(prepare_keyword_index(&candidate_cards, &stopword_set, &parser_config));
-        stopwatch_index.stop()
+        .expect("required by clap");
+    let compared_scorers: Vec<Arc<dyn Scorer>> = if algorithm_name == ALL_ALGORITHMS_NAME {
+        scorer_registry.scorers().to_vec()
     } else {
-        tfidf_index = Some(build_tfidf_index(
-            &candidate_cards,
-            &stopword_set,
-            &parser_config,
-        ));
-        stopwatch_index.stop()
+        vec![
+            scorer_registry
+                .get(algorithm_name)
+                .expect("restricted by clap value_parser"),
+        ]
     };
 
-    // Mode: interactive or single query
-    let is_interactive = *argument_matches
-        .get_one::<bool>("interactive")
-        .expect("set by clap");
+    if argument_matches.get_flag("eval") {
+        let top_k = *argument_matches
+            .get_one::<usize>("k")
+            .expect("defaulted by clap");
+        let engine_settings = EngineSettings::from_matches(argument_matches, &parser_config)?;
+        let engine = build_engine(
+            candidate_cards,
+            parser_config,
+            stopword_set,
+            &compared_scorers,
+            &engine_settings,
+        )?;
+        for evaluated_scorer in &compared_scorers {
+            let eval_report = evaluate_self_retrieval(&engine, evaluated_scorer.name(), top_k);
+            print!(
+                "{}",
+                format_eval_report(&eval_report, evaluated_scorer.name())
+            );
+        }
+        return Ok(());
+    }
+
+    let candidate_deck_size = candidate_cards.len();
+    let deterministic_output = parser_config.deterministic;
 
-    let top_k = *argument_matches
-        .get_one::<usize>("k")
-        .expect("defaulted by clap");
+    let mut stopwatch_index = Stopwatch::new();
+    stopwatch_index.start();
+    // Autocorrect draws on the TF–IDF vocabulary and diversity reranking on the keyword
+    // token sets, so those indices are built alongside.
+    let autocorrect = argument_matches.get_flag("autocorrect");
+    let diversity = argument_matches.get_flag("diversity");
+    let mut indexed_scorers: Vec<Arc<dyn Scorer>> = compared_scorers.clone();
+    if autocorrect {
+        indexed_scorers.extend(scorer_registry.get("tfidf"));
+    }
+    if diversity {
+        indexed_scorers.extend(scorer_registry.get("keyword"));
+    }
+    let engine_settings = EngineSettings::from_matches(argument_matches, &parser_config)?;
+    let mut engine = Arc::new(build_engine(
+        candidate_cards,
+        parser_config,
+        stopword_set,
+        &indexed_scorers,
+        &engine_settings,
+    )?);
+    let index_milliseconds = stopwatch_index.stop();
+    if let Some(tfidf_index) = engine.tfidf_index()
+        && tfidf_index.vocabulary_size != tfidf_index.unpruned_vocabulary_size
+    {
+        print_status(
+            json_output,
+            &format!(
+                "TF–IDF vocabulary: {} terms, {} after min_df/max_df_ratio pruning.",
+                tfidf_index.unpruned_vocabulary_size, tfidf_index.vocabulary_size
+            ),
+        );
+    }
 
-    let warmup_count = *argument_matches
-        .get_one::<usize>("warmup")
-        .expect("defaulted by clap");
+    let mut query_settings = QuerySettings {
+        scorer: Arc::clone(&compared_scorers[0]),
+        warmup_count: *argument_matches
+            .get_one::<usize>("warmup")
+            .expect("defaulted by clap"),
+        top_k: *argument_matches
+            .get_one::<usize>("k")
+            .expect("defaulted by clap"),
+        min_score: *argument_matches
+            .get_one::<f32>("min_score")
+            .expect("defaulted by clap"),
+        log_path: argument_matches.get_one::<String>("log").cloned(),
+        log_format: argument_matches
+            .get_one::<String>("log_format")
+            .expect("defaulted by clap")
+            .to_string(),
+        candidate_deck_size,
+        topic_label: topic_label_for_logs,
+        show_cards: argument_matches.get_flag("show_cards"),
+        explain: argument_matches.get_flag("explain"),
+        autocorrect,
+        highlight: argument_matches.get_flag("highlight"),
+        per_topic: argument_matches.get_flag("per_topic"),
+        topic_depth: *argument_matches
+            .get_one::<usize>("topic_depth")
+            .expect("defaulted by clap"),
+        stats: argument_matches.get_flag("stats"),
+        answer_max_chars: *argument_matches
+            .get_one::<usize>("answer_max_chars")
+            .expect("defaulted by clap"),
+        snippet: argument_matches.get_flag("snippet"),
+        result_template: argument_matches
+            .get_one::<String>("template")
+            .cloned()
+            .unwrap_or_else(|| DEFAULT_RESULT_TEMPLATE.to_string()),
+        answer_render: if argument_matches
+            .get_one::<String>("answer_render")
+            .is_some_and(|render_name| render_name == "plain")
+        {
+            AnswerRender::Plain
+        } else {
+            AnswerRender::Escaped
+        },
+        preview_source: if argument_matches
+            .get_one::<String>("preview")
+            .is_some_and(|preview_name| preview_name == "normalised")
+        {
+            PreviewSource::Normalised
+        } else {
+            PreviewSource::Raw
+        },
+        diversity,
+        normalise_scores: argument_matches.get_flag("normalise_scores"),
+        timeout: argument_matches
+            .get_one::<u64>("timeout_ms")
+            .filter(|timeout_milliseconds| **timeout_milliseconds > 0)
+            .map(|timeout_milliseconds| Duration::from_millis(*timeout_milliseconds)),
+        result_cache: argument_matches
+            .get_one::<usize>("result_cache")
+            .filter(|capacity| **capacity > 0)
+            .map(|capacity| ResultCache::new(*capacity)),
+        pool_generation: 0,
+        precision: *argument_matches
+            .get_one::<usize>("precision")
+            .expect("defaulted by clap"),
+        page_size: if is_interactive && !json_output {
+            *argument_matches
+                .get_one::<usize>("page")
+                .expect("defaulted by clap")
+        } else {
+            0
+        },
+        output_format,
+        parse_milliseconds,
+        index_milliseconds,
+    };
+
+    let mut output_file = match argument_matches.get_one::<String>("output_file") {
+        Some(output_file_path) => Some(create_output_file(output_file_path)?),
+        None => None,
+    };
+
+    if let Some(similar_guid) = argument_matches.get_one::<String>("similar_to") {
+        if engine.card(similar_guid).is_none() {
+            return Err(anyhow!(
+                "GUID {similar_guid:?} not found among the {candidate_deck_size} candidate cards"
+            ));
+        }
+        for similar_scorer in &compared_scorers {
+            if compared_scorers.len() > 1 {
+                print_status(json_output, &format!("== {} ==", similar_scorer.name()));
+            }
+            let answer_hits = with_preview_source(
+                engine.most_similar(
+                    similar_guid,
+                    similar_scorer.name(),
+                    query_settings.top_k,
+                    query_settings.min_score,
+                ),
+                query_settings.preview_source,
+                engine.guid_index(),
+            );
+            let result_entries = if json_output {
+                vec![format_hits_as_json(&answer_hits, engine.guid_index())?]
+            } else {
+                format_hits_for_display(
+                    &answer_hits,
+                    engine.guid_index(),
+                    &AnswerDisplay::Truncated(query_settings.answer_max_chars),
+                    query_settings.answer_render,
+                    &query_settings.result_template,
+                    query_settings.precision,
+                )
+            };
+            let mut stdout_handle = io::stdout();
+            let result_writer: &mut dyn Write = match output_file.as_mut() {
+                Some(file_handle) => file_handle,
+                None => &mut stdout_handle,
+            };
+            writeln!(result_writer, "{}", result_entries.join("\n"))
+                .with_context(|| "Failed to write results")?;
+            if query_settings.show_cards && !json_output {
+                for answer_hit in &answer_hits {
+                    writeln!(
+                        result_writer,
+                        "-> {}  score={:.precision$}",
+                        answer_hit.guid,
+                        answer_hit.score,
+                        precision = query_settings.precision
+                    )
+                    .with_context(|| "Failed to write results")?;
+                }
+            }
+        }
+        return Ok(());
+    }
 
-    let log_path_option = argument_matches.get_one::<String>("log").cloned();
+    // Warm-up runs happen inside `run_single_query` before its timers start, so only real
+    // queries reach the aggregate.
+    let mut timing_aggregator = TimingAggregator::new();
 
     if is_interactive {
-        println!("Interactive mode. Type a question, or 'exit' to exit.");
+        print_status(
+            json_output,
+            "Interactive mode. Type a question, ':help' for commands, or 'exit' to exit.",
+        );
+        let mut query_history = QueryHistory::load(
+            argument_matches
+                .get_one::<String>("history_file")
+                .map(String::as_str),
+        )?;
         let mut input_buffer = String::new();
         loop {
             input_buffer.clear();
-            print!("> ");
-            let _ = io::stdout().flush();
+            if json_output {
+                eprint!("> ");
+                let _ = io::stderr().flush();
+            } else {
+                print!("> ");
+                let _ = io::stdout().flush();
+            }
             if io::stdin().read_line(&mut input_buffer).is_err() {
-                println!("\nExiting.");
+                print_status(json_output, "\nExiting.");
                 break;
             }
             let user_query_text = input_buffer.trim().to_string();
@@ -218,188 +834,2090 @@ pub fn run() -> Result<()> {
             if user_query_text.is_empty() {
                 continue;
             }
-            run_single_query(
-                &user_query_text,
-                &algorithm_name,
-                &guid_index_map,
-                prepared_keyword_index.as_ref(),
-                tfidf_index.as_ref(),
-                &parser_config,
-                &stopword_set,
-                warmup_count,
-                top_k,
-                log_path_option.as_deref(),
-                candidate_cards.len(),
-                &topic_label_for_logs,
-                *argument_matches.get_one::<bool>("show_cards").unwrap_or(&false),
-                parse_milliseconds,
-                index_milliseconds,
+            if user_query_text == ":help" {
+                print_status(json_output, INTERACTIVE_HELP);
+                continue;
+            }
+            if user_query_text == ":topic" || user_query_text.starts_with(":topic ") {
+                let requested_topic_text = user_query_text[":topic".len()..].trim();
+                let switch_result = select_candidate_cards(
+                    &all_cards,
+                    (!requested_topic_text.is_empty()).then_some(requested_topic_text),
+                    &engine.parser_config().topic_separator,
+                    include_subtree_value,
+                    json_output,
+                );
+                match switch_result {
+                    Ok((candidate_cards, topic_label)) => {
+                        print_status(
+                            json_output,
+                            &format!(
+                                "Loaded {} cards; {} candidates in topic '{}'.",
+                                all_cards.len(),
+                                candidate_cards.len(),
+                                topic_label
+                            ),
+                        );
+                        let mut stopwatch_rebuild = Stopwatch::new();
+                        stopwatch_rebuild.start();
+                        query_settings.candidate_deck_size = candidate_cards.len();
+                        engine = Arc::new(build_engine(
+                            candidate_cards,
+                            engine.parser_config().clone(),
+                            engine.stopword_set().clone(),
+                            &indexed_scorers,
+                            &engine_settings,
+                        )?);
+                        query_settings.index_milliseconds = stopwatch_rebuild.stop();
+                        query_settings.topic_label = topic_label;
+                        query_settings.pool_generation += 1;
+                    }
+                    Err(error) => print_status(json_output, &format!("Error: {error}")),
+                }
+                continue;
+            }
+            if user_query_text == ":history" {
+                print_status(json_output, query_history.listing().trim_end());
+                continue;
+            }
+            let history_entry_number = user_query_text
+                .strip_prefix(':')
+                .and_then(|entry_text| entry_text.parse::<usize>().ok());
+            let query_text = match history_entry_number {
+                Some(entry_number) => match query_history.recall(entry_number) {
+                    Some(recalled_query) => {
+                        print_status(json_output, &format!("> {recalled_query}"));
+                        recalled_query.to_string()
+                    }
+                    None => {
+                        print_status(
+                            json_output,
+                            &format!("No history entry {entry_number}; ':history' lists them."),
+                        );
+                        continue;
+                    }
+                },
+                None => user_query_text,
+            };
+            if let Err(error) = query_history.add(&query_text) {
+                print_status(json_output, &format!("Error: {error}"));
+            }
+            run_compared_scorers(
+                &query_text,
+                "ad-hoc",
+                &engine,
+                &mut query_settings,
+                &compared_scorers,
+                &mut timing_aggregator,
+                output_file
+                    .as_mut()
+                    .map(|file_handle| file_handle as &mut dyn Write),
+            )?;
+        }
+    } else if let Some(queries_file_path) = queries_file_option {
+        let batch_queries = read_queries_file(queries_file_path)?;
+        for (batch_index, batch_query_text) in batch_queries.iter().enumerate() {
+            let query_id = format!("batch-{:04}", batch_index + 1);
+            run_compared_scorers(
+                batch_query_text,
+                &query_id,
+                &engine,
+                &mut query_settings,
+                &compared_scorers,
+                &mut timing_aggregator,
+                output_file
+                    .as_mut()
+                    .map(|file_handle| file_handle as &mut dyn Write),
             )?;
         }
     } else {
-        let single_query_text = argument_matches
-            .get_one::<String>("query")
-            .ok_or_else(|| anyhow!("--query is required unless --interactive is set"))?;
-        run_single_query(
+        let single_query_text = single_query_option.ok_or(ChatbotError::MissingQuery)?;
+        run_compared_scorers(
             single_query_text,
-            &algorithm_name,
-            &guid_index_map,
-            prepared_keyword_index.as_ref(),
-            tfidf_index.as_ref(),
-            &parser_config,
-            &stopword_set,
-            warmup_count,
-            top_k,
-            log_path_option.as_deref(),
-            candidate_cards.len(),
-            &topic_label_for_logs,
-            *argument_matches.get_one::<bool>("show_cards").unwrap_or(&false),
-            parse_milliseconds,
-            index_milliseconds,
+            "ad-hoc",
+            &engine,
+            &mut query_settings,
+            &compared_scorers,
+            &mut timing_aggregator,
+            output_file
+                .as_mut()
+                .map(|file_handle| file_handle as &mut dyn Write),
         )?;
     }
 
-    println!(
-        "Parse build: {:.3} ms   Index build: {:.3} ms",
-        parse_milliseconds, index_milliseconds
-    );
+    if argument_matches.get_flag("profile") {
+        print_status(json_output, timing_aggregator.summary().trim_end());
+    }
+
+    // Wall-clock timings differ run to run, so canonical output omits them.
+    if !deterministic_output {
+        print_status(
+            json_output,
+            &format!(
+                "Parse build: {:.3} ms   Index build: {:.3} ms",
+                parse_milliseconds, index_milliseconds
+            ),
+        );
+    }
 
     Ok(())
 }
 
-/// Run a single query end-to-end (warm-up, score, print, optional benchmark log).
-#[allow(clippy::too_many_arguments)]
-fn run_single_query(
-    query_text: &str,
-    algorithm_name: &str,
-    guid_index_map: &HashMap<String, Card>,
-    prepared_keyword_index_option: Option<&Vec<crate::scoring::keyword::PreparedQuestion>>,
-    tfidf_index_option: Option<&crate::scoring::tfidf::TfidfIndex>,
+/// Deck paths from the repeatable `--data`, or the default data path when none is given.
+fn deck_data_paths(argument_matches: &clap::ArgMatches) -> Vec<String> {
+    match argument_matches.get_many::<String>("data") {
+        Some(data_paths) => data_paths.cloned().collect(),
+        None => vec![DEFAULT_DATA_PATH.to_string()],
+    }
+}
+
+/// Load `--config`, apply the `config_override_arguments` flags, and load the stopwords it names.
+fn load_parser_settings(
+    argument_matches: &clap::ArgMatches,
+) -> Result<(ParserConfig, HashSet<String>)> {
+    let mut parser_config = load_parser_config(parser_config_path(argument_matches))?;
+    if argument_matches.get_flag("deterministic") {
+        parser_config.deterministic = true;
+    }
+    if argument_matches.get_flag("no_header_skip") {
+        parser_config.skip_comment_lines = false;
+    }
+    if let Some(hybrid_alpha) = argument_matches.get_one::<f32>("hybrid_alpha") {
+        parser_config.hybrid_alpha = *hybrid_alpha;
+    }
+    if let Some(query_boost_short) = argument_matches.get_one::<usize>("query_boost_short") {
+        parser_config.query_boost_short = *query_boost_short;
+    }
+    if let Some(count_mode_name) = argument_matches.get_one::<String>("keyword_count_mode") {
+        parser_config.keyword_count_mode = if count_mode_name == "multiset" {
+            KeywordCountMode::Multiset
+        } else {
+            KeywordCountMode::Set
+        };
+    }
+    let stopword_set = load_configured_stopwords(&parser_config)?;
+    Ok((parser_config, stopword_set))
+}
+
+/// Load every `--data` deck and log its invalid records. The caller applies
+/// `check_invalid_ratio`, so `--validate` can report before failing.
+fn load_corpus(
+    argument_matches: &clap::ArgMatches,
     parser_config: &ParserConfig,
-    stopword_set: &std::collections::HashSet<String>,
-    warmup_count: usize,
-    top_k: usize,
-    log_path_option: Option<&str>,
-    candidate_deck_size_for_log: usize,
-    topic_text_for_log: &str,
-    show_cards_flag: bool,
-    parse_milliseconds: f64,
-    index_milliseconds: f64,
+) -> Result<(Vec<Card>, Vec<InvalidRecord>)> {
+    let (all_cards, invalid_records) =
+        load_decks_multi(&deck_data_paths(argument_matches), parser_config)?;
+    write_invalid_log(&invalid_records, argument_matches);
+    Ok((all_cards, invalid_records))
+}
+
+/// Log any invalid records to `--invalid-log` in the `--invalid-log-format` layout. Failures to
+/// write the log are ignored; the run itself does not depend on it.
+fn write_invalid_log(invalid_records: &[InvalidRecord], argument_matches: &clap::ArgMatches) {
+    if invalid_records.is_empty() {
+        return;
+    }
+    let invalid_log_path = argument_matches
+        .get_one::<String>("invalid_log")
+        .expect("has default");
+    let _ = match argument_matches
+        .get_one::<String>("invalid_log_format")
+        .map(String::as_str)
+    {
+        Some("json") => log_invalid_records_json(invalid_records, invalid_log_path),
+        _ => log_invalid_records(invalid_records, invalid_log_path),
+    };
+}
+
+/// `--max-invalid-ratio`, shared by queries and `bench`.
+fn max_invalid_ratio_argument() -> Arg {
+    Arg::new("max_invalid_ratio")
+        .long("max-invalid-ratio")
+        .value_name("RATIO")
+        .value_parser(parse_invalid_ratio)
+        .default_value("1.0")
+        .help("Fail before indexing if more than this fraction of deck records are invalid (default: 1.0, never).")
+}
+
+/// A `--max-invalid-ratio` value: a number from 0 to 1 (NaN is rejected).
+fn parse_invalid_ratio(value_text: &str) -> Result<f64, String> {
+    let ratio: f64 = value_text
+        .parse()
+        .map_err(|_| format!("{value_text:?} is not a number"))?;
+    if (0.0..=1.0).contains(&ratio) {
+        Ok(ratio)
+    } else {
+        Err(format!("{value_text} is not between 0 and 1"))
+    }
+}
+
+/// Fail if more than `--max-invalid-ratio` of the loaded records were invalid.
+fn check_invalid_ratio(
+    card_count: usize,
+    invalid_records: &[InvalidRecord],
+    argument_matches: &clap::ArgMatches,
 ) -> Result<()> {
-    if warmup_count > 0 {
-        for _ in 0..warmup_count {
-            if algorithm_name == "keyword" {
-                let _ = score_keyword_overlap(
-                    "warmup",
-                    prepared_keyword_index_option.expect("built earlier"),
-                    stopword_set,
-                    parser_config,
-                    top_k,
-                );
-            } else {
-                let _ = score_tfidf(
-                    "warmup",
-                    tfidf_index_option.expect("built earlier"),
-                    stopword_set,
-                    parser_config,
-                    top_k,
-                );
-            }
+    let max_invalid_ratio = *argument_matches
+        .get_one::<f64>("max_invalid_ratio")
+        .expect("defaulted by clap");
+    let record_count = card_count + invalid_records.len();
+    if record_count > 0 {
+        let invalid_ratio = invalid_records.len() as f64 / record_count as f64;
+        if invalid_ratio > max_invalid_ratio {
+            return Err(anyhow!(
+                "{} of {record_count} records are invalid (ratio {invalid_ratio:.3}), above \
+                 --max-invalid-ratio {max_invalid_ratio}",
+                invalid_records.len()
+            ));
         }
     }
+    Ok(())
+}
 
-    let mut stopwatch_total = Stopwatch::new();
-    stopwatch_total.start();
+/// The parser config path from `--config` (defaulted to `Data/Configs/Parser.json`).
+fn parser_config_path(argument_matches: &clap::ArgMatches) -> &str {
+    argument_matches
+        .get_one::<String>("config")
+        .expect("defaulted by clap")
+}
 
-    let mut stopwatch_rank = Stopwatch::new();
-    stopwatch_rank.start();
+/// The `engine_arguments` settings, applied by `build_engine` to every engine.
+struct EngineSettings {
+    query_cache_capacity: usize,
+    min_overlap: usize,
+    /// `--expand` synonyms; empty without the flag.
+    synonym_map: HashMap<String, Vec<String>>,
+    show_progress: bool,
+}
 
-    let answer_hits: Vec<AnswerHit> = if algorithm_name == "keyword" {
-        score_keyword_overlap(
-            query_text,
-            prepared_keyword_index_option.expect("built earlier"),
-            stopword_set,
+impl EngineSettings {
+    fn from_matches(
+        argument_matches: &clap::ArgMatches,
+        parser_config: &ParserConfig,
+    ) -> Result<Self> {
+        Ok(Self {
+            query_cache_capacity: *argument_matches
+                .get_one::<usize>("cache_queries")
+                .expect("defaulted by clap"),
+            min_overlap: *argument_matches
+                .get_one::<usize>("min_overlap")
+                .expect("defaulted by clap"),
+            synonym_map: expansion_synonyms(argument_matches, parser_config)?,
+            show_progress: argument_matches.get_flag("progress"),
+        })
+    }
+}
+
+/// Build an engine over `candidate_cards` for `scorers` with `engine_settings` applied. Every
+/// mode builds its engine here, so they all rank with the same options.
+fn build_engine(
+    candidate_cards: Vec<Card>,
+    parser_config: ParserConfig,
+    stopword_set: HashSet<String>,
+    scorers: &[Arc<dyn Scorer>],
+    engine_settings: &EngineSettings,
+) -> Result<ChatbotEngine> {
+    Ok(build_indexed_engine(
+        engine_settings.show_progress,
+        candidate_cards,
+        parser_config,
+        stopword_set,
+        scorers,
+    )?
+    .with_query_cache(engine_settings.query_cache_capacity)
+    .with_min_overlap(engine_settings.min_overlap)
+    .with_synonyms(engine_settings.synonym_map.clone()))
+}
+
+/// Index `candidate_cards` for `scorers`, drawing an index-build progress bar on stderr when
+/// `show_progress` is set.
+#[cfg(feature = "progress")]
+fn build_indexed_engine(
+    show_progress: bool,
+    candidate_cards: Vec<Card>,
+    parser_config: ParserConfig,
+    stopword_set: HashSet<String>,
+    scorers: &[Arc<dyn Scorer>],
+) -> Result<ChatbotEngine> {
+    if !show_progress {
+        return Ok(ChatbotEngine::from_cards(
+            candidate_cards,
             parser_config,
-            top_k,
-        )
-    } else {
-        score_tfidf(
-            query_text,
-            tfidf_index_option.expect("built earlier"),
             stopword_set,
-            parser_config,
-            top_k,
-        )
+            scorers,
+        ));
+    }
+    let progress_bar = indicatif::ProgressBar::new(candidate_cards.len() as u64);
+    progress_bar.set_style(
+        indicatif::ProgressStyle::with_template("Indexing [{bar:40}] {pos}/{len} cards")
+            .expect("valid progress template")
+            .progress_chars("=> "),
+    );
+    // Each index counts up from zero again, so the bar restarts per index.
+    let update_progress_bar = |cards_indexed: usize, card_count: usize| {
+        progress_bar.set_length(card_count as u64);
+        progress_bar.set_position(cards_indexed as u64);
     };
+    let engine = ChatbotEngine::from_cards_with_progress(
+        candidate_cards,
+        parser_config,
+        stopword_set,
+        scorers,
+        Some(&update_progress_bar),
+    );
+    progress_bar.finish_and_clear();
+    Ok(engine)
+}
 
-    let rank_milliseconds = stopwatch_rank.stop();
-    let wall_milliseconds = stopwatch_total.stop();
-
-    println!("{}", format_hits_for_display(&answer_hits, guid_index_map));
-    if show_cards_flag {
-        for answer_hit in &answer_hits {
-            println!("-> {}  score={:.6}", answer_hit.guid, answer_hit.score);
-        }
+#[cfg(not(feature = "progress"))]
+fn build_indexed_engine(
+    show_progress: bool,
+    candidate_cards: Vec<Card>,
+    parser_config: ParserConfig,
+    stopword_set: HashSet<String>,
+    scorers: &[Arc<dyn Scorer>],
+) -> Result<ChatbotEngine> {
+    if show_progress {
+        return Err(anyhow!(
+            "--progress requires building with the `progress` feature"
+        ));
     }
+    Ok(ChatbotEngine::from_cards(
+        candidate_cards,
+        parser_config,
+        stopword_set,
+        scorers,
+    ))
+}
 
-    if let Some(log_file_path) = log_path_option {
-        let stage_timings = StageTimings {
-            parse_milliseconds,
-            index_milliseconds,
-            preprocess_milliseconds: 0.0,
-            rank_milliseconds: rank_milliseconds,
-            format_milliseconds: 0.0,
-        };
-        let benchmark_record = LogRecord {
-            timestamp_iso: chrono::Utc::now().to_rfc3339(),
-            language: "rust".to_string(),
-            algorithm: algorithm_name.to_string(),
-            deck_size: candidate_deck_size_for_log,
-            topic: topic_text_for_log.to_string(),
-            query_id: "ad-hoc".to_string(),
-            query_text: query_text.to_string(),
-            stage_milliseconds: stage_timings,
-            wall_milliseconds: wall_milliseconds,
-            rss_kilobytes: None,
-            top: answer_hits
-                .iter()
-                .map(|answer_hit| (answer_hit.guid.clone(), answer_hit.score as f64))
-                .collect(),
-        };
-        let _ = log_benchmark(&benchmark_record, log_file_path);
-    }
+/// Build one engine over every card, indexed for every registered scorer, and hand it to the
+/// HTTP server. `--algo` (default `tfidf`) is used when a request omits `algo`.
+#[cfg(feature = "server")]
+fn serve_engine(
+    serve_address: &str,
+    scorer_registry: &ScorerRegistry,
+    all_cards: Vec<Card>,
+    parser_config: ParserConfig,
+    stopword_set: HashSet<String>,
+    argument_matches: &clap::ArgMatches,
+) -> Result<()> {
+    let default_scorer_name = argument_matches
+        .get_one::<String>("algo")
+        .filter(|algorithm_name| *algorithm_name != ALL_ALGORITHMS_NAME)
+        .map_or("tfidf", String::as_str)
+        .to_string();
+    let include_subtree = match argument_matches.get_one::<String>("include_subtree") {
+        Some(value_text) => value_text == "true",
+        None => parser_config.include_subtree,
+    };
+    let engine_settings = EngineSettings::from_matches(argument_matches, &parser_config)?;
+    let engine = build_engine(
+        all_cards,
+        parser_config,
+        stopword_set,
+        scorer_registry.scorers(),
+        &engine_settings,
+    )?;
+    crate::server::serve(serve_address, engine, default_scorer_name, include_subtree)
+}
 
-    Ok(())
+#[cfg(not(feature = "server"))]
+fn serve_engine(
+    _serve_address: &str,
+    _scorer_registry: &ScorerRegistry,
+    _all_cards: Vec<Card>,
+    _parser_config: ParserConfig,
+    _stopword_set: HashSet<String>,
+    _argument_matches: &clap::ArgMatches,
+) -> Result<()> {
+    Err(anyhow!(
+        "--serve requires building with the `server` feature"
+    ))
 }
 
-/// Format result hits like Python: rank, GUID, score, topic, full Q and full escaped A.
-fn format_hits_for_display(
-    answer_hits: &[AnswerHit],
-    guid_index_map: &HashMap<String, Card>,
-) -> String {
-    if answer_hits.is_empty() {
-        return "No results.".to_string();
-    }
-    let mut output_lines: Vec<String> = Vec::new();
-    for (rank_index, answer_hit) in answer_hits.iter().enumerate() {
-        if let Some(card) = guid_index_map.get(&answer_hit.guid) {
-            let topic_text = deck_path_to_string(&answer_hit.deck_path);
-            let question_line = answer_hit
-                .question_preview
-                .clone()
-                .unwrap_or_else(|| card.question_text.clone());
-            let answer_display = normalise_for_display(&card.answer_raw);
-
-            output_lines.push(format!(
-                "{}. GUID={}  score={:.6}  topic={}",
-                rank_index + 1,
-                answer_hit.guid,
-                answer_hit.score,
-                topic_text
-            ));
-            output_lines.push(format!("   Q: {}", question_line));
-            output_lines.push(format!("   A: {}", answer_display));
-        }
+/// The `bench` subcommand: time one query repeatedly with a single scorer from `scorer_registry`.
+fn bench_command(scorer_registry: &ScorerRegistry) -> Command {
+    Command::new("bench")
+        .about("Rank one query --repeat times after --warmup untimed runs and print rank-time statistics.")
+        .arg(
+            Arg::new("algo")
+                .long("algo")
+                .required(true)
+                .value_parser(scorer_registry.names())
+                .help("Retrieval algorithm to benchmark."),
+        )
+        .arg(
+            Arg::new("query")
+                .long("query")
+                .required(true)
+                .help("Query to rank on every run."),
+        )
+        .arg(
+            Arg::new("repeat")
+                .long("repeat")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("10")
+                .help("Number of timed runs (default: 10)."),
+        )
+        .arg(
+            Arg::new("warmup")
+                .long("warmup")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("0")
+                .help("Number of untimed runs first (default: 0)."),
+        )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .value_name("PATH")
+                .default_value(DEFAULT_PARSER_CONFIG_PATH)
+                .help("Parser config JSON to load (default: Data/Configs/Parser.json)."),
+        )
+        .arg(
+            Arg::new("data")
+                .long("data")
+                .value_name("PATH")
+                .action(ArgAction::Append)
+                .help("Deck directory or file to load; repeat to combine several (default: Data/Decks)."),
+        )
+        .arg(
+            Arg::new("invalid_log")
+                .long("invalid-log")
+                .default_value("Logs/errors-rs.log")
+                .help("Path to invalid record log file."),
+        )
+        .arg(
+            Arg::new("invalid_log_format")
+                .long("invalid-log-format")
+                .value_parser(["text", "json"])
+                .default_value("text")
+                .help("Invalid record log format: two lines per record (default) or JSON Lines."),
+        )
+        .arg(max_invalid_ratio_argument())
+        .args(engine_arguments())
+        .args(config_override_arguments())
+}
+
+/// Load every card, index only `--algo`, run `--warmup` untimed queries, then time `--repeat`
+/// rankings of the query and print their mean, standard deviation, min and max.
+fn run_bench(bench_matches: &clap::ArgMatches, scorer_registry: &ScorerRegistry) -> Result<()> {
+    let algorithm_name = bench_matches
+        .get_one::<String>("algo")
+        .expect("required by clap");
+    let scorer = scorer_registry
+        .get(algorithm_name)
+        .expect("restricted by clap value_parser");
+    let query_text = bench_matches
+        .get_one::<String>("query")
+        .expect("required by clap");
+    let repeat_count = *bench_matches
+        .get_one::<usize>("repeat")
+        .expect("defaulted by clap");
+    let warmup_count = *bench_matches
+        .get_one::<usize>("warmup")
+        .expect("defaulted by clap");
+    if repeat_count == 0 {
+        return Err(anyhow!("--repeat must be at least 1"));
+    }
+
+    let (parser_config, stopword_set) = load_parser_settings(bench_matches)?;
+    let (all_cards, invalid_records) = load_corpus(bench_matches, &parser_config)?;
+    check_invalid_ratio(all_cards.len(), &invalid_records, bench_matches)?;
+    if all_cards.is_empty() {
+        return Err(ChatbotError::NoValidCards.into());
+    }
+    let card_count = all_cards.len();
+    let engine_settings = EngineSettings::from_matches(bench_matches, &parser_config)?;
+    let engine = build_engine(
+        all_cards,
+        parser_config,
+        stopword_set,
+        std::slice::from_ref(&scorer),
+        &engine_settings,
+    )?;
+    let rank_query = || engine.query(query_text, scorer.name(), 1);
+
+    for _ in 0..warmup_count {
+        let _ = rank_query();
+    }
+    let mut rank_samples: Vec<f64> = Vec::with_capacity(repeat_count);
+    for _ in 0..repeat_count {
+        let mut stopwatch_rank = Stopwatch::new();
+        stopwatch_rank.start();
+        let answer_hits = rank_query();
+        rank_samples.push(stopwatch_rank.stop());
+        std::hint::black_box(answer_hits);
+    }
+    let repeat_statistics =
+        RepeatStatistics::from_samples(&rank_samples).expect("at least one timed run");
+
+    println!(
+        "Bench: algo={} cards={card_count} warmup={warmup_count} repeat={repeat_count}",
+        scorer.name()
+    );
+    println!(
+        "rank: n={} mean={:.3} stddev={:.3} min={:.3} max={:.3} ms",
+        repeat_statistics.count,
+        repeat_statistics.mean,
+        repeat_statistics.stddev,
+        repeat_statistics.min,
+        repeat_statistics.max,
+    );
+    Ok(())
+}
+
+/// Resolve the requested topic (if any) and collect its candidate cards plus the topic label
+/// used in logs. Without a topic every card is a candidate.
+fn select_candidate_cards(
+    all_cards: &[Card],
+    requested_topic: Option<&str>,
+    topic_separator: &str,
+    include_subtree: bool,
+    json_output: bool,
+) -> Result<(Vec<Card>, String)> {
+    let Some(requested_topic_text) = requested_topic else {
+        return Ok((all_cards.to_vec(), "<ALL>".to_string()));
+    };
+
+    let known_topics = list_available_topics(all_cards);
+    let resolved_root_topic: DeckPath =
+        resolve_topic_string(requested_topic_text, topic_separator, &known_topics)?;
+    let matched_ignoring_case = resolved_root_topic
+        .iter()
+        .zip(requested_topic_text.split(topic_separator))
+        .any(|(resolved_segment, requested_segment)| resolved_segment != requested_segment.trim());
+    if matched_ignoring_case {
+        print_status(
+            json_output,
+            &format!(
+                "Note: topic '{requested_topic_text}' matched '{}' ignoring case.",
+                resolved_root_topic.join(topic_separator)
+            ),
+        );
+    }
+
+    let topic_index = build_topic_index(all_cards);
+    let candidates =
+        collect_subtree_candidates(&topic_index, &resolved_root_topic, include_subtree);
+
+    if candidates.is_empty() {
+        return Err(ChatbotError::EmptyCandidatePool.into());
+    }
+    Ok((candidates, requested_topic_text.to_string()))
+}
+
+/// Collect every card under a deck-path prefix (`--topic-prefix`), which need not be a known
+/// topic; the prefix doubles as the topic label in logs.
+fn select_prefix_candidate_cards(
+    all_cards: &[Card],
+    topic_prefix_text: &str,
+    topic_separator: &str,
+) -> Result<(Vec<Card>, String)> {
+    let prefix_path: DeckPath = topic_prefix_text
+        .split(topic_separator)
+        .map(|segment| segment.trim().to_string())
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    if prefix_path.is_empty() {
+        return Err(ChatbotError::EmptyTopic.into());
+    }
+    let candidates = collect_prefix_candidates(all_cards, &prefix_path);
+    if candidates.is_empty() {
+        return Err(ChatbotError::NoTopicPrefixMatch {
+            prefix: prefix_path.join(topic_separator),
+            top_level_topics: list_top_level_topics(all_cards),
+        }
+        .into());
+    }
+    Ok((candidates, topic_prefix_text.to_string()))
+}
+
+/// Queries entered in interactive mode, numbered from 1, optionally mirrored to a file that is
+/// read back at the next start.
+struct QueryHistory {
+    entries: Vec<String>,
+    history_file_path: Option<String>,
+}
+
+impl QueryHistory {
+    /// Start with the non-empty lines of `history_file_path` when it exists.
+    fn load(history_file_path: Option<&str>) -> Result<Self> {
+        let mut entries: Vec<String> = Vec::new();
+        if let Some(file_path) = history_file_path
+            && fs::metadata(file_path).is_ok()
+        {
+            let file_content = fs::read_to_string(file_path)
+                .with_context(|| format!("Failed to read history file: {file_path}"))?;
+            entries.extend(
+                file_content
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line_text| !line_text.is_empty())
+                    .map(str::to_string),
+            );
+        }
+        Ok(Self {
+            entries,
+            history_file_path: history_file_path.map(str::to_string),
+        })
+    }
+
+    /// Record a query (and append it to the history file). Blank input and `exit` are ignored.
+    fn add(&mut self, query_text: &str) -> Result<()> {
+        let query_text = query_text.trim();
+        if query_text.is_empty() || query_text.eq_ignore_ascii_case("exit") {
+            return Ok(());
+        }
+        self.entries.push(query_text.to_string());
+        if let Some(file_path) = &self.history_file_path {
+            let mut history_file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(file_path)
+                .with_context(|| format!("Failed to open history file: {file_path}"))?;
+            writeln!(history_file, "{query_text}")
+                .with_context(|| format!("Failed to write history file: {file_path}"))?;
+        }
+        Ok(())
+    }
+
+    /// Entry `entry_number` (1-based), if there is one.
+    fn recall(&self, entry_number: usize) -> Option<&str> {
+        entry_number
+            .checked_sub(1)
+            .and_then(|entry_index| self.entries.get(entry_index))
+            .map(String::as_str)
+    }
+
+    /// One `  N  query` line per entry, or a note that the history is empty.
+    fn listing(&self) -> String {
+        if self.entries.is_empty() {
+            return "History is empty.\n".to_string();
+        }
+        self.entries
+            .iter()
+            .enumerate()
+            .map(|(entry_index, query_text)| format!("{:>3}  {query_text}\n", entry_index + 1))
+            .collect()
+    }
+}
+
+/// Timings of one query, collected for `--profile`.
+struct QueryTimings {
+    wall_milliseconds: f64,
+    rank_milliseconds: f64,
+}
+
+fn record_query_timings(timing_aggregator: &mut TimingAggregator, query_timings: QueryTimings) {
+    timing_aggregator.record("wall", query_timings.wall_milliseconds);
+    timing_aggregator.record("rank", query_timings.rank_milliseconds);
+}
+
+/// Run one query with each of `compared_scorers`. With more than one (`--algo all`), each
+/// result list gets a `== name ==` header and its own benchmark log record.
+fn run_compared_scorers(
+    query_text: &str,
+    query_id: &str,
+    engine: &Arc<ChatbotEngine>,
+    query_settings: &mut QuerySettings,
+    compared_scorers: &[Arc<dyn Scorer>],
+    timing_aggregator: &mut TimingAggregator,
+    mut output_sink: Option<&mut dyn Write>,
+) -> Result<()> {
+    for scorer in compared_scorers {
+        if compared_scorers.len() > 1 {
+            print_status(
+                query_settings.output_format == "json",
+                &format!("== {} ==", scorer.name()),
+            );
+        }
+        query_settings.scorer = Arc::clone(scorer);
+        let query_timings = run_single_query(
+            query_text,
+            query_id,
+            engine,
+            query_settings,
+            output_sink
+                .as_mut()
+                .map(|output_sink| &mut **output_sink as &mut dyn Write),
+        )?;
+        record_query_timings(timing_aggregator, query_timings);
+    }
+    Ok(())
+}
+
+/// Per-run settings shared by every query in a session.
+struct QuerySettings {
+    scorer: Arc<dyn Scorer>,
+    warmup_count: usize,
+    top_k: usize,
+    min_score: f32,
+    log_path: Option<String>,
+    log_format: String,
+    candidate_deck_size: usize,
+    topic_label: String,
+    show_cards: bool,
+    explain: bool,
+    autocorrect: bool,
+    highlight: bool,
+    per_topic: bool,
+    /// Deck path segments `--per-topic` groups on (0 = the full path).
+    topic_depth: usize,
+    /// Print `k of N shown; M candidates matched` after each result list.
+    stats: bool,
+    answer_max_chars: usize,
+    /// Show `extract_snippet` excerpts of answers around the query tokens.
+    snippet: bool,
+    /// `--template` layout handed to `render_template` for every text hit.
+    result_template: String,
+    answer_render: AnswerRender,
+    preview_source: PreviewSource,
+    diversity: bool,
+    normalise_scores: bool,
+    /// Scoring budget per query (`--timeout-ms`); `None` waits however long scoring takes.
+    timeout: Option<Duration>,
+    /// Decimal places of displayed and logged scores.
+    precision: usize,
+    /// Results shown per page before waiting for Enter (interactive text output; 0 = no paging).
+    page_size: usize,
+    /// Ranked hits of recent queries; entries are dropped when `pool_generation` changes.
+    result_cache: Option<ResultCache>,
+    /// Bumped whenever the candidate pool is rebuilt (the interactive `:topic` command).
+    pool_generation: u64,
+    output_format: String,
+    parse_milliseconds: f64,
+    index_milliseconds: f64,
+}
+
+/// The synonym map for `--expand`, or an empty map (no expansion) without it.
+fn expansion_synonyms(
+    argument_matches: &clap::ArgMatches,
+    parser_config: &ParserConfig,
+) -> Result<HashMap<String, Vec<String>>> {
+    if !argument_matches.get_flag("expand") {
+        return Ok(HashMap::new());
+    }
+    if parser_config.synonyms_path.is_none() {
+        return Err(anyhow!(
+            "--expand needs `synonyms_path` in the tokenisation config"
+        ));
+    }
+    Ok(load_synonyms(parser_config)?)
+}
+
+/// Keep only the best hit of each deck path, then the `top_k` best of those.
+/// `ranked_hits` must already be in rank order, so each path's first hit is its best.
+fn group_best_per_topic(
+    ranked_hits: Vec<AnswerHit>,
+    top_k: usize,
+    topic_depth: usize,
+) -> Vec<AnswerHit> {
+    let mut seen_deck_paths: HashSet<DeckPath> = HashSet::new();
+    ranked_hits
+        .into_iter()
+        .filter(|answer_hit| {
+            seen_deck_paths.insert(truncate_deck_path(&answer_hit.deck_path, topic_depth))
+        })
+        .take(top_k)
+        .collect()
+}
+
+/// Create (or empty) the `--output-file`, creating its parent directory first. Every query of
+/// the session then appends its results to the one handle.
+fn create_output_file(output_file_path: &str) -> Result<fs::File> {
+    ensure_parent_directory_exists(output_file_path)?;
+    fs::File::create(output_file_path)
+        .with_context(|| format!("Failed to create output file {output_file_path}"))
+}
+
+/// Read one query per line, skipping blank lines and `#` comments.
+fn read_queries_file(file_path: &str) -> Result<Vec<String>> {
+    let file_content = fs::read_to_string(file_path)
+        .with_context(|| format!("Failed to read queries file {file_path}"))?;
+    Ok(file_content
+        .lines()
+        .map(str::trim)
+        .filter(|line_text| !line_text.is_empty() && !line_text.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Run a single query end-to-end (warm-up, score, print, optional benchmark log).
+/// Results go to `output_sink` when given (`--output-file`), otherwise to stdout.
+fn run_single_query(
+    query_text: &str,
+    query_id: &str,
+    engine: &Arc<ChatbotEngine>,
+    query_settings: &QuerySettings,
+    output_sink: Option<&mut dyn Write>,
+) -> Result<QueryTimings> {
+    let scorer_name = query_settings.scorer.name();
+    let top_k = query_settings.top_k;
+    let output_format = query_settings.output_format.as_str();
+
+    if query_settings.warmup_count > 0 {
+        for _ in 0..query_settings.warmup_count {
+            let _ = engine.query("warmup", scorer_name, top_k);
+        }
+    }
+
+    let mut stopwatch_total = Stopwatch::new();
+    stopwatch_total.start();
+
+    // The scorers tokenise internally; this times the same tokenisation pass (plus any
+    // spelling correction) on its own.
+    let mut stopwatch_preprocess = Stopwatch::new();
+    stopwatch_preprocess.start();
+    // Corrected tokens are scored as they are: re-tokenising them would stem them again.
+    let mut query_tokens = tokenise(query_text, engine.stopword_set(), engine.parser_config());
+    let mut substitutions: Vec<(String, String)> = Vec::new();
+    if query_settings.autocorrect
+        && let Some(tfidf_index) = engine.tfidf_index()
+    {
+        let (corrected_tokens, token_substitutions) = correct_tokens(
+            &query_tokens,
+            &tfidf_index.document_frequency,
+            AUTOCORRECT_MAX_EDIT_DISTANCE,
+        );
+        query_tokens = corrected_tokens;
+        substitutions = token_substitutions;
+    }
+    let preprocess_milliseconds = stopwatch_preprocess.stop();
+
+    let mut stopwatch_rank = Stopwatch::new();
+    stopwatch_rank.start();
+
+    // Every other ranking input is fixed for the session, so the key only needs these.
+    let result_cache_key = ResultCacheKey::new(
+        scorer_name,
+        &query_settings.topic_label,
+        query_text,
+        top_k,
+        engine.parser_config(),
+    );
+    let cached_hits = query_settings
+        .result_cache
+        .as_ref()
+        .and_then(|result_cache| {
+            result_cache.get(&result_cache_key, query_settings.pool_generation)
+        });
+    // Results emptied by `min_score` are never cached, so a cached hit list is never one.
+    let (answer_hits, filtered_by_threshold) = match cached_hits {
+        Some(cached_hits) => (cached_hits, false),
+        None => {
+            let scoring_engine = Arc::clone(engine);
+            let scoring_query_text = query_text.to_string();
+            let scoring_query_tokens = query_tokens.clone();
+            let (diversity, per_topic, topic_depth, min_score) = (
+                query_settings.diversity,
+                query_settings.per_topic,
+                query_settings.topic_depth,
+                query_settings.min_score,
+            );
+            let scoring_result = score_with_timeout(
+                move || {
+                    let pool_size = if diversity {
+                        ChatbotEngine::diversity_pool_size(top_k)
+                    } else if per_topic {
+                        scoring_engine.cards().len()
+                    } else {
+                        top_k
+                    };
+                    // Ranked without `min_score` so an empty result can tell "nothing matched"
+                    // from "nothing cleared the threshold". Hits come best first, so dropping
+                    // those below it afterwards keeps the same hits as the scorer would.
+                    let mut ranked_hits = scoring_engine.query_above_tokens(
+                        scoring_query_text.as_str(),
+                        &scoring_query_tokens,
+                        scorer_name,
+                        pool_size,
+                        0.0,
+                    );
+                    let any_hit_scored = !ranked_hits.is_empty();
+                    ranked_hits.retain(|answer_hit| answer_hit.score >= min_score);
+                    let answer_hits = if diversity {
+                        scoring_engine.rerank_diverse(ranked_hits, top_k)
+                    } else if per_topic {
+                        group_best_per_topic(ranked_hits, top_k, topic_depth)
+                    } else {
+                        ranked_hits
+                    };
+                    (answer_hits, any_hit_scored)
+                },
+                query_settings.timeout,
+            );
+            let Some((mut answer_hits, any_hit_scored)) = scoring_result else {
+                let rank_milliseconds = stopwatch_rank.stop();
+                let wall_milliseconds = stopwatch_total.stop();
+                print_status(output_format == "json", "query timed out");
+                if output_format == "json" {
+                    let timeout_json =
+                        serde_json::json!({ "query": query_text, "timed_out": true });
+                    match output_sink {
+                        Some(output_sink) => writeln!(output_sink, "{timeout_json}"),
+                        None => writeln!(io::stdout(), "{timeout_json}"),
+                    }
+                    .with_context(|| "Failed to write results")?;
+                }
+                let stage_timings = StageTimings {
+                    parse_milliseconds: query_settings.parse_milliseconds,
+                    index_milliseconds: query_settings.index_milliseconds,
+                    preprocess_milliseconds,
+                    rank_milliseconds,
+                    format_milliseconds: 0.0,
+                };
+                append_benchmark_record(
+                    &LogRecord {
+                        timed_out: true,
+                        ..benchmark_record(
+                            query_settings,
+                            query_id,
+                            query_text,
+                            stage_timings,
+                            wall_milliseconds,
+                        )
+                    },
+                    query_settings,
+                );
+                return Ok(QueryTimings {
+                    wall_milliseconds,
+                    rank_milliseconds,
+                });
+            };
+            if query_settings.normalise_scores {
+                answer_hits = normalise_hit_scores(
+                    answer_hits,
+                    &query_tokens,
+                    &*query_settings.scorer,
+                    engine.parser_config(),
+                );
+            }
+            let filtered_by_threshold = answer_hits.is_empty() && any_hit_scored;
+            if let Some(result_cache) = &query_settings.result_cache
+                && !filtered_by_threshold
+            {
+                result_cache.insert(
+                    result_cache_key,
+                    query_settings.pool_generation,
+                    answer_hits.clone(),
+                );
+            }
+            (answer_hits, filtered_by_threshold)
+        }
+    };
+
+    let rank_milliseconds = stopwatch_rank.stop();
+
+    let mut stopwatch_format = Stopwatch::new();
+    stopwatch_format.start();
+    let answer_hits = with_preview_source(
+        answer_hits,
+        query_settings.preview_source,
+        engine.guid_index(),
+    );
+    let result_entries: Vec<String> = if output_format == "json" {
+        vec![format_hits_as_json(&answer_hits, engine.guid_index())?]
+    } else {
+        let answer_display_mode = if query_settings.snippet {
+            AnswerDisplay::Snippet(query_tokens.iter().cloned().collect())
+        } else {
+            AnswerDisplay::Truncated(query_settings.answer_max_chars)
+        };
+        if filtered_by_threshold {
+            vec!["No results above threshold.".to_string()]
+        } else if query_settings.highlight {
+            let highlighted_hits: Vec<AnswerHit> = answer_hits
+                .iter()
+                .map(|answer_hit| {
+                    let preview_text = answer_hit.question_preview.clone().unwrap_or_else(|| {
+                        engine
+                            .card(&answer_hit.guid)
+                            .map(|card| card.question_text.clone())
+                            .unwrap_or_default()
+                    });
+                    AnswerHit {
+                        question_preview: Some(highlight_terms(
+                            &preview_text,
+                            &engine.matched_query_tokens(&query_tokens, &answer_hit.guid),
+                            engine.parser_config().stem,
+                        )),
+                        ..answer_hit.clone()
+                    }
+                })
+                .collect();
+            format_hits_for_display(
+                &highlighted_hits,
+                engine.guid_index(),
+                &answer_display_mode,
+                query_settings.answer_render,
+                &query_settings.result_template,
+                query_settings.precision,
+            )
+        } else {
+            format_hits_for_display(
+                &answer_hits,
+                engine.guid_index(),
+                &answer_display_mode,
+                query_settings.answer_render,
+                &query_settings.result_template,
+                query_settings.precision,
+            )
+        }
+    };
+    let formatted_output = result_entries.join("\n");
+    let format_milliseconds = stopwatch_format.stop();
+    let wall_milliseconds = stopwatch_total.stop();
+
+    if !substitutions.is_empty() {
+        let substitution_text = substitutions
+            .iter()
+            .map(|(original_token, replacement)| format!("{original_token} → {replacement}"))
+            .collect::<Vec<String>>()
+            .join(", ");
+        print_status(
+            output_format == "json",
+            &format!("Did you mean: {substitution_text}"),
+        );
+    }
+
+    // Paging only applies to results shown on the terminal (`page_size` is 0 otherwise).
+    let paging = query_settings.page_size > 0 && output_sink.is_none();
+    let mut stdout_handle = io::stdout();
+    let result_writer: &mut dyn Write = match output_sink {
+        Some(output_sink) => output_sink,
+        None => &mut stdout_handle,
+    };
+    let shown_in_full = if paging {
+        write_paged(
+            result_writer,
+            &paginate(&result_entries, query_settings.page_size),
+            &mut io::stdin().lock(),
+        )?
+    } else {
+        writeln!(result_writer, "{formatted_output}").with_context(|| "Failed to write results")?;
+        true
+    };
+    if query_settings.show_cards && output_format != "json" && shown_in_full {
+        for answer_hit in &answer_hits {
+            writeln!(
+                result_writer,
+                "-> {}  score={:.precision$}",
+                answer_hit.guid,
+                answer_hit.score,
+                precision = query_settings.precision
+            )
+            .with_context(|| "Failed to write results")?;
+        }
+    }
+    if query_settings.explain && output_format != "json" && shown_in_full {
+        for answer_hit in &answer_hits {
+            writeln!(
+                result_writer,
+                "{}",
+                format_explanation(&query_tokens, &answer_hit.guid, scorer_name, engine)
+            )
+            .with_context(|| "Failed to write results")?;
+        }
+    }
+    if query_settings.stats {
+        // Counted after timing: it ranks the whole pool again.
+        let score_report =
+            engine.score_report_tokens(query_text, &query_tokens, scorer_name, 0, 0.0);
+        print_status(
+            output_format == "json",
+            &format!(
+                "{} of {} shown; {} candidates matched",
+                answer_hits.len(),
+                score_report.total_candidates,
+                score_report.total_nonzero
+            ),
+        );
+    }
+
+    if query_settings.log_path.is_some() {
+        let stage_timings = StageTimings {
+            parse_milliseconds: query_settings.parse_milliseconds,
+            index_milliseconds: query_settings.index_milliseconds,
+            preprocess_milliseconds,
+            rank_milliseconds,
+            format_milliseconds,
+        };
+        append_benchmark_record(
+            &LogRecord {
+                top: top_entries_from_hits_with_decimals(&answer_hits, query_settings.precision),
+                ..benchmark_record(
+                    query_settings,
+                    query_id,
+                    query_text,
+                    stage_timings,
+                    wall_milliseconds,
+                )
+            },
+            query_settings,
+        );
+    }
+
+    Ok(QueryTimings {
+        wall_milliseconds,
+        rank_milliseconds,
+    })
+}
+
+/// A session benchmark record for one query, with no hits.
+fn benchmark_record(
+    query_settings: &QuerySettings,
+    query_id: &str,
+    query_text: &str,
+    stage_timings: StageTimings,
+    wall_milliseconds: f64,
+) -> LogRecord {
+    LogRecord {
+        timestamp_iso: chrono::Utc::now().to_rfc3339(),
+        language: "rust".to_string(),
+        algorithm: query_settings.scorer.name().to_string(),
+        deck_size: query_settings.candidate_deck_size,
+        topic: query_settings.topic_label.clone(),
+        query_id: query_id.to_string(),
+        query_text: query_text.to_string(),
+        stage_milliseconds: stage_timings,
+        wall_milliseconds,
+        rss_kilobytes: current_rss_kilobytes(),
+        top: Vec::new(),
+        timed_out: false,
+    }
+}
+
+/// Append `benchmark_record` to the `--log` file in `--log-format`, if there is one.
+fn append_benchmark_record(benchmark_record: &LogRecord, query_settings: &QuerySettings) {
+    let Some(log_file_path) = &query_settings.log_path else {
+        return;
+    };
+    let _ = if query_settings.log_format == "csv" {
+        log_benchmark_csv(benchmark_record, log_file_path)
+    } else {
+        log_benchmark(benchmark_record, log_file_path)
+    };
+}
+
+/// Scoring threads `score_with_timeout` lets run at once. A timed-out query's thread cannot be
+/// stopped and runs until scoring finishes, so this caps how many pile up behind slow queries.
+const MAX_SCORING_WORKERS: usize = 2;
+
+/// Scoring threads running now, and a signal for each one that finishes.
+static SCORING_WORKERS: (Mutex<usize>, Condvar) = (Mutex::new(0), Condvar::new());
+
+/// A `SCORING_WORKERS` slot, given back when the worker holding it finishes (or panics).
+struct ScoringWorkerSlot;
+
+impl ScoringWorkerSlot {
+    /// Wait until before `deadline` for a free slot; `None` if every slot stays taken.
+    fn acquire(deadline: Instant) -> Option<Self> {
+        let (running_count, slot_freed) = &SCORING_WORKERS;
+        let mut running_workers = running_count.lock().expect("scoring worker lock poisoned");
+        while *running_workers >= MAX_SCORING_WORKERS {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return None;
+            }
+            running_workers = slot_freed
+                .wait_timeout(running_workers, remaining)
+                .expect("scoring worker lock poisoned")
+                .0;
+        }
+        *running_workers += 1;
+        Some(Self)
+    }
+}
+
+impl Drop for ScoringWorkerSlot {
+    fn drop(&mut self) {
+        let (running_count, slot_freed) = &SCORING_WORKERS;
+        if let Ok(mut running_workers) = running_count.lock() {
+            *running_workers -= 1;
+        }
+        slot_freed.notify_one();
+    }
+}
+
+/// Run `scoring` on a worker thread and wait at most `timeout` for it, returning `None` if the
+/// budget runs out. The worker is left to finish on its own and its result is discarded; at
+/// most `MAX_SCORING_WORKERS` run at once, and waiting for a free one counts towards the
+/// budget. Without a timeout `scoring` runs on the calling thread.
+fn score_with_timeout<T, F>(scoring: F, timeout: Option<Duration>) -> Option<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let Some(timeout) = timeout else {
+        return Some(scoring());
+    };
+    let deadline = Instant::now() + timeout;
+    let worker_slot = ScoringWorkerSlot::acquire(deadline)?;
+    let (result_sender, result_receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let _worker_slot = worker_slot;
+        let _ = result_sender.send(scoring());
+    });
+    result_receiver
+        .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        .ok()
+}
+
+/// Describe why `guid` scored as it did: matched terms with their contributions, plus the
+/// cosine numerator/denominator for TF–IDF.
+fn format_explanation(
+    query_tokens: &[String],
+    guid: &str,
+    scorer_name: &str,
+    engine: &ChatbotEngine,
+) -> String {
+    let ScoreExplanation {
+        term_contributions,
+        cosine_parts,
+    } = engine.explain(query_tokens, guid, scorer_name);
+
+    let term_text = term_contributions
+        .iter()
+        .map(|(token_text, contribution)| format!("{token_text}={contribution:.6}"))
+        .collect::<Vec<String>>()
+        .join(" ");
+    match cosine_parts {
+        Some((numerator, denominator)) => {
+            format!("-> {guid}  explain: {term_text}  cosine={numerator:.6}/{denominator:.6}")
+        }
+        None => format!("-> {guid}  explain: {term_text}"),
+    }
+}
+
+/// Print a status line to stdout, or to stderr when stdout carries JSON results.
+fn print_status(json_output: bool, status_text: &str) {
+    if json_output {
+        eprintln!("{status_text}");
+    } else {
+        println!("{status_text}");
+    }
+}
+
+/// A hit enriched with its rank and display-escaped answer for JSON output.
+#[derive(Serialize)]
+struct JsonHit<'a> {
+    rank: usize,
+    #[serde(flatten)]
+    hit: &'a AnswerHit,
+    answer: String,
+    /// The card's `extra_columns` values, sorted by key; omitted when there are none.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    metadata: BTreeMap<&'a str, &'a str>,
+}
+
+/// Serialise result hits as a single JSON array (one element per hit, in rank order).
+pub(crate) fn format_hits_as_json(
+    answer_hits: &[AnswerHit],
+    guid_index_map: &HashMap<String, Card>,
+) -> Result<String> {
+    let json_hits: Vec<JsonHit> = answer_hits
+        .iter()
+        .enumerate()
+        .filter_map(|(rank_index, answer_hit)| {
+            guid_index_map.get(&answer_hit.guid).map(|card| JsonHit {
+                rank: rank_index + 1,
+                hit: answer_hit,
+                answer: normalise_for_display(&card.answer_raw),
+                metadata: card
+                    .metadata
+                    .iter()
+                    .map(|(metadata_key, metadata_value)| {
+                        (metadata_key.as_str(), metadata_value.as_str())
+                    })
+                    .collect(),
+            })
+        })
+        .collect();
+    serde_json::to_string(&json_hits).with_context(|| "Failed to serialise results to JSON")
+}
+
+/// One line per deck path with the number of cards at exactly that path, indented two spaces
+/// per level below the top-level deck. Parent paths holding no cards are listed with `(0)`.
+/// A non-zero `topic_depth` rolls deeper paths (and their cards) up to that many segments.
+fn format_topic_listing(all_cards: &[Card], topic_separator: &str, topic_depth: usize) -> String {
+    let topic_index = build_topic_index_with_depth(all_cards, topic_depth);
+    let listed_paths: BTreeSet<DeckPath> = topic_index
+        .keys()
+        .flat_map(|deck_path| {
+            (1..=deck_path.len())
+                .map(|prefix_length| deck_path[..prefix_length].to_vec())
+                .collect::<Vec<DeckPath>>()
+        })
+        .collect();
+    let mut listing_text = String::new();
+    for deck_path in listed_paths {
+        let card_count = topic_index.get(&deck_path).map_or(0, Vec::len);
+        let indentation = "  ".repeat(deck_path.len().saturating_sub(1));
+        listing_text.push_str(&format!(
+            "{indentation}{} ({card_count})\n",
+            deck_path.join(topic_separator)
+        ));
+    }
+    listing_text
+}
+
+/// The `--compare` report: overlap@k, a row per GUID both algorithms returned with its rank
+/// under each and the shift between them, then the GUIDs only one of them returned.
+fn format_comparison_report(
+    comparison_report: &ComparisonReport,
+    first_name: &str,
+    second_name: &str,
+) -> String {
+    let top_k = comparison_report.top_k;
+    let mut report_text = format!(
+        "Compare {first_name} vs {second_name} (top {top_k})\n\
+         overlap@{top_k}: {}/{top_k} ({:.2})\n",
+        comparison_report.overlap_count, comparison_report.overlap_at_k
+    );
+    if !comparison_report.rank_shifts.is_empty() {
+        let first_width = first_name.len();
+        let second_width = second_name.len();
+        report_text.push_str(&format!(
+            "{:<12}  {first_name}  {second_name}  shift\n",
+            "GUID"
+        ));
+        for rank_shift in &comparison_report.rank_shifts {
+            let shift_text = match rank_shift.delta() {
+                0 => "0".to_string(),
+                rank_delta => format!("{rank_delta:+}"),
+            };
+            report_text.push_str(&format!(
+                "{:<12}  {:>first_width$}  {:>second_width$}  {shift_text}\n",
+                rank_shift.guid, rank_shift.first_rank, rank_shift.second_rank
+            ));
+        }
+    }
+    for (algorithm_name, unique_guids) in [
+        (first_name, &comparison_report.only_in_first),
+        (second_name, &comparison_report.only_in_second),
+    ] {
+        let guid_list = if unique_guids.is_empty() {
+            "(none)".to_string()
+        } else {
+            unique_guids.join(", ")
+        };
+        report_text.push_str(&format!("Only in {algorithm_name}: {guid_list}\n"));
+    }
+    report_text
+}
+
+/// The `--eval` report for one algorithm: recall@k as a count and ratio, then MRR.
+fn format_eval_report(eval_report: &EvalReport, algorithm_name: &str) -> String {
+    let top_k = eval_report.top_k;
+    format!(
+        "Eval {algorithm_name} over {} cards (top {top_k})\n\
+         recall@{top_k}: {}/{} ({:.3})\n\
+         MRR: {:.3}\n",
+        eval_report.query_count,
+        eval_report.retrieved_count,
+        eval_report.query_count,
+        eval_report.recall_at_k,
+        eval_report.mean_reciprocal_rank
+    )
+}
+
+/// The `--validate` report: file and card totals, then each invalid-record reason with its count.
+fn format_validation_summary(
+    deck_file_count: usize,
+    card_count: usize,
+    invalid_records: &[InvalidRecord],
+) -> String {
+    let mut summary_text = format!(
+        "Deck files: {deck_file_count}\nValid cards: {card_count}\nInvalid records: {}\n",
+        invalid_records.len()
+    );
+    for (reason, record_count) in count_invalid_records_by_reason(invalid_records) {
+        summary_text.push_str(&format!("  {record_count:>6}  {reason}\n"));
+    }
+    summary_text
+}
+
+/// The `--count-only` report, one total per line.
+fn format_corpus_stats(stats: &CorpusStats, invalid_record_count: usize) -> String {
+    format!(
+        "Cards: {}\nInvalid records: {invalid_record_count}\nTopics: {}\n\
+         Deepest deck path: {}\nAverage question tokens: {:.2}\n",
+        stats.card_count, stats.topic_count, stats.max_deck_depth, stats.average_question_tokens
+    )
+}
+
+/// Every field of one card for `--show-guid`: the question as normalised for matching, the
+/// answer as escaped for display, then any `extra_columns` metadata sorted by key.
+fn format_card_details(card: &Card) -> String {
+    let mut details_text = format!(
+        "GUID: {}\nTopic: {}\nQ: {}\nA: {}\n",
+        card.guid,
+        deck_path_to_string(&card.deck_path),
+        card.question_text,
+        normalise_for_display(&card.answer_raw)
+    );
+    let sorted_metadata: BTreeMap<&String, &String> = card.metadata.iter().collect();
+    for (metadata_key, metadata_value) in sorted_metadata {
+        details_text.push_str(&format!("{metadata_key}: {metadata_value}\n"));
+    }
+    details_text
+}
+
+/// `--tokenise` output: the token vector, the distinct token set (sorted), then each raw token
+/// with what happened to it.
+fn format_token_trace(
+    input_text: &str,
+    stopword_set: &HashSet<String>,
+    parser_config: &ParserConfig,
+) -> String {
+    let token_vector = tokenise(input_text, stopword_set, parser_config);
+    let token_set: BTreeSet<String> = tokenise_to_set(input_text, stopword_set, parser_config)
+        .into_iter()
+        .collect();
+    let token_trace = tokenise_with_trace(input_text, stopword_set, parser_config);
+    let raw_token_width = token_trace
+        .iter()
+        .map(|(raw_token, _)| raw_token.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    let mut trace_text = format!("Tokens: {token_vector:?}\nToken set: {token_set:?}\nTrace:\n");
+    for (raw_token, token_fate) in token_trace {
+        let fate_label = token_fate.label();
+        let fate_text = match token_fate.into_emitted_token() {
+            Some(emitted_token) if emitted_token != raw_token => {
+                format!("{fate_label} as {emitted_token:?}")
+            }
+            _ => fate_label.to_string(),
+        };
+        trace_text.push_str(&format!("  {raw_token:<raw_token_width$}  {fate_text}\n"));
+    }
+    trace_text
+}
+
+/// Wrap each whole word of `preview` whose lowercase form (or stem, when `stem` is set) is in
+/// `matched_tokens` with `*` markers. Words inside an HTML entity (`&amp;`, `&#39;`) are left
+/// alone so escaping is never broken.
+fn highlight_terms(preview: &str, matched_tokens: &HashSet<String>, stem: bool) -> String {
+    let preview_characters: Vec<char> = preview.chars().collect();
+    let mut highlighted_text = String::with_capacity(preview.len());
+    let mut character_index = 0;
+    while character_index < preview_characters.len() {
+        if !preview_characters[character_index].is_alphanumeric() {
+            highlighted_text.push(preview_characters[character_index]);
+            character_index += 1;
+            continue;
+        }
+
+        let word_start = character_index;
+        while character_index < preview_characters.len()
+            && preview_characters[character_index].is_alphanumeric()
+        {
+            character_index += 1;
+        }
+        let word_text: String = preview_characters[word_start..character_index]
+            .iter()
+            .collect();
+
+        let entity_start = match word_start {
+            0 => false,
+            1 => preview_characters[0] == '&',
+            _ => {
+                preview_characters[word_start - 1] == '&'
+                    || (preview_characters[word_start - 1] == '#'
+                        && preview_characters[word_start - 2] == '&')
+            }
+        };
+        let inside_entity = entity_start && preview_characters.get(character_index) == Some(&';');
+
+        let lowercase_word = word_text.to_lowercase();
+        // Case-sensitive tokens (`lowercase` off) match the word as written.
+        let is_match = matched_tokens.contains(&lowercase_word)
+            || matched_tokens.contains(&word_text)
+            || (stem && matched_tokens.contains(&stem_token(&lowercase_word)));
+        if is_match && !inside_entity {
+            highlighted_text.push('*');
+            highlighted_text.push_str(&word_text);
+            highlighted_text.push('*');
+        } else {
+            highlighted_text.push_str(&word_text);
+        }
+    }
+    highlighted_text
+}
+
+/// How much of each escaped answer `format_hits_for_display` shows.
+enum AnswerDisplay {
+    /// The whole answer, or its first N characters via `short_preview` when N is non-zero.
+    Truncated(usize),
+    /// An `extract_snippet` excerpt around these query tokens.
+    Snippet(HashSet<String>),
+}
+
+/// How `format_hits_for_display` turns an answer's HTML into console text (`--answer-render`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AnswerRender {
+    /// `normalise_for_display`: tags stay visible with their angle brackets escaped.
+    Escaped,
+    /// `normalise_for_display_plain`: tags stripped, entities decoded, whitespace collapsed.
+    Plain,
+}
+
+/// Which form of the question a hit previews (`--preview`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PreviewSource {
+    /// `Card::question_display`: tags stripped and entities decoded, original case kept.
+    Raw,
+    /// `Card::question_text`: the form the scorers match against (lowercased unless
+    /// `lowercase` is off, plus any configured normalisation).
+    Normalised,
+}
+
+/// Replace each hit's question preview with the card's `question_text` under
+/// `PreviewSource::Normalised`. The indices store the display form, which `Raw` keeps as is.
+fn with_preview_source(
+    answer_hits: Vec<AnswerHit>,
+    preview_source: PreviewSource,
+    guid_index: &HashMap<String, Card>,
+) -> Vec<AnswerHit> {
+    if preview_source == PreviewSource::Raw {
+        return answer_hits;
+    }
+    answer_hits
+        .into_iter()
+        .map(|answer_hit| match guid_index.get(&answer_hit.guid) {
+            Some(card) => AnswerHit {
+                question_preview: Some(card.question_text.clone()),
+                ..answer_hit
+            },
+            None => answer_hit,
+        })
+        .collect()
+}
+
+/// The text layout of one hit: a header line, then the question and answer indented below it.
+const DEFAULT_RESULT_TEMPLATE: &str =
+    "{rank}. GUID={guid}  score={score}  topic={topic}\n   Q: {question}\n   A: {answer}";
+
+/// Characters in a `--snippet` excerpt.
+const SNIPPET_WINDOW_CHARS: usize = 200;
+
+/// Split `result_entries` into pages of `page_size` entries (one page when `page_size` is 0).
+fn paginate(result_entries: &[String], page_size: usize) -> Vec<&[String]> {
+    if page_size == 0 || result_entries.is_empty() {
+        return vec![result_entries];
+    }
+    result_entries.chunks(page_size).collect()
+}
+
+/// Write `pages` one at a time, waiting for Enter from `input_reader` between them. `q` (or end
+/// of input) stops early; returns whether every page was shown.
+fn write_paged(
+    result_writer: &mut dyn Write,
+    pages: &[&[String]],
+    input_reader: &mut dyn BufRead,
+) -> Result<bool> {
+    for (page_index, page_entries) in pages.iter().enumerate() {
+        writeln!(result_writer, "{}", page_entries.join("\n"))
+            .with_context(|| "Failed to write results")?;
+        if page_index + 1 == pages.len() {
+            break;
+        }
+        write!(
+            result_writer,
+            "-- more ({}/{}): Enter to continue, q to stop -- ",
+            page_index + 1,
+            pages.len()
+        )
+        .with_context(|| "Failed to write results")?;
+        result_writer
+            .flush()
+            .with_context(|| "Failed to write results")?;
+        let mut response_text = String::new();
+        let bytes_read = input_reader.read_line(&mut response_text).unwrap_or(0);
+        if bytes_read == 0 || response_text.trim().eq_ignore_ascii_case("q") {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Format result hits like Python: rank, GUID, score, topic, full Q and A rendered as
+/// `answer_render` asks, with the answer shortened as `answer_display_mode` asks, each laid out
+/// by `result_template` with scores to `precision` decimals. One entry per hit (`No results.` when there are none).
+fn format_hits_for_display(
+    answer_hits: &[AnswerHit],
+    guid_index_map: &HashMap<String, Card>,
+    answer_display_mode: &AnswerDisplay,
+    answer_render: AnswerRender,
+    result_template: &str,
+    precision: usize,
+) -> Vec<String> {
+    if answer_hits.is_empty() {
+        return vec!["No results.".to_string()];
+    }
+    let mut output_lines: Vec<String> = Vec::new();
+    for (rank_index, answer_hit) in answer_hits.iter().enumerate() {
+        if let Some(card) = guid_index_map.get(&answer_hit.guid) {
+            let mut answer_display = match answer_render {
+                AnswerRender::Escaped => normalise_for_display(&card.answer_raw),
+                AnswerRender::Plain => normalise_for_display_plain(&card.answer_raw),
+            };
+            match answer_display_mode {
+                AnswerDisplay::Truncated(answer_max_chars) if *answer_max_chars > 0 => {
+                    answer_display = short_preview(&answer_display, *answer_max_chars);
+                }
+                AnswerDisplay::Truncated(_) => {}
+                AnswerDisplay::Snippet(query_tokens) => {
+                    answer_display =
+                        extract_snippet(&answer_display, query_tokens, SNIPPET_WINDOW_CHARS);
+                }
+            }
+
+            output_lines.push(render_template(
+                result_template,
+                rank_index + 1,
+                answer_hit,
+                card,
+                &answer_display,
+                precision,
+            ));
+        }
+    }
+    output_lines
+}
+
+/// Expand `{rank}`, `{guid}`, `{score}` (`precision` decimals), `{topic}`, `{question}` (the hit's
+/// preview, else the card's question) and `{answer}` (already rendered for display) in
+/// `template_text`. Unknown placeholders and unmatched braces are left as written.
+fn render_template(
+    template_text: &str,
+    rank: usize,
+    answer_hit: &AnswerHit,
+    card: &Card,
+    answer_display: &str,
+    precision: usize,
+) -> String {
+    let mut rendered_text = String::with_capacity(template_text.len() + answer_display.len());
+    let mut remaining_text = template_text;
+    while let Some(open_index) = remaining_text.find('{') {
+        rendered_text.push_str(&remaining_text[..open_index]);
+        let placeholder_text = &remaining_text[open_index..];
+        let Some(close_index) = placeholder_text.find('}') else {
+            remaining_text = placeholder_text;
+            break;
+        };
+        let placeholder_name = &placeholder_text[1..close_index];
+        if placeholder_name.contains('{') {
+            // Only the innermost brace pair can be a placeholder.
+            rendered_text.push('{');
+            remaining_text = &placeholder_text[1..];
+            continue;
+        }
+        let placeholder_value = match placeholder_name {
+            "rank" => Some(rank.to_string()),
+            "guid" => Some(answer_hit.guid.clone()),
+            "score" => Some(format!("{:.precision$}", answer_hit.score)),
+            "topic" => Some(deck_path_to_string(&answer_hit.deck_path)),
+            "question" => Some(
+                answer_hit
+                    .question_preview
+                    .clone()
+                    .unwrap_or_else(|| card.question_text.clone()),
+            ),
+            "answer" => Some(answer_display.to_string()),
+            _ => None,
+        };
+        match placeholder_value {
+            Some(value_text) => rendered_text.push_str(&value_text),
+            None => rendered_text.push_str(&placeholder_text[..=close_index]),
+        }
+        remaining_text = &placeholder_text[close_index + 1..];
+    }
+    rendered_text.push_str(remaining_text);
+    rendered_text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rulebot_rust::data_model::{TOP_SCORE_DECIMALS, make_hit};
+
+    fn card(guid: &str, question_text: &str) -> Card {
+        Card::new(
+            guid,
+            question_text,
+            &format!("answer for {guid}"),
+            vec!["Deck".to_string()],
+        )
+    }
+
+    #[test]
+    fn test_paginate_chunks_results_and_q_stops_paging() {
+        let result_entries: Vec<String> = (1..=5).map(|rank| format!("{rank}. result")).collect();
+        let page_sizes: Vec<usize> = paginate(&result_entries, 2)
+            .iter()
+            .map(|page| page.len())
+            .collect();
+        assert_eq!(page_sizes, vec![2, 2, 1]);
+        assert_eq!(paginate(&result_entries, 0).len(), 1);
+        assert_eq!(paginate(&result_entries, 10).len(), 1);
+
+        let pages = paginate(&result_entries, 2);
+        let mut shown_output: Vec<u8> = Vec::new();
+        let shown_in_full =
+            write_paged(&mut shown_output, &pages, &mut "\n\n".as_bytes()).expect("writes");
+        assert!(shown_in_full);
+        assert!(String::from_utf8_lossy(&shown_output).contains("5. result"));
+
+        // `q` after the first page stops before the second.
+        let mut stopped_output: Vec<u8> = Vec::new();
+        let shown_in_full =
+            write_paged(&mut stopped_output, &pages, &mut "q\n".as_bytes()).expect("writes");
+        assert!(!shown_in_full);
+        let stopped_text = String::from_utf8_lossy(&stopped_output);
+        assert!(stopped_text.contains("2. result") && !stopped_text.contains("3. result"));
+    }
+
+    #[test]
+    fn test_format_hits_as_json() {
+        let cards = vec![card("g1", "what is <b>big</b> data")];
+        let guid_index_map = build_guid_index(&cards);
+        let answer_hits = vec![make_hit(
+            "g1".to_string(),
+            vec!["Deck".to_string()],
+            Some("what is big data".to_string()),
+            0.5,
+        )];
+
+        let json_text = format_hits_as_json(&answer_hits, &guid_index_map).expect("serialises");
+        let parsed: serde_json::Value = serde_json::from_str(&json_text).expect("valid JSON");
+        let first_hit = &parsed.as_array().expect("array")[0];
+        assert_eq!(first_hit["rank"], 1);
+        assert_eq!(first_hit["guid"], "g1");
+        assert_eq!(first_hit["score"], 0.5);
+        assert_eq!(first_hit["deck_path"][0], "Deck");
+        assert_eq!(first_hit["answer"], "answer for g1");
+    }
+
+    #[test]
+    fn test_preview_source_picks_display_or_matching_question() {
+        let cards = vec![Card {
+            question_text: "what is the cpu?".to_string(),
+            question_display: "What is the CPU?".to_string(),
+            ..card("g1", "unused")
+        }];
+        let guid_index_map = build_guid_index(&cards);
+        let answer_hits = vec![make_hit(
+            "g1".to_string(),
+            vec!["Deck".to_string()],
+            Some(cards[0].question_display.clone()),
+            1.0,
+        )];
+
+        let preview_lines = |preview_source: PreviewSource| {
+            format_hits_for_display(
+                &with_preview_source(answer_hits.clone(), preview_source, &guid_index_map),
+                &guid_index_map,
+                &AnswerDisplay::Truncated(0),
+                AnswerRender::Escaped,
+                "{question}",
+                TOP_SCORE_DECIMALS as usize,
+            )
+        };
+        assert_eq!(preview_lines(PreviewSource::Raw), vec!["What is the CPU?"]);
+        assert_eq!(
+            preview_lines(PreviewSource::Normalised),
+            vec!["what is the cpu?"]
+        );
+    }
+
+    #[test]
+    fn test_answer_max_chars_truncates_escaped_answer() {
+        let mut long_card = card("g1", "what is a café");
+        long_card.answer_raw = "Café — a <small> place".to_string();
+        let guid_index_map = build_guid_index(&[long_card]);
+        let answer_hits = vec![make_hit(
+            "g1".to_string(),
+            vec!["Deck".to_string()],
+            None,
+            1.0,
+        )];
+
+        let answer_line = |answer_max_chars: usize| {
+            format_hits_for_display(
+                &answer_hits,
+                &guid_index_map,
+                &AnswerDisplay::Truncated(answer_max_chars),
+                AnswerRender::Escaped,
+                DEFAULT_RESULT_TEMPLATE,
+                TOP_SCORE_DECIMALS as usize,
+            )
+            .join("\n")
+            .lines()
+            .last()
+            .expect("answer line")
+            .to_string()
+        };
+        assert_eq!(answer_line(0), "   A: Café — a &lt;small&gt; place");
+        // The cut lands right after "é" and then right after "—" without panicking.
+        assert_eq!(answer_line(5), "   A: Café…");
+        assert_eq!(answer_line(7), "   A: Café —…");
+        assert_eq!(answer_line(100), answer_line(0));
+    }
+
+    #[test]
+    fn test_precision_sets_displayed_and_logged_score_decimals() {
+        let cards = vec![card("g1", "what is a gate")];
+        let guid_index_map = build_guid_index(&cards);
+        let answer_hits = vec![make_hit(
+            "g1".to_string(),
+            vec!["Deck".to_string()],
+            Some("what is a gate".to_string()),
+            0.123_456_7,
+        )];
+
+        let result_entries = format_hits_for_display(
+            &answer_hits,
+            &guid_index_map,
+            &AnswerDisplay::Truncated(0),
+            AnswerRender::Escaped,
+            DEFAULT_RESULT_TEMPLATE,
+            3,
+        );
+        assert_eq!(
+            result_entries,
+            vec!["1. GUID=g1  score=0.123  topic=Deck\n   Q: what is a gate\n   A: answer for g1"]
+        );
+        assert_eq!(
+            top_entries_from_hits_with_decimals(&answer_hits, 3)[0].score,
+            0.123
+        );
+    }
+
+    #[test]
+    fn test_render_template_single_line_and_unknown_placeholder() {
+        let mut shown_card = card("g1", "what is a gate");
+        shown_card.answer_raw = "A <b>logic</b> element.".to_string();
+        let answer_hit = make_hit(
+            "g1".to_string(),
+            vec!["Deck".to_string(), "Unit 01".to_string()],
+            Some("What is a gate".to_string()),
+            0.5,
+        );
+        let answer_display = normalise_for_display(&shown_card.answer_raw);
+
+        assert_eq!(
+            render_template(
+                "#{rank} [{topic}] {question} => {answer} ({score})",
+                2,
+                &answer_hit,
+                &shown_card,
+                &answer_display,
+                TOP_SCORE_DECIMALS as usize,
+            ),
+            "#2 [Deck::Unit 01] What is a gate => A &lt;b&gt;logic&lt;/b&gt; element. (0.500000)"
+        );
+        assert_eq!(
+            render_template(
+                "{guid} {unknown} {{rank}} {",
+                1,
+                &answer_hit,
+                &shown_card,
+                "A",
+                TOP_SCORE_DECIMALS as usize,
+            ),
+            "g1 {unknown} {1} {"
+        );
+    }
+
+    #[test]
+    fn test_group_best_per_topic_keeps_one_hit_per_deck_path() {
+        let hit = |guid: &str, deck_name: &str, score: f32| {
+            let deck_path = vec!["Course".to_string(), deck_name.to_string()];
+            make_hit(guid.to_string(), deck_path, None, score)
+        };
+        let ranked_hits = vec![
+            hit("a1", "Unit 01", 0.9),
+            hit("a2", "Unit 01", 0.8),
+            hit("b1", "Unit 02", 0.7),
+            hit("a3", "Unit 01", 0.6),
+            hit("c1", "Unit 03", 0.5),
+            hit("b2", "Unit 02", 0.4),
+        ];
+
+        let guids = |answer_hits: Vec<AnswerHit>| {
+            answer_hits
+                .into_iter()
+                .map(|answer_hit| answer_hit.guid)
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            guids(group_best_per_topic(ranked_hits.clone(), 5, 0)),
+            vec!["a1", "b1", "c1"]
+        );
+        assert_eq!(
+            guids(group_best_per_topic(ranked_hits, 2, 0)),
+            vec!["a1", "b1"]
+        );
+    }
+
+    #[test]
+    fn test_score_with_timeout_gives_up_on_slow_scoring() {
+        let slow_scoring = || {
+            thread::sleep(Duration::from_millis(500));
+            vec![1]
+        };
+        assert_eq!(
+            score_with_timeout(slow_scoring, Some(Duration::from_millis(20))),
+            None
+        );
+        assert_eq!(
+            score_with_timeout(|| vec![2], Some(Duration::from_secs(5))),
+            Some(vec![2])
+        );
+        assert_eq!(score_with_timeout(slow_scoring, None), Some(vec![1]));
+
+        // Timed-out workers hold their slots until they finish, so once every slot is taken a
+        // query waits for one within its own budget.
+        for _ in 0..MAX_SCORING_WORKERS {
+            assert_eq!(
+                score_with_timeout(slow_scoring, Some(Duration::from_millis(20))),
+                None
+            );
+        }
+        assert_eq!(
+            score_with_timeout(|| vec![3], Some(Duration::from_millis(20))),
+            None
+        );
+        assert_eq!(
+            score_with_timeout(|| vec![3], Some(Duration::from_secs(5))),
+            Some(vec![3])
+        );
+    }
+
+    #[test]
+    fn test_format_validation_summary() {
+        let invalid_record = |reason: &str| InvalidRecord {
+            file_path: "deck.txt".to_string(),
+            line_number: 3,
+            reason: reason.to_string(),
+            raw_line: String::new(),
+        };
+        let invalid_records = vec![
+            invalid_record("Empty GUID"),
+            invalid_record("Duplicate GUID"),
+            invalid_record("Empty GUID"),
+        ];
+        assert_eq!(
+            format_validation_summary(2, 10, &invalid_records),
+            "Deck files: 2\nValid cards: 10\nInvalid records: 3\n       1  Duplicate GUID\n       2  Empty GUID\n"
+        );
+    }
+
+    #[test]
+    fn test_format_card_details() {
+        let mut shown_card = card("g1", "what is big data");
+        shown_card.answer_raw = "a &amp; b<br>c".to_string();
+        shown_card.deck_path = vec!["Course".to_string(), "Unit 05".to_string()];
+
+        assert_eq!(
+            format_card_details(&shown_card),
+            format!(
+                "GUID: g1\nTopic: Course::Unit 05\nQ: what is big data\nA: {}\n",
+                normalise_for_display("a &amp; b<br>c")
+            )
+        );
+    }
+
+    #[test]
+    fn test_read_queries_file_skips_blanks_and_comments() {
+        let file_path = std::env::temp_dir().join("rulebot_queries_file_test.txt");
+        fs::write(
+            &file_path,
+            "# header\nwhat is big data?\n\n  \nwhat is a gate?\n",
+        )
+        .expect("write fixture");
+
+        let batch_queries =
+            read_queries_file(file_path.to_str().expect("utf-8 path")).expect("reads");
+        assert_eq!(batch_queries, vec!["what is big data?", "what is a gate?"]);
+        let _ = fs::remove_file(&file_path);
+    }
+
+    #[test]
+    fn test_format_topic_listing_indents_children() {
+        let mut child_card = card("g1", "child question");
+        child_card.deck_path = vec!["Course".to_string(), "Unit 01".to_string()];
+        let sibling_card = Card {
+            guid: "g2".to_string(),
+            ..child_card.clone()
+        };
+        let mut other_card = card("g3", "other question");
+        other_card.deck_path = vec!["Other".to_string()];
+
+        let listing_text = format_topic_listing(
+            &[child_card.clone(), sibling_card, other_card.clone()],
+            "::",
+            0,
+        );
+        assert_eq!(
+            listing_text,
+            "Course (0)\n  Course::Unit 01 (2)\nOther (1)\n"
+        );
+        assert_eq!(
+            format_topic_listing(&[child_card, other_card], "::", 1),
+            "Course (1)\nOther (1)\n"
+        );
+    }
+
+    #[test]
+    fn test_highlight_terms_whole_words_only() {
+        let matched_tokens: HashSet<String> = ["search", "amp"]
+            .iter()
+            .map(|token| token.to_string())
+            .collect();
+
+        assert_eq!(
+            highlight_terms(
+                "Binary Search &amp; research: search!",
+                &matched_tokens,
+                false
+            ),
+            "Binary *Search* &amp; research: *search*!"
+        );
+        assert_eq!(
+            highlight_terms("searching amp", &matched_tokens, true),
+            "*searching* *amp*"
+        );
+    }
+
+    #[test]
+    fn test_query_history_add_recall_and_reload() {
+        let history_file_path =
+            std::env::temp_dir().join(format!("rulebot_cli_history_{}.txt", std::process::id()));
+        let _ = fs::remove_file(&history_file_path);
+        let history_file_text = history_file_path.display().to_string();
+
+        let mut query_history = QueryHistory::load(Some(&history_file_text)).expect("no file yet");
+        assert_eq!(query_history.listing(), "History is empty.\n");
+        for entered_text in ["What is big data?", "   ", "exit", "  What is a CPU?  "] {
+            query_history
+                .add(entered_text)
+                .expect("history file writable");
+        }
+        assert_eq!(query_history.recall(1), Some("What is big data?"));
+        assert_eq!(query_history.recall(2), Some("What is a CPU?"));
+        assert_eq!(query_history.recall(0), None);
+        assert_eq!(query_history.recall(3), None);
+        assert_eq!(
+            query_history.listing(),
+            "  1  What is big data?\n  2  What is a CPU?\n"
+        );
+
+        // A new session starts from what the previous one appended.
+        let reloaded_history = QueryHistory::load(Some(&history_file_text)).expect("readable");
+        assert_eq!(reloaded_history.recall(2), Some("What is a CPU?"));
+        let _ = fs::remove_file(&history_file_path);
     }
-    output_lines.join("\n")
 }
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+
+/// Where one GUID ranks in both compared rankings (1-based).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RankShift {
+    pub guid: String,
+    pub first_rank: usize,
+    pub second_rank: usize,
+}
+
+impl RankShift {
+    /// Positions the GUID moved from the first ranking to the second; positive means it fell.
+    pub fn delta(&self) -> i64 {
+        self.second_rank as i64 - self.first_rank as i64
+    }
+}
+
+/// How two top-k rankings of the same query agree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComparisonReport {
+    pub top_k: usize,
+    /// GUIDs in both top-k lists.
+    pub overlap_count: usize,
+    /// `overlap_count / top_k` (0 when `top_k` is 0).
+    pub overlap_at_k: f32,
+    /// GUIDs only the first ranking returned, in its order.
+    pub only_in_first: Vec<String>,
+    /// GUIDs only the second ranking returned, in its order.
+    pub only_in_second: Vec<String>,
+    /// Shared GUIDs in first-ranking order, with their rank in each.
+    pub rank_shifts: Vec<RankShift>,
+}
+
+/// Compare the top `top_k` GUIDs of two rankings: overlap@k, the GUIDs unique to each, and
+/// the rank of every shared GUID in both.
+pub fn compare_rankings(
+    first_ranking: &[String],
+    second_ranking: &[String],
+    top_k: usize,
+) -> ComparisonReport {
+    let first_top = &first_ranking[..first_ranking.len().min(top_k)];
+    let second_top = &second_ranking[..second_ranking.len().min(top_k)];
+    let second_ranks: HashMap<&String, usize> = second_top
+        .iter()
+        .enumerate()
+        .map(|(rank_index, guid)| (guid, rank_index + 1))
+        .collect();
+
+    let mut rank_shifts: Vec<RankShift> = Vec::new();
+    let mut only_in_first: Vec<String> = Vec::new();
+    for (rank_index, guid) in first_top.iter().enumerate() {
+        match second_ranks.get(guid) {
+            Some(second_rank) => rank_shifts.push(RankShift {
+                guid: guid.clone(),
+                first_rank: rank_index + 1,
+                second_rank: *second_rank,
+            }),
+            None => only_in_first.push(guid.clone()),
+        }
+    }
+    let only_in_second: Vec<String> = second_top
+        .iter()
+        .filter(|guid| !first_top.contains(guid))
+        .cloned()
+        .collect();
+
+    let overlap_count = rank_shifts.len();
+    ComparisonReport {
+        top_k,
+        overlap_count,
+        overlap_at_k: if top_k == 0 {
+            0.0
+        } else {
+            overlap_count as f32 / top_k as f32
+        },
+        only_in_first,
+        only_in_second,
+        rank_shifts,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guids(guid_texts: &[&str]) -> Vec<String> {
+        guid_texts.iter().map(|guid| guid.to_string()).collect()
+    }
+
+    #[test]
+    fn test_compare_rankings_overlap_unique_and_deltas() {
+        let keyword_ranking = guids(&["a", "b", "c", "d", "x"]);
+        let tfidf_ranking = guids(&["c", "a", "e", "b", "y"]);
+
+        let report = compare_rankings(&keyword_ranking, &tfidf_ranking, 4);
+        assert_eq!(report.overlap_count, 3);
+        assert!((report.overlap_at_k - 0.75).abs() < 1e-6);
+        assert_eq!(report.only_in_first, guids(&["d"]));
+        assert_eq!(report.only_in_second, guids(&["e"]));
+        let deltas: Vec<(&str, i64)> = report
+            .rank_shifts
+            .iter()
+            .map(|rank_shift| (rank_shift.guid.as_str(), rank_shift.delta()))
+            .collect();
+        assert_eq!(deltas, vec![("a", 1), ("b", 2), ("c", -2)]);
+
+        // Rankings shorter than k still divide by k.
+        let short_report = compare_rankings(&guids(&["a"]), &guids(&["a"]), 4);
+        assert!((short_report.overlap_at_k - 0.25).abs() < 1e-6);
+        assert_eq!(
+            compare_rankings(&keyword_ranking, &tfidf_ranking, 0).overlap_at_k,
+            0.0
+        );
+    }
+}
@@ -1,8 +1,13 @@
-use std::{collections::HashSet, fs, path::Path};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
 
-use anyhow::{anyhow, Context, Result};
 use serde::Deserialize;
 
+use crate::error::ChatbotError;
+
 #[allow(dead_code)]
 #[derive(Debug, Deserialize, Clone)]
 pub struct ParserConfig {
@@ -10,21 +15,286 @@ pub struct ParserConfig {
     pub split_on_non_alnum: bool,
     #[serde(default = "default_keep_digits")]
     pub keep_digits: bool,
-    #[serde(default = "default_min_token_length")]
-    pub min_token_length: usize,
+    #[serde(default)]
+    pub min_token_length: MinTokenLength,
     #[serde(default = "default_remove_stopwords")]
     pub remove_stopwords: bool,
-    #[serde(default)]
-    pub stopwords_path: Option<String>,
+    /// Lowercase tokens, matching text and stopwords. Off keeps the original case throughout.
+    #[serde(default = "default_lowercase")]
+    pub lowercase: bool,
+    /// One path or a list of paths; the files are merged.
+    #[serde(default, deserialize_with = "deserialize_one_or_many_paths")]
+    pub stopwords_path: Vec<String>,
 
     #[serde(default = "default_topic_separator")]
     pub topic_separator: String,
     #[serde(default = "default_include_subtree")]
     pub include_subtree: bool,
-    
+
     #[serde(default = "default_idf_smoothing")]
     pub idf_smoothing: bool,
+    #[serde(default)]
+    pub sublinear_tf: bool,
+    #[serde(default)]
+    pub phrase_boost: f32,
+    #[serde(default)]
+    pub stopword_weight: f32,
+
+    #[serde(default)]
+    pub deck_format: DeckFormat,
+    #[serde(default = "default_comment_prefix")]
+    pub comment_prefix: Option<String>,
+    #[serde(default = "default_skip_comment_lines")]
+    pub skip_comment_lines: bool,
+    #[serde(default)]
+    pub columns: ColumnLayout,
+    /// Further zero-based columns captured into `Card::metadata` (e.g. Anki's tags column).
+    #[serde(default)]
+    pub extra_columns: Vec<usize>,
+    /// Metadata key for each `extra_columns` entry, in the same order; columns without a name
+    /// are keyed `column_<index>`.
+    #[serde(default)]
+    pub extra_column_names: Vec<String>,
+    #[serde(default)]
+    pub search_field: SearchField,
+    #[serde(default = "default_field_weight")]
+    pub question_weight: f32,
+    #[serde(default = "default_field_weight")]
+    pub answer_weight: f32,
+    #[serde(default)]
+    pub stem: bool,
+    #[serde(default)]
+    pub preserve_patterns: Vec<String>,
+    /// Characters kept inside tokens instead of splitting them (e.g. `'` and `-` keep "don't"
+    /// and "well-known" whole).
+    #[serde(default)]
+    pub join_chars: Vec<char>,
+    #[serde(default = "default_fuzzy_ngram")]
+    pub fuzzy_ngram: usize,
+    #[serde(default)]
+    pub strip_math: bool,
+    #[serde(default)]
+    pub unicode_normalise: bool,
+    #[serde(default = "default_diversity_lambda")]
+    pub diversity_lambda: f32,
+    #[serde(default = "default_hybrid_alpha")]
+    pub hybrid_alpha: f32,
+    #[serde(default)]
+    pub tie_break: TieBreak,
+    #[serde(default)]
+    pub tie_break_secondary: TieBreakSecondary,
+    #[serde(default)]
+    pub keyword_count_mode: KeywordCountMode,
+    #[serde(default = "default_soft_cosine_terms")]
+    pub soft_cosine_terms: usize,
+    /// `term: synonym, synonym` lines used by `--expand`.
+    #[serde(default)]
+    pub synonyms_path: Option<String>,
+    #[serde(default = "default_synonym_weight")]
+    pub synonym_weight: f32,
+    /// TF–IDF drops terms found in fewer documents than this.
+    #[serde(default = "default_min_df")]
+    pub min_df: usize,
+    /// TF–IDF drops terms found in more than this fraction of documents.
+    #[serde(default = "default_max_df_ratio")]
+    pub max_df_ratio: f32,
+    /// TF–IDF queries with fewer tokens than this are scored by IDF sum instead of cosine
+    /// (0 = never).
+    #[serde(default)]
+    pub query_boost_short: usize,
+    /// Added to keyword and TF–IDF scores in proportion to how recent a card is (0 = off).
+    #[serde(default)]
+    pub recency_boost: f32,
+    /// `Card::metadata` key holding the modification time, as Unix seconds or RFC 3339.
+    #[serde(default = "default_recency_field")]
+    pub recency_field: String,
+
+    // Reproducibility umbrella; normally switched on by `--deterministic`.
+    #[serde(default)]
+    pub deterministic: bool,
+}
+
+/// Which card text the index builders tokenise.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchField {
+    #[default]
+    Question,
+    Answer,
+    Both,
+}
+
+/// Shortest token kept: one length for all text, or `{"question": 2, "answer": 3}` per card
+/// field. Queries use the question length, since they are matched against questions.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum MinTokenLength {
+    Uniform(usize),
+    PerField {
+        #[serde(default = "default_min_token_length")]
+        question: usize,
+        #[serde(default = "default_min_token_length")]
+        answer: usize,
+    },
+}
+
+impl Default for MinTokenLength {
+    fn default() -> Self {
+        MinTokenLength::Uniform(default_min_token_length())
+    }
 }
+
+impl MinTokenLength {
+    /// Minimum for question text and queries.
+    pub fn question(self) -> usize {
+        match self {
+            MinTokenLength::Uniform(min_length) => min_length,
+            MinTokenLength::PerField { question, .. } => question,
+        }
+    }
+
+    /// Minimum for answer text.
+    pub fn answer(self) -> usize {
+        match self {
+            MinTokenLength::Uniform(min_length) => min_length,
+            MinTokenLength::PerField { answer, .. } => answer,
+        }
+    }
+}
+
+/// How keyword, TF–IDF, Jaccard and Dice order candidates whose score and overlap are
+/// equal, before the final GUID comparison.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TieBreak {
+    /// Fewer question tokens first.
+    #[default]
+    Shortest,
+    /// More question tokens first, favouring detailed questions.
+    Longest,
+    /// Ignore question length; fall straight through to GUID order.
+    GuidOnly,
+}
+
+/// Extra comparator keyword, TF–IDF, Jaccard and Dice apply to still-tied candidates
+/// before the GUID.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TieBreakSecondary {
+    /// None; GUID order decides.
+    #[default]
+    Guid,
+    /// Earlier deck path first (segment by segment, alphabetically).
+    DeckPath,
+}
+
+/// How keyword scoring counts a query term that also matches in a candidate.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeywordCountMode {
+    /// Once, however often it appears in the query or the question.
+    #[default]
+    Set,
+    /// `min(query count, question count)` times, so repeating a query term can matter.
+    Multiset,
+}
+
+/// On-disk layout of deck files.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeckFormat {
+    /// Anki's raw tab-separated export.
+    #[default]
+    AnkiTsv,
+    /// Comma-separated with quoted fields.
+    Csv,
+}
+
+/// Zero-based column of each logical field in a deck record.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct ColumnLayout {
+    pub guid: usize,
+    pub deck_path: usize,
+    pub question: usize,
+    pub answer: usize,
+}
+
+impl Default for ColumnLayout {
+    /// Anki's export order: guid, notetype, deck, question, answer.
+    fn default() -> Self {
+        Self {
+            guid: 0,
+            deck_path: 2,
+            question: 3,
+            answer: 4,
+        }
+    }
+}
+
+impl ColumnLayout {
+    /// The field with the highest column index, which sets the minimum column count.
+    pub fn last_field(&self) -> (&'static str, usize) {
+        [
+            ("guid", self.guid),
+            ("deck_path", self.deck_path),
+            ("question", self.question),
+            ("answer", self.answer),
+        ]
+        .into_iter()
+        .max_by_key(|(_, column_index)| *column_index)
+        .expect("four fields")
+    }
+}
+
+impl Default for ParserConfig {
+    fn default() -> Self {
+        Self {
+            split_on_non_alnum: default_split_on_non_alnum(),
+            keep_digits: default_keep_digits(),
+            min_token_length: MinTokenLength::default(),
+            remove_stopwords: default_remove_stopwords(),
+            lowercase: default_lowercase(),
+            stopwords_path: Vec::new(),
+            topic_separator: default_topic_separator(),
+            include_subtree: default_include_subtree(),
+            idf_smoothing: default_idf_smoothing(),
+            sublinear_tf: false,
+            phrase_boost: 0.0,
+            stopword_weight: 0.0,
+            deck_format: DeckFormat::default(),
+            comment_prefix: default_comment_prefix(),
+            skip_comment_lines: default_skip_comment_lines(),
+            columns: ColumnLayout::default(),
+            extra_columns: Vec::new(),
+            extra_column_names: Vec::new(),
+            search_field: SearchField::default(),
+            question_weight: default_field_weight(),
+            answer_weight: default_field_weight(),
+            stem: false,
+            preserve_patterns: Vec::new(),
+            join_chars: Vec::new(),
+            fuzzy_ngram: default_fuzzy_ngram(),
+            strip_math: false,
+            unicode_normalise: false,
+            diversity_lambda: default_diversity_lambda(),
+            hybrid_alpha: default_hybrid_alpha(),
+            tie_break: TieBreak::default(),
+            tie_break_secondary: TieBreakSecondary::default(),
+            keyword_count_mode: KeywordCountMode::default(),
+            soft_cosine_terms: default_soft_cosine_terms(),
+            synonyms_path: None,
+            synonym_weight: default_synonym_weight(),
+            min_df: default_min_df(),
+            max_df_ratio: default_max_df_ratio(),
+            query_boost_short: 0,
+            recency_boost: 0.0,
+            recency_field: default_recency_field(),
+            deterministic: false,
+        }
+    }
+}
+
 fn default_split_on_non_alnum() -> bool {
     true
 }
@@ -34,6 +304,14 @@ fn default_keep_digits() -> bool {
 fn default_min_token_length() -> usize {
     2
 }
+fn default_recency_field() -> String {
+    "modified".to_string()
+}
+
+fn default_lowercase() -> bool {
+    true
+}
+
 fn default_remove_stopwords() -> bool {
     true
 }
@@ -46,10 +324,51 @@ fn default_include_subtree() -> bool {
 fn default_idf_smoothing() -> bool {
     true
 }
+fn default_comment_prefix() -> Option<String> {
+    Some("#".to_string())
+}
+fn default_skip_comment_lines() -> bool {
+    true
+}
+fn default_field_weight() -> f32 {
+    1.0
+}
+fn default_diversity_lambda() -> f32 {
+    0.5
+}
+fn default_hybrid_alpha() -> f32 {
+    0.5
+}
+fn default_fuzzy_ngram() -> usize {
+    3
+}
+fn default_soft_cosine_terms() -> usize {
+    1000
+}
+fn default_synonym_weight() -> f32 {
+    0.5
+}
+fn default_min_df() -> usize {
+    1
+}
+fn default_max_df_ratio() -> f32 {
+    1.0
+}
+
+/// `file_text` without a leading UTF-8 byte order mark, which Windows editors often write and
+/// which would otherwise corrupt the first JSON key or list entry.
+fn strip_byte_order_mark(file_text: &str) -> &str {
+    file_text.strip_prefix('\u{FEFF}').unwrap_or(file_text)
+}
 
-pub fn load_parser_config<P: AsRef<Path>>(path: P) -> Result<ParserConfig> {
-    let raw_json = fs::read_to_string(&path)
-        .with_context(|| format!("Failed to read Parser.json at {}", path.as_ref().display()))?;
+pub fn load_parser_config<P: AsRef<Path>>(path: P) -> Result<ParserConfig, ChatbotError> {
+    let file_text = fs::read_to_string(&path).map_err(|error| {
+        ChatbotError::ConfigLoad(format!(
+            "Failed to read parser config at {}: {error}",
+            path.as_ref().display()
+        ))
+    })?;
+    let raw_json = strip_byte_order_mark(&file_text);
 
     #[derive(Deserialize)]
     struct MaybeNested {
@@ -57,46 +376,309 @@ pub fn load_parser_config<P: AsRef<Path>>(path: P) -> Result<ParserConfig> {
         tokenisation: Option<ParserConfig>,
     }
 
-    let parsed_json: serde_json::Value = serde_json::from_str(&raw_json)
-        .with_context(|| "Parser.json is not valid JSON")?;
+    let parsed_json: serde_json::Value = serde_json::from_str(raw_json).map_err(|error| {
+        ChatbotError::ConfigLoad(format!(
+            "Parser config at {} is not valid JSON: {error}",
+            path.as_ref().display()
+        ))
+    })?;
 
-    // Try nested
-    if let Ok(nested) = serde_json::from_value::<MaybeNested>(parsed_json.clone()) {
-        if let Some(configuration) = nested.tokenisation {
-            if configuration.remove_stopwords
-                && configuration.stopwords_path.as_deref().unwrap_or("").is_empty()
-            {
-                return Err(anyhow!(
-                    "Stopwords file path is required when remove_stopwords=true"
-                ));
-            }
-            return Ok(configuration);
+    // Try nested. The shipped Parser.json keeps `idf_smoothing` under `algorithms.tfidf`; it
+    // applies unless `tokenisation` sets the key itself.
+    if let Ok(nested) = serde_json::from_value::<MaybeNested>(parsed_json.clone())
+        && let Some(mut configuration) = nested.tokenisation
+    {
+        let tokenisation_sets_smoothing =
+            parsed_json["tokenisation"].get("idf_smoothing").is_some();
+        if let Some(idf_smoothing) = parsed_json["algorithms"]["tfidf"]["idf_smoothing"].as_bool()
+            && !tokenisation_sets_smoothing
+        {
+            configuration.idf_smoothing = idf_smoothing;
         }
+        validate_parser_config(&configuration)?;
+        return Ok(configuration);
     }
 
     // Try flat directly into ParserConfig
-    let configuration: ParserConfig = serde_json::from_value(parsed_json)
-        .with_context(|| "Parser.json does not match expected schema")?;
+    let configuration: ParserConfig = serde_json::from_value(parsed_json).map_err(|error| {
+        ChatbotError::ConfigLoad(format!(
+            "Parser.json does not match expected schema: {error}"
+        ))
+    })?;
+    validate_parser_config(&configuration)?;
+    Ok(configuration)
+}
+
+/// Reject a missing stopwords path when stopword removal is on, and `preserve_patterns`
+/// entries that are not valid regexes.
+fn validate_parser_config(configuration: &ParserConfig) -> Result<(), ChatbotError> {
     if configuration.remove_stopwords
-        && configuration.stopwords_path.as_deref().unwrap_or("").is_empty()
+        && configuration
+            .stopwords_path
+            .iter()
+            .all(|path_text| path_text.is_empty())
     {
-        return Err(anyhow!(
-            "Stopwords file path is required when remove_stopwords=true"
-        ));
+        return Err(missing_stopwords_path_error());
     }
-    Ok(configuration)
+    for pattern_text in &configuration.preserve_patterns {
+        regex::Regex::new(pattern_text).map_err(|error| {
+            ChatbotError::ConfigLoad(format!(
+                "Invalid preserve_patterns entry: {pattern_text}: {error}"
+            ))
+        })?;
+    }
+    Ok(())
+}
+
+fn missing_stopwords_path_error() -> ChatbotError {
+    ChatbotError::ConfigLoad(
+        "Stopwords file path is required when remove_stopwords=true".to_string(),
+    )
+}
+
+/// Load the stopword set named by the config, or an empty set when stopword removal is off.
+pub fn load_configured_stopwords(
+    parser_config: &ParserConfig,
+) -> Result<HashSet<String>, ChatbotError> {
+    if !parser_config.remove_stopwords {
+        return Ok(HashSet::new());
+    }
+    if parser_config
+        .stopwords_path
+        .iter()
+        .all(|path_text| path_text.is_empty())
+    {
+        return Err(missing_stopwords_path_error());
+    }
+    load_stopwords(&parser_config.stopwords_path, parser_config.lowercase)
 }
 
-pub fn load_stopwords<P: AsRef<Path>>(path: P) -> Result<HashSet<String>> {
-    let content = fs::read_to_string(&path)
-        .with_context(|| format!("Failed to read stopwords at {}", path.as_ref().display()))?;
+/// Read every stopword file and merge them into one set, lowercased when `lowercase` is set and
+/// verbatim otherwise. Blank lines and `#` comments are skipped in each file; empty paths are
+/// ignored.
+pub fn load_stopwords(
+    stopwords_paths: &[String],
+    lowercase: bool,
+) -> Result<HashSet<String>, ChatbotError> {
     let mut stopwords_set = HashSet::new();
-    for line in content.lines() {
+    for stopwords_path in stopwords_paths
+        .iter()
+        .filter(|path_text| !path_text.is_empty())
+    {
+        let content = fs::read_to_string(stopwords_path).map_err(|error| {
+            ChatbotError::ConfigLoad(format!(
+                "Failed to read stopwords at {stopwords_path}: {error}"
+            ))
+        })?;
+        for line in strip_byte_order_mark(&content).lines() {
+            let trimmed_line = line.trim();
+            if trimmed_line.is_empty() || trimmed_line.starts_with('#') {
+                continue;
+            }
+            stopwords_set.insert(if lowercase {
+                trimmed_line.to_lowercase()
+            } else {
+                trimmed_line.to_string()
+            });
+        }
+    }
+    Ok(stopwords_set)
+}
+
+/// Load the synonym map named by `synonyms_path`: one `term: synonym, synonym` line per term,
+/// lowercased unless `lowercase` is off. Blank lines and `#` comments are skipped; repeated
+/// terms merge their synonyms.
+/// Empty when no path is configured.
+pub fn load_synonyms(
+    parser_config: &ParserConfig,
+) -> Result<HashMap<String, Vec<String>>, ChatbotError> {
+    let mut synonym_map: HashMap<String, Vec<String>> = HashMap::new();
+    let Some(synonyms_path) = parser_config.synonyms_path.as_deref() else {
+        return Ok(synonym_map);
+    };
+    let content = fs::read_to_string(synonyms_path).map_err(|error| {
+        ChatbotError::ConfigLoad(format!(
+            "Failed to read synonyms at {synonyms_path}: {error}"
+        ))
+    })?;
+    for (line_index, line) in strip_byte_order_mark(&content).lines().enumerate() {
         let trimmed_line = line.trim();
         if trimmed_line.is_empty() || trimmed_line.starts_with('#') {
             continue;
         }
-        stopwords_set.insert(trimmed_line.to_lowercase());
+        let Some((term_text, synonym_list)) = trimmed_line.split_once(':') else {
+            return Err(ChatbotError::ConfigLoad(format!(
+                "{synonyms_path}:{}: expected `term: synonym, synonym`",
+                line_index + 1
+            )));
+        };
+        let apply_casing = |text: &str| {
+            if parser_config.lowercase {
+                text.to_lowercase()
+            } else {
+                text.to_string()
+            }
+        };
+        let term_synonyms = synonym_map
+            .entry(apply_casing(term_text.trim()))
+            .or_default();
+        for synonym_text in synonym_list.split(',').map(str::trim) {
+            let synonym_text = apply_casing(synonym_text);
+            if !synonym_text.is_empty() && !term_synonyms.contains(&synonym_text) {
+                term_synonyms.push(synonym_text);
+            }
+        }
+    }
+    Ok(synonym_map)
+}
+
+/// Accept `"path"`, `["path", ...]`, or `null` for a list of paths.
+fn deserialize_one_or_many_paths<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match Option::<OneOrMany>::deserialize(deserializer)? {
+        Some(OneOrMany::One(path_text)) => vec![path_text],
+        Some(OneOrMany::Many(path_list)) => path_list,
+        None => Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nested_tfidf_idf_smoothing_is_read() {
+        let config_path = std::env::temp_dir().join(format!(
+            "rulebot_config_idf_smoothing_{}.json",
+            std::process::id()
+        ));
+        fs::write(
+            &config_path,
+            r#"{"tokenisation": {"remove_stopwords": false},
+                "algorithms": {"tfidf": {"idf_smoothing": false}}}"#,
+        )
+        .expect("write config");
+        assert!(
+            !load_parser_config(&config_path)
+                .expect("loads")
+                .idf_smoothing
+        );
+
+        fs::write(
+            &config_path,
+            r#"{"tokenisation": {"remove_stopwords": false, "idf_smoothing": true},
+                "algorithms": {"tfidf": {"idf_smoothing": false}}}"#,
+        )
+        .expect("write config");
+        assert!(
+            load_parser_config(&config_path)
+                .expect("loads")
+                .idf_smoothing
+        );
+        let _ = fs::remove_file(&config_path);
+    }
+
+    #[test]
+    fn test_byte_order_mark_is_stripped_from_config_and_stopwords() {
+        let fixture_directory = std::env::temp_dir().join(format!(
+            "rulebot_config_byte_order_mark_{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&fixture_directory).expect("create fixture directory");
+        let stopwords_path = fixture_directory.join("stopwords.txt");
+        fs::write(&stopwords_path, "\u{FEFF}the\nof\n").expect("write stopwords");
+        let config_path = fixture_directory.join("Parser.json");
+        fs::write(
+            &config_path,
+            format!(
+                "\u{FEFF}{}",
+                serde_json::json!({"tokenisation": {
+                    "remove_stopwords": true,
+                    "stopwords_path": stopwords_path.display().to_string(),
+                }})
+            ),
+        )
+        .expect("write config");
+
+        let parser_config = load_parser_config(&config_path).expect("loads despite the mark");
+        let stopword_set = load_configured_stopwords(&parser_config).expect("stopwords load");
+        assert_eq!(
+            stopword_set,
+            HashSet::from(["the".to_string(), "of".to_string()])
+        );
+        fs::remove_dir_all(&fixture_directory).ok();
+    }
+
+    #[test]
+    fn test_load_synonyms_parses_term_lines() {
+        let synonyms_path = std::env::temp_dir().join(format!(
+            "rulebot_config_synonyms_{}.txt",
+            std::process::id()
+        ));
+        fs::write(
+            &synonyms_path,
+            "# programming\nfunc: function, Procedure\n\nRAM: memory\nfunc: method, function\n",
+        )
+        .expect("write synonyms");
+        let parser_config = ParserConfig {
+            synonyms_path: Some(synonyms_path.display().to_string()),
+            ..Default::default()
+        };
+        let synonym_map = load_synonyms(&parser_config).expect("loads");
+        assert_eq!(synonym_map["func"], vec!["function", "procedure", "method"]);
+        assert_eq!(synonym_map["ram"], vec!["memory"]);
+        assert_eq!(synonym_map.len(), 2);
+
+        fs::write(&synonyms_path, "func function\n").expect("write synonyms");
+        assert!(matches!(
+            load_synonyms(&parser_config),
+            Err(ChatbotError::ConfigLoad(_))
+        ));
+        assert!(
+            load_synonyms(&ParserConfig::default())
+                .expect("no path")
+                .is_empty()
+        );
+        let _ = fs::remove_file(&synonyms_path);
+    }
+
+    #[test]
+    fn test_stopwords_path_accepts_string_or_list_and_merges_files() {
+        let fixture_directory =
+            std::env::temp_dir().join(format!("rulebot_config_stopwords_{}", std::process::id()));
+        fs::create_dir_all(&fixture_directory).expect("create fixture directory");
+        let general_path = fixture_directory.join("general.txt");
+        let domain_path = fixture_directory.join("domain.txt");
+        fs::write(&general_path, "# general\nthe\nof\nAnd\n").expect("write general");
+        fs::write(&domain_path, "\nand\nalgorithm\n# the\n").expect("write domain");
+        let general_text = general_path.display().to_string();
+        let domain_text = domain_path.display().to_string();
+
+        let single_config: ParserConfig =
+            serde_json::from_value(serde_json::json!({ "stopwords_path": general_text }))
+                .expect("single path");
+        assert_eq!(single_config.stopwords_path, vec![general_text.clone()]);
+
+        let list_config: ParserConfig = serde_json::from_value(
+            serde_json::json!({ "stopwords_path": [general_text, domain_text] }),
+        )
+        .expect("path list");
+        let merged_stopwords = load_configured_stopwords(&list_config).expect("readable");
+        let mut merged_words: Vec<&str> = merged_stopwords.iter().map(String::as_str).collect();
+        merged_words.sort();
+        assert_eq!(merged_words, vec!["algorithm", "and", "of", "the"]);
+
+        let missing_config: ParserConfig =
+            serde_json::from_value(serde_json::json!({ "stopwords_path": null })).expect("null");
+        assert!(load_configured_stopwords(&missing_config).is_err());
     }
-    Ok(stopwords_set)
 }
@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 
 /// A hierarchical deck path, e.g. ["Launch into Computing", "Unit 03 - Principles of Computer Science"].
@@ -9,8 +9,33 @@ pub type DeckPath = Vec<String>;
 pub struct Card {
     pub guid: String,
     pub question_text: String, // normalised for matching (tags stripped, entities decoded, lowercased)
-    pub answer_raw: String,    // raw HTML/text for display; never render without escaping
+    #[serde(default)]
+    pub question_display: String, // as `question_text` but keeping the original case; used for previews
+    pub answer_raw: String, // raw HTML/text for display; never render without escaping
     pub deck_path: DeckPath,
+    /// Values of the configured `extra_columns` (e.g. tags), keyed by `extra_column_names`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, String>,
+}
+
+impl Card {
+    /// A card with `question_display` equal to `question_text` and no metadata.
+    pub fn new(guid: &str, question_text: &str, answer_raw: &str, deck_path: DeckPath) -> Self {
+        Card {
+            guid: guid.to_string(),
+            question_text: question_text.to_string(),
+            question_display: question_text.to_string(),
+            answer_raw: answer_raw.to_string(),
+            deck_path,
+            metadata: HashMap::new(),
+        }
+    }
+}
+
+/// Test fixture: a card in deck "Deck" whose answer is "answer".
+#[cfg(test)]
+pub(crate) fn test_card(guid: &str, question_text: &str) -> Card {
+    Card::new(guid, question_text, "answer", vec!["Deck".to_string()])
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -21,6 +46,16 @@ pub struct AnswerHit {
     pub deck_path: DeckPath,
 }
 
+/// Ranked hits plus how selective the query was (see `ChatbotEngine::score_report`).
+#[derive(Debug, Clone)]
+pub struct ScoreReport {
+    pub hits: Vec<AnswerHit>,
+    /// Candidates that scored above zero, before `min_score` and `top_k` were applied.
+    pub total_nonzero: usize,
+    /// Candidates the query was ranked against.
+    pub total_candidates: usize,
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QueryRequest {
@@ -67,11 +102,60 @@ pub struct LogRecord {
     #[serde(rename = "rss_kb")]
     pub rss_kilobytes: Option<u64>,
     #[serde(rename = "top")]
-    pub top: Vec<(String, f64)>,
+    pub top: Vec<TopEntry>,
+    /// Scoring ran past `--timeout-ms`, so `top` is empty. Only written when set.
+    #[serde(
+        rename = "timed_out",
+        default,
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub timed_out: bool,
+}
+
+/// Decimal places kept for `TopEntry::score`, matching the `{score:.6f}` the Python CLI prints.
+/// The CLI's `--precision` overrides it.
+pub const TOP_SCORE_DECIMALS: i32 = 6;
+
+/// One ranked result in `LogRecord::top`.
+///
+/// Migration note: `top` used to serialise as `[guid, score]` pairs with the raw `f32` score
+/// widened to `f64`. It is now an array of `{"rank", "guid", "score"}` objects (rank from 1,
+/// score rounded to `--precision`, `TOP_SCORE_DECIMALS` by default), the same `guid`/`score` keys the Python log uses.
+/// Readers of older JSONL logs should accept both shapes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TopEntry {
+    #[serde(rename = "rank")]
+    pub rank: usize,
+    #[serde(rename = "guid")]
+    pub guid: String,
+    #[serde(rename = "score")]
+    pub score: f64,
+}
+
+/// `LogRecord::top` for hits in rank order.
+pub fn top_entries_from_hits(answer_hits: &[AnswerHit]) -> Vec<TopEntry> {
+    top_entries_from_hits_with_decimals(answer_hits, TOP_SCORE_DECIMALS as usize)
+}
+
+/// As `top_entries_from_hits`, with scores rounded to `score_decimals` places.
+pub fn top_entries_from_hits_with_decimals(
+    answer_hits: &[AnswerHit],
+    score_decimals: usize,
+) -> Vec<TopEntry> {
+    let rounding_factor = 10f64.powi(score_decimals as i32);
+    answer_hits
+        .iter()
+        .enumerate()
+        .map(|(rank_index, answer_hit)| TopEntry {
+            rank: rank_index + 1,
+            guid: answer_hit.guid.clone(),
+            score: (f64::from(answer_hit.score) * rounding_factor).round() / rounding_factor,
+        })
+        .collect()
 }
 
 /// Invalid line metadata captured during deck parsing/validation.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct InvalidRecord {
     pub file_path: String,
     pub line_number: usize,
@@ -79,20 +163,36 @@ pub struct InvalidRecord {
     pub raw_line: String,
 }
 
+/// Count invalid records per reason, in reason order, for validation summaries.
+pub fn count_invalid_records_by_reason(
+    invalid_records: &[InvalidRecord],
+) -> BTreeMap<String, usize> {
+    let mut reason_counts: BTreeMap<String, usize> = BTreeMap::new();
+    for invalid_record in invalid_records {
+        *reason_counts
+            .entry(invalid_record.reason.clone())
+            .or_insert(0) += 1;
+    }
+    reason_counts
+}
+
 /// Convert a deck path to a human-readable string (uses "::" separator).
 pub fn deck_path_to_string(deck_path: &DeckPath) -> String {
     deck_path.join("::")
 }
 
-/// Create a short preview of text, truncated to `max_length` with an ellipsis if needed.
-#[allow(dead_code)]
+/// Create a short preview of text, truncated to `max_length` characters (the ellipsis included)
+/// if needed. Lengths count chars, not bytes, so the cut never splits a multi-byte character.
 pub fn short_preview(full_text: &str, max_length: usize) -> String {
-    if full_text.len() <= max_length {
+    if full_text.chars().count() <= max_length {
         full_text.to_string()
     } else if max_length <= 1 {
         "…".to_string()
     } else {
-        let cut_index = max_length.saturating_sub(1);
+        let cut_index = full_text
+            .char_indices()
+            .nth(max_length - 1)
+            .map_or(full_text.len(), |(byte_index, _)| byte_index);
         format!("{}…", &full_text[..cut_index])
     }
 }
@@ -107,7 +207,12 @@ pub fn build_guid_index(cards: &[Card]) -> HashMap<String, Card> {
 }
 
 /// Optional helper for constructing `AnswerHit`.
-pub fn make_hit(guid: String, deck_path: DeckPath, question_preview: Option<String>, score: f32) -> AnswerHit {
+pub fn make_hit(
+    guid: String,
+    deck_path: DeckPath,
+    question_preview: Option<String>,
+    score: f32,
+) -> AnswerHit {
     AnswerHit {
         guid,
         deck_path,
@@ -139,3 +244,29 @@ impl fmt::Display for AnswerHit {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_preview_counts_chars() {
+        assert_eq!(short_preview("binary search", 20), "binary search");
+        assert_eq!(short_preview("binary search", 7), "binary…");
+        assert_eq!(short_preview("binary search", 1), "…");
+        // Multi-byte text is cut by character, never mid-character.
+        assert_eq!(short_preview("日本語のテキスト", 4), "日本語…");
+        assert_eq!(short_preview("日本語", 3), "日本語");
+    }
+
+    #[test]
+    fn test_short_preview_cut_next_to_accents_and_dashes() {
+        // "é" and "—" are 2 and 3 bytes; a byte-indexed cut at 4 or 8 would split them.
+        assert_eq!(short_preview("café au lait", 5), "café…");
+        assert_eq!(short_preview("café au lait", 4), "caf…");
+        assert_eq!(short_preview("LIFO — last in", 7), "LIFO —…");
+        assert_eq!(short_preview("LIFO — last in", 6), "LIFO …");
+        assert_eq!(short_preview("naïve", 5), "naïve");
+        assert_eq!(short_preview("é", 0), "…");
+    }
+}
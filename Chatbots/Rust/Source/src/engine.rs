@@ -0,0 +1,825 @@
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::config::{ParserConfig, load_configured_stopwords, load_parser_config};
+use crate::data_model::{AnswerHit, Card, InvalidRecord, ScoreReport, build_guid_index};
+use crate::error::ChatbotError;
+use crate::io_decks::load_decks;
+use crate::query_cache::QueryCache;
+use crate::query_parser::parse_query;
+use crate::scoring::diversity::rerank_for_diversity;
+use crate::scoring::keyword::PreparedQuestion;
+use crate::scoring::ngram::NgramIndex;
+use crate::scoring::proximity::ProximityIndex;
+use crate::scoring::tfidf::TfidfIndex;
+use crate::scoring::{
+    ProgressCallback, ScoreExplanation, ScoredIndex, Scorer, ScorerQuery, ScorerRegistry,
+    downcast_index,
+};
+use crate::tokenise::tokenise;
+
+/// How many candidates per requested hit `query_diverse` reranks.
+const DIVERSITY_CANDIDATE_FACTOR: usize = 4;
+
+/// Retrieval algorithms built into every `ScorerRegistry` (see `ScorerRegistry::builtin`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Algorithm {
+    Keyword,
+    Tfidf,
+    Jaccard,
+    Dice,
+    Ngram,
+    Hybrid,
+    TfidfSoft,
+    Proximity,
+}
+
+impl Algorithm {
+    /// Every algorithm, in the order they are listed on the CLI.
+    pub const ALL: [Algorithm; 8] = [
+        Algorithm::Keyword,
+        Algorithm::Tfidf,
+        Algorithm::Jaccard,
+        Algorithm::Dice,
+        Algorithm::Ngram,
+        Algorithm::Hybrid,
+        Algorithm::TfidfSoft,
+        Algorithm::Proximity,
+    ];
+
+    /// The CLI / log name of the algorithm (e.g. `"tfidf"`).
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Keyword => "keyword",
+            Algorithm::Tfidf => "tfidf",
+            Algorithm::Jaccard => "jaccard",
+            Algorithm::Dice => "dice",
+            Algorithm::Ngram => "ngram",
+            Algorithm::Hybrid => "hybrid",
+            Algorithm::TfidfSoft => "tfidf-soft",
+            Algorithm::Proximity => "proximity",
+        }
+    }
+
+    /// Parse a CLI / log name back into an algorithm.
+    pub fn from_name(algorithm_name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.name() == algorithm_name)
+    }
+
+    /// The algorithm as a shared `Scorer`, e.g. for `ChatbotEngine::from_cards`.
+    pub fn scorer(self) -> Arc<dyn Scorer> {
+        Arc::new(self)
+    }
+}
+
+/// A scorer with the index it ranks over; scorers with the same `index_key` share one index.
+#[derive(Clone)]
+struct IndexedScorer {
+    scorer: Arc<dyn Scorer>,
+    scored_index: Arc<dyn ScoredIndex>,
+}
+
+impl fmt::Debug for IndexedScorer {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("IndexedScorer")
+            .field("scorer", &self.scorer.name())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A loaded corpus plus prebuilt indices, ready to answer repeated queries.
+#[derive(Debug, Clone)]
+pub struct ChatbotEngine {
+    cards: Vec<Card>,
+    invalid_records: Vec<InvalidRecord>,
+    guid_index: HashMap<String, Card>,
+    parser_config: ParserConfig,
+    stopword_set: HashSet<String>,
+    /// Scorers the engine was built for, in the order given.
+    indexed_scorers: Vec<IndexedScorer>,
+    query_cache: Option<QueryCache>,
+    /// Distinct query tokens a keyword candidate must share to be eligible (see `with_min_overlap`).
+    min_overlap: usize,
+    /// Query token → tokenised synonyms appended by keyword and TF–IDF (see `with_synonyms`).
+    synonym_map: HashMap<String, Vec<String>>,
+}
+
+impl ChatbotEngine {
+    /// Load decks and the parser config from disk and build an index for every built-in scorer.
+    pub fn new<D: AsRef<Path>, C: AsRef<Path>>(
+        data_path: D,
+        config_path: C,
+    ) -> Result<Self, ChatbotError> {
+        let parser_config = load_parser_config(config_path)?;
+        let stopword_set = load_configured_stopwords(&parser_config)?;
+        let (cards, invalid_records) = load_decks(data_path, &parser_config)?;
+        if cards.is_empty() {
+            return Err(ChatbotError::NoValidCards);
+        }
+
+        let mut engine = Self::from_cards(
+            cards,
+            parser_config,
+            stopword_set,
+            ScorerRegistry::builtin().scorers(),
+        );
+        engine.invalid_records = invalid_records;
+        Ok(engine)
+    }
+
+    /// Build an engine over already-loaded cards, indexing only the listed scorers.
+    /// With `deterministic` set, cards are first sorted by deck path then GUID.
+    pub fn from_cards(
+        cards: Vec<Card>,
+        parser_config: ParserConfig,
+        stopword_set: HashSet<String>,
+        scorers: &[Arc<dyn Scorer>],
+    ) -> Self {
+        Self::from_cards_with_progress(cards, parser_config, stopword_set, scorers, None)
+    }
+
+    /// As `from_cards`, passing `progress` to each index build (each counting up to the card
+    /// count).
+    pub fn from_cards_with_progress(
+        mut cards: Vec<Card>,
+        parser_config: ParserConfig,
+        stopword_set: HashSet<String>,
+        scorers: &[Arc<dyn Scorer>],
+        progress: Option<ProgressCallback>,
+    ) -> Self {
+        if parser_config.deterministic {
+            cards.sort_by(|left, right| {
+                left.deck_path
+                    .cmp(&right.deck_path)
+                    .then_with(|| left.guid.cmp(&right.guid))
+            });
+        }
+
+        let mut indexed_scorers: Vec<IndexedScorer> = Vec::with_capacity(scorers.len());
+        for scorer in scorers {
+            if indexed_scorers
+                .iter()
+                .any(|indexed| indexed.scorer.name() == scorer.name())
+            {
+                continue;
+            }
+            let scored_index = match indexed_scorers
+                .iter()
+                .find(|indexed| indexed.scorer.index_key() == scorer.index_key())
+            {
+                Some(indexed) => Arc::clone(&indexed.scored_index),
+                None => {
+                    Arc::from(scorer.build_index(&cards, &stopword_set, &parser_config, progress))
+                }
+            };
+            indexed_scorers.push(IndexedScorer {
+                scorer: Arc::clone(scorer),
+                scored_index,
+            });
+        }
+
+        Self {
+            guid_index: build_guid_index(&cards),
+            cards,
+            invalid_records: Vec::new(),
+            parser_config,
+            stopword_set,
+            indexed_scorers,
+            query_cache: None,
+            min_overlap: 1,
+            synonym_map: HashMap::new(),
+        }
+    }
+
+    /// Cache the tokenisation of up to `capacity` recent queries for the keyword and TF–IDF
+    /// scorers (0 disables), so warm-up loops and repeated batch questions skip `tokenise`.
+    pub fn with_query_cache(mut self, capacity: usize) -> Self {
+        self.query_cache = (capacity > 0).then(|| QueryCache::new(capacity));
+        self
+    }
+
+    /// Require keyword candidates to share at least `min_overlap` distinct tokens with the query
+    /// (default 1; clamped to the query's token count when it is shorter).
+    pub fn with_min_overlap(mut self, min_overlap: usize) -> Self {
+        self.min_overlap = min_overlap;
+        self
+    }
+
+    /// Expand keyword and TF–IDF queries with `synonym_map` (from `load_synonyms`): each query
+    /// token's synonyms are added at `synonym_weight`. Terms and synonyms are tokenised like
+    /// queries, so they match after stemming; an empty map turns expansion off.
+    pub fn with_synonyms(mut self, synonym_map: HashMap<String, Vec<String>>) -> Self {
+        let mut tokenised_map: HashMap<String, Vec<String>> = HashMap::new();
+        for (term_text, synonym_list) in synonym_map {
+            let synonym_tokens: Vec<String> = synonym_list
+                .iter()
+                .flat_map(|synonym_text| self.tokenise_query(synonym_text))
+                .collect();
+            for term_token in self.tokenise_query(&term_text) {
+                let term_synonyms = tokenised_map.entry(term_token).or_default();
+                for synonym_token in &synonym_tokens {
+                    if !term_synonyms.contains(synonym_token) {
+                        term_synonyms.push(synonym_token.clone());
+                    }
+                }
+            }
+        }
+        self.synonym_map = tokenised_map;
+        self
+    }
+
+    fn tokenise_query(&self, query_text: &str) -> Vec<String> {
+        tokenise(query_text, &self.stopword_set, &self.parser_config)
+    }
+
+    /// Synonyms of `query_tokens` that are not query tokens themselves, in first-seen order.
+    pub fn expanded_tokens(&self, query_tokens: &[String]) -> Vec<String> {
+        let mut expanded_tokens: Vec<String> = Vec::new();
+        for synonym_token in query_tokens
+            .iter()
+            .filter_map(|query_token| self.synonym_map.get(query_token))
+            .flatten()
+        {
+            if !query_tokens.contains(synonym_token) && !expanded_tokens.contains(synonym_token) {
+                expanded_tokens.push(synonym_token.clone());
+            }
+        }
+        expanded_tokens
+    }
+
+    pub fn query_cache(&self) -> Option<&QueryCache> {
+        self.query_cache.as_ref()
+    }
+
+    /// The query's tokens, from the query cache when one is enabled.
+    fn query_tokens(&self, query_text: &str) -> Vec<String> {
+        match &self.query_cache {
+            Some(query_cache) => {
+                query_cache.tokens(query_text, &self.stopword_set, &self.parser_config)
+            }
+            None => tokenise(query_text, &self.stopword_set, &self.parser_config),
+        }
+    }
+
+    /// Rank the corpus against `query_text` with the scorer named `scorer_name` and return up
+    /// to `top_k` hits. Returns no hits if the engine was built without that scorer.
+    pub fn query(&self, query_text: &str, scorer_name: &str, top_k: usize) -> Vec<AnswerHit> {
+        self.query_above(query_text, scorer_name, top_k, 0.0)
+    }
+
+    /// As `query`, but drop hits scoring below `min_score` before truncating to `top_k`.
+    pub fn query_above(
+        &self,
+        query_text: &str,
+        scorer_name: &str,
+        top_k: usize,
+        min_score: f32,
+    ) -> Vec<AnswerHit> {
+        let query_tokens = self.query_tokens(query_text);
+        self.query_above_tokens(query_text, &query_tokens, scorer_name, top_k, min_score)
+    }
+
+    /// As `query_above`, ranking `query_tokens` (e.g. spelling-corrected) in place of the tokens
+    /// of `query_text`, which still supplies the quoted phrases.
+    pub fn query_above_tokens(
+        &self,
+        query_text: &str,
+        query_tokens: &[String],
+        scorer_name: &str,
+        top_k: usize,
+        min_score: f32,
+    ) -> Vec<AnswerHit> {
+        let Some(indexed) = self.indexed_scorer(scorer_name) else {
+            return Vec::new();
+        };
+        let phrases = if query_text.contains('"') {
+            parse_query(query_text, &self.stopword_set, &self.parser_config).1
+        } else {
+            Vec::new()
+        };
+        let scorer_query = ScorerQuery {
+            tokens: query_tokens,
+            expanded_tokens: &self.expanded_tokens(query_tokens),
+            phrases: &phrases,
+            min_overlap: self.min_overlap,
+        };
+        indexed.scorer.score(
+            &*indexed.scored_index,
+            &scorer_query,
+            &self.parser_config,
+            top_k,
+            min_score,
+        )
+    }
+
+    /// As `query_above`, but also count every candidate that scored above zero. Ranks the whole
+    /// corpus, so it costs more than `query_above` when `top_k` is small.
+    pub fn score_report(
+        &self,
+        query_text: &str,
+        scorer_name: &str,
+        top_k: usize,
+        min_score: f32,
+    ) -> ScoreReport {
+        let query_tokens = self.query_tokens(query_text);
+        self.score_report_tokens(query_text, &query_tokens, scorer_name, top_k, min_score)
+    }
+
+    /// As `score_report`, ranking `query_tokens` as `query_above_tokens` does.
+    pub fn score_report_tokens(
+        &self,
+        query_text: &str,
+        query_tokens: &[String],
+        scorer_name: &str,
+        top_k: usize,
+        min_score: f32,
+    ) -> ScoreReport {
+        let every_hit =
+            self.query_above_tokens(query_text, query_tokens, scorer_name, usize::MAX, 0.0);
+        let total_nonzero = every_hit.len();
+        ScoreReport {
+            hits: every_hit
+                .into_iter()
+                .filter(|answer_hit| answer_hit.score >= min_score)
+                .take(top_k)
+                .collect(),
+            total_nonzero,
+            total_candidates: self.cards.len(),
+        }
+    }
+
+    /// The `top_k` cards most similar to the card `guid`, ranking its question text as the query
+    /// and leaving the card itself out. Double quotes in the question are dropped so they are
+    /// not read as keyword phrases. Empty if `guid` is not an indexed card.
+    pub fn most_similar(
+        &self,
+        guid: &str,
+        scorer_name: &str,
+        top_k: usize,
+        min_score: f32,
+    ) -> Vec<AnswerHit> {
+        let Some(card) = self.guid_index.get(guid) else {
+            return Vec::new();
+        };
+        let query_text = card.question_text.replace('"', " ");
+        self.query_above(&query_text, scorer_name, top_k.saturating_add(1), min_score)
+            .into_iter()
+            .filter(|answer_hit| answer_hit.guid != guid)
+            .take(top_k)
+            .collect()
+    }
+
+    /// As `query_above`, but rerank a wider candidate pool with `rerank_for_diversity`
+    /// (using `diversity_lambda`) so near-duplicate questions do not crowd the top `top_k`.
+    /// Falls back to the plain ranking when no keyword index was built.
+    pub fn query_diverse(
+        &self,
+        query_text: &str,
+        scorer_name: &str,
+        top_k: usize,
+        min_score: f32,
+    ) -> Vec<AnswerHit> {
+        let query_tokens = self.query_tokens(query_text);
+        self.query_diverse_tokens(query_text, &query_tokens, scorer_name, top_k, min_score)
+    }
+
+    /// As `query_diverse`, ranking `query_tokens` as `query_above_tokens` does.
+    pub fn query_diverse_tokens(
+        &self,
+        query_text: &str,
+        query_tokens: &[String],
+        scorer_name: &str,
+        top_k: usize,
+        min_score: f32,
+    ) -> Vec<AnswerHit> {
+        let candidate_hits = self.query_above_tokens(
+            query_text,
+            query_tokens,
+            scorer_name,
+            Self::diversity_pool_size(top_k),
+            min_score,
+        );
+        self.rerank_diverse(candidate_hits, top_k)
+    }
+
+    /// How many ranked hits `query_diverse` reranks to pick `top_k`.
+    pub fn diversity_pool_size(top_k: usize) -> usize {
+        top_k.saturating_mul(DIVERSITY_CANDIDATE_FACTOR)
+    }
+
+    /// The `query_diverse` reranking of `candidate_hits` (best first, up to
+    /// `diversity_pool_size` of them); their first `top_k` when no keyword index was built.
+    pub fn rerank_diverse(&self, candidate_hits: Vec<AnswerHit>, top_k: usize) -> Vec<AnswerHit> {
+        match self.keyword_index() {
+            Some(keyword_index) => rerank_for_diversity(
+                candidate_hits,
+                keyword_index,
+                self.parser_config.diversity_lambda,
+                top_k,
+            ),
+            None => candidate_hits.into_iter().take(top_k).collect(),
+        }
+    }
+
+    /// Why `guid` scored as it did against `query_tokens` under the scorer named `scorer_name`
+    /// (see `Scorer::explain`); empty if the engine was built without that scorer.
+    pub fn explain(
+        &self,
+        query_tokens: &[String],
+        guid: &str,
+        scorer_name: &str,
+    ) -> ScoreExplanation {
+        self.indexed_scorer(scorer_name)
+            .map(|indexed| {
+                indexed.scorer.explain(
+                    &*indexed.scored_index,
+                    query_tokens,
+                    guid,
+                    &self.parser_config,
+                )
+            })
+            .unwrap_or_default()
+    }
+
+    /// Query tokens that also occur (exactly) among `guid`'s indexed tokens, from whichever
+    /// index was built. Empty if the card is unknown or no index holds it.
+    pub fn matched_tokens(&self, query_text: &str, guid: &str) -> HashSet<String> {
+        self.matched_query_tokens(&self.query_tokens(query_text), guid)
+    }
+
+    /// As `matched_tokens`, for a query already tokenised (e.g. spelling-corrected).
+    pub fn matched_query_tokens(&self, query_tokens: &[String], guid: &str) -> HashSet<String> {
+        let query_token_set: HashSet<&String> = query_tokens.iter().collect();
+        let indexed_tokens: Vec<&String> = if let Some(prepared_question) = self
+            .keyword_index()
+            .and_then(|keyword_index| keyword_index.iter().find(|prepared| prepared.guid == guid))
+        {
+            prepared_question.token_set.iter().collect()
+        } else if let Some(document_entry) = self.tfidf_index().and_then(|tfidf_index| {
+            tfidf_index
+                .documents
+                .iter()
+                .find(|entry| entry.guid == guid)
+        }) {
+            document_entry.term_frequencies.keys().collect()
+        } else if let Some(ngram_document) = self.ngram_index().and_then(|ngram_index| {
+            ngram_index
+                .documents
+                .iter()
+                .find(|document| document.guid == guid)
+        }) {
+            ngram_document
+                .tokens
+                .iter()
+                .map(|ngram_token| &ngram_token.token_text)
+                .collect()
+        } else {
+            return HashSet::new();
+        };
+        indexed_tokens
+            .into_iter()
+            .filter(|token_text| query_token_set.contains(token_text))
+            .cloned()
+            .collect()
+    }
+
+    pub fn cards(&self) -> &[Card] {
+        &self.cards
+    }
+
+    /// Records skipped while loading decks (empty when built via `from_cards`).
+    pub fn invalid_records(&self) -> &[InvalidRecord] {
+        &self.invalid_records
+    }
+
+    pub fn card(&self, guid: &str) -> Option<&Card> {
+        self.guid_index.get(guid)
+    }
+
+    pub fn guid_index(&self) -> &HashMap<String, Card> {
+        &self.guid_index
+    }
+
+    pub fn parser_config(&self) -> &ParserConfig {
+        &self.parser_config
+    }
+
+    pub fn stopword_set(&self) -> &HashSet<String> {
+        &self.stopword_set
+    }
+
+    /// Names of the scorers the engine was built for, in the order given.
+    pub fn scorer_names(&self) -> Vec<&'static str> {
+        self.indexed_scorers
+            .iter()
+            .map(|indexed| indexed.scorer.name())
+            .collect()
+    }
+
+    fn indexed_scorer(&self, scorer_name: &str) -> Option<&IndexedScorer> {
+        self.indexed_scorers
+            .iter()
+            .find(|indexed| indexed.scorer.name() == scorer_name)
+    }
+
+    /// The first built index of type `T`, whichever scorer it was built for.
+    fn built_index<T: Any>(&self) -> Option<&T> {
+        self.indexed_scorers
+            .iter()
+            .find_map(|indexed| downcast_index::<T>(&*indexed.scored_index))
+    }
+
+    pub fn keyword_index(&self) -> Option<&[PreparedQuestion]> {
+        self.built_index::<Vec<PreparedQuestion>>()
+            .map(Vec::as_slice)
+    }
+
+    pub fn tfidf_index(&self) -> Option<&TfidfIndex> {
+        self.built_index::<TfidfIndex>()
+    }
+
+    pub fn ngram_index(&self) -> Option<&NgramIndex> {
+        self.built_index::<NgramIndex>()
+    }
+
+    pub fn proximity_index(&self) -> Option<&ProximityIndex> {
+        self.built_index::<ProximityIndex>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_model::test_card;
+    use crate::tokenise::tokenise_to_set;
+
+    fn sample_cards() -> Vec<Card> {
+        vec![
+            Card::new(
+                "g1",
+                "what is big data",
+                "Large datasets",
+                vec!["Deck".to_string()],
+            ),
+            Card::new(
+                "g2",
+                "what is a relational database",
+                "Tables and keys",
+                vec!["Deck".to_string()],
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_engine_query_each_algorithm() {
+        let engine = ChatbotEngine::from_cards(
+            sample_cards(),
+            ParserConfig {
+                remove_stopwords: false,
+                ..Default::default()
+            },
+            HashSet::new(),
+            ScorerRegistry::builtin().scorers(),
+        );
+        for algorithm in Algorithm::ALL {
+            let answer_hits = engine.query("relational database", algorithm.name(), 1);
+            assert_eq!(answer_hits.len(), 1, "{}", algorithm.name());
+            assert_eq!(answer_hits[0].guid, "g2");
+        }
+    }
+
+    #[test]
+    fn test_most_similar_excludes_the_card_itself() {
+        let mut cards = sample_cards();
+        cards.push(Card::new(
+            "g3",
+            "what is a relational database schema",
+            "Table definitions",
+            vec!["Deck".to_string()],
+        ));
+        let engine = ChatbotEngine::from_cards(
+            cards,
+            ParserConfig::default(),
+            HashSet::new(),
+            &[Algorithm::Keyword.scorer(), Algorithm::Tfidf.scorer()],
+        );
+        for algorithm in [Algorithm::Keyword, Algorithm::Tfidf] {
+            let similar_hits = engine.most_similar("g2", algorithm.name(), 5, 0.0);
+            assert!(
+                similar_hits
+                    .iter()
+                    .all(|answer_hit| answer_hit.guid != "g2"),
+                "{}",
+                algorithm.name()
+            );
+            assert_eq!(similar_hits[0].guid, "g3", "{}", algorithm.name());
+        }
+        assert!(
+            engine
+                .most_similar("missing", Algorithm::Tfidf.name(), 5, 0.0)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_matched_tokens_from_any_index() {
+        for algorithm in [Algorithm::Keyword, Algorithm::Tfidf, Algorithm::Ngram] {
+            let engine = ChatbotEngine::from_cards(
+                sample_cards(),
+                ParserConfig {
+                    remove_stopwords: false,
+                    ..Default::default()
+                },
+                HashSet::new(),
+                &[algorithm.scorer()],
+            );
+            let matched_tokens = engine.matched_tokens("relational data", "g2");
+            assert_eq!(matched_tokens, HashSet::from(["relational".to_string()]));
+            assert!(engine.matched_tokens("relational", "missing").is_empty());
+        }
+    }
+
+    #[test]
+    fn test_query_above_tokens_ranks_the_given_tokens() {
+        let engine = ChatbotEngine::from_cards(
+            sample_cards(),
+            ParserConfig::default(),
+            HashSet::new(),
+            ScorerRegistry::builtin().scorers(),
+        );
+        let query_tokens = tokenise(
+            "relational data",
+            engine.stopword_set(),
+            engine.parser_config(),
+        );
+        let ranked = |answer_hits: Vec<AnswerHit>| {
+            answer_hits
+                .into_iter()
+                .map(|answer_hit| (answer_hit.guid, answer_hit.score))
+                .collect::<Vec<(String, f32)>>()
+        };
+        for algorithm in Algorithm::ALL {
+            // The text only supplies quoted phrases; the tokens are not taken from it.
+            assert_eq!(
+                ranked(engine.query_above_tokens("", &query_tokens, algorithm.name(), 3, 0.0)),
+                ranked(engine.query("relational data", algorithm.name(), 3)),
+                "{}",
+                algorithm.name()
+            );
+        }
+    }
+
+    #[test]
+    fn test_engine_without_index_returns_no_hits() {
+        let engine = ChatbotEngine::from_cards(
+            sample_cards(),
+            ParserConfig::default(),
+            HashSet::new(),
+            &[Algorithm::Keyword.scorer()],
+        );
+        assert!(
+            engine
+                .query("big data", Algorithm::Tfidf.name(), 1)
+                .is_empty()
+        );
+        assert_eq!(Algorithm::from_name("tfidf"), Some(Algorithm::Tfidf));
+    }
+
+    #[test]
+    fn test_score_report_counts_candidates_sharing_a_term() {
+        let mut cards = sample_cards();
+        cards.push(Card::new(
+            "g3",
+            "how are tables stored",
+            "On disk",
+            vec!["Deck".to_string()],
+        ));
+        let parser_config = ParserConfig {
+            remove_stopwords: false,
+            ..Default::default()
+        };
+        let query_text = "big relational tables";
+        let query_tokens = tokenise_to_set(query_text, &HashSet::new(), &parser_config);
+        let overlapping_count = cards
+            .iter()
+            .filter(|card| {
+                tokenise_to_set(&card.question_text, &HashSet::new(), &parser_config)
+                    .iter()
+                    .any(|token_text| query_tokens.contains(token_text))
+            })
+            .count();
+        assert_eq!(overlapping_count, 3);
+
+        let engine = ChatbotEngine::from_cards(
+            cards,
+            parser_config,
+            HashSet::new(),
+            ScorerRegistry::builtin().scorers(),
+        );
+        for algorithm in [Algorithm::Keyword, Algorithm::Tfidf, Algorithm::Jaccard] {
+            let score_report = engine.score_report(query_text, algorithm.name(), 1, 0.0);
+            assert_eq!(
+                score_report.total_nonzero,
+                overlapping_count,
+                "{}",
+                algorithm.name()
+            );
+            assert_eq!(score_report.total_candidates, 3);
+            assert_eq!(score_report.hits.len(), 1);
+            assert_eq!(
+                score_report.hits[0].guid,
+                engine.query(query_text, algorithm.name(), 1)[0].guid
+            );
+        }
+        let no_match_report = engine.score_report("quantum", Algorithm::Keyword.name(), 1, 0.0);
+        assert_eq!(
+            (no_match_report.total_nonzero, no_match_report.hits.len()),
+            (0, 0)
+        );
+    }
+
+    #[test]
+    fn test_synonym_expansion_matches_below_exact_terms() {
+        let cards = vec![
+            test_card("synonym", "what does a function return"),
+            test_card("exact", "what does a func return"),
+            test_card("other", "what is a loop"),
+        ];
+        let parser_config = ParserConfig {
+            remove_stopwords: false,
+            ..Default::default()
+        };
+        let engine = ChatbotEngine::from_cards(
+            cards,
+            parser_config,
+            HashSet::new(),
+            ScorerRegistry::builtin().scorers(),
+        );
+        let expanded_engine = engine.clone().with_synonyms(HashMap::from([(
+            "Func".to_string(),
+            vec!["function".to_string(), "procedure".to_string()],
+        )]));
+        assert_eq!(
+            expanded_engine.expanded_tokens(&["func".to_string()]),
+            vec!["function", "procedure"]
+        );
+
+        for algorithm in [Algorithm::Keyword, Algorithm::Tfidf] {
+            let ranked_guids = |engine: &ChatbotEngine| -> Vec<String> {
+                engine
+                    .query("func", algorithm.name(), 3)
+                    .into_iter()
+                    .map(|answer_hit| answer_hit.guid)
+                    .collect()
+            };
+            assert_eq!(ranked_guids(&engine), vec!["exact"], "{}", algorithm.name());
+            assert_eq!(
+                ranked_guids(&expanded_engine),
+                vec!["exact", "synonym"],
+                "{}",
+                algorithm.name()
+            );
+        }
+    }
+
+    #[test]
+    fn test_empty_corpus_and_empty_query_return_no_hits() {
+        let empty_engine = ChatbotEngine::from_cards(
+            Vec::new(),
+            ParserConfig::default(),
+            HashSet::new(),
+            ScorerRegistry::builtin().scorers(),
+        );
+        let engine = ChatbotEngine::from_cards(
+            sample_cards(),
+            ParserConfig::default(),
+            HashSet::new(),
+            ScorerRegistry::builtin().scorers(),
+        );
+        for algorithm in Algorithm::ALL {
+            assert!(
+                empty_engine
+                    .query("big data", algorithm.name(), 3)
+                    .is_empty(),
+                "{}",
+                algorithm.name()
+            );
+            assert!(
+                engine.query("", algorithm.name(), 3).is_empty(),
+                "{}",
+                algorithm.name()
+            );
+            assert!(
+                engine
+                    .query_diverse("  ", algorithm.name(), 3, 0.0)
+                    .is_empty()
+            );
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+/// Failure kinds surfaced by the library's loading and topic functions, so callers can match
+/// on "bad topic" versus "no data" instead of inspecting message text.
+#[derive(Debug, Error)]
+pub enum ChatbotError {
+    /// Parser.json or the stopword list could not be read, parsed, or validated.
+    #[error("Failed to load parser configuration: {0}")]
+    ConfigLoad(String),
+    /// A deck file or the data directory could not be read.
+    #[error("Failed to read {}: {reason}", path.display())]
+    DeckRead { path: PathBuf, reason: String },
+    #[error("No valid cards were loaded. Check your data path and data contract.")]
+    NoValidCards,
+    #[error("Topic cannot be empty; provide a valid deck path.")]
+    EmptyTopic,
+    /// `suggestions` holds up to five known topics, joined with the topic separator.
+    #[error(
+        "Unknown topic: \"{topic}\"\n{}",
+        format_topic_suggestions(suggestions)
+    )]
+    UnknownTopic {
+        topic: String,
+        suggestions: Vec<String>,
+    },
+    /// `top_level_topics` lists the first segment of every deck path.
+    #[error(
+        "No deck path starts with \"{prefix}\"\nTop-level topics:\n- {}",
+        top_level_topics.join("\n- ")
+    )]
+    NoTopicPrefixMatch {
+        prefix: String,
+        top_level_topics: Vec<String>,
+    },
+    #[error("No candidate cards found for the requested topic.")]
+    EmptyCandidatePool,
+    #[error("--query is required unless --interactive or --queries-file is set")]
+    MissingQuery,
+}
+
+fn format_topic_suggestions(suggestions: &[String]) -> String {
+    if suggestions.is_empty() {
+        String::from("No topics available.")
+    } else {
+        format!("Did you mean one of:\n- {}", suggestions.join("\n- "))
+    }
+}
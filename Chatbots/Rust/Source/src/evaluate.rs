@@ -0,0 +1,92 @@
+use crate::engine::ChatbotEngine;
+
+/// How well a scorer retrieves each card from its own question (see `evaluate_self_retrieval`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalReport {
+    pub top_k: usize,
+    /// Cards whose question was run as a query.
+    pub query_count: usize,
+    /// Queries whose own card ranked within the top k.
+    pub retrieved_count: usize,
+    /// `retrieved_count / query_count` (0 with no queries).
+    pub recall_at_k: f32,
+    /// Mean of 1 / rank of each query's own card, counting 0 when it missed the top k.
+    pub mean_reciprocal_rank: f32,
+}
+
+/// Query `engine` with every card's own question through the scorer named `scorer_name` and
+/// report recall@k and mean reciprocal rank of the card's own GUID. Cards sharing a question
+/// compete for rank 1, so duplicates in a deck lower both figures.
+pub fn evaluate_self_retrieval(
+    engine: &ChatbotEngine,
+    scorer_name: &str,
+    top_k: usize,
+) -> EvalReport {
+    let cards = engine.cards();
+    let mut retrieved_count = 0;
+    let mut reciprocal_rank_sum = 0.0f64;
+    for card in cards {
+        let answer_hits = engine.query(&card.question_text, scorer_name, top_k);
+        if let Some(rank_index) = answer_hits
+            .iter()
+            .take(top_k)
+            .position(|answer_hit| answer_hit.guid == card.guid)
+        {
+            retrieved_count += 1;
+            reciprocal_rank_sum += 1.0 / (rank_index + 1) as f64;
+        }
+    }
+
+    let query_count = cards.len();
+    let (recall_at_k, mean_reciprocal_rank) = if query_count == 0 {
+        (0.0, 0.0)
+    } else {
+        (
+            retrieved_count as f32 / query_count as f32,
+            (reciprocal_rank_sum / query_count as f64) as f32,
+        )
+    };
+    EvalReport {
+        top_k,
+        query_count,
+        retrieved_count,
+        recall_at_k,
+        mean_reciprocal_rank,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::config::ParserConfig;
+    use crate::data_model::test_card;
+    use crate::engine::Algorithm;
+
+    #[test]
+    fn test_self_retrieval_mrr_counts_duplicate_questions() {
+        // g1 and g2 share a question, so g2 ranks second behind g1 on the GUID tie-break.
+        let cards = vec![
+            test_card("g1", "what is a stack"),
+            test_card("g2", "what is a stack"),
+            test_card("g3", "hash table lookup"),
+        ];
+        let engine = ChatbotEngine::from_cards(
+            cards,
+            ParserConfig::default(),
+            HashSet::new(),
+            &[Algorithm::Tfidf.scorer()],
+        );
+        let eval_report = |top_k: usize| evaluate_self_retrieval(&engine, "tfidf", top_k);
+
+        let top_two = eval_report(2);
+        assert_eq!((top_two.query_count, top_two.retrieved_count), (3, 3));
+        assert_eq!(top_two.recall_at_k, 1.0);
+        assert!((top_two.mean_reciprocal_rank - 2.5 / 3.0).abs() < 1e-6);
+
+        let top_one = eval_report(1);
+        assert_eq!(top_one.retrieved_count, 2);
+        assert!((top_one.mean_reciprocal_rank - 2.0 / 3.0).abs() < 1e-6);
+    }
+}
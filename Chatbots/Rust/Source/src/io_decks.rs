@@ -1,30 +1,43 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::io::{BufRead, BufReader, Cursor, Read};
 use std::path::{Path, PathBuf};
 
-use anyhow::{Context, Result};
-
+use crate::config::{ColumnLayout, DeckFormat, ParserConfig};
 use crate::data_model::{Card, DeckPath, InvalidRecord};
-use crate::normalise::normalise_for_matching;
+use crate::error::ChatbotError;
+use crate::normalise::normalise_preserving_case;
 
 const TAB_DELIMITER: char = '\t';
 
-/// Find all `.txt` files under a path. If the path is a file, return just that file.
-pub fn list_deck_files<P: AsRef<Path>>(data_path: P) -> Result<Vec<PathBuf>> {
+/// Find all `.txt` files (`.csv` files for the CSV format, and `.gz` files with the `gzip`
+/// feature) under a path. If the path is a file, return just that file.
+pub fn list_deck_files<P: AsRef<Path>>(
+    data_path: P,
+    deck_format: DeckFormat,
+) -> Result<Vec<PathBuf>, ChatbotError> {
     let path_ref = data_path.as_ref();
     if path_ref.is_file() {
         return Ok(vec![path_ref.to_path_buf()]);
     }
+    let directory_error = |error: std::io::Error| ChatbotError::DeckRead {
+        path: path_ref.to_path_buf(),
+        reason: format!("data directory unreadable: {error}"),
+    };
     let mut files: Vec<PathBuf> = Vec::new();
-    for entry in fs::read_dir(path_ref)
-        .with_context(|| format!("Failed to read data directory {}", path_ref.display()))?
-    {
-        let entry = entry?;
+    for entry in fs::read_dir(path_ref).map_err(directory_error)? {
+        let entry = entry.map_err(directory_error)?;
         let entry_path = entry.path();
-        if entry_path.is_file() {
-            if let Some(extension) = entry_path.extension() {
-                if extension.to_string_lossy().eq_ignore_ascii_case("txt") {
-                    files.push(entry_path);
-                }
+        if entry_path.is_file()
+            && let Some(extension) = entry_path.extension()
+        {
+            let extension_text = extension.to_string_lossy();
+            if extension_text.eq_ignore_ascii_case("txt")
+                || (deck_format == DeckFormat::Csv && extension_text.eq_ignore_ascii_case("csv"))
+                || (cfg!(feature = "gzip") && extension_text.eq_ignore_ascii_case("gz"))
+            {
+                files.push(entry_path);
             }
         }
     }
@@ -32,102 +45,528 @@ pub fn list_deck_files<P: AsRef<Path>>(data_path: P) -> Result<Vec<PathBuf>> {
     Ok(files)
 }
 
-/// Read a single Anki `.txt` deck file into `Card`s, collecting invalid records.
-pub fn read_deck_file<P: AsRef<Path>>(file_path: P) -> Result<(Vec<Card>, Vec<InvalidRecord>)> {
-    let path_buf = file_path.as_ref().to_path_buf();
-    let file_content = fs::read_to_string(&path_buf)
-        .with_context(|| format!("Failed to read deck file {}", path_buf.display()))?;
+/// True if the path ends in `.gz`.
+fn is_gzip_path(file_path: &Path) -> bool {
+    file_path
+        .extension()
+        .is_some_and(|extension| extension.to_string_lossy().eq_ignore_ascii_case("gz"))
+}
 
-    let mut cards: Vec<Card> = Vec::new();
-    let mut invalid_records: Vec<InvalidRecord> = Vec::new();
+/// Open a deck file for buffered reading, transparently decompressing `.gz` files when the
+/// `gzip` feature is on.
+fn open_deck_reader(file_path: &Path) -> std::io::Result<Box<dyn BufRead>> {
+    let deck_file = fs::File::open(file_path)?;
+    if is_gzip_path(file_path) {
+        #[cfg(feature = "gzip")]
+        return Ok(Box::new(BufReader::new(flate2::read::GzDecoder::new(
+            deck_file,
+        ))));
+        #[cfg(not(feature = "gzip"))]
+        return Err(std::io::Error::other(
+            "gzip decks require the `gzip` feature",
+        ));
+    }
+    Ok(Box::new(BufReader::new(deck_file)))
+}
+
+/// The UTF-8 encoding of U+FEFF, the byte order mark.
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Streams a deck one line at a time, so only the current line is held in memory.
+struct DeckLineReader {
+    reader: Box<dyn BufRead>,
+    line_bytes: Vec<u8>,
+    /// Whether any line read so far contained invalid UTF-8.
+    replaced_invalid_utf8: bool,
+    /// Whether the next line is the file's first, where a byte order mark may sit.
+    at_first_line: bool,
+}
 
-    for (zero_based_index, line_text) in file_content.lines().enumerate() {
-        let line_number = zero_based_index + 1;
+impl DeckLineReader {
+    fn open(file_path: &Path) -> std::io::Result<Self> {
+        Ok(Self {
+            reader: open_deck_reader(file_path)?,
+            line_bytes: Vec::new(),
+            replaced_invalid_utf8: false,
+            at_first_line: true,
+        })
+    }
 
-        // Skip metadata header lines
-        if let Some(first_char) = line_text.chars().next() {
-            if first_char == '#' {
-                continue;
+    /// The next line without its `\n` or `\r\n` terminator (as `str::lines` splits), decoded
+    /// lossily, plus whether it contained invalid UTF-8. A UTF-8 byte order mark opening the file
+    /// (as Windows tools write) is dropped, so it cannot stick to the first GUID. `None` at end of
+    /// file.
+    fn next_line(&mut self) -> std::io::Result<Option<(String, bool)>> {
+        self.line_bytes.clear();
+        if self.reader.read_until(b'\n', &mut self.line_bytes)? == 0 {
+            return Ok(None);
+        }
+        if std::mem::take(&mut self.at_first_line) && self.line_bytes.starts_with(UTF8_BOM) {
+            self.line_bytes.drain(..UTF8_BOM.len());
+        }
+        if self.line_bytes.last() == Some(&b'\n') {
+            self.line_bytes.pop();
+            if self.line_bytes.last() == Some(&b'\r') {
+                self.line_bytes.pop();
             }
-        } else {
-            // Empty line: skip
-            continue;
         }
+        Ok(Some(match String::from_utf8_lossy(&self.line_bytes) {
+            Cow::Borrowed(valid_text) => (valid_text.to_string(), false),
+            Cow::Owned(replaced_text) => {
+                self.replaced_invalid_utf8 = true;
+                (replaced_text, true)
+            }
+        }))
+    }
+}
 
-        // Expect at least 5 columns
-        let columns: Vec<&str> = line_text.split(TAB_DELIMITER).collect();
-        if columns.len() < 5 {
-            invalid_records.push(InvalidRecord {
-                file_path: path_buf.display().to_string(),
-                line_number,
-                reason: format!("Expected at least 5 columns, found {}", columns.len()),
-                raw_line: line_text.to_string(),
+/// Feeds the CSV reader one deck line at a time, blanking comment lines that start a record
+/// (not continuation lines inside a quoted field); the CSV reader skips blank lines, so line
+/// numbers are kept.
+struct UncommentedCsvSource<'a> {
+    line_reader: DeckLineReader,
+    comment_prefix: Option<&'a str>,
+    inside_quotes: bool,
+    pending_bytes: Vec<u8>,
+    pending_offset: usize,
+}
+
+impl Read for UncommentedCsvSource<'_> {
+    fn read(&mut self, output_buffer: &mut [u8]) -> std::io::Result<usize> {
+        while self.pending_offset >= self.pending_bytes.len() {
+            let Some((line_text, _)) = self.line_reader.next_line()? else {
+                return Ok(0);
+            };
+            self.pending_bytes.clear();
+            self.pending_offset = 0;
+            if self.inside_quotes || !is_comment_line(&line_text, self.comment_prefix) {
+                // A doubled "" escape toggles twice, so parity tracks quoting correctly.
+                if line_text.matches('"').count() % 2 == 1 {
+                    self.inside_quotes = !self.inside_quotes;
+                }
+                self.pending_bytes.extend_from_slice(line_text.as_bytes());
+            }
+            self.pending_bytes.push(b'\n');
+        }
+        let copy_length = output_buffer
+            .len()
+            .min(self.pending_bytes.len() - self.pending_offset);
+        output_buffer[..copy_length].copy_from_slice(
+            &self.pending_bytes[self.pending_offset..self.pending_offset + copy_length],
+        );
+        self.pending_offset += copy_length;
+        Ok(copy_length)
+    }
+}
+
+/// Validate the logical columns (guid, deck path, question, answer) of one record at the
+/// positions given by `column_layout` and build a `Card`, or return the invalid-record reason.
+fn parse_deck_columns(
+    columns: &[&str],
+    column_layout: &ColumnLayout,
+    parser_config: &ParserConfig,
+) -> std::result::Result<Card, String> {
+    // Expect every configured column to be present
+    let (last_field_name, last_column_index) = column_layout.last_field();
+    if columns.len() <= last_column_index {
+        return Err(format!(
+            "Expected at least {} columns, found {} (missing {last_field_name})",
+            last_column_index + 1,
+            columns.len()
+        ));
+    }
+
+    let guid_text = columns[column_layout.guid].trim();
+    let deck_path_text = columns[column_layout.deck_path].trim();
+    let question_html = columns[column_layout.question].trim();
+    let answer_html = columns[column_layout.answer].trim();
+
+    if guid_text.is_empty() {
+        return Err("Empty GUID".to_string());
+    }
+    if question_html.is_empty() || answer_html.is_empty() {
+        return Err("Empty question or answer".to_string());
+    }
+
+    let deck_path: DeckPath = if deck_path_text.is_empty() {
+        Vec::new()
+    } else {
+        deck_path_text
+            .split("::")
+            .map(|segment| segment.trim().to_string())
+            .collect()
+    };
+
+    let question_display = normalise_preserving_case(question_html, parser_config);
+    let question_text = if parser_config.lowercase {
+        question_display.to_lowercase()
+    } else {
+        question_display.clone()
+    };
+    let answer_raw = answer_html.to_string();
+    let metadata = extra_column_metadata(columns, parser_config);
+
+    Ok(Card {
+        guid: guid_text.to_string(),
+        question_text,
+        question_display,
+        answer_raw,
+        deck_path,
+        metadata,
+    })
+}
+
+/// The `extra_columns` values of one record, keyed by `extra_column_names` (or
+/// `column_<index>`). Columns missing from a short record are left out rather than invalidating
+/// it.
+fn extra_column_metadata(
+    columns: &[&str],
+    parser_config: &ParserConfig,
+) -> HashMap<String, String> {
+    parser_config
+        .extra_columns
+        .iter()
+        .enumerate()
+        .filter_map(|(extra_index, column_index)| {
+            let column_value = columns.get(*column_index)?.trim();
+            let metadata_key = parser_config
+                .extra_column_names
+                .get(extra_index)
+                .cloned()
+                .unwrap_or_else(|| format!("column_{column_index}"));
+            Some((metadata_key, column_value.to_string()))
+        })
+        .collect()
+}
+
+/// The prefix marking header/comment lines, or `None` when nothing should be skipped
+/// (`skip_comment_lines` off, or `comment_prefix` null or empty).
+fn active_comment_prefix(parser_config: &ParserConfig) -> Option<&str> {
+    if !parser_config.skip_comment_lines {
+        return None;
+    }
+    parser_config
+        .comment_prefix
+        .as_deref()
+        .filter(|prefix_text| !prefix_text.is_empty())
+}
+
+fn is_comment_line(line_text: &str, comment_prefix: Option<&str>) -> bool {
+    comment_prefix.is_some_and(|prefix_text| line_text.starts_with(prefix_text))
+}
+
+/// Delimiter and column positions for one Anki export, starting from the tab default and
+/// `parser_config.columns` and overridden by its header directives.
+#[derive(Debug, Clone, Copy)]
+struct DeckDirectives {
+    delimiter: char,
+    column_layout: ColumnLayout,
+    /// Set by `#separator:`; Anki then quotes fields CSV-style.
+    quoted_fields: bool,
+}
+
+/// Splits Anki lines into columns for one file. After a `#separator:` directive, fields follow
+/// CSV quoting (a quoted field may contain the delimiter, and `""` is a literal quote); otherwise,
+/// or with a non-ASCII delimiter, a line is split on every delimiter. Lines without a quote take
+/// the plain split either way.
+#[derive(Default)]
+struct DeckLineSplitter {
+    /// Reused across lines (building a reader costs far more than parsing a line), keyed by the
+    /// delimiter it was built for.
+    quoted_reader: Option<(char, csv::Reader<Cursor<Vec<u8>>>)>,
+    record: csv::StringRecord,
+}
+
+impl DeckLineSplitter {
+    fn split(&mut self, line_text: &str, deck_directives: &DeckDirectives) -> Vec<String> {
+        let delimiter = deck_directives.delimiter;
+        if !deck_directives.quoted_fields || !delimiter.is_ascii() || !line_text.contains('"') {
+            return line_text.split(delimiter).map(str::to_string).collect();
+        }
+        if !matches!(&self.quoted_reader, Some((reader_delimiter, _)) if *reader_delimiter == delimiter)
+        {
+            let csv_reader = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .delimiter(delimiter as u8)
+                .from_reader(Cursor::new(Vec::new()));
+            self.quoted_reader = Some((delimiter, csv_reader));
+        }
+        let (_, csv_reader) = self.quoted_reader.as_mut().expect("built above");
+        *csv_reader.get_mut() = Cursor::new(line_text.as_bytes().to_vec());
+        let line_parsed = csv_reader.seek(csv::Position::new()).is_ok()
+            && csv_reader.read_record(&mut self.record).unwrap_or(false);
+        if !line_parsed {
+            return line_text.split(delimiter).map(str::to_string).collect();
+        }
+        self.record.iter().map(str::to_string).collect()
+    }
+}
+
+/// Apply one `#key:value` header line. `#separator:` takes `tab`, `comma`, `semicolon`,
+/// `space`, `pipe`, `colon`, or a single literal character; `#columns:` lists column names in
+/// the current delimiter, and `guid`, `deck`, `front`/`question` and `back`/`answer` (any case)
+/// move those fields. Fields a `#columns:` header leaves unnamed follow its listed columns in
+/// guid, deck, question, answer order, so they never reuse a named column. Anything else
+/// (e.g. `#html:`) is ignored.
+fn apply_header_directive(
+    line_text: &str,
+    deck_directives: &mut DeckDirectives,
+    line_splitter: &mut DeckLineSplitter,
+) {
+    let Some((directive_key, directive_value)) = line_text
+        .strip_prefix('#')
+        .and_then(|directive_text| directive_text.split_once(':'))
+    else {
+        return;
+    };
+    match directive_key.trim().to_lowercase().as_str() {
+        "separator" => {
+            let delimiter = match directive_value.trim().to_lowercase().as_str() {
+                "tab" => Some(TAB_DELIMITER),
+                "comma" => Some(','),
+                "semicolon" => Some(';'),
+                "space" => Some(' '),
+                "pipe" => Some('|'),
+                "colon" => Some(':'),
+                _ => {
+                    let mut value_characters = directive_value.chars();
+                    match (value_characters.next(), value_characters.next()) {
+                        (Some(literal_character), None) => Some(literal_character),
+                        _ => None,
+                    }
+                }
+            };
+            if let Some(delimiter) = delimiter {
+                deck_directives.delimiter = delimiter;
+                deck_directives.quoted_fields = true;
+            }
+        }
+        "columns" => {
+            let column_names = line_splitter.split(directive_value, deck_directives);
+            // guid, deck path, question, answer
+            let mut named_columns: [Option<usize>; 4] = [None; 4];
+            for (column_index, column_name) in column_names.iter().enumerate() {
+                let field_position = match column_name.trim().to_lowercase().as_str() {
+                    "guid" => 0,
+                    "deck" | "deck_path" => 1,
+                    "front" | "question" => 2,
+                    "back" | "answer" => 3,
+                    _ => continue,
+                };
+                named_columns[field_position] = Some(column_index);
+            }
+            if named_columns.iter().all(Option::is_none) {
+                return;
+            }
+            let mut next_unlisted_column = column_names.len();
+            let [guid, deck_path, question, answer] = named_columns.map(|named_column| {
+                named_column.unwrap_or_else(|| {
+                    next_unlisted_column += 1;
+                    next_unlisted_column - 1
+                })
             });
-            continue;
+            deck_directives.column_layout = ColumnLayout {
+                guid,
+                deck_path,
+                question,
+                answer,
+            };
         }
+        _ => {}
+    }
+}
+
+/// Read a single deck file (optionally `.gz`) into `Card`s, collecting invalid records.
+/// The file is streamed line by line rather than read into memory whole.
+/// Lines with invalid UTF-8 still load, with the bad bytes replaced and the line recorded.
+/// `deck_format` selects Anki's raw tab-separated export or quoted CSV; `columns` gives the
+/// position of each logical field. Lines starting with `comment_prefix` are skipped unless
+/// `skip_comment_lines` is off. In Anki exports, skipped lines before the first record are read
+/// as header directives: `#separator:` and `#columns:` override the delimiter (then honouring
+/// CSV quoting) and column positions for the rest of that file.
+pub fn read_deck_file<P: AsRef<Path>>(
+    file_path: P,
+    parser_config: &ParserConfig,
+) -> Result<(Vec<Card>, Vec<InvalidRecord>), ChatbotError> {
+    let path_buf = file_path.as_ref().to_path_buf();
+    let read_error = |error: std::io::Error| ChatbotError::DeckRead {
+        path: path_buf.clone(),
+        reason: error.to_string(),
+    };
+    let line_reader = DeckLineReader::open(&path_buf).map_err(read_error)?;
 
-        let guid_text = columns[0].trim();
-        let deck_path_text = columns[2].trim();
-        let question_html = columns[3].trim();
-        let answer_html = columns[4].trim();
+    let mut cards: Vec<Card> = Vec::new();
+    let mut invalid_records: Vec<InvalidRecord> = Vec::new();
 
-        if guid_text.is_empty() {
+    let mut push_record = |line_number: usize,
+                           columns: &[&str],
+                           column_layout: &ColumnLayout,
+                           raw_line: String,
+                           replaced_invalid_utf8: bool| {
+        // The card is still loaded; the record only flags the damaged line.
+        if replaced_invalid_utf8 && raw_line.contains(char::REPLACEMENT_CHARACTER) {
             invalid_records.push(InvalidRecord {
                 file_path: path_buf.display().to_string(),
                 line_number,
-                reason: "Empty GUID".to_string(),
-                raw_line: line_text.to_string(),
+                reason: "Replaced invalid UTF-8".to_string(),
+                raw_line: raw_line.clone(),
             });
-            continue;
         }
-        if question_html.is_empty() || answer_html.is_empty() {
-            invalid_records.push(InvalidRecord {
+        match parse_deck_columns(columns, column_layout, parser_config) {
+            Ok(card) => cards.push(card),
+            Err(reason) => invalid_records.push(InvalidRecord {
                 file_path: path_buf.display().to_string(),
                 line_number,
-                reason: "Empty question or answer".to_string(),
-                raw_line: line_text.to_string(),
-            });
-            continue;
+                reason,
+                raw_line,
+            }),
         }
+    };
 
-        let deck_path: DeckPath = if deck_path_text.is_empty() {
-            Vec::new()
-        } else {
-            deck_path_text
-                .split("::")
-                .map(|segment| segment.trim().to_string())
-                .collect()
-        };
-
-        let question_text = normalise_for_matching(question_html);
-        let answer_raw = answer_html.to_string();
+    let comment_prefix = active_comment_prefix(parser_config);
+    match parser_config.deck_format {
+        DeckFormat::AnkiTsv => {
+            let mut line_reader = line_reader;
+            let mut line_number = 0;
+            let mut deck_directives = DeckDirectives {
+                delimiter: TAB_DELIMITER,
+                column_layout: parser_config.columns,
+                quoted_fields: false,
+            };
+            let mut line_splitter = DeckLineSplitter::default();
+            let mut seen_record = false;
+            while let Some((line_text, line_had_invalid_utf8)) =
+                line_reader.next_line().map_err(read_error)?
+            {
+                line_number += 1;
 
-        let card = Card {
-            guid: guid_text.to_string(),
-            question_text,
-            answer_raw,
-            deck_path,
-        };
+                // Skip empty lines and metadata header lines
+                if line_text.is_empty() {
+                    continue;
+                }
+                if is_comment_line(&line_text, comment_prefix) {
+                    if !seen_record {
+                        apply_header_directive(
+                            &line_text,
+                            &mut deck_directives,
+                            &mut line_splitter,
+                        );
+                    }
+                    continue;
+                }
 
-        cards.push(card);
+                seen_record = true;
+                let column_texts = line_splitter.split(&line_text, &deck_directives);
+                let columns: Vec<&str> = column_texts.iter().map(String::as_str).collect();
+                push_record(
+                    line_number,
+                    &columns,
+                    &deck_directives.column_layout,
+                    line_text.clone(),
+                    line_had_invalid_utf8,
+                );
+            }
+        }
+        DeckFormat::Csv => {
+            // Quoted fields may contain commas and newlines, so the CSV reader sees the
+            // stream with comment lines already blanked.
+            let mut csv_reader = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .from_reader(UncommentedCsvSource {
+                    line_reader,
+                    comment_prefix,
+                    inside_quotes: false,
+                    pending_bytes: Vec::new(),
+                    pending_offset: 0,
+                });
+            let mut record = csv::StringRecord::new();
+            while csv_reader
+                .read_record(&mut record)
+                .map_err(|error| ChatbotError::DeckRead {
+                    path: path_buf.clone(),
+                    reason: format!("malformed CSV: {error}"),
+                })?
+            {
+                let line_number = record
+                    .position()
+                    .map_or(0, |position| position.line() as usize);
+                let columns: Vec<&str> = record.iter().collect();
+                // The reader runs ahead of the current record, so this is per file so far.
+                let replaced_invalid_utf8 = csv_reader.get_ref().line_reader.replaced_invalid_utf8;
+                push_record(
+                    line_number,
+                    &columns,
+                    &parser_config.columns,
+                    columns.join(","),
+                    replaced_invalid_utf8,
+                );
+            }
+        }
     }
 
     Ok((cards, invalid_records))
 }
 
+/// Keep the first card for each GUID; also return the original positions and GUIDs of the
+/// dropped duplicates.
+fn split_duplicate_cards(cards: Vec<Card>) -> (Vec<Card>, Vec<(usize, String)>) {
+    let mut seen_guids: HashSet<String> = HashSet::with_capacity(cards.len());
+    let mut kept_cards: Vec<Card> = Vec::with_capacity(cards.len());
+    let mut duplicate_positions: Vec<(usize, String)> = Vec::new();
+    for (card_position, card) in cards.into_iter().enumerate() {
+        if seen_guids.insert(card.guid.clone()) {
+            kept_cards.push(card);
+        } else {
+            duplicate_positions.push((card_position, card.guid));
+        }
+    }
+    (kept_cards, duplicate_positions)
+}
+
 /// Load a directory (or single file) of decks and concatenate results.
-pub fn load_decks<P: AsRef<Path>>(data_path: P) -> Result<(Vec<Card>, Vec<InvalidRecord>)> {
+/// A GUID seen in an earlier file (or earlier in the same file) is dropped and logged.
+pub fn load_decks<P: AsRef<Path>>(
+    data_path: P,
+    parser_config: &ParserConfig,
+) -> Result<(Vec<Card>, Vec<InvalidRecord>), ChatbotError> {
+    let files = list_deck_files(&data_path, parser_config.deck_format)?;
+    load_deck_files(files, parser_config)
+}
+
+/// As `load_decks`, over several directories (or files) in order. Invalid records keep the file
+/// they came from, and a GUID already loaded from an earlier path is dropped as a duplicate.
+pub fn load_decks_multi<P: AsRef<Path>>(
+    data_paths: &[P],
+    parser_config: &ParserConfig,
+) -> Result<(Vec<Card>, Vec<InvalidRecord>), ChatbotError> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    for data_path in data_paths {
+        files.extend(list_deck_files(data_path, parser_config.deck_format)?);
+    }
+    load_deck_files(files, parser_config)
+}
+
+/// Read each deck file in order, then drop repeated GUIDs (logging them against their file).
+fn load_deck_files(
+    files: Vec<PathBuf>,
+    parser_config: &ParserConfig,
+) -> Result<(Vec<Card>, Vec<InvalidRecord>), ChatbotError> {
     let mut all_cards: Vec<Card> = Vec::new();
     let mut all_invalid_records: Vec<InvalidRecord> = Vec::new();
+    // Source file of each entry in `all_cards`, for duplicate records.
+    let mut card_file_paths: Vec<String> = Vec::new();
 
-    let files = list_deck_files(&data_path)?;
     if files.is_empty() {
         return Ok((all_cards, all_invalid_records));
     }
 
     for file_path in files {
-        match read_deck_file(&file_path) {
+        match read_deck_file(&file_path, parser_config) {
             Ok((mut cards, mut invalids)) => {
+                let file_path_text = file_path.display().to_string();
+                card_file_paths.extend(std::iter::repeat_n(file_path_text, cards.len()));
                 all_cards.append(&mut cards);
                 all_invalid_records.append(&mut invalids);
             }
@@ -142,5 +581,490 @@ pub fn load_decks<P: AsRef<Path>>(data_path: P) -> Result<(Vec<Card>, Vec<Invali
         }
     }
 
-    Ok((all_cards, all_invalid_records))
+    let (deduplicated_cards, duplicate_positions) = split_duplicate_cards(all_cards);
+    for (card_position, guid) in duplicate_positions {
+        all_invalid_records.push(InvalidRecord {
+            file_path: card_file_paths[card_position].clone(),
+            line_number: 0,
+            reason: "Duplicate GUID".to_string(),
+            raw_line: guid,
+        });
+    }
+
+    Ok((deduplicated_cards, all_invalid_records))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ColumnLayout;
+
+    const SAMPLE_DECK: &str = "#separator:tab\n#html:false\n\
+g1\tBasic\tDeck::Unit 01\tWhat is computing?\tProcessing information.\n\
+g2\tBasic\tDeck::Unit 02\tWhat is a gate?\n";
+
+    /// Write `content` to a fresh file under the system temp directory.
+    fn write_fixture(file_name: &str, content: &[u8]) -> PathBuf {
+        let fixture_directory =
+            std::env::temp_dir().join(format!("rulebot_io_decks_{}", std::process::id()));
+        fs::create_dir_all(&fixture_directory).expect("create fixture directory");
+        let fixture_path = fixture_directory.join(file_name);
+        fs::write(&fixture_path, content).expect("write fixture");
+        fixture_path
+    }
+
+    #[test]
+    fn test_read_deck_file_plain() {
+        let fixture_path = write_fixture("plain.txt", SAMPLE_DECK.as_bytes());
+        let (cards, invalid_records) =
+            read_deck_file(&fixture_path, &ParserConfig::default()).expect("readable");
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].guid, "g1");
+        assert_eq!(
+            cards[0].deck_path,
+            vec!["Deck".to_string(), "Unit 01".to_string()]
+        );
+        assert_eq!(invalid_records.len(), 1);
+        assert_eq!(invalid_records[0].line_number, 4);
+    }
+
+    #[test]
+    fn test_extra_columns_captured_as_metadata() {
+        let fixture_path = write_fixture(
+            "tagged.txt",
+            b"g1\tBasic\tDeck\tWhat is a bit?\tA binary digit.\tdata::basics\n\
+g2\tBasic\tDeck\tWhat is a byte?\tEight bits.\n",
+        );
+        let parser_config = ParserConfig {
+            extra_columns: vec![5, 6],
+            extra_column_names: vec!["tags".to_string()],
+            ..ParserConfig::default()
+        };
+        let (cards, invalid_records) =
+            read_deck_file(&fixture_path, &parser_config).expect("readable");
+        assert!(invalid_records.is_empty());
+        assert_eq!(
+            cards[0].metadata,
+            HashMap::from([("tags".to_string(), "data::basics".to_string())])
+        );
+        // A record without the extra columns still loads, with no metadata.
+        assert!(cards[1].metadata.is_empty());
+
+        let tagged_json = serde_json::to_value(&cards[0]).expect("serialises");
+        assert_eq!(tagged_json["metadata"]["tags"], "data::basics");
+        let untagged_json = serde_json::to_value(&cards[1]).expect("serialises");
+        assert!(untagged_json.get("metadata").is_none());
+    }
+
+    #[test]
+    fn test_load_decks_invalid_records_grouped_by_reason() {
+        use crate::data_model::count_invalid_records_by_reason;
+
+        let deck_directory =
+            std::env::temp_dir().join(format!("rulebot_io_decks_validate_{}", std::process::id()));
+        fs::create_dir_all(&deck_directory).expect("create deck directory");
+        fs::write(
+            deck_directory.join("first.txt"),
+            "#separator:tab\n\
+g1\tBasic\tDeck\tWhat is a bit?\tA binary digit.\n\
+\tBasic\tDeck\tNo GUID?\tDropped.\n\
+g2\tBasic\tDeck\tNo answer?\t\n\
+g3\tBasic\tDeck\tWhat is a byte?\tEight bits.\n",
+        )
+        .expect("write first deck");
+        fs::write(
+            deck_directory.join("second.txt"),
+            "g1\tBasic\tDeck\tWhat is a bit?\tA binary digit.\n\
+\tBasic\tDeck\tAlso no GUID?\tDropped.\n",
+        )
+        .expect("write second deck");
+
+        let (cards, invalid_records) =
+            load_decks(&deck_directory, &ParserConfig::default()).expect("loads");
+        assert_eq!(cards.len(), 2);
+        let reason_counts = count_invalid_records_by_reason(&invalid_records);
+        assert_eq!(
+            reason_counts.into_iter().collect::<Vec<(String, usize)>>(),
+            vec![
+                ("Duplicate GUID".to_string(), 1),
+                ("Empty GUID".to_string(), 2),
+                ("Empty question or answer".to_string(), 1),
+            ]
+        );
+        let _ = fs::remove_dir_all(&deck_directory);
+    }
+
+    #[test]
+    fn test_leading_byte_order_mark_is_dropped() {
+        let fixture_path = write_fixture(
+            "byte_order_mark.txt",
+            b"\xEF\xBB\xBFg1\tBasic\tDeck\tWhat is a BOM?\tA marker.\n\
+              g2\tBasic\tDeck\tWhat is \xEF\xBB\xBF?\tOnly leading marks go.\n",
+        );
+        let (cards, invalid_records) =
+            read_deck_file(&fixture_path, &ParserConfig::default()).expect("readable");
+        assert!(invalid_records.is_empty());
+        assert_eq!(cards[0].guid, "g1");
+        assert!(cards[1].question_text.contains('\u{FEFF}'));
+    }
+
+    #[test]
+    fn test_question_display_keeps_case_while_matching_lowercase() {
+        use crate::scoring::keyword::{prepare_keyword_index, score_keyword_overlap};
+        use crate::scoring::tfidf::{build_tfidf_index, score_tfidf};
+
+        let fixture_path = write_fixture(
+            "display_case.txt",
+            b"g1\tBasic\tDeck\tWhat is <b>CPU</b>?\tThe processor.\n",
+        );
+        let parser_config = ParserConfig::default();
+        let (cards, _) = read_deck_file(&fixture_path, &parser_config).expect("readable");
+        assert_eq!(cards[0].question_display, "What is CPU?");
+        assert_eq!(cards[0].question_text, "what is cpu?");
+
+        let stopword_set = HashSet::new();
+        let keyword_index = prepare_keyword_index(&cards, &stopword_set, &parser_config);
+        let keyword_hits = score_keyword_overlap(
+            "cpu",
+            &keyword_index,
+            &stopword_set,
+            &parser_config,
+            1,
+            0.0,
+            1,
+        );
+        let tfidf_index = build_tfidf_index(&cards, &stopword_set, &parser_config);
+        let tfidf_hits = score_tfidf("cpu", &tfidf_index, &stopword_set, &parser_config, 1, 0.0);
+        for answer_hits in [keyword_hits, tfidf_hits] {
+            assert_eq!(answer_hits.len(), 1);
+            assert_eq!(
+                answer_hits[0].question_preview.as_deref(),
+                Some("What is CPU?")
+            );
+        }
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_read_deck_file_gzip_matches_plain() {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(SAMPLE_DECK.as_bytes()).expect("compress");
+        let gzip_path = write_fixture("compressed.txt.gz", &encoder.finish().expect("finish"));
+        let plain_path = write_fixture("uncompressed.txt", SAMPLE_DECK.as_bytes());
+
+        let (gzip_cards, gzip_invalid) =
+            read_deck_file(&gzip_path, &ParserConfig::default()).expect("readable");
+        let (plain_cards, plain_invalid) =
+            read_deck_file(&plain_path, &ParserConfig::default()).expect("readable");
+        assert_eq!(gzip_cards.len(), plain_cards.len());
+        assert_eq!(gzip_cards[0].guid, plain_cards[0].guid);
+        assert_eq!(gzip_cards[0].question_text, plain_cards[0].question_text);
+        assert_eq!(gzip_invalid.len(), plain_invalid.len());
+        assert_eq!(gzip_invalid[0].reason, plain_invalid[0].reason);
+    }
+
+    #[test]
+    fn test_read_deck_file_csv_keeps_quoted_commas() {
+        let csv_deck = "#separator:comma\n\
+g1,Basic,Deck::Unit 01,\"What are sets, maps and lists?\",\"Collections, mostly.\"\n\
+,Basic,Deck::Unit 01,Question?,Answer.\n\
+g3,Basic,Deck::Unit 01,\"Only four, columns\"\n";
+        let fixture_path = write_fixture("quoted.csv", csv_deck.as_bytes());
+        let csv_config = ParserConfig {
+            deck_format: DeckFormat::Csv,
+            ..ParserConfig::default()
+        };
+        let (cards, invalid_records) =
+            read_deck_file(&fixture_path, &csv_config).expect("readable");
+
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].guid, "g1");
+        assert_eq!(cards[0].answer_raw, "Collections, mostly.");
+        assert!(cards[0].question_text.contains("sets"));
+        assert!(cards[0].question_text.contains("lists"));
+        let reasons: Vec<&str> = invalid_records
+            .iter()
+            .map(|record| record.reason.as_str())
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                "Empty GUID",
+                "Expected at least 5 columns, found 4 (missing answer)"
+            ]
+        );
+        assert_eq!(invalid_records[0].line_number, 3);
+    }
+
+    #[test]
+    fn test_invalid_utf8_only_affects_its_line() {
+        let mut deck_bytes = SAMPLE_DECK
+            .replace("What is a gate?\n", "What is a gate?\tGates.\n")
+            .into_bytes();
+        let stray_position = deck_bytes
+            .windows(b"computing".len())
+            .position(|window| window == b"computing")
+            .expect("fixture text");
+        deck_bytes.insert(stray_position, 0xFF);
+        let fixture_path = write_fixture("stray_byte.txt", &deck_bytes);
+
+        let (cards, invalid_records) = read_deck_file(&fixture_path, &ParserConfig::default())
+            .expect("readable despite bad byte");
+        assert_eq!(cards.len(), 2);
+        assert!(cards[0].question_text.contains('\u{FFFD}'));
+        assert_eq!(cards[1].question_text, "what is a gate?");
+        assert_eq!(invalid_records.len(), 1);
+        assert_eq!(invalid_records[0].reason, "Replaced invalid UTF-8");
+        assert_eq!(invalid_records[0].line_number, 3);
+    }
+
+    #[test]
+    fn test_read_deck_file_with_reordered_columns() {
+        let reordered_deck = "#separator:tab\n\
+What is computing?\tProcessing information.\tg1\tDeck::Unit 01\n\
+What is a gate?\tA logic element.\t\tDeck::Unit 02\n\
+Too short\n";
+        let fixture_path = write_fixture("reordered.txt", reordered_deck.as_bytes());
+        let reordered_config = ParserConfig {
+            columns: ColumnLayout {
+                question: 0,
+                answer: 1,
+                guid: 2,
+                deck_path: 3,
+            },
+            ..ParserConfig::default()
+        };
+
+        let (cards, invalid_records) =
+            read_deck_file(&fixture_path, &reordered_config).expect("readable");
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].guid, "g1");
+        assert_eq!(cards[0].answer_raw, "Processing information.");
+        assert_eq!(
+            cards[0].deck_path,
+            vec!["Deck".to_string(), "Unit 01".to_string()]
+        );
+        let reasons: Vec<&str> = invalid_records
+            .iter()
+            .map(|record| record.reason.as_str())
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                "Empty GUID",
+                "Expected at least 4 columns, found 1 (missing deck_path)"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_comment_prefix_controls_skipped_lines() {
+        // Question-first layout, so a question starting with '#' begins the line.
+        let hash_question_deck = "#separator:tab\n#html:false\n\
+#include guards?\tPrevent double inclusion.\tg1\tDeck\n\
+What is a macro?\tA text substitution.\tg2\tDeck\n";
+        let fixture_path = write_fixture("hash_question.txt", hash_question_deck.as_bytes());
+        let question_first_config = ParserConfig {
+            columns: ColumnLayout {
+                question: 0,
+                answer: 1,
+                guid: 2,
+                deck_path: 3,
+            },
+            ..ParserConfig::default()
+        };
+        let load_guids = |parser_config: &ParserConfig| -> (Vec<String>, usize) {
+            let (cards, invalid_records) =
+                read_deck_file(&fixture_path, parser_config).expect("readable");
+            (
+                cards.into_iter().map(|card| card.guid).collect(),
+                invalid_records.len(),
+            )
+        };
+
+        // Default '#' prefix drops the legitimate card along with the headers.
+        assert_eq!(
+            load_guids(&question_first_config),
+            (vec!["g2".to_string()], 0)
+        );
+
+        // Skipping disabled: the card loads; the header lines become invalid records.
+        let no_skip_config = ParserConfig {
+            skip_comment_lines: false,
+            ..question_first_config.clone()
+        };
+        assert_eq!(
+            load_guids(&no_skip_config),
+            (vec!["g1".to_string(), "g2".to_string()], 2)
+        );
+
+        // An exact header prefix skips only the lines that match it.
+        let exact_prefix_config = ParserConfig {
+            comment_prefix: Some("#separator:".to_string()),
+            ..question_first_config.clone()
+        };
+        assert_eq!(
+            load_guids(&exact_prefix_config),
+            (vec!["g1".to_string(), "g2".to_string()], 1)
+        );
+    }
+
+    #[test]
+    fn test_header_directives_set_separator_and_columns() {
+        let comma_deck = "#separator:comma\n#html:false\n\
+g1,Basic,Deck::Unit 01,What is computing?,Processing information.\n\
+g2,Basic,Deck::Unit 02,What is a gate?,A logic element.\n";
+        let fixture_path = write_fixture("comma_directive.txt", comma_deck.as_bytes());
+        let (cards, invalid_records) =
+            read_deck_file(&fixture_path, &ParserConfig::default()).expect("readable");
+        assert!(invalid_records.is_empty());
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].guid, "g1");
+        assert_eq!(
+            cards[0].deck_path,
+            vec!["Deck".to_string(), "Unit 01".to_string()]
+        );
+        assert_eq!(cards[1].answer_raw, "A logic element.");
+
+        let columns_deck = "#separator:;\n#columns:Front;Back;Deck;GUID\n\
+What is a gate?;A logic element.;Deck;g3\n";
+        let fixture_path = write_fixture("columns_directive.txt", columns_deck.as_bytes());
+        let (cards, invalid_records) =
+            read_deck_file(&fixture_path, &ParserConfig::default()).expect("readable");
+        assert!(invalid_records.is_empty());
+        assert_eq!(cards[0].guid, "g3");
+        assert_eq!(cards[0].question_display, "What is a gate?");
+        assert_eq!(cards[0].deck_path, vec!["Deck".to_string()]);
+    }
+
+    #[test]
+    fn test_separator_directive_keeps_quoted_delimiters() {
+        // GUID,Front,Back names three columns, so the unnamed deck path is read from a fourth.
+        let quoted_deck = "#separator:comma\n#columns:GUID,Front,Back\n\
+g1,\"What is a gate, briefly?\",\"A \"\"logic\"\" element.\",Deck::Unit 02\n\
+g2,What is a bit?,A binary digit.\n";
+        let fixture_path = write_fixture("quoted_directive.txt", quoted_deck.as_bytes());
+        let (cards, invalid_records) =
+            read_deck_file(&fixture_path, &ParserConfig::default()).expect("readable");
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].question_display, "What is a gate, briefly?");
+        assert_eq!(cards[0].answer_raw, "A \"logic\" element.");
+        assert_eq!(
+            cards[0].deck_path,
+            vec!["Deck".to_string(), "Unit 02".to_string()]
+        );
+        assert_eq!(invalid_records.len(), 1);
+        assert!(invalid_records[0].reason.contains("missing deck_path"));
+    }
+
+    #[test]
+    fn test_csv_comment_prefix_ignores_quoted_continuation_lines() {
+        let csv_deck = "#separator:comma\n\
+g1,Basic,Deck,\"Multi-line\n#not a comment\",Answer.\n";
+        let fixture_path = write_fixture("continuation.csv", csv_deck.as_bytes());
+        let csv_config = ParserConfig {
+            deck_format: DeckFormat::Csv,
+            ..ParserConfig::default()
+        };
+        let (cards, invalid_records) =
+            read_deck_file(&fixture_path, &csv_config).expect("readable");
+        assert!(invalid_records.is_empty());
+        assert_eq!(cards.len(), 1);
+        assert!(cards[0].question_text.contains("#not a comment"));
+    }
+
+    #[test]
+    fn test_read_deck_file_streams_large_deck() {
+        const LINE_COUNT: usize = 100_000;
+        let fixture_directory =
+            std::env::temp_dir().join(format!("rulebot_io_decks_large_{}", std::process::id()));
+        fs::create_dir_all(&fixture_directory).expect("create fixture directory");
+        let fixture_path = fixture_directory.join("large.txt");
+        {
+            use std::io::Write;
+
+            let mut deck_writer =
+                std::io::BufWriter::new(fs::File::create(&fixture_path).expect("create fixture"));
+            writeln!(deck_writer, "#separator:tab").expect("write header");
+            for card_number in 0..LINE_COUNT {
+                writeln!(
+                    deck_writer,
+                    "g{card_number}\tBasic\tDeck::Unit {}\tQuestion number {card_number}?\tAnswer {card_number}.",
+                    card_number % 10
+                )
+                .expect("write line");
+            }
+        }
+
+        let (cards, invalid_records) =
+            read_deck_file(&fixture_path, &ParserConfig::default()).expect("readable");
+        assert_eq!(cards.len(), LINE_COUNT);
+        assert!(invalid_records.is_empty());
+        assert_eq!(cards[LINE_COUNT - 1].guid, format!("g{}", LINE_COUNT - 1));
+        fs::remove_dir_all(&fixture_directory).expect("remove fixture");
+    }
+
+    #[test]
+    fn test_load_decks_drops_duplicate_guids_across_files() {
+        let deck_directory = std::env::temp_dir().join(format!(
+            "rulebot_io_decks_duplicates_{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&deck_directory).expect("create deck directory");
+        fs::write(
+            deck_directory.join("a.txt"),
+            "g1\tBasic\tDeck\tWhat is a bit?\tA binary digit.\n",
+        )
+        .expect("write first deck");
+        fs::write(
+            deck_directory.join("b.txt"),
+            "g1\tBasic\tDeck\tWhat is a bit?\tA binary digit.\n\
+g2\tBasic\tDeck\tWhat is a byte?\tEight bits.\n",
+        )
+        .expect("write second deck");
+
+        let (cards, invalid_records) =
+            load_decks(&deck_directory, &ParserConfig::default()).expect("loadable");
+        let guids: Vec<&str> = cards.iter().map(|card| card.guid.as_str()).collect();
+        assert_eq!(guids, vec!["g1", "g2"]);
+        assert_eq!(invalid_records.len(), 1);
+        assert_eq!(invalid_records[0].reason, "Duplicate GUID");
+        assert!(invalid_records[0].file_path.ends_with("b.txt"));
+    }
+
+    #[test]
+    fn test_load_decks_multi_combines_directories() {
+        let fixture_root =
+            std::env::temp_dir().join(format!("rulebot_io_decks_multi_{}", std::process::id()));
+        let first_directory = fixture_root.join("first");
+        let second_directory = fixture_root.join("second");
+        fs::create_dir_all(&first_directory).expect("create first directory");
+        fs::create_dir_all(&second_directory).expect("create second directory");
+        fs::write(
+            first_directory.join("a.txt"),
+            "g1\tBasic\tDeck\tWhat is a bit?\tA binary digit.\n\
+g2\tBasic\tDeck\tWhat is a byte?\tEight bits.\n",
+        )
+        .expect("write first deck");
+        fs::write(
+            second_directory.join("a.txt"),
+            "g3\tBasic\tDeck\tWhat is a nibble?\tFour bits.\n\
+\tBasic\tDeck\tNo GUID?\tDropped.\n",
+        )
+        .expect("write second deck");
+
+        let (cards, invalid_records) = load_decks_multi(
+            &[&first_directory, &second_directory],
+            &ParserConfig::default(),
+        )
+        .expect("loads");
+        let guids: Vec<&str> = cards.iter().map(|card| card.guid.as_str()).collect();
+        assert_eq!(guids, vec!["g1", "g2", "g3"]);
+        // The invalid record points at the second directory's file, not the first's same name.
+        assert_eq!(invalid_records.len(), 1);
+        assert!(invalid_records[0].file_path.contains("second"));
+        let _ = fs::remove_dir_all(&fixture_root);
+    }
 }
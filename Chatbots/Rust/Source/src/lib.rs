@@ -0,0 +1,23 @@
+pub mod compare;
+pub mod config;
+pub mod data_model;
+pub mod engine;
+pub mod error;
+pub mod evaluate;
+pub mod io_decks;
+pub mod logging_io;
+pub mod lru_cache;
+pub mod memory;
+pub mod normalise;
+pub mod query_cache;
+pub mod query_parser;
+pub mod result_cache;
+pub mod scoring;
+pub mod snippet;
+pub mod spelling;
+pub mod timing;
+pub mod tokenise;
+pub mod topics;
+
+pub use engine::{Algorithm, ChatbotEngine};
+pub use error::ChatbotError;
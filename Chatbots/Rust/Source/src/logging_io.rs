@@ -1,4 +1,4 @@
-use std::fs::{create_dir_all, OpenOptions};
+use std::fs::{self, OpenOptions, create_dir_all};
 use std::io::{BufWriter, Write};
 use std::path::Path;
 
@@ -8,16 +8,17 @@ use serde_json::to_string;
 use crate::data_model::{InvalidRecord, LogRecord};
 
 /// Ensure the parent directory of `file_path` exists. No-op if it already exists.
-fn ensure_parent_directory_exists(file_path: &str) -> Result<()> {
-    if let Some(parent_directory) = Path::new(file_path).parent() {
-        if !parent_directory.as_os_str().is_empty() && !parent_directory.exists() {
-            create_dir_all(parent_directory).with_context(|| {
-                format!(
-                    "Failed to create parent directory for log file: {}",
-                    parent_directory.display()
-                )
-            })?;
-        }
+pub fn ensure_parent_directory_exists(file_path: &str) -> Result<()> {
+    if let Some(parent_directory) = Path::new(file_path).parent()
+        && !parent_directory.as_os_str().is_empty()
+        && !parent_directory.exists()
+    {
+        create_dir_all(parent_directory).with_context(|| {
+            format!(
+                "Failed to create parent directory for file: {}",
+                parent_directory.display()
+            )
+        })?;
     }
     Ok(())
 }
@@ -61,9 +62,205 @@ pub fn log_invalid_records(invalid_records: &[InvalidRecord], file_path: &str) -
     Ok(())
 }
 
+/// Write invalid-record entries in JSON Lines format, one
+/// `{"file_path", "line_number", "reason", "raw_line"}` object per record.
+pub fn log_invalid_records_json(invalid_records: &[InvalidRecord], file_path: &str) -> Result<()> {
+    for invalid_record in invalid_records {
+        let json_text = to_string(invalid_record)
+            .with_context(|| "Failed to serialise invalid record to JSON")?;
+        append_text_line(file_path, &json_text)?;
+    }
+    Ok(())
+}
+
 /// Append a single benchmark record in JSON Lines format.
 pub fn log_benchmark(record: &LogRecord, file_path: &str) -> Result<()> {
     let json_text =
         to_string(record).with_context(|| "Failed to serialise benchmark record to JSON")?;
     append_text_line(file_path, &json_text)
 }
+
+/// Column names of the CSV benchmark log, in row order.
+const BENCHMARK_CSV_HEADER: [&str; 14] = [
+    "ts",
+    "lang",
+    "algo",
+    "deck_size",
+    "topic",
+    "query_id",
+    "wall_ms",
+    "parse_ms",
+    "index_ms",
+    "preproc_ms",
+    "rank_ms",
+    "format_ms",
+    "top",
+    "timed_out",
+];
+
+/// Append a single benchmark record as a CSV row, writing the header first if the file is new
+/// or empty. `top` is flattened to `guid:score` pairs joined with `;`.
+pub fn log_benchmark_csv(record: &LogRecord, file_path: &str) -> Result<()> {
+    // Checked before the append-mode open below creates the file.
+    let needs_header = fs::metadata(file_path).map_or(true, |metadata| metadata.len() == 0);
+
+    let stage_timings = &record.stage_milliseconds;
+    let top_text = record
+        .top
+        .iter()
+        .map(|top_entry| format!("{}:{}", top_entry.guid, top_entry.score))
+        .collect::<Vec<String>>()
+        .join(";");
+    let row_fields: [String; 14] = [
+        record.timestamp_iso.clone(),
+        record.language.clone(),
+        record.algorithm.clone(),
+        record.deck_size.to_string(),
+        record.topic.clone(),
+        record.query_id.clone(),
+        record.wall_milliseconds.to_string(),
+        stage_timings.parse_milliseconds.to_string(),
+        stage_timings.index_milliseconds.to_string(),
+        stage_timings.preprocess_milliseconds.to_string(),
+        stage_timings.rank_milliseconds.to_string(),
+        stage_timings.format_milliseconds.to_string(),
+        top_text,
+        record.timed_out.to_string(),
+    ];
+
+    // The csv writer handles quoting (topics and GUIDs may contain commas or quotes).
+    let mut csv_writer = csv::WriterBuilder::new()
+        .terminator(csv::Terminator::Any(b'\n'))
+        .from_writer(Vec::new());
+    if needs_header {
+        csv_writer
+            .write_record(BENCHMARK_CSV_HEADER)
+            .with_context(|| "Failed to serialise CSV header")?;
+    }
+    csv_writer
+        .write_record(&row_fields)
+        .with_context(|| "Failed to serialise benchmark record to CSV")?;
+    let csv_bytes = csv_writer
+        .into_inner()
+        .map_err(|error| anyhow::anyhow!("Failed to flush CSV row: {error}"))?;
+    let csv_text = String::from_utf8(csv_bytes).with_context(|| "CSV row is not valid UTF-8")?;
+    append_text_line(file_path, csv_text.trim_end_matches('\n'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_model::{StageTimings, TopEntry, make_hit, top_entries_from_hits};
+
+    fn sample_record(query_id: &str) -> LogRecord {
+        LogRecord {
+            timestamp_iso: "2024-01-01T00:00:00+00:00".to_string(),
+            language: "rust".to_string(),
+            algorithm: "tfidf".to_string(),
+            deck_size: 3,
+            topic: "Deck::Unit 01, Part A".to_string(),
+            query_id: query_id.to_string(),
+            query_text: "what is big data".to_string(),
+            stage_milliseconds: StageTimings {
+                parse_milliseconds: 1.0,
+                index_milliseconds: 2.0,
+                preprocess_milliseconds: 0.5,
+                rank_milliseconds: 3.0,
+                format_milliseconds: 0.25,
+            },
+            wall_milliseconds: 4.0,
+            rss_kilobytes: None,
+            top: vec![
+                TopEntry {
+                    rank: 1,
+                    guid: "g1".to_string(),
+                    score: 0.5,
+                },
+                TopEntry {
+                    rank: 2,
+                    guid: "g2".to_string(),
+                    score: 0.25,
+                },
+            ],
+            timed_out: false,
+        }
+    }
+
+    #[test]
+    fn test_log_record_json_top_shape() {
+        let mut record = sample_record("q1");
+        record.top = top_entries_from_hits(&[
+            make_hit(
+                "g1".to_string(),
+                vec!["Deck".to_string()],
+                None,
+                0.123_456_79,
+            ),
+            make_hit("g2".to_string(), vec!["Deck".to_string()], None, 0.1),
+        ]);
+
+        let record_json: serde_json::Value =
+            serde_json::from_str(&to_string(&record).expect("serialises")).expect("valid JSON");
+        assert_eq!(
+            record_json["top"],
+            serde_json::json!([
+                {"rank": 1, "guid": "g1", "score": 0.123457},
+                {"rank": 2, "guid": "g2", "score": 0.1},
+            ])
+        );
+        assert!(record_json.get("timed_out").is_none());
+    }
+
+    #[test]
+    fn test_log_invalid_records_json_round_trips() {
+        let log_path = std::env::temp_dir()
+            .join(format!("rulebot_logging_io_invalid_{}", std::process::id()))
+            .join("errors.jsonl");
+        let _ = fs::remove_file(&log_path);
+        let invalid_records = vec![
+            InvalidRecord {
+                file_path: "Decks/unit01.txt".to_string(),
+                line_number: 3,
+                reason: "Missing GUID".to_string(),
+                raw_line: "\tBasic\tDeck\tWhat is \"RAM\"?\tMemory".to_string(),
+            },
+            InvalidRecord {
+                file_path: "Decks/unit02.txt".to_string(),
+                line_number: 10,
+                reason: "Expected at least 5 columns, found 2".to_string(),
+                raw_line: "g9\tBasic".to_string(),
+            },
+        ];
+
+        log_invalid_records_json(&invalid_records, &log_path.display().to_string())
+            .expect("log written");
+        let log_text = fs::read_to_string(&log_path).expect("log readable");
+        let read_back: Vec<InvalidRecord> = log_text
+            .lines()
+            .map(|line_text| serde_json::from_str(line_text).expect("one record per line"))
+            .collect();
+        assert_eq!(read_back, invalid_records);
+    }
+
+    #[test]
+    fn test_log_benchmark_csv_writes_header_once() {
+        let log_path = std::env::temp_dir()
+            .join(format!("rulebot_logging_io_{}", std::process::id()))
+            .join("bench.csv");
+        let _ = fs::remove_file(&log_path);
+        let log_path_text = log_path.display().to_string();
+
+        log_benchmark_csv(&sample_record("q1"), &log_path_text).expect("first row");
+        log_benchmark_csv(&sample_record("q2"), &log_path_text).expect("second row");
+
+        let log_text = fs::read_to_string(&log_path).expect("log written");
+        let log_lines: Vec<&str> = log_text.lines().collect();
+        assert_eq!(log_lines.len(), 3);
+        assert_eq!(log_lines[0], BENCHMARK_CSV_HEADER.join(","));
+        assert_eq!(
+            log_lines[1],
+            "2024-01-01T00:00:00+00:00,rust,tfidf,3,\"Deck::Unit 01, Part A\",q1,4,1,2,0.5,3,0.25,g1:0.5;g2:0.25,false"
+        );
+        assert!(log_lines[2].contains(",q2,"));
+    }
+}
@@ -0,0 +1,139 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+
+/// Small least-recently-used map shared by the query and result caches.
+/// Safe to share between threads; a capacity of 0 caches nothing.
+#[derive(Debug)]
+pub struct LruCache<K, V> {
+    capacity: usize,
+    state: Mutex<LruCacheState<K, V>>,
+}
+
+#[derive(Debug, Clone)]
+struct LruCacheState<K, V> {
+    /// Values plus the tick of their last use.
+    entries: HashMap<K, (V, u64)>,
+    tick: u64,
+    hit_count: u64,
+    miss_count: u64,
+}
+
+impl<K, V> LruCache<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Mutex::new(LruCacheState {
+                entries: HashMap::new(),
+                tick: 0,
+                hit_count: 0,
+                miss_count: 0,
+            }),
+        }
+    }
+
+    /// The value stored for `cache_key`, counting a hit or a miss.
+    pub fn get<Q>(&self, cache_key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let mut state = self.state.lock().expect("LRU cache lock poisoned");
+        state.tick += 1;
+        let current_tick = state.tick;
+        match state.entries.get_mut(cache_key) {
+            Some((cached_value, last_used_tick)) => {
+                *last_used_tick = current_tick;
+                let cached_value = cached_value.clone();
+                state.hit_count += 1;
+                Some(cached_value)
+            }
+            None => {
+                state.miss_count += 1;
+                None
+            }
+        }
+    }
+
+    /// Store `value` for `cache_key`, evicting the least recently used entry once full.
+    pub fn insert(&self, cache_key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut state = self.state.lock().expect("LRU cache lock poisoned");
+        state.tick += 1;
+        let current_tick = state.tick;
+        if !state.entries.contains_key(&cache_key) && state.entries.len() >= self.capacity {
+            let least_recent_key = state
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_used_tick))| *last_used_tick)
+                .map(|(entry_key, _)| entry_key.clone());
+            if let Some(least_recent_key) = least_recent_key {
+                state.entries.remove(&least_recent_key);
+            }
+        }
+        state.entries.insert(cache_key, (value, current_tick));
+    }
+
+    /// Drop every entry, keeping the hit and miss counts.
+    pub fn clear(&self) {
+        self.state
+            .lock()
+            .expect("LRU cache lock poisoned")
+            .entries
+            .clear();
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// (hits, misses) since the cache was created.
+    pub fn hit_counts(&self) -> (u64, u64) {
+        let state = self.state.lock().expect("LRU cache lock poisoned");
+        (state.hit_count, state.miss_count)
+    }
+}
+
+impl<K: Clone, V: Clone> Clone for LruCache<K, V> {
+    fn clone(&self) -> Self {
+        let state = self.state.lock().expect("LRU cache lock poisoned");
+        Self {
+            capacity: self.capacity,
+            state: Mutex::new(state.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_least_recently_used_entry_is_evicted() {
+        let lru_cache: LruCache<String, usize> = LruCache::new(2);
+        lru_cache.insert("first".to_string(), 1);
+        lru_cache.insert("second".to_string(), 2);
+        assert_eq!(lru_cache.get("first"), Some(1));
+
+        // "second" is now least recently used and makes way for "third".
+        lru_cache.insert("third".to_string(), 3);
+        assert_eq!(lru_cache.get("second"), None);
+        assert_eq!(lru_cache.get("first"), Some(1));
+        assert_eq!(lru_cache.get("third"), Some(3));
+        assert_eq!(lru_cache.hit_counts(), (3, 1));
+
+        lru_cache.clear();
+        assert_eq!(lru_cache.get("first"), None);
+
+        let disabled_cache: LruCache<String, usize> = LruCache::new(0);
+        disabled_cache.insert("first".to_string(), 1);
+        assert_eq!(disabled_cache.get("first"), None);
+    }
+}
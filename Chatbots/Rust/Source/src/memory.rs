@@ -0,0 +1,54 @@
+/// Current resident set size of this process in kilobytes, or `None` where unsupported.
+/// Linux reads `/proc/self/statm`; macOS asks the kernel via `task_info`.
+#[cfg(target_os = "linux")]
+pub fn current_rss_kilobytes() -> Option<u64> {
+    let statm_text = std::fs::read_to_string("/proc/self/statm").ok()?;
+    // Fields: size resident shared text lib data dt (all in pages)
+    let resident_pages: u64 = statm_text.split_whitespace().nth(1)?.parse().ok()?;
+    // SAFETY: sysconf has no preconditions and only reads a system constant.
+    let page_size_bytes = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    if page_size_bytes <= 0 {
+        return None;
+    }
+    Some(resident_pages * page_size_bytes as u64 / 1024)
+}
+
+/// Current resident set size of this process in kilobytes, or `None` where unsupported.
+/// Linux reads `/proc/self/statm`; macOS asks the kernel via `task_info`.
+#[cfg(target_os = "macos")]
+#[allow(deprecated)] // libc points at the mach2 crate for these bindings, but they remain correct
+pub fn current_rss_kilobytes() -> Option<u64> {
+    let mut task_basic_info: libc::mach_task_basic_info = unsafe { std::mem::zeroed() };
+    let mut info_count = libc::MACH_TASK_BASIC_INFO_COUNT;
+    // SAFETY: the out-pointer and count describe a correctly sized, writable mach_task_basic_info.
+    let status = unsafe {
+        libc::task_info(
+            libc::mach_task_self(),
+            libc::MACH_TASK_BASIC_INFO,
+            &mut task_basic_info as *mut libc::mach_task_basic_info as libc::task_info_t,
+            &mut info_count,
+        )
+    };
+    if status != libc::KERN_SUCCESS {
+        return None;
+    }
+    Some(task_basic_info.resident_size / 1024)
+}
+
+/// Current resident set size of this process in kilobytes, or `None` where unsupported.
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn current_rss_kilobytes() -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_current_rss_is_reported_on_linux() {
+        let rss_kilobytes = current_rss_kilobytes().expect("statm readable on Linux");
+        assert!(rss_kilobytes > 0);
+    }
+}
@@ -1,4 +1,10 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use unicode_normalization::UnicodeNormalization;
+
+use crate::config::ParserConfig;
 
 /// Remove simple HTML tags by skipping anything between '<' and '>'.
 pub fn strip_html_tags(input_text: &str) -> String {
@@ -16,21 +22,186 @@ pub fn strip_html_tags(input_text: &str) -> String {
     output_text
 }
 
-/// Decode a safe subset of HTML entities
+/// Named HTML4 entities decoded by [`decode_basic_entities`], beyond the five always handled.
+/// `nbsp` maps to a plain space so matching and display treat it as ordinary whitespace.
+const NAMED_HTML_ENTITIES: &[(&str, char)] = &[
+    ("amp", '&'),
+    ("lt", '<'),
+    ("gt", '>'),
+    ("quot", '"'),
+    ("apos", '\''),
+    ("#39", '\''),
+    ("nbsp", ' '),
+    ("iexcl", '\u{00a1}'),
+    ("cent", '\u{00a2}'),
+    ("pound", '\u{00a3}'),
+    ("curren", '\u{00a4}'),
+    ("yen", '\u{00a5}'),
+    ("brvbar", '\u{00a6}'),
+    ("sect", '\u{00a7}'),
+    ("uml", '\u{00a8}'),
+    ("copy", '\u{00a9}'),
+    ("ordf", '\u{00aa}'),
+    ("laquo", '\u{00ab}'),
+    ("not", '\u{00ac}'),
+    ("shy", '\u{00ad}'),
+    ("reg", '\u{00ae}'),
+    ("macr", '\u{00af}'),
+    ("deg", '\u{00b0}'),
+    ("plusmn", '\u{00b1}'),
+    ("sup2", '\u{00b2}'),
+    ("sup3", '\u{00b3}'),
+    ("acute", '\u{00b4}'),
+    ("micro", '\u{00b5}'),
+    ("para", '\u{00b6}'),
+    ("middot", '\u{00b7}'),
+    ("cedil", '\u{00b8}'),
+    ("sup1", '\u{00b9}'),
+    ("ordm", '\u{00ba}'),
+    ("raquo", '\u{00bb}'),
+    ("frac14", '\u{00bc}'),
+    ("frac12", '\u{00bd}'),
+    ("frac34", '\u{00be}'),
+    ("iquest", '\u{00bf}'),
+    ("times", '\u{00d7}'),
+    ("divide", '\u{00f7}'),
+    ("ndash", '\u{2013}'),
+    ("mdash", '\u{2014}'),
+    ("lsquo", '\u{2018}'),
+    ("rsquo", '\u{2019}'),
+    ("sbquo", '\u{201a}'),
+    ("ldquo", '\u{201c}'),
+    ("rdquo", '\u{201d}'),
+    ("bdquo", '\u{201e}'),
+    ("dagger", '\u{2020}'),
+    ("Dagger", '\u{2021}'),
+    ("bull", '\u{2022}'),
+    ("hellip", '\u{2026}'),
+    ("permil", '\u{2030}'),
+    ("prime", '\u{2032}'),
+    ("Prime", '\u{2033}'),
+    ("lsaquo", '\u{2039}'),
+    ("rsaquo", '\u{203a}'),
+    ("euro", '\u{20ac}'),
+    ("trade", '\u{2122}'),
+    ("larr", '\u{2190}'),
+    ("uarr", '\u{2191}'),
+    ("rarr", '\u{2192}'),
+    ("darr", '\u{2193}'),
+    ("harr", '\u{2194}'),
+    ("rArr", '\u{21d2}'),
+    ("hArr", '\u{21d4}'),
+    ("forall", '\u{2200}'),
+    ("exist", '\u{2203}'),
+    ("empty", '\u{2205}'),
+    ("isin", '\u{2208}'),
+    ("notin", '\u{2209}'),
+    ("sum", '\u{2211}'),
+    ("minus", '\u{2212}'),
+    ("radic", '\u{221a}'),
+    ("infin", '\u{221e}'),
+    ("and", '\u{2227}'),
+    ("or", '\u{2228}'),
+    ("cap", '\u{2229}'),
+    ("cup", '\u{222a}'),
+    ("ne", '\u{2260}'),
+    ("equiv", '\u{2261}'),
+    ("le", '\u{2264}'),
+    ("ge", '\u{2265}'),
+    ("sub", '\u{2282}'),
+    ("sup", '\u{2283}'),
+    ("sube", '\u{2286}'),
+    ("supe", '\u{2287}'),
+    ("alpha", '\u{03b1}'),
+    ("beta", '\u{03b2}'),
+    ("gamma", '\u{03b3}'),
+    ("delta", '\u{03b4}'),
+    ("epsilon", '\u{03b5}'),
+    ("lambda", '\u{03bb}'),
+    ("mu", '\u{03bc}'),
+    ("pi", '\u{03c0}'),
+    ("sigma", '\u{03c3}'),
+    ("theta", '\u{03b8}'),
+    ("omega", '\u{03c9}'),
+    ("Delta", '\u{0394}'),
+    ("Sigma", '\u{03a3}'),
+    ("Omega", '\u{03a9}'),
+    // Accented Latin-1 letters.
+    ("Agrave", '\u{00c0}'),
+    ("Aacute", '\u{00c1}'),
+    ("Acirc", '\u{00c2}'),
+    ("Atilde", '\u{00c3}'),
+    ("Auml", '\u{00c4}'),
+    ("Aring", '\u{00c5}'),
+    ("AElig", '\u{00c6}'),
+    ("Ccedil", '\u{00c7}'),
+    ("Egrave", '\u{00c8}'),
+    ("Eacute", '\u{00c9}'),
+    ("Ecirc", '\u{00ca}'),
+    ("Euml", '\u{00cb}'),
+    ("Igrave", '\u{00cc}'),
+    ("Iacute", '\u{00cd}'),
+    ("Icirc", '\u{00ce}'),
+    ("Iuml", '\u{00cf}'),
+    ("ETH", '\u{00d0}'),
+    ("Ntilde", '\u{00d1}'),
+    ("Ograve", '\u{00d2}'),
+    ("Oacute", '\u{00d3}'),
+    ("Ocirc", '\u{00d4}'),
+    ("Otilde", '\u{00d5}'),
+    ("Ouml", '\u{00d6}'),
+    ("Oslash", '\u{00d8}'),
+    ("Ugrave", '\u{00d9}'),
+    ("Uacute", '\u{00da}'),
+    ("Ucirc", '\u{00db}'),
+    ("Uuml", '\u{00dc}'),
+    ("Yacute", '\u{00dd}'),
+    ("THORN", '\u{00de}'),
+    ("szlig", '\u{00df}'),
+    ("agrave", '\u{00e0}'),
+    ("aacute", '\u{00e1}'),
+    ("acirc", '\u{00e2}'),
+    ("atilde", '\u{00e3}'),
+    ("auml", '\u{00e4}'),
+    ("aring", '\u{00e5}'),
+    ("aelig", '\u{00e6}'),
+    ("ccedil", '\u{00e7}'),
+    ("egrave", '\u{00e8}'),
+    ("eacute", '\u{00e9}'),
+    ("ecirc", '\u{00ea}'),
+    ("euml", '\u{00eb}'),
+    ("igrave", '\u{00ec}'),
+    ("iacute", '\u{00ed}'),
+    ("icirc", '\u{00ee}'),
+    ("iuml", '\u{00ef}'),
+    ("eth", '\u{00f0}'),
+    ("ntilde", '\u{00f1}'),
+    ("ograve", '\u{00f2}'),
+    ("oacute", '\u{00f3}'),
+    ("ocirc", '\u{00f4}'),
+    ("otilde", '\u{00f5}'),
+    ("ouml", '\u{00f6}'),
+    ("oslash", '\u{00f8}'),
+    ("ugrave", '\u{00f9}'),
+    ("uacute", '\u{00fa}'),
+    ("ucirc", '\u{00fb}'),
+    ("uuml", '\u{00fc}'),
+    ("yacute", '\u{00fd}'),
+    ("thorn", '\u{00fe}'),
+    ("yuml", '\u{00ff}'),
+];
+
+/// Decode named HTML4 entities (see `NAMED_HTML_ENTITIES`) and decimal/hex numeric references.
+/// Unknown or malformed entities are left as written.
 pub fn decode_basic_entities(input_text: &str) -> String {
-    let mut named_map: HashMap<&'static str, char> = HashMap::new();
-    named_map.insert("amp", '&');
-    named_map.insert("lt", '<');
-    named_map.insert("gt", '>');
-    named_map.insert("quot", '"');
-    named_map.insert("#39", '\'');
+    static NAMED_MAP: OnceLock<HashMap<&'static str, char>> = OnceLock::new();
+    let named_map = NAMED_MAP.get_or_init(|| NAMED_HTML_ENTITIES.iter().copied().collect());
 
     let mut output_text = String::with_capacity(input_text.len());
     let mut entity_buffer = String::new();
     let mut inside_entity = false;
 
-    let mut characters = input_text.chars().peekable();
-    while let Some(character) = characters.next() {
+    for character in input_text.chars() {
         if character == '&' {
             inside_entity = true;
             entity_buffer.clear();
@@ -46,33 +217,36 @@ pub fn decode_basic_entities(input_text: &str) -> String {
                         if let Some(decoded) = char::from_u32(code_point) {
                             output_text.push(decoded);
                         } else {
-                            output_text.push_str("&");
+                            output_text.push('&');
                             output_text.push_str(entity_text);
                             output_text.push(';');
                         }
-                    } else if let Some(hex_str) = stripped.strip_prefix('x').or_else(|| stripped.strip_prefix('X')) {
+                    } else if let Some(hex_str) = stripped
+                        .strip_prefix('x')
+                        .or_else(|| stripped.strip_prefix('X'))
+                    {
                         if let Ok(code_point) = u32::from_str_radix(hex_str, 16) {
                             if let Some(decoded) = char::from_u32(code_point) {
                                 output_text.push(decoded);
                             } else {
-                                output_text.push_str("&");
+                                output_text.push('&');
                                 output_text.push_str(entity_text);
                                 output_text.push(';');
                             }
                         } else {
-                            output_text.push_str("&");
+                            output_text.push('&');
                             output_text.push_str(entity_text);
                             output_text.push(';');
                         }
                     } else {
-                        output_text.push_str("&");
+                        output_text.push('&');
                         output_text.push_str(entity_text);
                         output_text.push(';');
                     }
                 } else if let Some(decoded) = named_map.get(entity_text) {
                     output_text.push(*decoded);
                 } else {
-                    output_text.push_str("&");
+                    output_text.push('&');
                     output_text.push_str(entity_text);
                     output_text.push(';');
                 }
@@ -107,12 +281,109 @@ pub fn escape_angle_brackets(input_text: &str) -> String {
     output_text
 }
 
-/// Pipeline for text used in matching
-pub fn normalise_for_matching(input_text: &str) -> String {
+/// Remove MathJax delimiters (`\(…\)`, `\[…\]`, `$$…$$`, `$…$`), keeping the text inside.
+/// A delimiter is only stripped when its closing partner exists. A single `$` also needs a
+/// non-space after it, a closer with a non-space before it, and no digit after the closer,
+/// so prices such as "$5 and $10" survive.
+pub fn strip_math_markup(input_text: &str) -> String {
+    let characters: Vec<char> = input_text.chars().collect();
+    let mut output_text = String::with_capacity(input_text.len());
+    let mut character_index = 0;
+
+    while character_index < characters.len() {
+        if let Some((inner_start, inner_end, resume_index)) =
+            math_span_at(&characters, character_index)
+        {
+            output_text.extend(&characters[inner_start..inner_end]);
+            character_index = resume_index;
+        } else {
+            output_text.push(characters[character_index]);
+            character_index += 1;
+        }
+    }
+    output_text
+}
+
+/// If a math span opens at `start_index`, return (inner start, inner end, index after closer).
+fn math_span_at(characters: &[char], start_index: usize) -> Option<(usize, usize, usize)> {
+    let remaining = &characters[start_index..];
+    if start_index > 0 && characters[start_index - 1] == '\\' {
+        return None;
+    }
+    let closing_delimiter: &[char] = if remaining.starts_with(&['\\', '(']) {
+        &['\\', ')']
+    } else if remaining.starts_with(&['\\', '[']) {
+        &['\\', ']']
+    } else if remaining.starts_with(&['$', '$']) {
+        &['$', '$']
+    } else if remaining.starts_with(&['$']) {
+        return inline_dollar_span_at(characters, start_index);
+    } else {
+        return None;
+    };
+
+    let inner_start = start_index + closing_delimiter.len();
+    (inner_start..characters.len())
+        .find(|&candidate_index| characters[candidate_index..].starts_with(closing_delimiter))
+        .map(|inner_end| (inner_start, inner_end, inner_end + closing_delimiter.len()))
+}
+
+fn inline_dollar_span_at(characters: &[char], start_index: usize) -> Option<(usize, usize, usize)> {
+    let inner_start = start_index + 1;
+    if characters
+        .get(inner_start)
+        .is_none_or(|character| character.is_whitespace())
+    {
+        return None;
+    }
+    (inner_start + 1..characters.len())
+        .find(|&candidate_index| {
+            characters[candidate_index] == '$'
+                && !characters[candidate_index - 1].is_whitespace()
+                && characters[candidate_index - 1] != '\\'
+                && !characters
+                    .get(candidate_index + 1)
+                    .is_some_and(|character| character.is_ascii_digit())
+        })
+        .map(|inner_end| (inner_start, inner_end, inner_end + 1))
+}
+
+/// Pipeline for text used in matching (lowercased unless `lowercase` is off)
+pub fn normalise_for_matching(input_text: &str, parser_config: &ParserConfig) -> String {
+    let normalised_text = normalise_preserving_case(input_text, parser_config);
+    if parser_config.lowercase {
+        normalised_text.to_lowercase()
+    } else {
+        normalised_text
+    }
+}
+
+/// `normalise_for_matching` without the lowercasing, for question previews that should keep
+/// acronyms and proper nouns as written.
+pub fn normalise_preserving_case(input_text: &str, parser_config: &ParserConfig) -> String {
     let without_tags = strip_html_tags(input_text);
-    let decoded_text = decode_basic_entities(&without_tags);
-    let lowercased_text = decoded_text.to_lowercase();
-    lowercased_text.trim().to_string()
+    let without_math = if parser_config.strip_math {
+        strip_math_markup(&without_tags)
+    } else {
+        without_tags
+    };
+    let decoded_text = decode_basic_entities(&without_math);
+    apply_unicode_normalisation(&decoded_text, parser_config)
+        .trim()
+        .to_string()
+}
+
+/// NFKC-normalise `input_text` when `unicode_normalise` is set, so composed and decomposed
+/// accents (and full-width forms) compare equal; otherwise borrow it unchanged.
+pub fn apply_unicode_normalisation<'a>(
+    input_text: &'a str,
+    parser_config: &ParserConfig,
+) -> Cow<'a, str> {
+    if parser_config.unicode_normalise {
+        Cow::Owned(input_text.nfkc().collect())
+    } else {
+        Cow::Borrowed(input_text)
+    }
 }
 
 /// Pipeline for text used in display
@@ -120,3 +391,126 @@ pub fn normalise_for_display(input_text: &str) -> String {
     let escaped_text = escape_angle_brackets(input_text);
     escaped_text.trim().to_string()
 }
+
+/// Display pipeline for `--answer-render plain`: strip tags, decode entities, and collapse
+/// whitespace runs (including the newlines tags leave behind) into single spaces.
+pub fn normalise_for_display_plain(input_text: &str) -> String {
+    let decoded_text = decode_basic_entities(&strip_html_tags(input_text));
+    decoded_text
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalise_for_matching_default(input_text: &str) -> String {
+        normalise_for_matching(input_text, &ParserConfig::default())
+    }
+
+    #[test]
+    fn test_unicode_normalise_unifies_cafe_spellings() {
+        let composed_text = "Caf\u{e9} menu";
+        let decomposed_text = "Cafe\u{301} menu";
+        let normalising_config = ParserConfig {
+            unicode_normalise: true,
+            ..ParserConfig::default()
+        };
+        assert_ne!(
+            normalise_for_matching_default(composed_text),
+            normalise_for_matching_default(decomposed_text)
+        );
+        assert_eq!(
+            normalise_for_matching(composed_text, &normalising_config),
+            normalise_for_matching(decomposed_text, &normalising_config)
+        );
+        // NFKC also folds compatibility forms such as full-width letters.
+        assert_eq!(normalise_for_matching("ＣＰＵ", &normalising_config), "cpu");
+
+        let stopword_set = std::collections::HashSet::new();
+        let tokens_of = |input_text: &str, parser_config: &ParserConfig| {
+            crate::tokenise::tokenise(input_text, &stopword_set, parser_config)
+        };
+        assert_ne!(
+            tokens_of(composed_text, &ParserConfig::default()),
+            tokens_of(decomposed_text, &ParserConfig::default())
+        );
+        assert_eq!(
+            tokens_of(composed_text, &normalising_config),
+            tokens_of(decomposed_text, &normalising_config)
+        );
+        assert_eq!(
+            tokens_of(decomposed_text, &normalising_config),
+            vec!["caf\u{e9}", "menu"]
+        );
+    }
+
+    #[test]
+    fn test_plain_display_strips_tags_that_escaped_display_keeps() {
+        assert_eq!(normalise_for_display_plain("<b>Bold</b>"), "Bold");
+        assert_eq!(
+            normalise_for_display("<b>Bold</b>"),
+            "&lt;b&gt;Bold&lt;/b&gt;"
+        );
+        assert_eq!(
+            normalise_for_display_plain("  A &amp; B<br>\n<i>C</i>&nbsp; D "),
+            "A & B C D"
+        );
+    }
+
+    #[test]
+    fn test_named_entities_decode_for_matching() {
+        assert_eq!(
+            normalise_for_matching_default("stack&nbsp;frame"),
+            "stack frame"
+        );
+        assert_eq!(
+            normalise_for_matching_default("LIFO&mdash;last in"),
+            "lifo\u{2014}last in"
+        );
+        assert_eq!(
+            normalise_for_matching_default("Caf&eacute; &amp; na&iuml;ve"),
+            "caf\u{e9} & na\u{ef}ve"
+        );
+        assert_eq!(
+            normalise_for_matching_default("&Agrave;&hellip;&trade;"),
+            "\u{e0}\u{2026}\u{2122}"
+        );
+        // Numeric paths and unknown names are unchanged.
+        assert_eq!(decode_basic_entities("&#65;&#x42;&bogus;"), "AB&bogus;");
+    }
+
+    #[test]
+    fn test_strip_math_markup_inline_and_display() {
+        assert_eq!(
+            strip_math_markup(r"Binary search is \(O(\log n)\)."),
+            r"Binary search is O(\log n)."
+        );
+        assert_eq!(strip_math_markup(r"\[a^2 + b^2\] holds"), "a^2 + b^2 holds");
+        assert_eq!(
+            strip_math_markup("Sum: $$x_1 + x_2$$ and $n^2$ steps"),
+            "Sum: x_1 + x_2 and n^2 steps"
+        );
+        // Unmatched or price-like dollars are left alone.
+        assert_eq!(strip_math_markup("costs $5 and $10"), "costs $5 and $10");
+        assert_eq!(
+            strip_math_markup(r"open \(never closed"),
+            r"open \(never closed"
+        );
+
+        let math_config = ParserConfig {
+            strip_math: true,
+            ..ParserConfig::default()
+        };
+        assert_eq!(
+            normalise_for_matching(r"<b>Cost</b> is \(O(n)\)", &math_config),
+            "cost is o(n)"
+        );
+        assert_eq!(
+            normalise_for_matching_default(r"Cost is \(O(n)\)"),
+            r"cost is \(o(n)\)"
+        );
+    }
+}
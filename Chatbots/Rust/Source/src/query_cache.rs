@@ -0,0 +1,81 @@
+use std::collections::HashSet;
+
+use crate::config::ParserConfig;
+use crate::lru_cache::LruCache;
+use crate::tokenise::tokenise;
+
+/// Small LRU cache of query tokenisations, keyed by the trimmed query text.
+/// Safe to share between threads; a capacity of 0 caches nothing.
+/// Entries are only valid for the stopwords and config they were tokenised with, so keep one
+/// cache per engine.
+#[derive(Debug, Clone)]
+pub struct QueryCache {
+    entries: LruCache<String, Vec<String>>,
+}
+
+impl QueryCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: LruCache::new(capacity),
+        }
+    }
+
+    /// `tokenise(query_text)`, served from the cache when the same query was seen recently.
+    /// On a miss the least recently used entry is evicted once the cache is full.
+    pub fn tokens(
+        &self,
+        query_text: &str,
+        stopword_set: &HashSet<String>,
+        parser_config: &ParserConfig,
+    ) -> Vec<String> {
+        if self.capacity() == 0 {
+            return tokenise(query_text, stopword_set, parser_config);
+        }
+        let cache_key = query_text.trim();
+        if let Some(cached_tokens) = self.entries.get(cache_key) {
+            return cached_tokens;
+        }
+        let query_tokens = tokenise(query_text, stopword_set, parser_config);
+        self.entries
+            .insert(cache_key.to_string(), query_tokens.clone());
+        query_tokens
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.entries.capacity()
+    }
+
+    /// (hits, misses) since the cache was created.
+    pub fn hit_counts(&self) -> (u64, u64) {
+        self.entries.hit_counts()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_hit_matches_miss_and_evicts_least_recent() {
+        let stopword_set: HashSet<String> = ["the".to_string()].into_iter().collect();
+        let parser_config = ParserConfig::default();
+        let query_cache = QueryCache::new(2);
+
+        let uncached_tokens = tokenise("What is the OSI model?", &stopword_set, &parser_config);
+        let miss_tokens =
+            query_cache.tokens("What is the OSI model?", &stopword_set, &parser_config);
+        let hit_tokens =
+            query_cache.tokens("  What is the OSI model?\n", &stopword_set, &parser_config);
+        assert_eq!(miss_tokens, uncached_tokens);
+        assert_eq!(hit_tokens, uncached_tokens);
+        assert_eq!(query_cache.hit_counts(), (1, 1));
+
+        // "second" is now least recently used and makes way for "third".
+        query_cache.tokens("second query", &stopword_set, &parser_config);
+        query_cache.tokens("What is the OSI model?", &stopword_set, &parser_config);
+        query_cache.tokens("third query", &stopword_set, &parser_config);
+        query_cache.tokens("What is the OSI model?", &stopword_set, &parser_config);
+        query_cache.tokens("second query", &stopword_set, &parser_config);
+        assert_eq!(query_cache.hit_counts(), (3, 4));
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::HashSet;
+
+use crate::config::ParserConfig;
+use crate::tokenise::tokenise;
+
+/// Split `query_text` into the tokens of its unquoted text and the token sequence of each
+/// double-quoted phrase (e.g. `what is "binary search"` → `(["what"], [["binary", "search"]])`).
+/// Both are tokenised like any query. A quote without a closing partner is ordinary text, and
+/// phrases left with no tokens (e.g. only stopwords) are dropped.
+pub fn parse_query(
+    query_text: &str,
+    stopword_set: &HashSet<String>,
+    parser_config: &ParserConfig,
+) -> (Vec<String>, Vec<Vec<String>>) {
+    let mut free_text = String::new();
+    let mut phrases: Vec<Vec<String>> = Vec::new();
+    let mut remaining_text = query_text;
+    while let Some(open_index) = remaining_text.find('"') {
+        let Some(close_offset) = remaining_text[open_index + 1..].find('"') else {
+            break;
+        };
+        let close_index = open_index + 1 + close_offset;
+        free_text.push_str(&remaining_text[..open_index]);
+        free_text.push(' ');
+        let phrase_tokens = tokenise(
+            &remaining_text[open_index + 1..close_index],
+            stopword_set,
+            parser_config,
+        );
+        if !phrase_tokens.is_empty() {
+            phrases.push(phrase_tokens);
+        }
+        remaining_text = &remaining_text[close_index + 1..];
+    }
+    free_text.push_str(remaining_text);
+    (tokenise(&free_text, stopword_set, parser_config), phrases)
+}
+
+/// Whether `phrase` appears as a contiguous run of `token_vector`.
+pub fn contains_phrase(token_vector: &[String], phrase: &[String]) -> bool {
+    !phrase.is_empty()
+        && token_vector
+            .windows(phrase.len())
+            .any(|window| window == phrase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(token_texts: &[&str]) -> Vec<String> {
+        token_texts
+            .iter()
+            .map(|token_text| token_text.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_query_separates_free_terms_and_phrases() {
+        let stopword_set = HashSet::from(["is".to_string()]);
+        let parser_config = ParserConfig {
+            remove_stopwords: true,
+            ..ParserConfig::default()
+        };
+        let (free_tokens, phrases) = parse_query(
+            r#"what is "Binary Search" vs "hash table" sort"#,
+            &stopword_set,
+            &parser_config,
+        );
+        assert_eq!(free_tokens, tokens(&["what", "vs", "sort"]));
+        assert_eq!(
+            phrases,
+            vec![tokens(&["binary", "search"]), tokens(&["hash", "table"])]
+        );
+
+        // An unmatched quote is plain text.
+        let (free_tokens, phrases) =
+            parse_query(r#"what is "binary search"#, &stopword_set, &parser_config);
+        assert_eq!(free_tokens, tokens(&["what", "binary", "search"]));
+        assert!(phrases.is_empty());
+
+        let phrase = tokens(&["binary", "search"]);
+        assert!(contains_phrase(
+            &tokens(&["a", "binary", "search", "tree"]),
+            &phrase
+        ));
+        assert!(!contains_phrase(
+            &tokens(&["search", "binary", "tree"]),
+            &phrase
+        ));
+    }
+}
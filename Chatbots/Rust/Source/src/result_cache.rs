@@ -0,0 +1,151 @@
+use std::sync::Mutex;
+
+use crate::config::ParserConfig;
+use crate::data_model::AnswerHit;
+use crate::lru_cache::LruCache;
+use crate::normalise::normalise_for_matching;
+
+/// What a cached ranking depends on besides the candidate pool.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ResultCacheKey {
+    /// Name of the scorer that ranked the hits.
+    pub scorer_name: String,
+    pub topic: String,
+    /// Matching-normalised query with whitespace collapsed, so case and spacing variants share
+    /// an entry.
+    pub normalised_query: String,
+    pub top_k: usize,
+}
+
+impl ResultCacheKey {
+    pub fn new(
+        scorer_name: &str,
+        topic: &str,
+        query_text: &str,
+        top_k: usize,
+        parser_config: &ParserConfig,
+    ) -> Self {
+        let normalised_query = normalise_for_matching(query_text, parser_config)
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" ");
+        Self {
+            scorer_name: scorer_name.to_string(),
+            topic: topic.to_string(),
+            normalised_query,
+            top_k,
+        }
+    }
+}
+
+/// Small LRU cache of ranked hits. Every lookup names the candidate pool's generation; when it
+/// differs from the generation the entries were stored under (e.g. after `:topic` rebuilt the
+/// index), the cache is emptied first. A capacity of 0 caches nothing.
+#[derive(Debug)]
+pub struct ResultCache {
+    entries: LruCache<ResultCacheKey, Vec<AnswerHit>>,
+    /// Generation the entries were stored under; held while they are read or written.
+    pool_generation: Mutex<u64>,
+}
+
+impl ResultCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: LruCache::new(capacity),
+            pool_generation: Mutex::new(0),
+        }
+    }
+
+    /// Empty the cache if `pool_generation` is not the one its entries were stored under, then
+    /// run `action` while holding the generation.
+    fn with_pool_generation<T>(&self, pool_generation: u64, action: impl FnOnce() -> T) -> T {
+        let mut stored_generation = self
+            .pool_generation
+            .lock()
+            .expect("result cache lock poisoned");
+        if *stored_generation != pool_generation {
+            self.entries.clear();
+            *stored_generation = pool_generation;
+        }
+        action()
+    }
+
+    /// The hits stored for `cache_key` under `pool_generation`, counting a hit or a miss.
+    pub fn get(&self, cache_key: &ResultCacheKey, pool_generation: u64) -> Option<Vec<AnswerHit>> {
+        self.with_pool_generation(pool_generation, || self.entries.get(cache_key))
+    }
+
+    /// Store `answer_hits` for `cache_key`, evicting the least recently used entry once full.
+    pub fn insert(
+        &self,
+        cache_key: ResultCacheKey,
+        pool_generation: u64,
+        answer_hits: Vec<AnswerHit>,
+    ) {
+        self.with_pool_generation(pool_generation, || {
+            self.entries.insert(cache_key, answer_hits)
+        })
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.entries.capacity()
+    }
+
+    /// (hits, misses) since the cache was created.
+    pub fn hit_counts(&self) -> (u64, u64) {
+        self.entries.hit_counts()
+    }
+}
+
+impl Clone for ResultCache {
+    fn clone(&self) -> Self {
+        let pool_generation = *self
+            .pool_generation
+            .lock()
+            .expect("result cache lock poisoned");
+        Self {
+            entries: self.entries.clone(),
+            pool_generation: Mutex::new(pool_generation),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_model::make_hit;
+
+    #[test]
+    fn test_repeated_query_is_served_from_cache_until_pool_changes() {
+        let parser_config = ParserConfig::default();
+        let result_cache = ResultCache::new(4);
+        let cache_key =
+            |query_text: &str| ResultCacheKey::new("tfidf", "<ALL>", query_text, 3, &parser_config);
+        let ranked_hits = vec![make_hit(
+            "g1".to_string(),
+            vec!["Deck".to_string()],
+            None,
+            0.5,
+        )];
+
+        assert!(
+            result_cache
+                .get(&cache_key("What is big data?"), 0)
+                .is_none()
+        );
+        result_cache.insert(cache_key("What is big data?"), 0, ranked_hits);
+        let cached_hits = result_cache
+            .get(&cache_key("  what is   BIG data?"), 0)
+            .expect("second query is a hit");
+        assert_eq!(cached_hits[0].guid, "g1");
+        assert_eq!(result_cache.hit_counts(), (1, 1));
+
+        // A new pool generation (e.g. `:topic`) drops everything cached before it.
+        assert!(
+            result_cache
+                .get(&cache_key("What is big data?"), 1)
+                .is_none()
+        );
+        assert_eq!(result_cache.hit_counts(), (1, 2));
+    }
+}
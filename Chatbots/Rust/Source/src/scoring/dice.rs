@@ -0,0 +1,191 @@
+use std::collections::HashSet;
+
+use crate::config::ParserConfig;
+use crate::data_model::{AnswerHit, make_hit};
+use crate::scoring::keyword::PreparedQuestion;
+use crate::scoring::{compare_question_length, compare_secondary};
+use crate::tokenise::tokenise;
+
+/// Score candidates by the Sørensen–Dice coefficient 2|query ∩ doc| / (|query| + |doc|) over token sets.
+/// Hits scoring below `min_score` are dropped before truncating to `top_k`.
+/// Uses the keyword index (`prepare_keyword_index`). Tie-breakers, applied only on exactly equal scores:
+/// 1) Greater count of distinct overlapping query tokens (kept stopwords included)
+/// 2) Candidate question token count, shorter first unless `tie_break` says otherwise
+/// 3) Deck path, when `tie_break_secondary` is `deck_path`
+/// 4) Lexicographic order of GUID
+pub fn score_dice(
+    query_text: &str,
+    prepared_index: &[PreparedQuestion],
+    stopword_set: &HashSet<String>,
+    parser_config: &ParserConfig,
+    top_k: usize,
+    min_score: f32,
+) -> Vec<AnswerHit> {
+    let query_tokens = tokenise(query_text, stopword_set, parser_config);
+    score_dice_tokens(
+        &query_tokens,
+        prepared_index,
+        parser_config,
+        top_k,
+        min_score,
+    )
+}
+
+/// As `score_dice`, for a query already tokenised (e.g. corrected by `--autocorrect`).
+pub fn score_dice_tokens(
+    query_tokens: &[String],
+    prepared_index: &[PreparedQuestion],
+    parser_config: &ParserConfig,
+    top_k: usize,
+    min_score: f32,
+) -> Vec<AnswerHit> {
+    let query_token_set: HashSet<String> = query_tokens.iter().cloned().collect();
+    let query_set_size = query_token_set.len();
+
+    let mut scored_hits: Vec<(AnswerHit, usize, usize)> = Vec::with_capacity(prepared_index.len());
+
+    for prepared_question in prepared_index {
+        let overlap_count = query_token_set
+            .intersection(&prepared_question.token_set)
+            .count();
+        if overlap_count == 0 {
+            continue;
+        }
+
+        let cardinality_sum = query_set_size + prepared_question.token_set.len();
+        let score_value = 2.0 * overlap_count as f32 / cardinality_sum as f32;
+        let answer_hit = make_hit(
+            prepared_question.guid.clone(),
+            prepared_question.deck_path.clone(),
+            Some(prepared_question.question_preview.clone()),
+            score_value,
+        );
+
+        scored_hits.push((answer_hit, overlap_count, prepared_question.token_count));
+    }
+
+    // Sort with tie-breakers: higher score, then higher overlap, then question length per `tie_break`, then deck path per `tie_break_secondary`, then lexicographic GUID
+    scored_hits.sort_by(|left, right| {
+        right
+            .0
+            .score
+            .partial_cmp(&left.0.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| right.1.cmp(&left.1)) // greater overlap wins
+            .then_with(|| compare_question_length(left.2, right.2, parser_config.tie_break))
+            .then_with(|| compare_secondary(&left.0, &right.0, parser_config.tie_break_secondary))
+            .then_with(|| left.0.guid.cmp(&right.0.guid))
+    });
+
+    scored_hits
+        .into_iter()
+        .filter(|tuple| tuple.0.score >= min_score)
+        .take(top_k)
+        .map(|tuple| tuple.0)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TieBreak;
+    use crate::data_model::test_card;
+    use crate::scoring::jaccard::score_jaccard;
+    use crate::scoring::keyword::prepare_keyword_index;
+
+    #[test]
+    fn test_dice_scores_differ_from_jaccard() {
+        // Dice = 2J / (1 + J) is strictly increasing in Jaccard J, so over the same token sets the
+        // two always rank identically; what differs is the score scale, which matters for
+        // thresholds such as `--min-score`.
+        // Query {alpha, beta, gamma, delta}.
+        // "wide":   overlap 3, |doc| 6 → Jaccard 3/7 ≈ 0.4286, Dice 6/10 = 0.6
+        // "mid":    overlap 2, |doc| 2 → Jaccard 2/4 = 0.5,    Dice 4/6  ≈ 0.6667
+        let cards = vec![
+            test_card("wide", "alpha beta gamma one two three"),
+            test_card("mid", "alpha beta"),
+        ];
+        let stopword_set = HashSet::new();
+        let parser_config = ParserConfig::default();
+        let prepared_index = prepare_keyword_index(&cards, &stopword_set, &parser_config);
+        let query_text = "alpha beta gamma delta";
+
+        let dice_hits = score_dice(
+            query_text,
+            &prepared_index,
+            &stopword_set,
+            &parser_config,
+            2,
+            0.0,
+        );
+        let jaccard_hits = score_jaccard(
+            query_text,
+            &prepared_index,
+            &stopword_set,
+            &parser_config,
+            2,
+            0.0,
+        );
+
+        assert_eq!(dice_hits[0].guid, "mid");
+        assert!((dice_hits[0].score - 2.0 / 3.0).abs() < 1e-6);
+        assert!((dice_hits[1].score - 0.6).abs() < 1e-6);
+        for (dice_hit, jaccard_hit) in dice_hits.iter().zip(&jaccard_hits) {
+            assert_eq!(dice_hit.guid, jaccard_hit.guid);
+            let jaccard_score = jaccard_hit.score;
+            assert!((dice_hit.score - 2.0 * jaccard_score / (1.0 + jaccard_score)).abs() < 1e-6);
+            assert!(dice_hit.score > jaccard_score);
+        }
+
+        // A threshold between the two scales separates them.
+        let dice_above = score_dice(
+            query_text,
+            &prepared_index,
+            &stopword_set,
+            &parser_config,
+            2,
+            0.55,
+        );
+        let jaccard_above = score_jaccard(
+            query_text,
+            &prepared_index,
+            &stopword_set,
+            &parser_config,
+            2,
+            0.55,
+        );
+        assert_eq!(dice_above.len(), 2);
+        assert!(jaccard_above.is_empty());
+    }
+
+    #[test]
+    fn test_dice_length_tie_break_follows_config() {
+        let cards = vec![
+            test_card("long", "binary search tree tree"), // set {binary, search, tree}, 4 tokens
+            test_card("short", "binary search tree"),     // same set, 3 tokens
+        ];
+        let stopword_set = HashSet::new();
+        let ranked_guids = |tie_break: TieBreak| -> Vec<String> {
+            let parser_config = ParserConfig {
+                tie_break,
+                ..ParserConfig::default()
+            };
+            let prepared_index = prepare_keyword_index(&cards, &stopword_set, &parser_config);
+            score_dice(
+                "binary search",
+                &prepared_index,
+                &stopword_set,
+                &parser_config,
+                2,
+                0.0,
+            )
+            .into_iter()
+            .map(|answer_hit| answer_hit.guid)
+            .collect()
+        };
+
+        assert_eq!(ranked_guids(TieBreak::Shortest), vec!["short", "long"]);
+        assert_eq!(ranked_guids(TieBreak::Longest), vec!["long", "short"]);
+        assert_eq!(ranked_guids(TieBreak::GuidOnly), vec!["long", "short"]);
+    }
+}
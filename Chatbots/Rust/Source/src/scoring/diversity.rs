@@ -0,0 +1,102 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::data_model::AnswerHit;
+use crate::scoring::keyword::PreparedQuestion;
+
+/// Greedy MMR reranking: repeatedly pick the hit maximising
+/// `score - lambda * max_similarity_to_already_selected`, where similarity is Jaccard over the
+/// keyword index's token sets. The first pick is always the top-scored hit; hits missing from
+/// `prepared_index` count as dissimilar to everything. Scores are left unchanged on the
+/// returned hits, which are truncated to `top_k`.
+/// Works with any scorer's hits, but the penalty is on a 0..=1 scale, so `lambda` bites harder
+/// on normalised scores (TF–IDF, Jaccard, Dice) than on raw keyword counts.
+pub fn rerank_for_diversity(
+    candidate_hits: Vec<AnswerHit>,
+    prepared_index: &[PreparedQuestion],
+    lambda: f32,
+    top_k: usize,
+) -> Vec<AnswerHit> {
+    let token_sets: HashMap<&str, &HashSet<String>> = prepared_index
+        .iter()
+        .map(|prepared_question| {
+            (
+                prepared_question.guid.as_str(),
+                &prepared_question.token_set,
+            )
+        })
+        .collect();
+
+    let mut remaining_hits = candidate_hits;
+    let mut selected_hits: Vec<AnswerHit> = Vec::with_capacity(top_k.min(remaining_hits.len()));
+    while selected_hits.len() < top_k && !remaining_hits.is_empty() {
+        let mut best_position = 0;
+        let mut best_value = f32::NEG_INFINITY;
+        for (position, candidate_hit) in remaining_hits.iter().enumerate() {
+            let max_similarity = selected_hits
+                .iter()
+                .map(|selected_hit| {
+                    token_set_similarity(
+                        token_sets.get(candidate_hit.guid.as_str()).copied(),
+                        token_sets.get(selected_hit.guid.as_str()).copied(),
+                    )
+                })
+                .fold(0.0_f32, f32::max);
+            let mmr_value = candidate_hit.score - lambda * max_similarity;
+            // Strictly greater keeps the scorer's own order (and tie-breakers) on equal values.
+            if mmr_value > best_value {
+                best_value = mmr_value;
+                best_position = position;
+            }
+        }
+        selected_hits.push(remaining_hits.remove(best_position));
+    }
+    selected_hits
+}
+
+fn token_set_similarity(
+    left_tokens: Option<&HashSet<String>>,
+    right_tokens: Option<&HashSet<String>>,
+) -> f32 {
+    let (Some(left_tokens), Some(right_tokens)) = (left_tokens, right_tokens) else {
+        return 0.0;
+    };
+    let union_count = left_tokens.union(right_tokens).count();
+    if union_count == 0 {
+        return 0.0;
+    }
+    left_tokens.intersection(right_tokens).count() as f32 / union_count as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ParserConfig;
+    use crate::data_model::make_hit;
+    use crate::data_model::test_card;
+    use crate::scoring::keyword::prepare_keyword_index;
+
+    #[test]
+    fn test_diversity_promotes_distinct_candidate() {
+        let cards = vec![
+            test_card("near1", "what is a binary search tree"),
+            test_card("near2", "what is a binary search tree exactly"),
+            test_card("distinct", "how does binary search work on arrays"),
+        ];
+        let stopword_set = HashSet::new();
+        let parser_config = ParserConfig::default();
+        let prepared_index = prepare_keyword_index(&cards, &stopword_set, &parser_config);
+        let answer_hits: Vec<AnswerHit> = [("near1", 0.9), ("near2", 0.85), ("distinct", 0.6)]
+            .into_iter()
+            .map(|(guid, score)| make_hit(guid.to_string(), vec!["Deck".to_string()], None, score))
+            .collect();
+
+        // near2 shares 5 of 6 tokens with near1: 0.85 - 0.5 * 5/6 < 0.6 - 0.5 * 2/10.
+        let reranked_hits = rerank_for_diversity(answer_hits.clone(), &prepared_index, 0.5, 2);
+        let reranked_order: Vec<&str> = reranked_hits.iter().map(|hit| hit.guid.as_str()).collect();
+        assert_eq!(reranked_order, ["near1", "distinct"]);
+
+        // With no penalty the scorer's order is kept.
+        let unpenalised_hits = rerank_for_diversity(answer_hits, &prepared_index, 0.0, 2);
+        assert_eq!(unpenalised_hits[1].guid, "near2");
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::config::ParserConfig;
+use crate::data_model::{AnswerHit, make_hit};
+use crate::scoring::keyword::{
+    KeywordQuery, PreparedQuestion, keyword_score_ceiling, score_keyword_query_with_recency,
+};
+use crate::scoring::tfidf::{TfidfIndex, round_score, score_tfidf_with_recency};
+use crate::tokenise::tokenise;
+
+/// Score candidates by blending keyword overlap and TF–IDF cosine:
+/// `hybrid_alpha * normalised_keyword + (1 - hybrid_alpha) * tfidf_cosine`.
+/// The keyword score is divided by `keyword_score_ceiling` so both signals are 0..1;
+/// a card found by only one scorer gets 0 for the other. Needs both indices over the same cards.
+/// `recency_boost` times the card's recency is added once, to the blended score.
+/// Hits scoring below `min_score` are dropped before truncating to `top_k`.
+/// Tie-breakers, applied only on exactly equal scores:
+/// 1) Greater count of distinct overlapping query tokens (kept stopwords included)
+/// 2) Shorter candidate question by token count
+/// 3) Lexicographic order of GUID
+pub fn score_hybrid(
+    query_text: &str,
+    prepared_index: &[PreparedQuestion],
+    tfidf_index: &TfidfIndex,
+    stopword_set: &HashSet<String>,
+    parser_config: &ParserConfig,
+    top_k: usize,
+    min_score: f32,
+) -> Vec<AnswerHit> {
+    let query_tokens = tokenise(query_text, stopword_set, parser_config);
+    score_hybrid_tokens(
+        &query_tokens,
+        prepared_index,
+        tfidf_index,
+        parser_config,
+        top_k,
+        min_score,
+    )
+}
+
+/// As `score_hybrid`, for a query already tokenised (e.g. by a `QueryCache`).
+pub fn score_hybrid_tokens(
+    query_tokens: &[String],
+    prepared_index: &[PreparedQuestion],
+    tfidf_index: &TfidfIndex,
+    parser_config: &ParserConfig,
+    top_k: usize,
+    min_score: f32,
+) -> Vec<AnswerHit> {
+    let query_token_set: HashSet<&String> = query_tokens.iter().collect();
+    if query_token_set.is_empty() {
+        return Vec::new();
+    }
+    let hybrid_alpha = parser_config.hybrid_alpha;
+    let keyword_ceiling = keyword_score_ceiling(query_tokens, parser_config);
+
+    // Rank every card with each scorer, then merge by GUID: (hit, keyword, tfidf).
+    let mut blended_by_guid: HashMap<String, (AnswerHit, f32, f32)> = HashMap::new();
+    let keyword_query = KeywordQuery {
+        tokens: query_tokens,
+        expanded_tokens: &[],
+        phrases: &[],
+    };
+    let keyword_hits = score_keyword_query_with_recency(
+        keyword_query,
+        prepared_index,
+        parser_config,
+        usize::MAX,
+        0.0,
+        1,
+        0.0,
+    );
+    for keyword_hit in keyword_hits {
+        let normalised_keyword = keyword_hit.score / keyword_ceiling as f32;
+        blended_by_guid.insert(
+            keyword_hit.guid.clone(),
+            (keyword_hit, normalised_keyword, 0.0),
+        );
+    }
+    let tfidf_hits = score_tfidf_with_recency(
+        query_tokens,
+        &[],
+        tfidf_index,
+        parser_config,
+        usize::MAX,
+        0.0,
+        0.0,
+    );
+    for tfidf_hit in tfidf_hits {
+        let tfidf_score = tfidf_hit.score;
+        blended_by_guid
+            .entry(tfidf_hit.guid.clone())
+            .or_insert((tfidf_hit, 0.0, 0.0))
+            .2 = tfidf_score;
+    }
+
+    let prepared_by_guid: HashMap<&str, &PreparedQuestion> = prepared_index
+        .iter()
+        .map(|prepared_question| (prepared_question.guid.as_str(), prepared_question))
+        .collect();
+
+    let mut scored_hits: Vec<(AnswerHit, usize, usize)> = blended_by_guid
+        .into_values()
+        .map(|(answer_hit, keyword_score, tfidf_score)| {
+            let (overlap_count, token_count, recency) = prepared_by_guid
+                .get(answer_hit.guid.as_str())
+                .map_or((0, usize::MAX, 0.0), |prepared_question| {
+                    (
+                        prepared_question
+                            .token_set
+                            .iter()
+                            .filter(|token_text| query_token_set.contains(token_text))
+                            .count(),
+                        prepared_question.token_count,
+                        prepared_question.recency,
+                    )
+                });
+            let mut score_value = hybrid_alpha * keyword_score
+                + (1.0 - hybrid_alpha) * tfidf_score
+                + parser_config.recency_boost * recency;
+            if parser_config.deterministic {
+                score_value = round_score(score_value);
+            }
+            let blended_hit = make_hit(
+                answer_hit.guid,
+                answer_hit.deck_path,
+                answer_hit.question_preview,
+                score_value,
+            );
+            (blended_hit, overlap_count, token_count)
+        })
+        .collect();
+
+    // Sort with tie-breakers: higher score, then higher overlap, then shorter question, then lexicographic GUID
+    scored_hits.sort_by(|left, right| {
+        right
+            .0
+            .score
+            .partial_cmp(&left.0.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| right.1.cmp(&left.1)) // greater overlap wins
+            .then_with(|| left.2.cmp(&right.2)) // shorter question wins
+            .then_with(|| left.0.guid.cmp(&right.0.guid))
+    });
+
+    scored_hits
+        .into_iter()
+        .filter(|tuple| tuple.0.score >= min_score)
+        .take(top_k)
+        .map(|tuple| tuple.0)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_model::{Card, test_card};
+    use crate::scoring::keyword::{prepare_keyword_index, score_keyword_overlap};
+    use crate::scoring::tfidf::{build_tfidf_index, score_tfidf};
+
+    #[test]
+    fn test_hybrid_blends_disagreeing_scorers() {
+        let mut cards = vec![
+            // Most overlap, but the matched words are common and buried in a long question.
+            test_card(
+                "overlap",
+                "sql join index planner picks nested loop or hash strategies from table statistics \
+                 cost estimates cardinality histograms",
+            ),
+            // One rare word alone: best cosine, weakest overlap.
+            test_card("rare", "btree"),
+            test_card("compromise", "btree sql page"),
+        ];
+        for filler_number in 0..3 {
+            for common_term in ["sql", "join", "index"] {
+                cards.push(test_card(
+                    &format!("{common_term}{filler_number}"),
+                    common_term,
+                ));
+            }
+        }
+        let stopword_set = HashSet::new();
+        let parser_config = ParserConfig::default();
+        let keyword_index = prepare_keyword_index(&cards, &stopword_set, &parser_config);
+        let tfidf_index = build_tfidf_index(&cards, &stopword_set, &parser_config);
+        let query_text = "btree sql join index";
+        let top_guid = |algorithm_hits: Vec<AnswerHit>| algorithm_hits[0].guid.clone();
+
+        let keyword_hits = score_keyword_overlap(
+            query_text,
+            &keyword_index,
+            &stopword_set,
+            &parser_config,
+            1,
+            0.0,
+            1,
+        );
+        assert_eq!(top_guid(keyword_hits), "overlap");
+        let tfidf_hits = score_tfidf(
+            query_text,
+            &tfidf_index,
+            &stopword_set,
+            &parser_config,
+            1,
+            0.0,
+        );
+        assert_eq!(top_guid(tfidf_hits), "rare");
+
+        let hybrid_hits = score_hybrid(
+            query_text,
+            &keyword_index,
+            &tfidf_index,
+            &stopword_set,
+            &parser_config,
+            3,
+            0.0,
+        );
+        let hybrid_guids: Vec<&str> = hybrid_hits.iter().map(|hit| hit.guid.as_str()).collect();
+        assert_eq!(hybrid_guids, vec!["compromise", "overlap", "rare"]);
+    }
+
+    #[test]
+    fn test_hybrid_adds_recency_boost_once() {
+        let dated_card = |guid: &str, modified: &str| Card {
+            metadata: HashMap::from([("modified".to_string(), modified.to_string())]),
+            ..test_card(guid, "what is a stack")
+        };
+        let cards = vec![
+            dated_card("old", "1600000000"),
+            dated_card("new", "1700000000"),
+        ];
+        let stopword_set = HashSet::new();
+        let hybrid_scores = |recency_boost: f32| {
+            let parser_config = ParserConfig {
+                recency_boost,
+                ..ParserConfig::default()
+            };
+            let keyword_index = prepare_keyword_index(&cards, &stopword_set, &parser_config);
+            let tfidf_index = build_tfidf_index(&cards, &stopword_set, &parser_config);
+            score_hybrid(
+                "stack",
+                &keyword_index,
+                &tfidf_index,
+                &stopword_set,
+                &parser_config,
+                2,
+                0.0,
+            )
+            .into_iter()
+            .map(|hit| (hit.guid, hit.score))
+            .collect::<HashMap<String, f32>>()
+        };
+
+        let unboosted = hybrid_scores(0.0);
+        let boosted = hybrid_scores(0.5);
+        assert!((boosted["new"] - (unboosted["new"] + 0.5)).abs() < 1e-6);
+        assert!((boosted["old"] - unboosted["old"]).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::HashSet;
+
+use crate::config::ParserConfig;
+use crate::data_model::{AnswerHit, make_hit};
+use crate::scoring::keyword::PreparedQuestion;
+use crate::scoring::{compare_question_length, compare_secondary};
+use crate::tokenise::tokenise;
+
+/// Score candidates by Jaccard similarity |query ∩ doc| / |query ∪ doc| over token sets.
+/// Hits scoring below `min_score` are dropped before truncating to `top_k`.
+/// Uses the keyword index (`prepare_keyword_index`). Tie-breakers, applied only on exactly equal scores:
+/// 1) Greater count of distinct overlapping query tokens (kept stopwords included)
+/// 2) Candidate question token count, shorter first unless `tie_break` says otherwise
+/// 3) Deck path, when `tie_break_secondary` is `deck_path`
+/// 4) Lexicographic order of GUID
+pub fn score_jaccard(
+    query_text: &str,
+    prepared_index: &[PreparedQuestion],
+    stopword_set: &HashSet<String>,
+    parser_config: &ParserConfig,
+    top_k: usize,
+    min_score: f32,
+) -> Vec<AnswerHit> {
+    let query_tokens = tokenise(query_text, stopword_set, parser_config);
+    score_jaccard_tokens(
+        &query_tokens,
+        prepared_index,
+        parser_config,
+        top_k,
+        min_score,
+    )
+}
+
+/// As `score_jaccard`, for a query already tokenised (e.g. corrected by `--autocorrect`).
+pub fn score_jaccard_tokens(
+    query_tokens: &[String],
+    prepared_index: &[PreparedQuestion],
+    parser_config: &ParserConfig,
+    top_k: usize,
+    min_score: f32,
+) -> Vec<AnswerHit> {
+    let query_token_set: HashSet<String> = query_tokens.iter().cloned().collect();
+
+    let mut scored_hits: Vec<(AnswerHit, usize, usize)> = Vec::with_capacity(prepared_index.len());
+
+    for prepared_question in prepared_index {
+        let overlap_count = query_token_set
+            .intersection(&prepared_question.token_set)
+            .count();
+        if overlap_count == 0 {
+            continue;
+        }
+
+        let union_count = query_token_set.len() + prepared_question.token_set.len() - overlap_count;
+        let score_value = overlap_count as f32 / union_count as f32;
+        let answer_hit = make_hit(
+            prepared_question.guid.clone(),
+            prepared_question.deck_path.clone(),
+            Some(prepared_question.question_preview.clone()),
+            score_value,
+        );
+
+        scored_hits.push((answer_hit, overlap_count, prepared_question.token_count));
+    }
+
+    // Sort with tie-breakers: higher score, then higher overlap, then question length per `tie_break`, then deck path per `tie_break_secondary`, then lexicographic GUID
+    scored_hits.sort_by(|left, right| {
+        right
+            .0
+            .score
+            .partial_cmp(&left.0.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| right.1.cmp(&left.1)) // greater overlap wins
+            .then_with(|| compare_question_length(left.2, right.2, parser_config.tie_break))
+            .then_with(|| compare_secondary(&left.0, &right.0, parser_config.tie_break_secondary))
+            .then_with(|| left.0.guid.cmp(&right.0.guid))
+    });
+
+    scored_hits
+        .into_iter()
+        .filter(|tuple| tuple.0.score >= min_score)
+        .take(top_k)
+        .map(|tuple| tuple.0)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TieBreak;
+    use crate::data_model::test_card;
+    use crate::scoring::keyword::prepare_keyword_index;
+
+    #[test]
+    fn test_jaccard_scores_and_length_tie_break() {
+        let cards = vec![
+            test_card("long", "binary search tree tree"), // set {binary, search, tree}, 4 tokens
+            test_card("short", "binary search tree"),     // same set, 3 tokens
+            test_card("exact", "binary search"),          // 2/2 = 1.0
+            test_card("partial", "binary"),               // 1/2 = 0.5, shortest of all
+        ];
+        let stopword_set = HashSet::new();
+        let parser_config = ParserConfig::default();
+        let prepared_index = prepare_keyword_index(&cards, &stopword_set, &parser_config);
+
+        let answer_hits = score_jaccard(
+            "binary search",
+            &prepared_index,
+            &stopword_set,
+            &parser_config,
+            4,
+            0.0,
+        );
+        let ranked_guids: Vec<&str> = answer_hits.iter().map(|hit| hit.guid.as_str()).collect();
+
+        // Shorter question only wins on exactly equal scores: "partial" is shortest but ranks last.
+        assert_eq!(ranked_guids, vec!["exact", "short", "long", "partial"]);
+        assert_eq!(answer_hits[0].score, 1.0);
+        assert!((answer_hits[1].score - 2.0 / 3.0).abs() < 1e-6);
+        assert_eq!(answer_hits[1].score, answer_hits[2].score);
+        assert_eq!(answer_hits[3].score, 0.5);
+
+        let longest_first = ParserConfig {
+            tie_break: TieBreak::Longest,
+            ..ParserConfig::default()
+        };
+        let answer_hits = score_jaccard(
+            "binary search",
+            &prepared_index,
+            &stopword_set,
+            &longest_first,
+            4,
+            0.0,
+        );
+        let ranked_guids: Vec<&str> = answer_hits.iter().map(|hit| hit.guid.as_str()).collect();
+        assert_eq!(ranked_guids, vec!["exact", "long", "short", "partial"]);
+    }
+}
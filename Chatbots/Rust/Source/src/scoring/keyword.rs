@@ -1,8 +1,13 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
-use crate::config::ParserConfig;
-use crate::data_model::{make_hit, AnswerHit, Card};
-use crate::tokenise::{tokenise, tokenise_to_set};
+use crate::config::{KeywordCountMode, ParserConfig};
+use crate::data_model::{AnswerHit, Card, make_hit};
+use crate::query_parser::{contains_phrase, parse_query};
+use crate::scoring::{
+    ProgressCallback, card_search_tokens, card_timestamp, compare_question_length,
+    compare_secondary, recency_factors, report_progress,
+};
+use crate::tokenise::{token_weight, tokenise, weighted_stopword_terms};
 
 /// A prepared representation of a candidate question for keyword overlap scoring.
 #[derive(Debug, Clone)]
@@ -11,7 +16,19 @@ pub struct PreparedQuestion {
     pub deck_path: Vec<String>,
     pub question_preview: String,
     pub token_set: HashSet<String>,
+    /// Adjacent token pairs in question order, for phrase boosting.
+    pub bigram_set: HashSet<(String, String)>,
+    /// Tokens in question order, for quoted-phrase matching.
+    pub token_vector: Vec<String>,
+    /// Occurrences of each token, for `KeywordCountMode::Multiset`.
+    pub token_counts: HashMap<String, usize>,
+    /// Modification time from the card's metadata, for `recency_boost`.
+    pub timestamp: Option<i64>,
+    /// `timestamp` scaled from 0 (oldest card in the index) to 1 (newest), set at build.
+    pub recency: f32,
     pub token_count: usize,
+    /// Tokens of `token_set` that are stopwords kept at `stopword_weight` (empty when removed).
+    pub stopword_tokens: HashSet<String>,
 }
 
 /// Build a prepared index over candidate cards for keyword overlap scoring.
@@ -20,49 +37,337 @@ pub fn prepare_keyword_index(
     stopword_set: &std::collections::HashSet<String>,
     parser_config: &ParserConfig,
 ) -> Vec<PreparedQuestion> {
+    prepare_keyword_index_with_progress(candidate_cards, stopword_set, parser_config, None)
+}
+
+/// As `prepare_keyword_index`, calling `progress` as cards are prepared.
+pub fn prepare_keyword_index_with_progress(
+    candidate_cards: &[Card],
+    stopword_set: &HashSet<String>,
+    parser_config: &ParserConfig,
+    progress: Option<ProgressCallback>,
+) -> Vec<PreparedQuestion> {
+    let weighted_stopwords = weighted_stopword_terms(stopword_set, parser_config);
     let mut prepared_list: Vec<PreparedQuestion> = Vec::with_capacity(candidate_cards.len());
     for card in candidate_cards {
-        let token_vector = tokenise(&card.question_text, stopword_set, parser_config);
-        let token_set = token_vector.iter().cloned().collect::<HashSet<String>>();
+        let token_vector = card_search_tokens(card, stopword_set, parser_config);
+        let token_counts = token_occurrence_counts(&token_vector);
+        let token_set = token_counts.keys().cloned().collect::<HashSet<String>>();
+        let bigram_set = token_bigrams(&token_vector).into_iter().collect();
+        let stopword_tokens = token_set
+            .iter()
+            .filter(|token_text| {
+                token_weight(token_text, &weighted_stopwords, parser_config) != 1.0
+            })
+            .cloned()
+            .collect();
         let prepared_question = PreparedQuestion {
             guid: card.guid.clone(),
             deck_path: card.deck_path.clone(),
-            question_preview: card.question_text.clone(),
+            question_preview: card.question_display.clone(),
             token_set,
+            bigram_set,
             token_count: token_vector.len(),
+            token_vector,
+            token_counts,
+            timestamp: card_timestamp(card, parser_config),
+            recency: 0.0,
+            stopword_tokens,
         };
         prepared_list.push(prepared_question);
+        report_progress(progress, prepared_list.len(), candidate_cards.len());
+    }
+    let timestamps: Vec<Option<i64>> = prepared_list
+        .iter()
+        .map(|prepared_question| prepared_question.timestamp)
+        .collect();
+    for (prepared_question, recency) in prepared_list.iter_mut().zip(recency_factors(&timestamps)) {
+        prepared_question.recency = recency;
     }
     prepared_list
 }
 
-/// Score candidates by keyword overlap (sum of weights = 1 per overlapping token).
+/// Occurrences of each distinct token.
+fn token_occurrence_counts(token_vector: &[String]) -> HashMap<String, usize> {
+    let mut token_counts: HashMap<String, usize> = HashMap::new();
+    for token_text in token_vector {
+        *token_counts.entry(token_text.clone()).or_insert(0) += 1;
+    }
+    token_counts
+}
+
+/// The highest overlap score a card can reach for `query_tokens` (before phrase boosts): the
+/// number of distinct tokens, or every occurrence under `KeywordCountMode::Multiset`.
+pub fn keyword_score_ceiling(query_tokens: &[String], parser_config: &ParserConfig) -> usize {
+    match parser_config.keyword_count_mode {
+        KeywordCountMode::Set => query_tokens.iter().collect::<HashSet<&String>>().len(),
+        KeywordCountMode::Multiset => query_tokens.len(),
+    }
+}
+
+/// Times a query token found in `prepared_question` is counted: once under
+/// `KeywordCountMode::Set`, otherwise the smaller of its query and question counts.
+fn matched_token_multiplicity(
+    prepared_question: &PreparedQuestion,
+    token_text: &str,
+    query_token_counts: &HashMap<String, usize>,
+    parser_config: &ParserConfig,
+) -> usize {
+    match parser_config.keyword_count_mode {
+        KeywordCountMode::Set => 1,
+        KeywordCountMode::Multiset => query_token_counts
+            .get(token_text)
+            .copied()
+            .unwrap_or(1)
+            .min(
+                prepared_question
+                    .token_counts
+                    .get(token_text)
+                    .copied()
+                    .unwrap_or(1),
+            ),
+    }
+}
+
+/// Ordered pairs of adjacent tokens.
+fn token_bigrams(token_vector: &[String]) -> Vec<(String, String)> {
+    token_vector
+        .windows(2)
+        .map(|pair| (pair[0].clone(), pair[1].clone()))
+        .collect()
+}
+
+/// Distinct query bigrams, in query order.
+fn query_bigrams(query_tokens: &[String]) -> Vec<(String, String)> {
+    let mut seen_bigrams: HashSet<(String, String)> = HashSet::new();
+    token_bigrams(query_tokens)
+        .into_iter()
+        .filter(|bigram| seen_bigrams.insert(bigram.clone()))
+        .collect()
+}
+
+/// Weight of a query token found in `prepared_question`: `stopword_weight` for a kept stopword,
+/// otherwise 1.
+fn matched_token_weight(
+    prepared_question: &PreparedQuestion,
+    token_text: &str,
+    parser_config: &ParserConfig,
+) -> f32 {
+    if prepared_question.stopword_tokens.contains(token_text) {
+        parser_config.stopword_weight
+    } else {
+        1.0
+    }
+}
+
+/// Score candidates by keyword overlap (sum of weights = 1 per overlapping token, or
+/// `stopword_weight` per overlapping stopword when stopwords are kept, each counted
+/// `min(query count, question count)` times under `KeywordCountMode::Multiset`), plus
+/// `phrase_boost` for each query bigram that appears contiguously in the candidate, plus
+/// `recency_boost` times the candidate's recency (0 oldest to 1 newest, see `card_timestamp`).
+/// Candidates sharing fewer than `min_overlap` distinct tokens with the query are not eligible;
+/// `min_overlap` is clamped to the query's distinct token count so short queries still match.
+/// Double-quoted phrases in the query (see `parse_query`) must appear contiguously in a
+/// candidate for it to be eligible; their words count towards the overlap like unquoted ones.
+/// Hits scoring below `min_score` are dropped before truncating to `top_k`.
 /// Tie-breakers:
-/// 1) Greater count of overlapping non-stopword tokens
-/// 2) Shorter candidate question by token count
-/// 3) Lexicographic order of GUID
+/// 1) Greater count of distinct overlapping query tokens (kept stopwords included)
+/// 2) Candidate question token count, shorter first unless `tie_break` says otherwise
+/// 3) Deck path, when `tie_break_secondary` is `deck_path`
+/// 4) Lexicographic order of GUID
 pub fn score_keyword_overlap(
     query_text: &str,
     prepared_index: &[PreparedQuestion],
     stopword_set: &std::collections::HashSet<String>,
     parser_config: &ParserConfig,
     top_k: usize,
+    min_score: f32,
+    min_overlap: usize,
+) -> Vec<AnswerHit> {
+    // Quotes are not token characters, so this keeps phrase words in query order.
+    let query_tokens = tokenise(query_text, stopword_set, parser_config);
+    let (_, phrases) = parse_query(query_text, stopword_set, parser_config);
+    let keyword_query = KeywordQuery {
+        tokens: &query_tokens,
+        expanded_tokens: &[],
+        phrases: &phrases,
+    };
+    score_keyword_query(
+        keyword_query,
+        prepared_index,
+        parser_config,
+        top_k,
+        min_score,
+        min_overlap,
+    )
+}
+
+/// As `score_keyword_overlap`, for a query already tokenised (e.g. by a `QueryCache`).
+pub fn score_keyword_tokens(
+    query_tokens: &[String],
+    prepared_index: &[PreparedQuestion],
+    parser_config: &ParserConfig,
+    top_k: usize,
+    min_score: f32,
+    min_overlap: usize,
+) -> Vec<AnswerHit> {
+    score_keyword_expanded_tokens(
+        query_tokens,
+        &[],
+        prepared_index,
+        parser_config,
+        top_k,
+        min_score,
+        min_overlap,
+    )
+}
+
+/// As `score_keyword_tokens`, where each of `expanded_tokens` (e.g. synonyms of the query
+/// tokens) also counts towards `min_overlap` but adds only `synonym_weight` to the score, so a
+/// card matched through a synonym ranks below one matching the query word itself. A candidate
+/// counts at most one synonym per query token it does not match exactly, and the overlap
+/// tie-breaker counts exact matches only.
+pub fn score_keyword_expanded_tokens(
+    query_tokens: &[String],
+    expanded_tokens: &[String],
+    prepared_index: &[PreparedQuestion],
+    parser_config: &ParserConfig,
+    top_k: usize,
+    min_score: f32,
+    min_overlap: usize,
+) -> Vec<AnswerHit> {
+    let keyword_query = KeywordQuery {
+        tokens: query_tokens,
+        expanded_tokens,
+        phrases: &[],
+    };
+    score_keyword_query(
+        keyword_query,
+        prepared_index,
+        parser_config,
+        top_k,
+        min_score,
+        min_overlap,
+    )
+}
+
+/// A tokenised keyword query for `score_keyword_query`.
+#[derive(Debug, Clone, Copy)]
+pub struct KeywordQuery<'a> {
+    /// Every query token in order, quoted phrase words included.
+    pub tokens: &'a [String],
+    /// Synonyms scored at `synonym_weight` (see `score_keyword_expanded_tokens`).
+    pub expanded_tokens: &'a [String],
+    /// Token sequences a candidate must contain contiguously (from `parse_query`).
+    pub phrases: &'a [Vec<String>],
+}
+
+/// As `score_keyword_expanded_tokens`, also requiring `keyword_query.phrases` in candidates.
+pub fn score_keyword_query(
+    keyword_query: KeywordQuery,
+    prepared_index: &[PreparedQuestion],
+    parser_config: &ParserConfig,
+    top_k: usize,
+    min_score: f32,
+    min_overlap: usize,
+) -> Vec<AnswerHit> {
+    score_keyword_query_with_recency(
+        keyword_query,
+        prepared_index,
+        parser_config,
+        top_k,
+        min_score,
+        min_overlap,
+        parser_config.recency_boost,
+    )
+}
+
+/// As `score_keyword_query`, adding `recency_boost` (rather than the configured one) times each
+/// candidate's recency; hybrid passes 0 and applies the boost once after blending.
+pub(crate) fn score_keyword_query_with_recency(
+    keyword_query: KeywordQuery,
+    prepared_index: &[PreparedQuestion],
+    parser_config: &ParserConfig,
+    top_k: usize,
+    min_score: f32,
+    min_overlap: usize,
+    recency_boost: f32,
 ) -> Vec<AnswerHit> {
-    let query_token_set = tokenise_to_set(query_text, stopword_set, parser_config);
+    let KeywordQuery {
+        tokens: query_tokens,
+        expanded_tokens,
+        phrases,
+    } = keyword_query;
+    let query_token_counts = token_occurrence_counts(query_tokens);
+    let query_token_set: HashSet<String> = query_token_counts.keys().cloned().collect();
+    if query_token_set.is_empty() || prepared_index.is_empty() {
+        return Vec::new();
+    }
+    let expanded_token_set: HashSet<&String> = expanded_tokens
+        .iter()
+        .filter(|token_text| !query_token_set.contains(*token_text))
+        .collect();
+    // Sum weights in sorted token order, so float rounding never depends on hash iteration order.
+    let mut ordered_query_tokens: Vec<&String> = query_token_set.iter().collect();
+    ordered_query_tokens.sort();
+    let required_overlap = min_overlap.clamp(1, query_token_set.len().max(1));
+    let query_bigram_list = if parser_config.phrase_boost != 0.0 {
+        query_bigrams(query_tokens)
+    } else {
+        Vec::new()
+    };
 
     let mut scored_hits: Vec<(AnswerHit, usize, usize)> = Vec::with_capacity(prepared_index.len());
 
     for prepared_question in prepared_index {
-        let overlap_count = query_token_set
-            .intersection(&prepared_question.token_set)
-            .count();
+        if !phrases
+            .iter()
+            .all(|phrase| contains_phrase(&prepared_question.token_vector, phrase))
+        {
+            continue;
+        }
+        let mut overlap_count = 0;
+        let mut overlap_weight: f32 = 0.0;
+        for token_text in ordered_query_tokens
+            .iter()
+            .filter(|token_text| prepared_question.token_set.contains(**token_text))
+        {
+            overlap_count += 1;
+            overlap_weight += matched_token_weight(prepared_question, token_text, parser_config)
+                * matched_token_multiplicity(
+                    prepared_question,
+                    token_text,
+                    &query_token_counts,
+                    parser_config,
+                ) as f32;
+        }
+        // Synonyms only stand in for query tokens the candidate lacks, so matching several
+        // synonyms of one word never outscores (or out-ties) matching the word itself.
+        let mut synonym_weights: Vec<f32> = expanded_token_set
+            .iter()
+            .filter(|token_text| prepared_question.token_set.contains(**token_text))
+            .map(|token_text| {
+                parser_config.synonym_weight
+                    * matched_token_weight(prepared_question, token_text, parser_config)
+            })
+            .collect();
+        synonym_weights.sort_by(|left, right| right.total_cmp(left));
+        synonym_weights.truncate(query_token_set.len() - overlap_count);
+        overlap_weight += synonym_weights.iter().sum::<f32>();
 
-        if overlap_count == 0 {
-            // Baseline disallows stopword weights and gives no phrase boosts; zero overlap → score 0, skip.
+        if overlap_count + synonym_weights.len() < required_overlap {
+            // Below `min_overlap` the candidate is not eligible.
             continue;
         }
 
-        let score_value = overlap_count as f32; // default weight = 1 per token
+        let phrase_match_count = query_bigram_list
+            .iter()
+            .filter(|bigram| prepared_question.bigram_set.contains(*bigram))
+            .count();
+        // weight = 1 per token, `stopword_weight` per kept stopword
+        let score_value = overlap_weight
+            + phrase_match_count as f32 * parser_config.phrase_boost
+            + recency_boost * prepared_question.recency;
         let answer_hit = make_hit(
             prepared_question.guid.clone(),
             prepared_question.deck_path.clone(),
@@ -70,24 +375,280 @@ pub fn score_keyword_overlap(
             score_value,
         );
 
-        scored_hits.push((
-            answer_hit,
-            overlap_count,
-            prepared_question.token_count,
-        ));
+        scored_hits.push((answer_hit, overlap_count, prepared_question.token_count));
     }
 
-    // Sort with tie-breakers: higher score, then higher overlap, then shorter question, then lexicographic GUID
+    // Sort with tie-breakers: higher score, then higher overlap, then question length per `tie_break`, then deck path per `tie_break_secondary`, then lexicographic GUID
     scored_hits.sort_by(|left, right| {
-        right.0.score.partial_cmp(&left.0.score).unwrap_or(std::cmp::Ordering::Equal)
+        right
+            .0
+            .score
+            .partial_cmp(&left.0.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
             .then_with(|| right.1.cmp(&left.1)) // greater overlap wins
-            .then_with(|| left.2.cmp(&right.2)) // shorter question wins
+            .then_with(|| compare_question_length(left.2, right.2, parser_config.tie_break))
+            .then_with(|| compare_secondary(&left.0, &right.0, parser_config.tie_break_secondary))
             .then_with(|| left.0.guid.cmp(&right.0.guid))
     });
 
     scored_hits
         .into_iter()
+        .filter(|tuple| tuple.0.score >= min_score)
         .take(top_k)
         .map(|tuple| tuple.0)
         .collect()
 }
+
+/// Explain one candidate's score: each query token it shares, with its weight (1, or
+/// `stopword_weight` for a kept stopword),
+/// in token order, then each matched query bigram ("first second") with `phrase_boost`.
+/// Empty if `guid` is not in the index.
+pub fn explain_keyword(
+    query_tokens: &[String],
+    prepared_index: &[PreparedQuestion],
+    guid: &str,
+    parser_config: &ParserConfig,
+) -> Vec<(String, f32)> {
+    let Some(prepared_question) = prepared_index.iter().find(|prepared| prepared.guid == guid)
+    else {
+        return Vec::new();
+    };
+    let query_token_counts = token_occurrence_counts(query_tokens);
+    let query_token_set: HashSet<String> = query_token_counts.keys().cloned().collect();
+    let mut matched_tokens: Vec<String> = query_token_set
+        .intersection(&prepared_question.token_set)
+        .cloned()
+        .collect();
+    matched_tokens.sort();
+    let mut contributions: Vec<(String, f32)> = matched_tokens
+        .into_iter()
+        .map(|token_text| {
+            let token_weight = matched_token_weight(prepared_question, &token_text, parser_config)
+                * matched_token_multiplicity(
+                    prepared_question,
+                    &token_text,
+                    &query_token_counts,
+                    parser_config,
+                ) as f32;
+            (token_text, token_weight)
+        })
+        .collect();
+    if parser_config.phrase_boost != 0.0 {
+        for (first_token, second_token) in query_bigrams(query_tokens) {
+            let bigram = (first_token, second_token);
+            if prepared_question.bigram_set.contains(&bigram) {
+                let phrase_text = format!("{} {}", bigram.0, bigram.1);
+                contributions.push((phrase_text, parser_config.phrase_boost));
+            }
+        }
+    }
+    contributions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_model::test_card;
+
+    #[test]
+    fn test_quoted_phrase_must_appear_contiguously() {
+        let cards = vec![
+            test_card("g1", "search the binary tree"),
+            test_card("g2", "the binary search tree"),
+            test_card("g3", "binary search algorithm"),
+        ];
+        let stopword_set = HashSet::new();
+        let parser_config = ParserConfig::default();
+        let prepared_index = prepare_keyword_index(&cards, &stopword_set, &parser_config);
+        let ranked_guids = |query_text: &str| -> Vec<String> {
+            score_keyword_overlap(
+                query_text,
+                &prepared_index,
+                &stopword_set,
+                &parser_config,
+                5,
+                0.0,
+                1,
+            )
+            .into_iter()
+            .map(|answer_hit| answer_hit.guid)
+            .collect()
+        };
+
+        // Unquoted, every card shares words with the query.
+        assert_eq!(ranked_guids("tree binary search"), vec!["g1", "g2", "g3"]);
+        // Quoted, "g1" lacks the adjacent pair; the free term "tree" still ranks "g2" first.
+        assert_eq!(ranked_guids(r#"tree "binary search""#), vec!["g2", "g3"]);
+    }
+
+    #[test]
+    fn test_phrase_boost_prefers_adjacent_pair() {
+        // Same unigram overlap and length; without a boost "g1" would win on GUID order.
+        let cards = vec![
+            test_card("g1", "search the binary tree"),
+            test_card("g2", "the binary search tree"),
+        ];
+        let stopword_set = HashSet::new();
+        let boosted_config = ParserConfig {
+            phrase_boost: 0.5,
+            ..ParserConfig::default()
+        };
+        let prepared_index = prepare_keyword_index(&cards, &stopword_set, &boosted_config);
+
+        let answer_hits = score_keyword_overlap(
+            "binary search",
+            &prepared_index,
+            &stopword_set,
+            &boosted_config,
+            2,
+            0.0,
+            1,
+        );
+        assert_eq!(answer_hits[0].guid, "g2");
+        assert_eq!(answer_hits[0].score, 2.5);
+        assert_eq!(answer_hits[1].score, 2.0);
+
+        let unboosted_config = ParserConfig::default();
+        let unboosted_hits = score_keyword_overlap(
+            "binary search",
+            &prepared_index,
+            &stopword_set,
+            &unboosted_config,
+            2,
+            0.0,
+            1,
+        );
+        assert_eq!(unboosted_hits[0].guid, "g1");
+        assert_eq!(unboosted_hits[0].score, unboosted_hits[1].score);
+    }
+
+    #[test]
+    fn test_multiset_count_mode_rewards_repeated_query_terms() {
+        let cards = vec![
+            test_card("g1", "loop over loop inside loop"),
+            test_card("g2", "while loop"),
+        ];
+        let stopword_set = HashSet::new();
+        let scores_for = |keyword_count_mode: KeywordCountMode, query_text: &str| {
+            let parser_config = ParserConfig {
+                keyword_count_mode,
+                ..ParserConfig::default()
+            };
+            let prepared_index = prepare_keyword_index(&cards, &stopword_set, &parser_config);
+            score_keyword_overlap(
+                query_text,
+                &prepared_index,
+                &stopword_set,
+                &parser_config,
+                5,
+                0.0,
+                1,
+            )
+            .into_iter()
+            .map(|answer_hit| (answer_hit.guid, answer_hit.score))
+            .collect::<Vec<(String, f32)>>()
+        };
+
+        // As a set, repetition is ignored and the shorter "g2" wins the tie.
+        let set_scores = scores_for(KeywordCountMode::Set, "loop loop");
+        assert_eq!(
+            set_scores,
+            vec![("g2".to_string(), 1.0), ("g1".to_string(), 1.0)]
+        );
+        // As a multiset, "loop" counts min(2, 3) = 2 times for "g1" but min(2, 1) = 1 for "g2".
+        let multiset_scores = scores_for(KeywordCountMode::Multiset, "loop loop");
+        assert_eq!(
+            multiset_scores,
+            vec![("g1".to_string(), 2.0), ("g2".to_string(), 1.0)]
+        );
+        // A single query occurrence caps the count at 1 either way.
+        assert_eq!(scores_for(KeywordCountMode::Multiset, "loop"), set_scores);
+    }
+
+    #[test]
+    fn test_min_overlap_excludes_single_token_matches() {
+        let cards = vec![
+            test_card("one", "what is a hash table"),
+            test_card("two", "hash join versus nested loop join"),
+            test_card("three", "hash join strategies"),
+        ];
+        let stopword_set = HashSet::new();
+        let parser_config = ParserConfig::default();
+        let prepared_index = prepare_keyword_index(&cards, &stopword_set, &parser_config);
+        let ranked_guids = |query_text: &str, min_overlap: usize| -> Vec<String> {
+            score_keyword_overlap(
+                query_text,
+                &prepared_index,
+                &stopword_set,
+                &parser_config,
+                10,
+                0.0,
+                min_overlap,
+            )
+            .into_iter()
+            .map(|answer_hit| answer_hit.guid)
+            .collect()
+        };
+
+        assert_eq!(ranked_guids("hash join", 1), vec!["three", "two", "one"]);
+        assert_eq!(ranked_guids("hash join", 2), vec!["three", "two"]);
+        // A one-token query clamps the requirement to 1 rather than matching nothing.
+        assert_eq!(ranked_guids("hash", 2), vec!["three", "one", "two"]);
+    }
+
+    #[test]
+    fn test_several_synonyms_do_not_outrank_the_query_word() {
+        // Two synonyms at 0.5 would tie one exact match and win on overlap if each counted.
+        let cards = vec![
+            test_card("a_synonyms", "function procedure"),
+            test_card("b_exact", "func call"),
+        ];
+        let stopword_set = HashSet::new();
+        let parser_config = ParserConfig::default();
+        let prepared_index = prepare_keyword_index(&cards, &stopword_set, &parser_config);
+
+        let answer_hits = score_keyword_expanded_tokens(
+            &["func".to_string()],
+            &["function".to_string(), "procedure".to_string()],
+            &prepared_index,
+            &parser_config,
+            5,
+            0.0,
+            1,
+        );
+        let ranked_guids: Vec<&str> = answer_hits.iter().map(|hit| hit.guid.as_str()).collect();
+        assert_eq!(ranked_guids, vec!["b_exact", "a_synonyms"]);
+        assert_eq!(answer_hits[1].score, parser_config.synonym_weight);
+    }
+
+    #[test]
+    fn test_empty_query_or_index_returns_no_hits() {
+        let stopword_set = HashSet::from(["what".to_string(), "is".to_string()]);
+        let parser_config = ParserConfig::default();
+        let prepared_index = prepare_keyword_index(
+            &[test_card("g1", "hash table")],
+            &stopword_set,
+            &parser_config,
+        );
+        let empty_index = prepare_keyword_index(&[], &stopword_set, &parser_config);
+
+        // "what is" tokenises to nothing once stopwords are removed.
+        for (query_text, keyword_index) in [
+            ("", &prepared_index),
+            ("what is", &prepared_index),
+            ("hash table", &empty_index),
+            ("", &empty_index),
+        ] {
+            let answer_hits = score_keyword_overlap(
+                query_text,
+                keyword_index,
+                &stopword_set,
+                &parser_config,
+                5,
+                0.0,
+                1,
+            );
+            assert!(answer_hits.is_empty(), "{query_text:?}");
+        }
+    }
+}
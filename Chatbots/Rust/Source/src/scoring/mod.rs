@@ -1,2 +1,1121 @@
+pub mod dice;
+pub mod diversity;
+pub mod hybrid;
+pub mod jaccard;
 pub mod keyword;
+pub mod ngram;
+pub mod proximity;
+pub mod soft_cosine;
 pub mod tfidf;
+
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::config::{ParserConfig, SearchField, TieBreak, TieBreakSecondary};
+use crate::data_model::{AnswerHit, Card};
+use crate::engine::Algorithm;
+use crate::normalise::normalise_for_matching;
+use crate::scoring::dice::score_dice_tokens;
+use crate::scoring::hybrid::score_hybrid_tokens;
+use crate::scoring::jaccard::score_jaccard_tokens;
+use crate::scoring::keyword::{
+    KeywordQuery, PreparedQuestion, explain_keyword, keyword_score_ceiling,
+    prepare_keyword_index_with_progress, score_keyword_query,
+};
+use crate::scoring::ngram::{NgramIndex, build_ngram_index, explain_ngram, score_ngram_tokens};
+use crate::scoring::proximity::{
+    ProximityIndex, build_proximity_index, explain_proximity, score_proximity_tokens,
+};
+use crate::scoring::soft_cosine::{explain_soft_cosine, score_soft_cosine_tokens};
+use crate::scoring::tfidf::{
+    TfidfIndex, build_tfidf_index_with_progress, cosine_components, explain_tfidf,
+    score_tfidf_expanded_tokens,
+};
+use crate::tokenise::tokenise_with_min_length;
+
+/// The card's fields selected by `search_field`, each paired with its configured weight.
+/// Used by TF–IDF to weight question and answer terms separately.
+pub fn card_search_fields(card: &Card, parser_config: &ParserConfig) -> Vec<(String, f32)> {
+    let question_field = (card.question_text.clone(), parser_config.question_weight);
+    let answer_field = (
+        normalise_for_matching(&card.answer_raw, parser_config),
+        parser_config.answer_weight,
+    );
+    match parser_config.search_field {
+        SearchField::Question => vec![question_field],
+        SearchField::Answer => vec![answer_field],
+        SearchField::Both => vec![question_field, answer_field],
+    }
+}
+
+/// Tokens of each field selected by `search_field`, with its weight. Each field is tokenised
+/// with its own `min_token_length`.
+pub fn card_field_tokens(
+    card: &Card,
+    stopword_set: &HashSet<String>,
+    parser_config: &ParserConfig,
+) -> Vec<(Vec<String>, f32)> {
+    let question_minimum = parser_config.min_token_length.question();
+    let answer_minimum = parser_config.min_token_length.answer();
+    let field_minimums = match parser_config.search_field {
+        SearchField::Question => vec![question_minimum],
+        SearchField::Answer => vec![answer_minimum],
+        SearchField::Both => vec![question_minimum, answer_minimum],
+    };
+    card_search_fields(card, parser_config)
+        .into_iter()
+        .zip(field_minimums)
+        .map(|((field_text, field_weight), min_token_length)| {
+            let field_tokens = tokenise_with_min_length(
+                &field_text,
+                stopword_set,
+                parser_config,
+                min_token_length,
+            );
+            (field_tokens, field_weight)
+        })
+        .collect()
+}
+
+/// The card's search tokens in field order (question then answer), for the unweighted scorers.
+pub fn card_search_tokens(
+    card: &Card,
+    stopword_set: &HashSet<String>,
+    parser_config: &ParserConfig,
+) -> Vec<String> {
+    card_field_tokens(card, stopword_set, parser_config)
+        .into_iter()
+        .flat_map(|(field_tokens, _)| field_tokens)
+        .collect()
+}
+
+/// The card's modification time in Unix seconds, from the `recency_field` metadata value
+/// (Unix seconds, as in Anki's `mod` column, or RFC 3339). `None` if absent or unparseable.
+pub fn card_timestamp(card: &Card, parser_config: &ParserConfig) -> Option<i64> {
+    let timestamp_text = card.metadata.get(&parser_config.recency_field)?.trim();
+    timestamp_text.parse::<i64>().ok().or_else(|| {
+        chrono::DateTime::parse_from_rfc3339(timestamp_text)
+            .ok()
+            .map(|date_time| date_time.timestamp())
+    })
+}
+
+/// Oldest and newest timestamp among the candidates, for `recency_factor`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RecencyRange {
+    oldest: i64,
+    newest: i64,
+}
+
+impl RecencyRange {
+    /// The range of the timestamps given, `None` if there are none.
+    pub(crate) fn from_timestamps(timestamps: impl Iterator<Item = Option<i64>>) -> Option<Self> {
+        timestamps.flatten().fold(None, |range, timestamp| {
+            Some(match range {
+                None => Self {
+                    oldest: timestamp,
+                    newest: timestamp,
+                },
+                Some(Self { oldest, newest }) => Self {
+                    oldest: oldest.min(timestamp),
+                    newest: newest.max(timestamp),
+                },
+            })
+        })
+    }
+
+    /// 0 for the oldest candidate up to 1 for the newest; 0 without a timestamp, or when every
+    /// timestamp is the same.
+    pub(crate) fn recency_factor(&self, timestamp: Option<i64>) -> f32 {
+        match timestamp {
+            Some(timestamp) if self.newest > self.oldest => {
+                ((timestamp - self.oldest) as f64 / (self.newest - self.oldest) as f64) as f32
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+/// `recency_factor` of each timestamp within the range of all of them, in the same order.
+pub(crate) fn recency_factors(timestamps: &[Option<i64>]) -> Vec<f32> {
+    let recency_range = RecencyRange::from_timestamps(timestamps.iter().copied());
+    timestamps
+        .iter()
+        .map(|timestamp| recency_range.map_or(0.0, |range| range.recency_factor(*timestamp)))
+        .collect()
+}
+
+/// Cards indexed between calls of an index-build progress callback.
+pub const PROGRESS_INTERVAL_CARDS: usize = 256;
+
+/// Index-build progress callback, called with `(cards_indexed, card_count)` every
+/// `PROGRESS_INTERVAL_CARDS` cards and once more when the last card is indexed.
+pub type ProgressCallback<'a> = &'a dyn Fn(usize, usize);
+
+/// Call `progress` if `cards_indexed` lands on an interval boundary or the end of the build.
+pub(crate) fn report_progress(
+    progress: Option<ProgressCallback>,
+    cards_indexed: usize,
+    card_count: usize,
+) {
+    if let Some(progress) = progress
+        && (cards_indexed.is_multiple_of(PROGRESS_INTERVAL_CARDS) || cards_indexed == card_count)
+    {
+        progress(cards_indexed, card_count);
+    }
+}
+
+/// Order two tied candidates by question token count as `tie_break` asks: shorter first,
+/// longer first, or equal so the GUID decides.
+pub(crate) fn compare_question_length(
+    left_token_count: usize,
+    right_token_count: usize,
+    tie_break: TieBreak,
+) -> Ordering {
+    match tie_break {
+        TieBreak::Shortest => left_token_count.cmp(&right_token_count),
+        TieBreak::Longest => right_token_count.cmp(&left_token_count),
+        TieBreak::GuidOnly => Ordering::Equal,
+    }
+}
+
+/// Order two candidates still tied after the length rule: by deck path when
+/// `tie_break_secondary` asks for it, otherwise equal so the GUID decides.
+pub(crate) fn compare_secondary(
+    left_hit: &AnswerHit,
+    right_hit: &AnswerHit,
+    tie_break_secondary: TieBreakSecondary,
+) -> Ordering {
+    match tie_break_secondary {
+        TieBreakSecondary::Guid => Ordering::Equal,
+        TieBreakSecondary::DeckPath => left_hit.deck_path.cmp(&right_hit.deck_path),
+    }
+}
+
+/// Rescale ranked hits' scores to 0..1 so they compare across algorithms, dividing by the
+/// scorer's `score_ceiling` (scores already bounded by 1 are returned unchanged). Order is
+/// preserved.
+pub fn normalise_hit_scores(
+    answer_hits: Vec<AnswerHit>,
+    query_tokens: &[String],
+    scorer: &dyn Scorer,
+    parser_config: &ParserConfig,
+) -> Vec<AnswerHit> {
+    let Some(score_ceiling) = scorer.score_ceiling(query_tokens, parser_config) else {
+        return answer_hits;
+    };
+    answer_hits
+        .into_iter()
+        .map(|answer_hit| AnswerHit {
+            score: answer_hit.score / score_ceiling,
+            ..answer_hit
+        })
+        .collect()
+}
+
+/// An index built by a `Scorer`, handed back to the same scorer's `score`.
+pub trait ScoredIndex: Any + Send + Sync {}
+
+impl<T: Any + Send + Sync> ScoredIndex for T {}
+
+/// The concrete index behind `scored_index`, or `None` if it was built by another scorer.
+pub fn downcast_index<T: Any>(scored_index: &dyn ScoredIndex) -> Option<&T> {
+    (scored_index as &dyn Any).downcast_ref::<T>()
+}
+
+/// A tokenised query plus the engine options a scorer may honour.
+#[derive(Debug, Clone, Copy)]
+pub struct ScorerQuery<'a> {
+    pub tokens: &'a [String],
+    /// Synonyms of `tokens` (see `ChatbotEngine::with_synonyms`); empty without expansion.
+    pub expanded_tokens: &'a [String],
+    /// Double-quoted phrases of the query text (see `parse_query`).
+    pub phrases: &'a [Vec<String>],
+    /// Distinct tokens a keyword candidate must share (see `ChatbotEngine::with_min_overlap`).
+    pub min_overlap: usize,
+}
+
+/// Why a card scored as it did (see `Scorer::explain`).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScoreExplanation {
+    /// Matched query terms with their contribution to the score.
+    pub term_contributions: Vec<(String, f32)>,
+    /// Cosine numerator and denominator, for scorers ranking by TF–IDF cosine.
+    pub cosine_parts: Option<(f32, f32)>,
+}
+
+/// A retrieval algorithm that can be registered in a `ScorerRegistry` and selected by name.
+pub trait Scorer: Send + Sync {
+    /// The CLI / log name (e.g. `"tfidf"`).
+    fn name(&self) -> &'static str;
+
+    /// Scorers with the same key build identical indices, so an engine indexing several of them
+    /// builds it once. Defaults to `name`.
+    fn index_key(&self) -> &'static str {
+        self.name()
+    }
+
+    /// Index the candidate cards for this scorer, calling `progress` as cards are indexed.
+    fn build_index(
+        &self,
+        candidate_cards: &[Card],
+        stopword_set: &HashSet<String>,
+        parser_config: &ParserConfig,
+        progress: Option<ProgressCallback>,
+    ) -> Box<dyn ScoredIndex>;
+
+    /// Rank the indexed cards against `scorer_query`; no hits if `scored_index` came from
+    /// another scorer.
+    fn score(
+        &self,
+        scored_index: &dyn ScoredIndex,
+        scorer_query: &ScorerQuery,
+        parser_config: &ParserConfig,
+        top_k: usize,
+        min_score: f32,
+    ) -> Vec<AnswerHit>;
+
+    /// The most a card can score against `query_tokens`, for `normalise_hit_scores`; `None`
+    /// when scores are already bounded by 1.
+    fn score_ceiling(
+        &self,
+        _query_tokens: &[String],
+        _parser_config: &ParserConfig,
+    ) -> Option<f32> {
+        None
+    }
+
+    /// Break down `guid`'s score against `query_tokens` for `--explain`; empty by default.
+    fn explain(
+        &self,
+        _scored_index: &dyn ScoredIndex,
+        _query_tokens: &[String],
+        _guid: &str,
+        _parser_config: &ParserConfig,
+    ) -> ScoreExplanation {
+        ScoreExplanation::default()
+    }
+}
+
+/// The built-in algorithms. Keyword, Jaccard and Dice share the keyword index; hybrid blends
+/// keyword and TF–IDF, so it builds both; soft cosine adds a term similarity matrix to TF–IDF.
+impl Scorer for Algorithm {
+    fn name(&self) -> &'static str {
+        Algorithm::name(*self)
+    }
+
+    fn index_key(&self) -> &'static str {
+        match self {
+            Algorithm::Keyword | Algorithm::Jaccard | Algorithm::Dice => Algorithm::Keyword.name(),
+            _ => Algorithm::name(*self),
+        }
+    }
+
+    fn build_index(
+        &self,
+        candidate_cards: &[Card],
+        stopword_set: &HashSet<String>,
+        parser_config: &ParserConfig,
+        progress: Option<ProgressCallback>,
+    ) -> Box<dyn ScoredIndex> {
+        let keyword_index = || {
+            prepare_keyword_index_with_progress(
+                candidate_cards,
+                stopword_set,
+                parser_config,
+                progress,
+            )
+        };
+        let tfidf_index = || {
+            build_tfidf_index_with_progress(candidate_cards, stopword_set, parser_config, progress)
+        };
+        match self {
+            Algorithm::Keyword | Algorithm::Jaccard | Algorithm::Dice => Box::new(keyword_index()),
+            Algorithm::Tfidf => Box::new(tfidf_index()),
+            Algorithm::Ngram => Box::new(build_ngram_index(
+                candidate_cards,
+                stopword_set,
+                parser_config,
+            )),
+            Algorithm::Hybrid => Box::new((keyword_index(), tfidf_index())),
+            Algorithm::TfidfSoft => Box::new(tfidf_index().with_term_similarity(parser_config)),
+            Algorithm::Proximity => Box::new(build_proximity_index(
+                candidate_cards,
+                stopword_set,
+                parser_config,
+            )),
+        }
+    }
+
+    fn score(
+        &self,
+        scored_index: &dyn ScoredIndex,
+        scorer_query: &ScorerQuery,
+        parser_config: &ParserConfig,
+        top_k: usize,
+        min_score: f32,
+    ) -> Vec<AnswerHit> {
+        let query_tokens = scorer_query.tokens;
+        let scored_hits = match self {
+            Algorithm::Keyword => {
+                downcast_index::<Vec<PreparedQuestion>>(scored_index).map(|prepared_index| {
+                    let keyword_query = KeywordQuery {
+                        tokens: query_tokens,
+                        expanded_tokens: scorer_query.expanded_tokens,
+                        phrases: scorer_query.phrases,
+                    };
+                    score_keyword_query(
+                        keyword_query,
+                        prepared_index,
+                        parser_config,
+                        top_k,
+                        min_score,
+                        scorer_query.min_overlap,
+                    )
+                })
+            }
+            Algorithm::Tfidf => downcast_index::<TfidfIndex>(scored_index).map(|tfidf_index| {
+                score_tfidf_expanded_tokens(
+                    query_tokens,
+                    scorer_query.expanded_tokens,
+                    tfidf_index,
+                    parser_config,
+                    top_k,
+                    min_score,
+                )
+            }),
+            Algorithm::Jaccard => {
+                downcast_index::<Vec<PreparedQuestion>>(scored_index).map(|prepared_index| {
+                    score_jaccard_tokens(
+                        query_tokens,
+                        prepared_index,
+                        parser_config,
+                        top_k,
+                        min_score,
+                    )
+                })
+            }
+            Algorithm::Dice => {
+                downcast_index::<Vec<PreparedQuestion>>(scored_index).map(|prepared_index| {
+                    score_dice_tokens(
+                        query_tokens,
+                        prepared_index,
+                        parser_config,
+                        top_k,
+                        min_score,
+                    )
+                })
+            }
+            Algorithm::Ngram => downcast_index::<NgramIndex>(scored_index)
+                .map(|ngram_index| score_ngram_tokens(query_tokens, ngram_index, top_k, min_score)),
+            Algorithm::Hybrid => downcast_index::<(Vec<PreparedQuestion>, TfidfIndex)>(
+                scored_index,
+            )
+            .map(|(prepared_index, tfidf_index)| {
+                score_hybrid_tokens(
+                    query_tokens,
+                    prepared_index,
+                    tfidf_index,
+                    parser_config,
+                    top_k,
+                    min_score,
+                )
+            }),
+            Algorithm::TfidfSoft => downcast_index::<TfidfIndex>(scored_index).map(|tfidf_index| {
+                score_soft_cosine_tokens(query_tokens, tfidf_index, parser_config, top_k, min_score)
+            }),
+            Algorithm::Proximity => {
+                downcast_index::<ProximityIndex>(scored_index).map(|proximity_index| {
+                    score_proximity_tokens(
+                        query_tokens,
+                        proximity_index,
+                        parser_config,
+                        top_k,
+                        min_score,
+                    )
+                })
+            }
+        };
+        scored_hits.unwrap_or_default()
+    }
+
+    /// Keyword scores are overlap counts, so they are divided by the most a card could share
+    /// (see `keyword_score_ceiling`; phrase boosts can still lift a score above 1). Proximity
+    /// scores add at most 1 to the distinct query token count, so they are divided by one more.
+    fn score_ceiling(&self, query_tokens: &[String], parser_config: &ParserConfig) -> Option<f32> {
+        let distinct_token_count = query_tokens.iter().collect::<HashSet<&String>>().len();
+        match self {
+            Algorithm::Keyword if distinct_token_count > 0 => {
+                Some(keyword_score_ceiling(query_tokens, parser_config) as f32)
+            }
+            Algorithm::Proximity if distinct_token_count > 0 => {
+                Some((distinct_token_count + 1) as f32)
+            }
+            _ => None,
+        }
+    }
+
+    fn explain(
+        &self,
+        scored_index: &dyn ScoredIndex,
+        query_tokens: &[String],
+        guid: &str,
+        parser_config: &ParserConfig,
+    ) -> ScoreExplanation {
+        let tfidf_explanation = |tfidf_index: &TfidfIndex| ScoreExplanation {
+            term_contributions: explain_tfidf(query_tokens, tfidf_index, guid, parser_config),
+            cosine_parts: cosine_components(query_tokens, tfidf_index, guid, parser_config),
+        };
+        let term_explanation = |term_contributions: Vec<(String, f32)>| ScoreExplanation {
+            term_contributions,
+            cosine_parts: None,
+        };
+        let explanation = match self {
+            Algorithm::Keyword | Algorithm::Jaccard | Algorithm::Dice => {
+                downcast_index::<Vec<PreparedQuestion>>(scored_index).map(|prepared_index| {
+                    term_explanation(explain_keyword(
+                        query_tokens,
+                        prepared_index,
+                        guid,
+                        parser_config,
+                    ))
+                })
+            }
+            Algorithm::Tfidf => downcast_index::<TfidfIndex>(scored_index).map(tfidf_explanation),
+            // Hybrid's cosine half is the more informative breakdown.
+            Algorithm::Hybrid => {
+                downcast_index::<(Vec<PreparedQuestion>, TfidfIndex)>(scored_index)
+                    .map(|(_, tfidf_index)| tfidf_explanation(tfidf_index))
+            }
+            Algorithm::TfidfSoft => downcast_index::<TfidfIndex>(scored_index).map(|tfidf_index| {
+                term_explanation(explain_soft_cosine(
+                    query_tokens,
+                    tfidf_index,
+                    guid,
+                    parser_config,
+                ))
+            }),
+            Algorithm::Proximity => {
+                downcast_index::<ProximityIndex>(scored_index).map(|proximity_index| {
+                    term_explanation(explain_proximity(query_tokens, proximity_index, guid))
+                })
+            }
+            Algorithm::Ngram => downcast_index::<NgramIndex>(scored_index).map(|ngram_index| {
+                term_explanation(explain_ngram(query_tokens, ngram_index, guid))
+            }),
+        };
+        explanation.unwrap_or_default()
+    }
+}
+
+/// Scorers by name, in registration order (the order `--algo` lists them).
+#[derive(Default)]
+pub struct ScorerRegistry {
+    scorers: Vec<Arc<dyn Scorer>>,
+}
+
+impl ScorerRegistry {
+    /// An empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every built-in algorithm, in `Algorithm::ALL` order.
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        for algorithm in Algorithm::ALL {
+            registry.register(Box::new(algorithm));
+        }
+        registry
+    }
+
+    /// Add a scorer, replacing any registered under the same name.
+    pub fn register(&mut self, scorer: Box<dyn Scorer>) {
+        let scorer: Arc<dyn Scorer> = Arc::from(scorer);
+        match self
+            .scorers
+            .iter_mut()
+            .find(|registered| registered.name() == scorer.name())
+        {
+            Some(registered) => *registered = scorer,
+            None => self.scorers.push(scorer),
+        }
+    }
+
+    pub fn get(&self, scorer_name: &str) -> Option<Arc<dyn Scorer>> {
+        self.scorers
+            .iter()
+            .find(|registered| registered.name() == scorer_name)
+            .cloned()
+    }
+
+    /// Registered scorers, in registration order (e.g. to index an engine for all of them).
+    pub fn scorers(&self) -> &[Arc<dyn Scorer>] {
+        &self.scorers
+    }
+
+    /// Registered names, in registration order.
+    pub fn names(&self) -> Vec<&'static str> {
+        self.scorers
+            .iter()
+            .map(|registered| registered.name())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use super::*;
+    use crate::config::{KeywordCountMode, MinTokenLength};
+    use crate::data_model::{make_hit, test_card};
+    use crate::scoring::keyword::{
+        prepare_keyword_index, prepare_keyword_index_with_progress, score_keyword_overlap,
+        score_keyword_tokens,
+    };
+    use crate::scoring::tfidf::{build_tfidf_index, build_tfidf_index_with_progress, score_tfidf};
+    use crate::tokenise::tokenise;
+
+    fn answer_only_cards() -> Vec<Card> {
+        vec![
+            Card::new(
+                "g1",
+                "what is this structure",
+                "A <b>hashmap</b> stores key/value pairs",
+                vec!["Deck".to_string()],
+            ),
+            Card::new(
+                "g2",
+                "what is a queue",
+                "First in, first out",
+                vec!["Deck".to_string()],
+            ),
+        ]
+    }
+
+    /// Index `cards` for keyword and TF–IDF under one config and run the same query on both.
+    fn keyword_and_tfidf_hits(
+        cards: &[Card],
+        stopword_set: &HashSet<String>,
+        parser_config: &ParserConfig,
+        query_text: &str,
+        top_k: usize,
+        min_score: f32,
+    ) -> (Vec<AnswerHit>, Vec<AnswerHit>) {
+        let keyword_index = prepare_keyword_index(cards, stopword_set, parser_config);
+        let keyword_hits = score_keyword_overlap(
+            query_text,
+            &keyword_index,
+            stopword_set,
+            parser_config,
+            top_k,
+            min_score,
+            1,
+        );
+        let tfidf_index = build_tfidf_index(cards, stopword_set, parser_config);
+        let tfidf_hits = score_tfidf(
+            query_text,
+            &tfidf_index,
+            stopword_set,
+            parser_config,
+            top_k,
+            min_score,
+        );
+        (keyword_hits, tfidf_hits)
+    }
+
+    fn hit_guids(answer_hits: &[AnswerHit]) -> Vec<&str> {
+        answer_hits
+            .iter()
+            .map(|answer_hit| answer_hit.guid.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_progress_callback_ticks_every_interval_and_at_end() {
+        let card_count = 2 * PROGRESS_INTERVAL_CARDS + 1;
+        let cards: Vec<Card> = (0..card_count)
+            .map(|card_index| {
+                test_card(&format!("g{card_index}"), &format!("question {card_index}"))
+            })
+            .collect();
+        let stopword_set = HashSet::new();
+        let parser_config = ParserConfig::default();
+        let expected_ticks = vec![
+            (PROGRESS_INTERVAL_CARDS, card_count),
+            (2 * PROGRESS_INTERVAL_CARDS, card_count),
+            (card_count, card_count),
+        ];
+
+        let keyword_ticks = std::cell::RefCell::new(Vec::new());
+        let record_keyword = |indexed: usize, total: usize| {
+            keyword_ticks.borrow_mut().push((indexed, total));
+        };
+        prepare_keyword_index_with_progress(
+            &cards,
+            &stopword_set,
+            &parser_config,
+            Some(&record_keyword),
+        );
+        assert_eq!(keyword_ticks.into_inner(), expected_ticks);
+
+        let tfidf_ticks = std::cell::RefCell::new(Vec::new());
+        let record_tfidf = |indexed: usize, total: usize| {
+            tfidf_ticks.borrow_mut().push((indexed, total));
+        };
+        build_tfidf_index_with_progress(&cards, &stopword_set, &parser_config, Some(&record_tfidf));
+        assert_eq!(tfidf_ticks.into_inner(), expected_ticks);
+    }
+
+    #[test]
+    fn test_answer_only_term_hits_with_answer_field() {
+        let cards = answer_only_cards();
+        let stopword_set = HashSet::new();
+
+        for (search_field, expect_hit) in [
+            (SearchField::Question, false),
+            (SearchField::Answer, true),
+            (SearchField::Both, true),
+        ] {
+            let parser_config = ParserConfig {
+                search_field,
+                ..Default::default()
+            };
+            let (keyword_hits, tfidf_hits) =
+                keyword_and_tfidf_hits(&cards, &stopword_set, &parser_config, "hashmap", 5, 0.0);
+
+            let expected_guids = if expect_hit { vec!["g1"] } else { vec![] };
+            assert_eq!(hit_guids(&keyword_hits), expected_guids, "{search_field:?}");
+            assert_eq!(hit_guids(&tfidf_hits), expected_guids, "{search_field:?}");
+        }
+    }
+
+    #[test]
+    fn test_normalise_hit_scores_per_algorithm() {
+        let cards = answer_only_cards();
+        let stopword_set = HashSet::new();
+        let parser_config = ParserConfig::default();
+        let query_text = "what is this queue";
+        let query_tokens = tokenise(query_text, &stopword_set, &parser_config);
+        assert_eq!(query_tokens.len(), 4);
+
+        let (keyword_hits, tfidf_hits) =
+            keyword_and_tfidf_hits(&cards, &stopword_set, &parser_config, query_text, 5, 0.0);
+        let raw_scores: Vec<f32> = keyword_hits.iter().map(|hit| hit.score).collect();
+        assert_eq!(raw_scores, vec![3.0, 3.0]);
+        let normalised_hits = normalise_hit_scores(
+            keyword_hits,
+            &query_tokens,
+            &Algorithm::Keyword,
+            &parser_config,
+        );
+        let normalised_scores: Vec<f32> = normalised_hits.iter().map(|hit| hit.score).collect();
+        assert_eq!(normalised_scores, vec![0.75, 0.75]);
+
+        // Cosine is already bounded, so TF–IDF scores pass through untouched.
+        let tfidf_scores: Vec<f32> = tfidf_hits.iter().map(|hit| hit.score).collect();
+        let passed_through_hits =
+            normalise_hit_scores(tfidf_hits, &query_tokens, &Algorithm::Tfidf, &parser_config);
+        let passed_through_scores: Vec<f32> =
+            passed_through_hits.iter().map(|hit| hit.score).collect();
+        assert_eq!(passed_through_scores, tfidf_scores);
+        assert!(
+            passed_through_scores
+                .iter()
+                .all(|score| (0.0..=1.0).contains(score))
+        );
+    }
+
+    #[test]
+    fn test_normalise_hit_scores_divides_by_multiset_size() {
+        let cards = vec![test_card("g1", "push stack stack")];
+        let stopword_set = HashSet::new();
+        let parser_config = ParserConfig {
+            keyword_count_mode: KeywordCountMode::Multiset,
+            ..ParserConfig::default()
+        };
+        let query_tokens = tokenise("stack stack push", &stopword_set, &parser_config);
+        let keyword_index = prepare_keyword_index(&cards, &stopword_set, &parser_config);
+        let keyword_hits =
+            score_keyword_tokens(&query_tokens, &keyword_index, &parser_config, 5, 0.0, 1);
+        assert_eq!(keyword_hits[0].score, 3.0);
+        let normalised_hits = normalise_hit_scores(
+            keyword_hits,
+            &query_tokens,
+            &Algorithm::Keyword,
+            &parser_config,
+        );
+        assert_eq!(normalised_hits[0].score, 1.0);
+
+        // Hybrid blends the keyword score on the same scale, so a perfect match stays at 1.
+        let tfidf_index = build_tfidf_index(&cards, &stopword_set, &parser_config);
+        let hybrid_hits = crate::scoring::hybrid::score_hybrid_tokens(
+            &query_tokens,
+            &keyword_index,
+            &tfidf_index,
+            &parser_config,
+            5,
+            0.0,
+        );
+        assert!(
+            hybrid_hits[0].score <= 1.0 + 1e-6,
+            "{}",
+            hybrid_hits[0].score
+        );
+    }
+
+    #[test]
+    fn test_stopword_weight_lets_all_stopword_query_match() {
+        let cards = vec![
+            test_card("loop", "what is a for loop"),
+            test_card("tree", "binary tree traversal"),
+        ];
+        let stopword_set: HashSet<String> = ["what", "is", "a", "for"]
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        let query_text = "what is a for";
+        let ranked_hits = |stopword_weight: f32| {
+            let parser_config = ParserConfig {
+                remove_stopwords: true,
+                stopword_weight,
+                ..Default::default()
+            };
+            keyword_and_tfidf_hits(&cards, &stopword_set, &parser_config, query_text, 5, 0.0)
+        };
+
+        // Weight 0 keeps hard removal: nothing is left of the query.
+        let (keyword_hits, tfidf_hits) = ranked_hits(0.0);
+        assert!(keyword_hits.is_empty() && tfidf_hits.is_empty());
+
+        // "a" is below `min_token_length`, so three stopwords match at 0.1 each.
+        let (keyword_hits, tfidf_hits) = ranked_hits(0.1);
+        assert_eq!(hit_guids(&keyword_hits), ["loop"]);
+        assert!((keyword_hits[0].score - 0.3).abs() < 1e-6);
+        assert_eq!(hit_guids(&tfidf_hits), ["loop"]);
+        assert!(tfidf_hits[0].score > 0.0);
+    }
+
+    #[test]
+    fn test_stopword_weight_applies_to_stemmed_stopwords() {
+        // With stemming, the kept stopword "was" reaches scoring as "wa".
+        let cards = vec![test_card("loop", "the loop was fast")];
+        let stopword_set: HashSet<String> = ["was"].into_iter().map(str::to_string).collect();
+        let parser_config = ParserConfig {
+            remove_stopwords: true,
+            stopword_weight: 0.1,
+            stem: true,
+            ..Default::default()
+        };
+        let keyword_index = prepare_keyword_index(&cards, &stopword_set, &parser_config);
+        let keyword_hits = score_keyword_overlap(
+            "was",
+            &keyword_index,
+            &stopword_set,
+            &parser_config,
+            5,
+            0.0,
+            1,
+        );
+        assert_eq!(keyword_hits.len(), 1);
+        assert!((keyword_hits[0].score - 0.1).abs() < 1e-6);
+
+        let tfidf_index = build_tfidf_index(&cards, &stopword_set, &parser_config);
+        assert!(tfidf_index.stopword_terms.contains("wa"));
+    }
+
+    #[test]
+    fn test_min_score_drops_weak_hits_before_top_k() {
+        let cards = vec![
+            test_card("strong", "binary search tree"),
+            test_card("weak", "binary numbers and other number systems"),
+        ];
+        let stopword_set = HashSet::new();
+        let parser_config = ParserConfig::default();
+
+        let ranked_hits = |min_score: f32| {
+            keyword_and_tfidf_hits(
+                &cards,
+                &stopword_set,
+                &parser_config,
+                "binary search",
+                5,
+                min_score,
+            )
+        };
+        let (keyword_hits, unfiltered_tfidf_hits) = ranked_hits(0.0);
+        assert_eq!(hit_guids(&keyword_hits), ["strong", "weak"]);
+        assert_eq!(hit_guids(&unfiltered_tfidf_hits), ["strong", "weak"]);
+        assert_eq!(hit_guids(&ranked_hits(2.0).0), ["strong"]);
+        assert!(ranked_hits(3.0).0.is_empty());
+
+        let (_, filtered_tfidf_hits) = ranked_hits(unfiltered_tfidf_hits[1].score + 0.01);
+        assert_eq!(hit_guids(&filtered_tfidf_hits), ["strong"]);
+    }
+
+    #[test]
+    fn test_tie_break_strategies_order_equal_scores() {
+        // Repeating the question doubles its term counts, so both cards tie on score and overlap.
+        let cards = vec![
+            test_card("long", "binary search binary search"),
+            test_card("short", "binary search"),
+            test_card("other", "unrelated topic"),
+        ];
+        let stopword_set = HashSet::new();
+        for (tie_break, expected_guids) in [
+            (TieBreak::Shortest, ["short", "long"]),
+            (TieBreak::Longest, ["long", "short"]),
+            // Without the length rule, "long" wins on GUID order.
+            (TieBreak::GuidOnly, ["long", "short"]),
+        ] {
+            let parser_config = ParserConfig {
+                tie_break,
+                ..ParserConfig::default()
+            };
+            let (keyword_hits, tfidf_hits) = keyword_and_tfidf_hits(
+                &cards,
+                &stopword_set,
+                &parser_config,
+                "binary search",
+                2,
+                0.0,
+            );
+            assert_eq!(keyword_hits[0].score, keyword_hits[1].score);
+            assert_eq!(tfidf_hits[0].score, tfidf_hits[1].score);
+            assert_eq!(
+                hit_guids(&keyword_hits),
+                expected_guids,
+                "keyword with {tie_break:?}"
+            );
+            assert_eq!(
+                hit_guids(&tfidf_hits),
+                expected_guids,
+                "tfidf with {tie_break:?}"
+            );
+        }
+        assert_eq!(
+            serde_json::from_str::<TieBreak>("\"guid_only\"").expect("snake_case name"),
+            TieBreak::GuidOnly
+        );
+    }
+
+    #[test]
+    fn test_tie_break_secondary_orders_equal_hits_by_deck_path() {
+        // Same question text: equal score, overlap, and length. GUID order alone puts "a" first.
+        let deck_card = |guid: &str, deck_path: &[&str]| Card {
+            deck_path: deck_path
+                .iter()
+                .map(|segment| segment.to_string())
+                .collect(),
+            ..test_card(guid, "what is a stack")
+        };
+        let cards = vec![
+            deck_card("a", &["Unit 02", "Stacks"]),
+            deck_card("b", &["Unit 01", "Stacks", "Intro"]),
+            deck_card("c", &["Unit 01", "Queues"]),
+        ];
+        let stopword_set = HashSet::new();
+        for (tie_break_secondary, expected_guids) in [
+            (TieBreakSecondary::Guid, ["a", "b", "c"]),
+            (TieBreakSecondary::DeckPath, ["c", "b", "a"]),
+        ] {
+            let parser_config = ParserConfig {
+                tie_break_secondary,
+                ..ParserConfig::default()
+            };
+            let (keyword_hits, tfidf_hits) =
+                keyword_and_tfidf_hits(&cards, &stopword_set, &parser_config, "stack", 3, 0.0);
+            let keyword_index = prepare_keyword_index(&cards, &stopword_set, &parser_config);
+            let query_tokens = vec!["stack".to_string()];
+            let jaccard_hits =
+                score_jaccard_tokens(&query_tokens, &keyword_index, &parser_config, 3, 0.0);
+            let dice_hits =
+                score_dice_tokens(&query_tokens, &keyword_index, &parser_config, 3, 0.0);
+            for answer_hits in [keyword_hits, tfidf_hits, jaccard_hits, dice_hits] {
+                assert_eq!(
+                    hit_guids(&answer_hits),
+                    expected_guids,
+                    "{tie_break_secondary:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_min_token_length_per_field_keeps_short_question_tokens() {
+        let cards = vec![Card::new(
+            "g1",
+            "what is an os",
+            "An os schedules jobs",
+            vec!["Deck".to_string()],
+        )];
+        let stopword_set = HashSet::new();
+        let asymmetric_config: ParserConfig = serde_json::from_value(serde_json::json!({
+            "remove_stopwords": false,
+            "search_field": "both",
+            "min_token_length": {"question": 2, "answer": 3}
+        }))
+        .expect("per-field minimum");
+        let field_tokens = card_field_tokens(&cards[0], &stopword_set, &asymmetric_config);
+        assert_eq!(field_tokens[0].0, vec!["what", "is", "an", "os"]);
+        assert_eq!(field_tokens[1].0, vec!["schedules", "jobs"]);
+
+        // "os" only survives in the question, so it matches through the question field alone.
+        let tfidf_index = build_tfidf_index(&cards, &stopword_set, &asymmetric_config);
+        assert_eq!(tfidf_index.documents[0].term_frequencies["os"], 1.0);
+        assert_eq!(
+            tokenise("an os", &stopword_set, &asymmetric_config),
+            vec!["an", "os"]
+        );
+
+        // A plain number still applies to every field.
+        let scalar_config: ParserConfig = serde_json::from_value(serde_json::json!({
+            "remove_stopwords": false,
+            "search_field": "both",
+            "min_token_length": 3
+        }))
+        .expect("scalar minimum");
+        assert_eq!(scalar_config.min_token_length, MinTokenLength::Uniform(3));
+        let scalar_tokens = card_search_tokens(&cards[0], &stopword_set, &scalar_config);
+        assert_eq!(scalar_tokens, vec!["what", "schedules", "jobs"]);
+    }
+
+    /// Ranks every card by question length, longest first.
+    struct QuestionLengthScorer;
+
+    impl Scorer for QuestionLengthScorer {
+        fn name(&self) -> &'static str {
+            "length"
+        }
+
+        fn build_index(
+            &self,
+            candidate_cards: &[Card],
+            _stopword_set: &HashSet<String>,
+            _parser_config: &ParserConfig,
+            _progress: Option<ProgressCallback>,
+        ) -> Box<dyn ScoredIndex> {
+            Box::new(candidate_cards.to_vec())
+        }
+
+        fn score(
+            &self,
+            scored_index: &dyn ScoredIndex,
+            _scorer_query: &ScorerQuery,
+            _parser_config: &ParserConfig,
+            top_k: usize,
+            _min_score: f32,
+        ) -> Vec<AnswerHit> {
+            let Some(cards) = downcast_index::<Vec<Card>>(scored_index) else {
+                return Vec::new();
+            };
+            let mut answer_hits: Vec<AnswerHit> = cards
+                .iter()
+                .map(|card| {
+                    make_hit(
+                        card.guid.clone(),
+                        card.deck_path.clone(),
+                        None,
+                        card.question_text.len() as f32,
+                    )
+                })
+                .collect();
+            answer_hits.sort_by(|left, right| right.score.total_cmp(&left.score));
+            answer_hits.truncate(top_k);
+            answer_hits
+        }
+    }
+
+    #[test]
+    fn test_registry_dispatches_to_registered_scorer_by_name() {
+        let mut scorer_registry = ScorerRegistry::builtin();
+        assert_eq!(scorer_registry.names(), Algorithm::ALL.map(Algorithm::name));
+        scorer_registry.register(Box::new(QuestionLengthScorer));
+        assert_eq!(scorer_registry.names().last(), Some(&"length"));
+
+        let cards = answer_only_cards();
+        let stopword_set = HashSet::new();
+        let parser_config = ParserConfig::default();
+        let length_scorer = scorer_registry.get("length").expect("registered");
+        let length_index = length_scorer.build_index(&cards, &stopword_set, &parser_config, None);
+        let scorer_query = |query_tokens| ScorerQuery {
+            tokens: query_tokens,
+            expanded_tokens: &[],
+            phrases: &[],
+            min_overlap: 1,
+        };
+        let answer_hits =
+            length_scorer.score(&*length_index, &scorer_query(&[]), &parser_config, 1, 0.0);
+        assert_eq!(answer_hits[0].guid, "g1");
+
+        // Built-ins go through the same interface, and reject each other's indices.
+        let keyword_scorer = scorer_registry.get("keyword").expect("built in");
+        let keyword_index = keyword_scorer.build_index(&cards, &stopword_set, &parser_config, None);
+        let queue_tokens = ["queue".to_string()];
+        let keyword_hits = keyword_scorer.score(
+            &*keyword_index,
+            &scorer_query(&queue_tokens),
+            &parser_config,
+            1,
+            0.0,
+        );
+        assert_eq!(keyword_hits[0].guid, "g2");
+        assert!(
+            keyword_scorer
+                .score(
+                    &*length_index,
+                    &scorer_query(&queue_tokens),
+                    &parser_config,
+                    1,
+                    0.0
+                )
+                .is_empty()
+        );
+        assert!(scorer_registry.get("missing").is_none());
+    }
+
+    #[test]
+    fn test_recency_boost_ranks_newer_equal_card_first() {
+        // Identical questions, so base scores tie and the GUID tie-break puts the older "g1"
+        // first; one card is dated by Unix seconds and the other by RFC 3339.
+        let dated_card = |guid: &str, modified: &str| Card {
+            metadata: HashMap::from([("modified".to_string(), modified.to_string())]),
+            ..test_card(guid, "what is a stack")
+        };
+        let cards = vec![
+            dated_card("g1", "1600000000"),
+            dated_card("g2", "2024-01-01T00:00:00Z"),
+        ];
+        let stopword_set = HashSet::new();
+        let ranked_guids = |recency_boost: f32| {
+            let parser_config = ParserConfig {
+                recency_boost,
+                ..ParserConfig::default()
+            };
+            let keyword_index = prepare_keyword_index(&cards, &stopword_set, &parser_config);
+            let keyword_hits = score_keyword_overlap(
+                "stack",
+                &keyword_index,
+                &stopword_set,
+                &parser_config,
+                5,
+                0.0,
+                1,
+            );
+            let tfidf_index = build_tfidf_index(&cards, &stopword_set, &parser_config);
+            let tfidf_hits =
+                score_tfidf("stack", &tfidf_index, &stopword_set, &parser_config, 5, 0.0);
+            [keyword_hits, tfidf_hits].map(|hits| {
+                hits.into_iter()
+                    .map(|hit| hit.guid)
+                    .collect::<Vec<String>>()
+            })
+        };
+
+        assert_eq!(ranked_guids(0.0), [vec!["g1", "g2"], vec!["g1", "g2"]]);
+        assert_eq!(ranked_guids(0.5), [vec!["g2", "g1"], vec!["g2", "g1"]]);
+    }
+}
@@ -0,0 +1,307 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::config::ParserConfig;
+use crate::data_model::{AnswerHit, Card, make_hit};
+use crate::scoring::card_search_tokens;
+use crate::tokenise::{character_ngrams, tokenise};
+
+/// Upper bound for a non-identical token pair, so an exact match always outranks a fuzzy one
+/// even when two different tokens happen to share every n-gram.
+const FUZZY_MATCH_CEILING: f32 = 0.99;
+
+/// A distinct card token together with its character n-grams.
+#[derive(Debug, Clone)]
+pub struct NgramToken {
+    pub token_text: String,
+    pub ngram_set: HashSet<String>,
+}
+
+/// A prepared representation of a candidate card for n-gram scoring.
+#[derive(Debug, Clone)]
+pub struct NgramDocument {
+    pub guid: String,
+    pub deck_path: Vec<String>,
+    pub question_preview: String,
+    pub tokens: Vec<NgramToken>,
+    pub token_count: usize,
+}
+
+/// Character n-gram index: per-card token grams plus postings from each gram to the cards containing it.
+#[derive(Debug, Clone)]
+pub struct NgramIndex {
+    pub ngram_size: usize,
+    pub documents: Vec<NgramDocument>,
+    pub postings: HashMap<String, Vec<usize>>,
+}
+
+/// Build the n-gram index over candidate cards using `fuzzy_ngram` as the gram length.
+pub fn build_ngram_index(
+    candidate_cards: &[Card],
+    stopword_set: &HashSet<String>,
+    parser_config: &ParserConfig,
+) -> NgramIndex {
+    let ngram_size = parser_config.fuzzy_ngram;
+    let mut documents: Vec<NgramDocument> = Vec::with_capacity(candidate_cards.len());
+    let mut postings: HashMap<String, Vec<usize>> = HashMap::new();
+
+    for (document_position, card) in candidate_cards.iter().enumerate() {
+        let token_vector = card_search_tokens(card, stopword_set, parser_config);
+        let mut distinct_tokens: Vec<&String> = token_vector
+            .iter()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        distinct_tokens.sort();
+
+        let mut document_ngrams: HashSet<String> = HashSet::new();
+        let tokens: Vec<NgramToken> = distinct_tokens
+            .into_iter()
+            .map(|token_text| {
+                let ngram_set = character_ngrams(token_text, ngram_size);
+                document_ngrams.extend(ngram_set.iter().cloned());
+                NgramToken {
+                    token_text: token_text.clone(),
+                    ngram_set,
+                }
+            })
+            .collect();
+        for ngram_text in document_ngrams {
+            postings
+                .entry(ngram_text)
+                .or_default()
+                .push(document_position);
+        }
+
+        documents.push(NgramDocument {
+            guid: card.guid.clone(),
+            deck_path: card.deck_path.clone(),
+            question_preview: card.question_display.clone(),
+            tokens,
+            token_count: token_vector.len(),
+        });
+    }
+
+    NgramIndex {
+        ngram_size,
+        documents,
+        postings,
+    }
+}
+
+/// Shared n-gram fraction of two tokens (Dice coefficient: 2|A ∩ B| / (|A| + |B|)).
+/// Identical tokens score 1.0; anything else is capped below it.
+fn token_similarity(query_token: &NgramToken, document_token: &NgramToken) -> f32 {
+    if query_token.token_text == document_token.token_text {
+        return 1.0;
+    }
+    let shared_count = query_token
+        .ngram_set
+        .intersection(&document_token.ngram_set)
+        .count();
+    let total_count = query_token.ngram_set.len() + document_token.ngram_set.len();
+    if total_count == 0 {
+        return 0.0;
+    }
+    let similarity = 2.0 * shared_count as f32 / total_count as f32;
+    similarity.min(FUZZY_MATCH_CEILING)
+}
+
+/// Expand each distinct query token into its n-grams, in sorted token order.
+fn prepare_query_tokens(query_tokens: &[String], ngram_size: usize) -> Vec<NgramToken> {
+    let mut distinct_tokens: Vec<String> = query_tokens
+        .iter()
+        .cloned()
+        .collect::<HashSet<String>>()
+        .into_iter()
+        .collect();
+    distinct_tokens.sort();
+    distinct_tokens
+        .into_iter()
+        .map(|token_text| NgramToken {
+            ngram_set: character_ngrams(&token_text, ngram_size),
+            token_text,
+        })
+        .collect()
+}
+
+/// Best similarity of each query token against the document's tokens.
+fn best_token_similarities(query_tokens: &[NgramToken], document: &NgramDocument) -> Vec<f32> {
+    query_tokens
+        .iter()
+        .map(|query_token| {
+            document
+                .tokens
+                .iter()
+                .map(|document_token| token_similarity(query_token, document_token))
+                .fold(0.0_f32, f32::max)
+        })
+        .collect()
+}
+
+/// Score candidates by fuzzy token matching: each query token takes its best shared n-gram
+/// fraction against the card's tokens, and the card score is the mean over query tokens.
+/// Exact token matches score 1.0, so they always outrank misspelt near-matches.
+/// Hits scoring below `min_score` are dropped before truncating to `top_k`.
+/// Tie-breakers, applied only on exactly equal scores:
+/// 1) Shorter candidate text by token count
+/// 2) Lexicographic order of GUID
+pub fn score_ngram(
+    query_text: &str,
+    ngram_index: &NgramIndex,
+    stopword_set: &HashSet<String>,
+    parser_config: &ParserConfig,
+    top_k: usize,
+    min_score: f32,
+) -> Vec<AnswerHit> {
+    let query_tokens = tokenise(query_text, stopword_set, parser_config);
+    score_ngram_tokens(&query_tokens, ngram_index, top_k, min_score)
+}
+
+/// As `score_ngram`, for a query already tokenised (e.g. corrected by `--autocorrect`).
+pub fn score_ngram_tokens(
+    query_tokens: &[String],
+    ngram_index: &NgramIndex,
+    top_k: usize,
+    min_score: f32,
+) -> Vec<AnswerHit> {
+    let query_tokens = prepare_query_tokens(query_tokens, ngram_index.ngram_size);
+    if query_tokens.is_empty() {
+        return Vec::new();
+    }
+
+    // Only cards sharing at least one gram with the query can score above zero.
+    let mut candidate_positions: Vec<usize> = query_tokens
+        .iter()
+        .flat_map(|query_token| query_token.ngram_set.iter())
+        .filter_map(|ngram_text| ngram_index.postings.get(ngram_text))
+        .flatten()
+        .copied()
+        .collect::<HashSet<usize>>()
+        .into_iter()
+        .collect();
+    candidate_positions.sort_unstable();
+
+    let mut scored_hits: Vec<(AnswerHit, usize)> = Vec::with_capacity(candidate_positions.len());
+    for document_position in candidate_positions {
+        let document = &ngram_index.documents[document_position];
+        let token_similarities = best_token_similarities(&query_tokens, document);
+        let score_value = token_similarities.iter().sum::<f32>() / query_tokens.len() as f32;
+        if score_value <= 0.0 {
+            continue;
+        }
+        let answer_hit = make_hit(
+            document.guid.clone(),
+            document.deck_path.clone(),
+            Some(document.question_preview.clone()),
+            score_value,
+        );
+        scored_hits.push((answer_hit, document.token_count));
+    }
+
+    scored_hits.sort_by(|left, right| {
+        right
+            .0
+            .score
+            .partial_cmp(&left.0.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| left.1.cmp(&right.1)) // shorter text wins
+            .then_with(|| left.0.guid.cmp(&right.0.guid))
+    });
+
+    scored_hits
+        .into_iter()
+        .filter(|tuple| tuple.0.score >= min_score)
+        .take(top_k)
+        .map(|tuple| tuple.0)
+        .collect()
+}
+
+/// Per-query-token contributions for `guid`: each token's best similarity divided by the
+/// number of query tokens, so the contributions sum to the hit's score.
+pub fn explain_ngram(
+    query_tokens: &[String],
+    ngram_index: &NgramIndex,
+    guid: &str,
+) -> Vec<(String, f32)> {
+    let Some(document) = ngram_index
+        .documents
+        .iter()
+        .find(|document| document.guid == guid)
+    else {
+        return Vec::new();
+    };
+    let query_tokens = prepare_query_tokens(query_tokens, ngram_index.ngram_size);
+    let token_similarities = best_token_similarities(&query_tokens, document);
+    let query_token_count = query_tokens.len() as f32;
+    query_tokens
+        .into_iter()
+        .zip(token_similarities)
+        .filter(|(_, similarity)| *similarity > 0.0)
+        .map(|(query_token, similarity)| (query_token.token_text, similarity / query_token_count))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_model::test_card;
+
+    #[test]
+    fn test_typo_matches_and_exact_outranks_fuzzy() {
+        let cards = vec![
+            test_card("exact", "define recursion"),
+            test_card("near", "define recursive"),
+            test_card("unrelated", "what is a database"),
+        ];
+        let stopword_set = HashSet::new();
+        let parser_config = ParserConfig::default();
+        let ngram_index = build_ngram_index(&cards, &stopword_set, &parser_config);
+
+        let typo_hits = score_ngram(
+            "recurssion",
+            &ngram_index,
+            &stopword_set,
+            &parser_config,
+            3,
+            0.0,
+        );
+        assert_eq!(typo_hits[0].guid, "exact");
+        assert!(typo_hits[0].score > typo_hits[1].score);
+        assert!(typo_hits.iter().all(|hit| hit.guid != "unrelated"));
+
+        let exact_hits = score_ngram(
+            "recursion",
+            &ngram_index,
+            &stopword_set,
+            &parser_config,
+            3,
+            0.0,
+        );
+        assert_eq!(exact_hits[0].guid, "exact");
+        assert_eq!(exact_hits[0].score, 1.0);
+        assert!(exact_hits[1].score < exact_hits[0].score);
+
+        let contributions = explain_ngram(
+            &tokenise("recursion", &stopword_set, &parser_config),
+            &ngram_index,
+            "near",
+        );
+        assert_eq!(contributions.len(), 1);
+        assert!((contributions[0].1 - exact_hits[1].score).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_one_edit_never_reaches_exact_score() {
+        let query_token = NgramToken {
+            token_text: "aaa".to_string(),
+            ngram_set: character_ngrams("aaa", 3),
+        };
+        let document_token = NgramToken {
+            token_text: "aaaa".to_string(),
+            ngram_set: character_ngrams("aaaa", 3),
+        };
+        // Identical gram sets, different tokens.
+        assert_eq!(query_token.ngram_set, document_token.ngram_set);
+        assert!(token_similarity(&query_token, &document_token) < 1.0);
+    }
+}
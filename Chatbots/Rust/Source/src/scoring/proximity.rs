@@ -0,0 +1,273 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::config::ParserConfig;
+use crate::data_model::{AnswerHit, Card, make_hit};
+use crate::scoring::card_search_tokens;
+use crate::scoring::tfidf::round_score;
+use crate::tokenise::tokenise;
+
+/// A prepared representation of a candidate card for proximity scoring.
+#[derive(Debug, Clone)]
+pub struct ProximityDocument {
+    pub guid: String,
+    pub deck_path: Vec<String>,
+    pub question_preview: String,
+    /// Each distinct token → its ascending positions in the card's token sequence.
+    pub token_positions: HashMap<String, Vec<usize>>,
+    pub token_count: usize,
+}
+
+/// Positional index: per-card token position lists.
+#[derive(Debug, Clone)]
+pub struct ProximityIndex {
+    pub documents: Vec<ProximityDocument>,
+}
+
+/// Build the proximity index over candidate cards. Positions count tokens after stopword
+/// removal, so a dropped stopword does not separate its neighbours.
+pub fn build_proximity_index(
+    candidate_cards: &[Card],
+    stopword_set: &HashSet<String>,
+    parser_config: &ParserConfig,
+) -> ProximityIndex {
+    let documents = candidate_cards
+        .iter()
+        .map(|card| {
+            let token_vector = card_search_tokens(card, stopword_set, parser_config);
+            let mut token_positions: HashMap<String, Vec<usize>> = HashMap::new();
+            for (token_position, token_text) in token_vector.iter().enumerate() {
+                token_positions
+                    .entry(token_text.clone())
+                    .or_default()
+                    .push(token_position);
+            }
+            ProximityDocument {
+                guid: card.guid.clone(),
+                deck_path: card.deck_path.clone(),
+                question_preview: card.question_display.clone(),
+                token_positions,
+                token_count: token_vector.len(),
+            }
+        })
+        .collect();
+    ProximityIndex { documents }
+}
+
+/// Length in tokens of the shortest window holding at least one occurrence of every term whose
+/// positions are given (1 for a single term).
+fn minimum_covering_span(term_positions: &[&Vec<usize>]) -> usize {
+    let mut tagged_positions: Vec<(usize, usize)> = term_positions
+        .iter()
+        .enumerate()
+        .flat_map(|(term_index, positions)| {
+            positions
+                .iter()
+                .map(move |position| (*position, term_index))
+        })
+        .collect();
+    tagged_positions.sort_unstable();
+
+    let mut window_counts = vec![0usize; term_positions.len()];
+    let mut covered_terms = 0;
+    let mut window_start = 0;
+    let mut shortest_span = usize::MAX;
+    for &(end_position, term_index) in &tagged_positions {
+        window_counts[term_index] += 1;
+        if window_counts[term_index] == 1 {
+            covered_terms += 1;
+        }
+        while covered_terms == term_positions.len() {
+            let (start_position, start_term) = tagged_positions[window_start];
+            shortest_span = shortest_span.min(end_position - start_position + 1);
+            window_counts[start_term] -= 1;
+            if window_counts[start_term] == 0 {
+                covered_terms -= 1;
+            }
+            window_start += 1;
+        }
+    }
+    shortest_span
+}
+
+/// Distinct query tokens found in `document` (sorted) and the proximity bonus
+/// `matched count / minimum covering span`, which is 1 when they sit side by side.
+fn matched_terms_and_proximity(
+    query_token_set: &HashSet<String>,
+    document: &ProximityDocument,
+) -> (Vec<String>, f32) {
+    let mut matched_terms: Vec<String> = query_token_set
+        .iter()
+        .filter(|token_text| document.token_positions.contains_key(*token_text))
+        .cloned()
+        .collect();
+    if matched_terms.is_empty() {
+        return (matched_terms, 0.0);
+    }
+    matched_terms.sort();
+    let term_positions: Vec<&Vec<usize>> = matched_terms
+        .iter()
+        .map(|token_text| &document.token_positions[token_text])
+        .collect();
+    let proximity = matched_terms.len() as f32 / minimum_covering_span(&term_positions) as f32;
+    (matched_terms, proximity)
+}
+
+/// Score candidates by overlap plus proximity: the number of distinct query tokens the card
+/// contains, plus that count divided by the shortest token window holding all of them (0–1).
+/// Overlap dominates, so proximity only orders cards sharing as many query terms, putting
+/// adjacent terms ahead of the same terms scattered across a long question.
+/// Hits scoring below `min_score` are dropped before truncating to `top_k`.
+/// Tie-breakers, applied only on exactly equal scores:
+/// 1) Shorter candidate question by token count
+/// 2) Lexicographic order of GUID
+pub fn score_proximity(
+    query_text: &str,
+    proximity_index: &ProximityIndex,
+    stopword_set: &HashSet<String>,
+    parser_config: &ParserConfig,
+    top_k: usize,
+    min_score: f32,
+) -> Vec<AnswerHit> {
+    let query_tokens = tokenise(query_text, stopword_set, parser_config);
+    score_proximity_tokens(
+        &query_tokens,
+        proximity_index,
+        parser_config,
+        top_k,
+        min_score,
+    )
+}
+
+/// As `score_proximity`, for a query already tokenised (e.g. corrected by `--autocorrect`).
+pub fn score_proximity_tokens(
+    query_tokens: &[String],
+    proximity_index: &ProximityIndex,
+    parser_config: &ParserConfig,
+    top_k: usize,
+    min_score: f32,
+) -> Vec<AnswerHit> {
+    let query_token_set: HashSet<String> = query_tokens.iter().cloned().collect();
+
+    let mut scored_hits: Vec<(AnswerHit, usize)> = Vec::new();
+    for document in &proximity_index.documents {
+        let (matched_terms, proximity) = matched_terms_and_proximity(&query_token_set, document);
+        if matched_terms.is_empty() {
+            continue;
+        }
+        let mut score_value = matched_terms.len() as f32 + proximity;
+        if parser_config.deterministic {
+            score_value = round_score(score_value);
+        }
+        let answer_hit = make_hit(
+            document.guid.clone(),
+            document.deck_path.clone(),
+            Some(document.question_preview.clone()),
+            score_value,
+        );
+        scored_hits.push((answer_hit, document.token_count));
+    }
+
+    scored_hits.sort_by(|left, right| {
+        right
+            .0
+            .score
+            .partial_cmp(&left.0.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| left.1.cmp(&right.1)) // shorter question wins
+            .then_with(|| left.0.guid.cmp(&right.0.guid))
+    });
+
+    scored_hits
+        .into_iter()
+        .filter(|tuple| tuple.0.score >= min_score)
+        .take(top_k)
+        .map(|tuple| tuple.0)
+        .collect()
+}
+
+/// Per-matched-term contributions for `guid`: 1 for the overlap plus an equal share of the
+/// proximity bonus, so the contributions sum to the hit's score.
+pub fn explain_proximity(
+    query_tokens: &[String],
+    proximity_index: &ProximityIndex,
+    guid: &str,
+) -> Vec<(String, f32)> {
+    let Some(document) = proximity_index
+        .documents
+        .iter()
+        .find(|document| document.guid == guid)
+    else {
+        return Vec::new();
+    };
+    let query_token_set: HashSet<String> = query_tokens.iter().cloned().collect();
+    let (matched_terms, proximity) = matched_terms_and_proximity(&query_token_set, document);
+    let proximity_share = proximity / matched_terms.len().max(1) as f32;
+    matched_terms
+        .into_iter()
+        .map(|token_text| (token_text, 1.0 + proximity_share))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_model::test_card;
+
+    #[test]
+    fn test_adjacent_terms_beat_scattered_terms() {
+        // Same token set and length; only the distance between "binary" and "search" differs.
+        let cards = vec![
+            test_card("a_scattered", "binary trees make search fast"),
+            test_card("b_adjacent", "binary search make trees fast"),
+        ];
+        let stopword_set = HashSet::new();
+        let parser_config = ParserConfig::default();
+        let proximity_index = build_proximity_index(&cards, &stopword_set, &parser_config);
+
+        let answer_hits = score_proximity(
+            "search binary",
+            &proximity_index,
+            &stopword_set,
+            &parser_config,
+            5,
+            0.0,
+        );
+        let ranked_guids: Vec<&str> = answer_hits.iter().map(|hit| hit.guid.as_str()).collect();
+        assert_eq!(ranked_guids, vec!["b_adjacent", "a_scattered"]);
+        // Two terms matched: adjacent covers a 2-token span, scattered a 4-token one.
+        assert_eq!(answer_hits[0].score, 3.0);
+        assert_eq!(answer_hits[1].score, 2.5);
+
+        let contributions = explain_proximity(
+            &tokenise("search binary", &stopword_set, &parser_config),
+            &proximity_index,
+            "a_scattered",
+        );
+        assert_eq!(
+            contributions,
+            vec![("binary".to_string(), 1.25), ("search".to_string(), 1.25)]
+        );
+    }
+
+    #[test]
+    fn test_deterministic_rounds_proximity_score() {
+        // Two terms over a 3-token span: 2 + 2/3, which is not exact in f32.
+        let cards = vec![test_card("g1", "binary tree search")];
+        let stopword_set = HashSet::new();
+        let parser_config = ParserConfig {
+            deterministic: true,
+            ..ParserConfig::default()
+        };
+        let proximity_index = build_proximity_index(&cards, &stopword_set, &parser_config);
+
+        let answer_hits = score_proximity(
+            "binary search",
+            &proximity_index,
+            &stopword_set,
+            &parser_config,
+            5,
+            0.0,
+        );
+        assert_eq!(answer_hits[0].score, 2.666667);
+    }
+}
@@ -0,0 +1,313 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::config::ParserConfig;
+use crate::data_model::{AnswerHit, make_hit};
+use crate::scoring::tfidf::{
+    DocumentEntry, TfidfIndex, ordered_terms, round_score, weight_query_tokens,
+};
+use crate::scoring::{compare_question_length, compare_secondary};
+use crate::tokenise::tokenise;
+
+/// Term–term similarities from co-occurrence, for soft-cosine TF–IDF.
+/// Only the `max_terms` terms with the highest document frequency get related terms; every
+/// other term is similar only to itself, so scoring falls back to plain cosine for them.
+#[derive(Debug, Clone)]
+pub struct TermSimilarity {
+    /// For each capped term, the other capped terms it shares a document with and their
+    /// similarity `co-occurrences / sqrt(df(a) × df(b))` (in 0..=1).
+    pub related_terms: HashMap<String, HashMap<String, f32>>,
+    /// Soft norm `sqrt(dᵀ S d)` of every document, aligned with `TfidfIndex::documents`.
+    pub document_soft_norms: Vec<f32>,
+    pub max_terms: usize,
+}
+
+/// Count how often each pair of the `max_terms` most frequent terms appears in the same
+/// document and normalise the counts to a similarity. The matrix is the Gram matrix of the
+/// terms' unit document-incidence vectors, so soft cosine stays within 0..=1.
+pub fn build_term_similarity(
+    documents: &[DocumentEntry],
+    document_frequency: &HashMap<String, usize>,
+    max_terms: usize,
+) -> TermSimilarity {
+    // Highest DF first, ties by term, so the cap picks the same terms on every build.
+    let mut terms_by_frequency: Vec<(&String, &usize)> = document_frequency.iter().collect();
+    terms_by_frequency.sort_by(|left, right| right.1.cmp(left.1).then_with(|| left.0.cmp(right.0)));
+    let capped_terms: HashSet<&String> = terms_by_frequency
+        .into_iter()
+        .take(max_terms)
+        .map(|(term_text, _)| term_text)
+        .collect();
+
+    let mut co_occurrence_counts: HashMap<(&String, &String), usize> = HashMap::new();
+    for document_entry in documents {
+        let mut document_terms: Vec<&String> = document_entry
+            .term_frequencies
+            .keys()
+            .filter(|term_text| capped_terms.contains(term_text))
+            .collect();
+        document_terms.sort();
+        for (term_position, left_term) in document_terms.iter().enumerate() {
+            for right_term in &document_terms[term_position + 1..] {
+                *co_occurrence_counts
+                    .entry((*left_term, *right_term))
+                    .or_insert(0) += 1;
+            }
+        }
+    }
+
+    let mut related_terms: HashMap<String, HashMap<String, f32>> = HashMap::new();
+    for ((left_term, right_term), pair_count) in co_occurrence_counts {
+        let similarity = pair_count as f32
+            / ((document_frequency[left_term] * document_frequency[right_term]) as f32).sqrt();
+        related_terms
+            .entry(left_term.clone())
+            .or_default()
+            .insert(right_term.clone(), similarity);
+        related_terms
+            .entry(right_term.clone())
+            .or_default()
+            .insert(left_term.clone(), similarity);
+    }
+
+    let document_soft_norms = documents
+        .iter()
+        .map(|document_entry| {
+            let document_weights = document_weight_map(document_entry);
+            soft_inner_product(&document_weights, &document_weights, &related_terms).sqrt()
+        })
+        .collect();
+
+    TermSimilarity {
+        related_terms,
+        document_soft_norms,
+        max_terms,
+    }
+}
+
+/// A document's TF–IDF weights, in sorted term order.
+fn document_weight_map(document_entry: &DocumentEntry) -> Vec<(&String, f32)> {
+    ordered_terms(&document_entry.weighted_terms)
+        .into_iter()
+        .map(|(term_text, weighted_value)| (term_text, *weighted_value))
+        .collect()
+}
+
+/// `leftᵀ S right`: exact term matches count fully, related terms by their similarity.
+/// Both sides are in sorted term order, so sums accumulate identically on every run.
+fn soft_inner_product(
+    left_weights: &[(&String, f32)],
+    right_weights: &[(&String, f32)],
+    related_terms: &HashMap<String, HashMap<String, f32>>,
+) -> f32 {
+    let mut product_sum: f32 = 0.0;
+    for (left_term, left_weight) in left_weights {
+        product_sum += left_weight * soft_term_match(left_term, right_weights, related_terms);
+    }
+    product_sum
+}
+
+/// How strongly `term_text` is present in `weights`: its own weight plus every related term's
+/// weight scaled by the similarity.
+fn soft_term_match(
+    term_text: &String,
+    weights: &[(&String, f32)],
+    related_terms: &HashMap<String, HashMap<String, f32>>,
+) -> f32 {
+    let term_similarities = related_terms.get(term_text);
+    weights
+        .iter()
+        .map(|(other_term, other_weight)| {
+            if *other_term == term_text {
+                *other_weight
+            } else {
+                term_similarities
+                    .and_then(|similarities| similarities.get(*other_term))
+                    .map_or(0.0, |similarity| similarity * other_weight)
+            }
+        })
+        .sum()
+}
+
+/// Rank candidates by soft cosine `qᵀ S d / (sqrt(qᵀ S q) × sqrt(dᵀ S d))` over TF–IDF vectors,
+/// so a query term also matches terms it often shares a question with. Needs an index built by
+/// `build_soft_tfidf_index`; returns no hits otherwise.
+/// Hits scoring below `min_score` are dropped before truncating to `top_k`.
+/// Tie-breakers as for `score_tfidf`: exact overlap, question length per `tie_break`, deck path
+/// per `tie_break_secondary`, GUID.
+pub fn score_soft_cosine(
+    query_text: &str,
+    tfidf_index: &TfidfIndex,
+    stopword_set: &HashSet<String>,
+    parser_config: &ParserConfig,
+    top_k: usize,
+    min_score: f32,
+) -> Vec<AnswerHit> {
+    let query_token_vector = tokenise(query_text, stopword_set, parser_config);
+    score_soft_cosine_tokens(
+        &query_token_vector,
+        tfidf_index,
+        parser_config,
+        top_k,
+        min_score,
+    )
+}
+
+/// As `score_soft_cosine`, for a query already tokenised (e.g. by a `QueryCache`).
+pub fn score_soft_cosine_tokens(
+    query_token_vector: &[String],
+    tfidf_index: &TfidfIndex,
+    parser_config: &ParserConfig,
+    top_k: usize,
+    min_score: f32,
+) -> Vec<AnswerHit> {
+    let Some(term_similarity) = &tfidf_index.term_similarity else {
+        return Vec::new();
+    };
+    let query_weighted_map = weight_query_tokens(query_token_vector, tfidf_index, parser_config);
+    let query_weights: Vec<(&String, f32)> = ordered_terms(&query_weighted_map)
+        .into_iter()
+        .map(|(term_text, weight)| (term_text, *weight))
+        .collect();
+    let related_terms = &term_similarity.related_terms;
+    let query_soft_norm = soft_inner_product(&query_weights, &query_weights, related_terms).sqrt();
+    if query_soft_norm == 0.0 {
+        return Vec::new();
+    }
+    let query_token_set: HashSet<&String> = query_token_vector.iter().collect();
+
+    let mut scored_hits: Vec<(AnswerHit, usize, usize)> = Vec::new();
+    for (document_index, document_entry) in tfidf_index.documents.iter().enumerate() {
+        let document_soft_norm = term_similarity.document_soft_norms[document_index];
+        if document_soft_norm == 0.0 {
+            continue;
+        }
+        let document_weights = document_weight_map(document_entry);
+        let dot_product_sum = soft_inner_product(&query_weights, &document_weights, related_terms);
+        if dot_product_sum <= 0.0 {
+            continue;
+        }
+
+        let mut soft_similarity = dot_product_sum / (query_soft_norm * document_soft_norm);
+        if parser_config.deterministic {
+            soft_similarity = round_score(soft_similarity);
+        }
+        let overlap_count = document_entry
+            .term_frequencies
+            .keys()
+            .filter(|term_text| query_token_set.contains(term_text))
+            .count();
+        scored_hits.push((
+            make_hit(
+                document_entry.guid.clone(),
+                document_entry.deck_path.clone(),
+                Some(document_entry.question_preview.clone()),
+                soft_similarity,
+            ),
+            overlap_count,
+            document_entry.token_count,
+        ));
+    }
+
+    scored_hits.sort_by(|left, right| {
+        right
+            .0
+            .score
+            .partial_cmp(&left.0.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| right.1.cmp(&left.1))
+            .then_with(|| compare_question_length(left.2, right.2, parser_config.tie_break))
+            .then_with(|| compare_secondary(&left.0, &right.0, parser_config.tie_break_secondary))
+            .then_with(|| left.0.guid.cmp(&right.0.guid))
+    });
+
+    scored_hits
+        .into_iter()
+        .filter(|(answer_hit, _, _)| answer_hit.score >= min_score)
+        .take(top_k)
+        .map(|(answer_hit, _, _)| answer_hit)
+        .collect()
+}
+
+/// Explain one document's soft-cosine score: each query term with its share of the numerator
+/// `qᵀ S d` (exact and related matches together), in term order. Terms contributing nothing are
+/// left out; empty if `guid` is not in the index or it has no term similarities.
+pub fn explain_soft_cosine(
+    query_token_vector: &[String],
+    tfidf_index: &TfidfIndex,
+    guid: &str,
+    parser_config: &ParserConfig,
+) -> Vec<(String, f32)> {
+    let (Some(term_similarity), Some(document_entry)) = (
+        &tfidf_index.term_similarity,
+        tfidf_index
+            .documents
+            .iter()
+            .find(|entry| entry.guid == guid),
+    ) else {
+        return Vec::new();
+    };
+    let query_weighted_map = weight_query_tokens(query_token_vector, tfidf_index, parser_config);
+    let document_weights = document_weight_map(document_entry);
+
+    ordered_terms(&query_weighted_map)
+        .into_iter()
+        .map(|(term_text, query_weight)| {
+            let term_match =
+                soft_term_match(term_text, &document_weights, &term_similarity.related_terms);
+            (term_text.clone(), query_weight * term_match)
+        })
+        .filter(|(_, contribution)| *contribution > 0.0)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_model::test_card;
+    use crate::scoring::tfidf::{build_soft_tfidf_index, build_tfidf_index, score_tfidf};
+
+    #[test]
+    fn test_co_occurring_term_scores_without_exact_overlap() {
+        // "cpu" and "processor" share most questions; the target card only says "processor".
+        let cards = vec![
+            test_card("pair1", "cpu processor cores"),
+            test_card("pair2", "cpu processor clock"),
+            test_card("pair3", "cpu processor cache"),
+            test_card("target", "processor pipeline"),
+            test_card("other", "disk storage"),
+        ];
+        let stopword_set = HashSet::new();
+        let parser_config = ParserConfig::default();
+
+        let plain_index = build_tfidf_index(&cards, &stopword_set, &parser_config);
+        let plain_hits = score_tfidf("cpu", &plain_index, &stopword_set, &parser_config, 5, 0.0);
+        assert!(plain_hits.iter().all(|hit| hit.guid != "target"));
+
+        let soft_index = build_soft_tfidf_index(&cards, &stopword_set, &parser_config);
+        let soft_hits =
+            score_soft_cosine("cpu", &soft_index, &stopword_set, &parser_config, 5, 0.0);
+        let target_hit = soft_hits
+            .iter()
+            .find(|hit| hit.guid == "target")
+            .expect("soft match");
+        assert!(target_hit.score > 0.0 && target_hit.score <= 1.0);
+        // Exact matches still rank first, and unrelated cards stay out.
+        assert!(soft_hits[0].guid.starts_with("pair"));
+        assert!(soft_hits.iter().all(|hit| hit.guid != "other"));
+
+        let explanation =
+            explain_soft_cosine(&["cpu".to_string()], &soft_index, "target", &parser_config);
+        assert_eq!(explanation.len(), 1);
+        assert_eq!(explanation[0].0, "cpu");
+
+        // With a cap of one term only "processor" is tracked, so nothing relates to "cpu".
+        let capped_config = ParserConfig {
+            soft_cosine_terms: 1,
+            ..ParserConfig::default()
+        };
+        let capped_index = build_soft_tfidf_index(&cards, &stopword_set, &capped_config);
+        let capped_hits =
+            score_soft_cosine("cpu", &capped_index, &stopword_set, &capped_config, 5, 0.0);
+        assert!(capped_hits.iter().all(|hit| hit.guid != "target"));
+    }
+}
@@ -1,9 +1,17 @@
 use std::collections::HashMap;
 use std::collections::HashSet;
 
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
 use crate::config::ParserConfig;
-use crate::data_model::{make_hit, AnswerHit, Card};
-use crate::tokenise::{tokenise, tokenise_to_set};
+use crate::data_model::{AnswerHit, Card, make_hit};
+use crate::scoring::soft_cosine::{TermSimilarity, build_term_similarity};
+use crate::scoring::{
+    ProgressCallback, card_field_tokens, card_timestamp, compare_question_length,
+    compare_secondary, recency_factors, report_progress,
+};
+use crate::tokenise::{token_weight, tokenise, weighted_stopword_terms};
 
 /// A single TF vector for a document (question), with metadata for display.
 #[derive(Debug, Clone)]
@@ -12,7 +20,14 @@ pub struct DocumentEntry {
     pub deck_path: Vec<String>,
     pub question_preview: String,
     pub term_frequencies: HashMap<String, f32>,
+    /// `tf * idf` for every term, filled once the corpus IDF is known so scoring skips the
+    /// IDF lookups.
+    pub weighted_terms: HashMap<String, f32>,
     pub token_count: usize,
+    /// Modification time from the card's metadata, for `recency_boost`.
+    pub timestamp: Option<i64>,
+    /// `timestamp` scaled from 0 (oldest document in the index) to 1 (newest), set at build.
+    pub recency: f32,
 }
 
 /// The TF–IDF index with per-document TF maps, global IDF weights, and precomputed norms.
@@ -21,157 +36,495 @@ pub struct DocumentEntry {
 pub struct TfidfIndex {
     pub documents: Vec<DocumentEntry>,
     pub inverse_document_frequency: HashMap<String, f32>,
+    /// Number of documents containing each term (the vocabulary, with counts).
+    pub document_frequency: HashMap<String, usize>,
     pub document_l2_norms: Vec<f32>,
+    /// Terms weighted by IDF, after `min_df` / `max_df_ratio` pruning.
     pub vocabulary_size: usize,
+    /// Distinct terms before pruning (equal to `vocabulary_size` when nothing is pruned).
+    pub unpruned_vocabulary_size: usize,
     pub document_count: usize,
+    /// Vocabulary terms that are stopwords kept at `stopword_weight` (empty when removed).
+    pub stopword_terms: HashSet<String>,
+    /// Co-occurrence similarities for `tfidf-soft`; only built by `build_soft_tfidf_index`.
+    pub term_similarity: Option<TermSimilarity>,
 }
 
-/// Build a TF–IDF index over the candidate cards (question text only).
-/// - TF = raw term count
-/// - IDF = log((N + 1) / (df + 1)) + 1
-/// - Document vectors L2-normalised during scoring via precomputed norms
+/// Build a TF–IDF index over the candidate cards (text selected by `search_field`).
+/// - TF = term count weighted by field (`question_weight` / `answer_weight`), or 1 + ln(count)
+///   with `sublinear_tf` (applied to queries too)
+/// - IDF = log((N + 1) / (df + 1)) + 1, or log(N / df) (floored at 0) with `idf_smoothing` off
+/// - Terms with df below `min_df` or above `max_df_ratio * N` get no IDF, so they drop out of
+///   document vectors, norms and query weights
+/// - Document `tf * idf` weights and their L2 norms precomputed; vectors are normalised during
+///   scoring via the norms
 pub fn build_tfidf_index(
     candidate_cards: &[Card],
     stopword_set: &HashSet<String>,
     parser_config: &ParserConfig,
 ) -> TfidfIndex {
-    let mut document_entries: Vec<DocumentEntry> = Vec::with_capacity(candidate_cards.len());
-    let mut document_frequency_map: HashMap<String, usize> = HashMap::new();
+    build_tfidf_index_with_progress(candidate_cards, stopword_set, parser_config, None)
+}
 
-    // 1) Build documents with raw TF and gather DF
-    for card in candidate_cards {
-        let token_vector = tokenise(&card.question_text, stopword_set, parser_config);
-        let token_count = token_vector.len();
+/// As `build_tfidf_index`, calling `progress` as documents are built.
+pub fn build_tfidf_index_with_progress(
+    candidate_cards: &[Card],
+    stopword_set: &HashSet<String>,
+    parser_config: &ParserConfig,
+    progress: Option<ProgressCallback>,
+) -> TfidfIndex {
+    let weighted_stopwords = weighted_stopword_terms(stopword_set, parser_config);
 
-        let mut term_counts: HashMap<String, usize> = HashMap::new();
-        for token_text in token_vector {
-            *term_counts.entry(token_text).or_insert(0) += 1;
+    // 1) Build documents with raw TF, then gather DF (per-thread maps merged when parallel).
+    // With a progress callback, parallel builds run in interval-sized chunks so it is called
+    // from this thread.
+    #[cfg(feature = "parallel")]
+    let (mut document_entries, document_frequency_map) = {
+        let chunk_size = match progress {
+            Some(_) => crate::scoring::PROGRESS_INTERVAL_CARDS,
+            None => candidate_cards.len().max(1),
+        };
+        let mut document_entries: Vec<DocumentEntry> = Vec::with_capacity(candidate_cards.len());
+        for card_chunk in candidate_cards.chunks(chunk_size) {
+            document_entries.par_extend(card_chunk.par_iter().map(|card| {
+                build_document_entry(card, stopword_set, &weighted_stopwords, parser_config)
+            }));
+            report_progress(progress, document_entries.len(), candidate_cards.len());
         }
+        let document_frequency_map = document_entries
+            .par_iter()
+            .fold(HashMap::new, |mut partial_map, document_entry| {
+                add_document_frequencies(&mut partial_map, document_entry);
+                partial_map
+            })
+            .reduce(HashMap::new, merge_document_frequencies);
+        (document_entries, document_frequency_map)
+    };
 
-        // Increment DF once per unique term in this document
-        let unique_terms = term_counts.keys().cloned().collect::<HashSet<String>>();
-        for unique_term in unique_terms {
-            *document_frequency_map.entry(unique_term).or_insert(0) += 1;
+    #[cfg(not(feature = "parallel"))]
+    let (mut document_entries, document_frequency_map) = {
+        let mut document_entries: Vec<DocumentEntry> = Vec::with_capacity(candidate_cards.len());
+        let mut document_frequency_map: HashMap<String, usize> = HashMap::new();
+        for card in candidate_cards {
+            let document_entry =
+                build_document_entry(card, stopword_set, &weighted_stopwords, parser_config);
+            add_document_frequencies(&mut document_frequency_map, &document_entry);
+            document_entries.push(document_entry);
+            report_progress(progress, document_entries.len(), candidate_cards.len());
         }
-
-        // Convert usize counts to f32 early for speed later
-        let term_frequencies = term_counts
-            .into_iter()
-            .map(|(term_text, count_value)| (term_text, count_value as f32))
-            .collect::<HashMap<String, f32>>();
-
-        let document_entry = DocumentEntry {
-            guid: card.guid.clone(),
-            deck_path: card.deck_path.clone(),
-            question_preview: card.question_text.clone(),
-            term_frequencies,
-            token_count,
-        };
-        document_entries.push(document_entry);
-    }
+        (document_entries, document_frequency_map)
+    };
 
     let document_count = document_entries.len();
-    let mut inverse_document_frequency: HashMap<String, f32> = HashMap::new();
 
-    // 2) Compute IDF with smoothing: log((N + 1) / (df + 1)) + 1
-    for (term_text, document_frequency) in document_frequency_map.into_iter() {
-        let numerator = (document_count as f32) + 1.0;
-        let denominator = (document_frequency as f32) + 1.0;
-        let idf_value = (numerator / denominator).ln() + 1.0;
-        inverse_document_frequency.insert(term_text, idf_value);
-    }
+    // 2) Compute IDF, smoothed unless `idf_smoothing` is off
+    let inverse_document_frequency =
+        compute_inverse_document_frequency(&document_frequency_map, document_count, parser_config);
 
-    // 3) Precompute document vector norms (L2)
-    let mut document_l2_norms: Vec<f32> = Vec::with_capacity(document_entries.len());
-    for document_entry in &document_entries {
-        let mut squared_sum: f32 = 0.0;
-        for (term_text, term_frequency) in &document_entry.term_frequencies {
-            let idf_value = *inverse_document_frequency.get(term_text).unwrap_or(&0.0);
-            let weighted_value = (*term_frequency) * idf_value;
-            squared_sum += weighted_value * weighted_value;
-        }
-        document_l2_norms.push(squared_sum.sqrt());
-    }
+    // 3) Precompute document weights and vector norms (L2); indices stay aligned with `documents`
+    fill_weighted_terms(&mut document_entries, &inverse_document_frequency);
+    fill_recency(&mut document_entries);
+    let document_l2_norms = compute_document_l2_norms(&document_entries);
 
     let vocabulary_size = inverse_document_frequency.len();
+    let unpruned_vocabulary_size = document_frequency_map.len();
+    let stopword_terms =
+        collect_stopword_terms(&document_frequency_map, &weighted_stopwords, parser_config);
 
     TfidfIndex {
         documents: document_entries,
         inverse_document_frequency,
+        document_frequency: document_frequency_map,
         document_l2_norms,
         vocabulary_size,
+        unpruned_vocabulary_size,
         document_count,
+        stopword_terms,
+        term_similarity: None,
     }
 }
 
+/// As `build_tfidf_index`, plus the term–term similarity matrix over the `soft_cosine_terms`
+/// most frequent terms that `tfidf-soft` needs. Kept separate so plain TF–IDF builds skip the
+/// co-occurrence counting.
+pub fn build_soft_tfidf_index(
+    candidate_cards: &[Card],
+    stopword_set: &HashSet<String>,
+    parser_config: &ParserConfig,
+) -> TfidfIndex {
+    build_tfidf_index(candidate_cards, stopword_set, parser_config)
+        .with_term_similarity(parser_config)
+}
+
+impl TfidfIndex {
+    /// Attach the term similarity matrix `tfidf-soft` needs (see `build_soft_tfidf_index`).
+    pub fn with_term_similarity(mut self, parser_config: &ParserConfig) -> Self {
+        self.term_similarity = Some(build_term_similarity(
+            &self.documents,
+            &self.document_frequency,
+            parser_config.soft_cosine_terms,
+        ));
+        self
+    }
+
+    /// Append `new_cards` without re-tokenising the existing documents.
+    /// Every IDF depends on the document count, so adding documents shifts the weight of every
+    /// term and therefore every norm. IDF, document weights, and all norms are recomputed from
+    /// the stored DF and TF maps (cheap next to tokenising), so the result ranks exactly like a
+    /// from-scratch build over the combined cards. Use the same `stopword_set` and
+    /// `parser_config` as the original build; GUIDs are not deduplicated against existing
+    /// documents. A term similarity matrix, if built, is rebuilt too.
+    pub fn add_documents(
+        &mut self,
+        new_cards: &[Card],
+        stopword_set: &HashSet<String>,
+        parser_config: &ParserConfig,
+    ) {
+        if new_cards.is_empty() {
+            return;
+        }
+        let weighted_stopwords = weighted_stopword_terms(stopword_set, parser_config);
+        for card in new_cards {
+            let document_entry =
+                build_document_entry(card, stopword_set, &weighted_stopwords, parser_config);
+            add_document_frequencies(&mut self.document_frequency, &document_entry);
+            self.documents.push(document_entry);
+        }
+        self.document_count = self.documents.len();
+        self.inverse_document_frequency = compute_inverse_document_frequency(
+            &self.document_frequency,
+            self.document_count,
+            parser_config,
+        );
+        fill_weighted_terms(&mut self.documents, &self.inverse_document_frequency);
+        fill_recency(&mut self.documents);
+        self.document_l2_norms = compute_document_l2_norms(&self.documents);
+        self.vocabulary_size = self.inverse_document_frequency.len();
+        self.unpruned_vocabulary_size = self.document_frequency.len();
+        self.stopword_terms =
+            collect_stopword_terms(&self.document_frequency, &weighted_stopwords, parser_config);
+        if let Some(term_similarity) = &self.term_similarity {
+            self.term_similarity = Some(build_term_similarity(
+                &self.documents,
+                &self.document_frequency,
+                term_similarity.max_terms,
+            ));
+        }
+    }
+}
+
+/// Vocabulary terms weighted as stopwords (see `token_weight`).
+fn collect_stopword_terms(
+    document_frequency_map: &HashMap<String, usize>,
+    weighted_stopwords: &HashSet<String>,
+    parser_config: &ParserConfig,
+) -> HashSet<String> {
+    document_frequency_map
+        .keys()
+        .filter(|term_text| token_weight(term_text, weighted_stopwords, parser_config) != 1.0)
+        .cloned()
+        .collect()
+}
+
+/// IDF for every term kept by `min_df` / `max_df_ratio`: log((N + 1) / (df + 1)) + 1 when
+/// `idf_smoothing` is set, else log(N / df). The unsmoothed form is 0 for a term in every
+/// document and is floored at 0 so it can never go negative. With no documents there are no
+/// terms, so the map is empty.
+fn compute_inverse_document_frequency(
+    document_frequency_map: &HashMap<String, usize>,
+    document_count: usize,
+    parser_config: &ParserConfig,
+) -> HashMap<String, f32> {
+    let max_document_frequency = parser_config.max_df_ratio * document_count as f32;
+    let mut inverse_document_frequency: HashMap<String, f32> =
+        HashMap::with_capacity(document_frequency_map.len());
+    for (term_text, document_frequency) in document_frequency_map {
+        if *document_frequency < parser_config.min_df
+            || *document_frequency as f32 > max_document_frequency
+        {
+            continue;
+        }
+        let idf_value = if parser_config.idf_smoothing {
+            let numerator = (document_count as f32) + 1.0;
+            let denominator = (*document_frequency as f32) + 1.0;
+            (numerator / denominator).ln() + 1.0
+        } else if *document_frequency == 0 || document_count == 0 {
+            0.0
+        } else {
+            ((document_count as f32) / (*document_frequency as f32))
+                .ln()
+                .max(0.0)
+        };
+        inverse_document_frequency.insert(term_text.clone(), idf_value);
+    }
+    inverse_document_frequency
+}
+
+/// Set every document's `weighted_terms` to its term frequencies times the corpus IDF, leaving
+/// out pruned terms (those without an IDF).
+fn fill_weighted_terms(
+    document_entries: &mut [DocumentEntry],
+    inverse_document_frequency: &HashMap<String, f32>,
+) {
+    #[cfg(feature = "parallel")]
+    let document_iterator = document_entries.par_iter_mut();
+    #[cfg(not(feature = "parallel"))]
+    let document_iterator = document_entries.iter_mut();
+
+    document_iterator.for_each(|document_entry| {
+        document_entry.weighted_terms = document_entry
+            .term_frequencies
+            .iter()
+            .filter_map(|(term_text, term_frequency)| {
+                let idf_value = inverse_document_frequency.get(term_text)?;
+                Some((term_text.clone(), term_frequency * idf_value))
+            })
+            .collect();
+    });
+}
+
+/// Set each document's `recency` within the range of every document's timestamp.
+fn fill_recency(document_entries: &mut [DocumentEntry]) {
+    let timestamps: Vec<Option<i64>> = document_entries
+        .iter()
+        .map(|document_entry| document_entry.timestamp)
+        .collect();
+    for (document_entry, recency) in document_entries
+        .iter_mut()
+        .zip(recency_factors(&timestamps))
+    {
+        document_entry.recency = recency;
+    }
+}
+
+/// L2 norm of every document, aligned with `document_entries`.
+fn compute_document_l2_norms(document_entries: &[DocumentEntry]) -> Vec<f32> {
+    #[cfg(feature = "parallel")]
+    let document_iterator = document_entries.par_iter();
+    #[cfg(not(feature = "parallel"))]
+    let document_iterator = document_entries.iter();
+
+    document_iterator.map(document_l2_norm).collect()
+}
+
+/// Tokenise one card's fields and build its TF document entry; each occurrence counts the
+/// weight of the field it came from (times `stopword_weight` for a kept stopword).
+fn build_document_entry(
+    card: &Card,
+    stopword_set: &HashSet<String>,
+    weighted_stopwords: &HashSet<String>,
+    parser_config: &ParserConfig,
+) -> DocumentEntry {
+    let mut token_count = 0;
+    let mut term_counts: HashMap<String, f32> = HashMap::new();
+    for (token_vector, field_weight) in card_field_tokens(card, stopword_set, parser_config) {
+        token_count += token_vector.len();
+        for token_text in token_vector {
+            let occurrence_weight =
+                field_weight * token_weight(&token_text, weighted_stopwords, parser_config);
+            *term_counts.entry(token_text).or_insert(0.0) += occurrence_weight;
+        }
+    }
+
+    // Convert usize counts to f32 early for speed later
+    let term_frequencies = term_counts
+        .into_iter()
+        .map(|(term_text, count_value)| {
+            (
+                term_text,
+                term_frequency_weight(count_value, parser_config.sublinear_tf),
+            )
+        })
+        .collect::<HashMap<String, f32>>();
+
+    DocumentEntry {
+        guid: card.guid.clone(),
+        deck_path: card.deck_path.clone(),
+        question_preview: card.question_display.clone(),
+        term_frequencies,
+        weighted_terms: HashMap::new(),
+        token_count,
+        timestamp: card_timestamp(card, parser_config),
+        recency: 0.0,
+    }
+}
+
+/// TF weight for a (possibly field-weighted) term count: the count itself, or `1 + ln(count)`
+/// when sublinear. Sublinear scaling leaves counts below 1 linear so they stay positive.
+fn term_frequency_weight(count_value: f32, sublinear_tf: bool) -> f32 {
+    if sublinear_tf && count_value > 1.0 {
+        1.0 + count_value.ln()
+    } else {
+        count_value
+    }
+}
+
+/// Increment DF once per unique term in this document.
+fn add_document_frequencies(
+    document_frequency_map: &mut HashMap<String, usize>,
+    document_entry: &DocumentEntry,
+) {
+    for unique_term in document_entry.term_frequencies.keys() {
+        *document_frequency_map
+            .entry(unique_term.clone())
+            .or_insert(0) += 1;
+    }
+}
+
+/// Merge two partial DF maps (the reduction step of the parallel build).
+#[cfg(feature = "parallel")]
+fn merge_document_frequencies(
+    mut left_map: HashMap<String, usize>,
+    right_map: HashMap<String, usize>,
+) -> HashMap<String, usize> {
+    for (term_text, document_frequency) in right_map {
+        *left_map.entry(term_text).or_insert(0) += document_frequency;
+    }
+    left_map
+}
+
+/// L2 norm of a document's TF–IDF vector.
+fn document_l2_norm(document_entry: &DocumentEntry) -> f32 {
+    let mut squared_sum: f32 = 0.0;
+    for (_, weighted_value) in ordered_terms(&document_entry.weighted_terms) {
+        squared_sum += weighted_value * weighted_value;
+    }
+    squared_sum.sqrt()
+}
+
 /// Rank candidates by cosine similarity between the query TF–IDF vector and each document.
+/// Queries with fewer than `query_boost_short` tokens are scored instead by the sum of their
+/// matched query weights (TF × IDF) over the sum of all their query weights, with no document
+/// length normalisation, since a one- or two-term query vector makes cosine favour whichever
+/// short document happens to repeat the term. Both forms lie in 0..1, so `min_score` reads the
+/// same either way.
+/// Matching documents gain `recency_boost` times their recency (0 oldest to 1 newest).
+/// Hits scoring below `min_score` are dropped before truncating to `top_k`.
 /// Tie-breakers (after equal similarity):
-/// 1) Greater count of overlapping non-stopword tokens
-/// 2) Shorter candidate question by token count
-/// 3) Lexicographic order of GUID
+/// 1) Greater count of distinct overlapping query tokens (kept stopwords included)
+/// 2) Candidate question token count, shorter first unless `tie_break` says otherwise
+/// 3) Deck path, when `tie_break_secondary` is `deck_path`
+/// 4) Lexicographic order of GUID
 pub fn score_tfidf(
     query_text: &str,
     tfidf_index: &TfidfIndex,
     stopword_set: &HashSet<String>,
     parser_config: &ParserConfig,
     top_k: usize,
+    min_score: f32,
 ) -> Vec<AnswerHit> {
     // 1) Tokenise the query and build its TF map
     let query_token_vector = tokenise(query_text, stopword_set, parser_config);
-    if query_token_vector.is_empty() {
-        return Vec::new();
-    }
+    score_tfidf_tokens(
+        &query_token_vector,
+        tfidf_index,
+        parser_config,
+        top_k,
+        min_score,
+    )
+}
 
-    let mut query_term_counts: HashMap<String, usize> = HashMap::new();
-    for token_text in query_token_vector.iter() {
-        *query_term_counts.entry(token_text.clone()).or_insert(0) += 1;
-    }
+/// As `score_tfidf`, for a query already tokenised (e.g. by a `QueryCache`).
+pub fn score_tfidf_tokens(
+    query_token_vector: &[String],
+    tfidf_index: &TfidfIndex,
+    parser_config: &ParserConfig,
+    top_k: usize,
+    min_score: f32,
+) -> Vec<AnswerHit> {
+    score_tfidf_expanded_tokens(
+        query_token_vector,
+        &[],
+        tfidf_index,
+        parser_config,
+        top_k,
+        min_score,
+    )
+}
 
-    // 2) Convert to TF–IDF and compute query norm
-    let mut query_weighted_map: HashMap<String, f32> = HashMap::new();
-    for (term_text, count_value) in &query_term_counts {
-        let idf_value = *tfidf_index
-            .inverse_document_frequency
-            .get(term_text)
-            .unwrap_or(&0.0);
-        if idf_value == 0.0 {
-            continue; // term unseen in the corpus → contributes nothing
-        }
-        query_weighted_map.insert(term_text.clone(), (*count_value as f32) * idf_value);
+/// As `score_tfidf_tokens`, with `expanded_token_vector` (e.g. synonyms of the query tokens)
+/// added to the query vector at `synonym_weight` times their usual TF–IDF weight, capped at the
+/// heaviest query token's weight so a rare synonym cannot outweigh the query's own words.
+/// Expanded tokens that are also query tokens keep the full weight; overlap tie-breaks count
+/// only the query tokens.
+pub fn score_tfidf_expanded_tokens(
+    query_token_vector: &[String],
+    expanded_token_vector: &[String],
+    tfidf_index: &TfidfIndex,
+    parser_config: &ParserConfig,
+    top_k: usize,
+    min_score: f32,
+) -> Vec<AnswerHit> {
+    score_tfidf_with_recency(
+        query_token_vector,
+        expanded_token_vector,
+        tfidf_index,
+        parser_config,
+        top_k,
+        min_score,
+        parser_config.recency_boost,
+    )
+}
+
+/// As `score_tfidf_expanded_tokens`, adding `recency_boost` (rather than the configured one)
+/// times each document's recency; hybrid passes 0 and applies the boost once after blending.
+pub(crate) fn score_tfidf_with_recency(
+    query_token_vector: &[String],
+    expanded_token_vector: &[String],
+    tfidf_index: &TfidfIndex,
+    parser_config: &ParserConfig,
+    top_k: usize,
+    min_score: f32,
+    recency_boost: f32,
+) -> Vec<AnswerHit> {
+    if query_token_vector.is_empty() || tfidf_index.documents.is_empty() {
+        return Vec::new();
     }
 
-    let mut query_squared_sum: f32 = 0.0;
-    for weighted_value in query_weighted_map.values() {
-        query_squared_sum += weighted_value * weighted_value;
+    // 2) Convert to TF–IDF and compute query norm
+    let mut query_weighted_map =
+        weight_query_tokens(query_token_vector, tfidf_index, parser_config);
+    let strongest_query_weight = query_weighted_map.values().copied().fold(0.0f32, f32::max);
+    for (term_text, term_weight) in
+        weight_query_tokens(expanded_token_vector, tfidf_index, parser_config)
+    {
+        query_weighted_map
+            .entry(term_text)
+            .or_insert(term_weight.min(strongest_query_weight) * parser_config.synonym_weight);
     }
-    let query_l2_norm = query_squared_sum.sqrt();
+    let ordered_query_terms = ordered_terms(&query_weighted_map);
+    let query_l2_norm = weighted_l2_norm(&ordered_query_terms);
     if query_l2_norm == 0.0 {
         return Vec::new();
     }
 
     // Prepare set for overlap-based tie-breaker
-    let query_token_set = tokenise_to_set(query_text, stopword_set, parser_config);
+    let query_token_set: HashSet<String> = query_token_vector.iter().cloned().collect();
+    let idf_sum_scoring = query_token_vector.len() < parser_config.query_boost_short;
+    let query_weight_total: f32 = ordered_query_terms
+        .iter()
+        .map(|(_, query_weight)| **query_weight)
+        .sum();
 
     // 3) Score each document by cosine similarity
-    let mut scored_hits: Vec<(AnswerHit, f32, usize, usize)> = Vec::with_capacity(tfidf_index.documents.len());
+    let mut scored_hits: Vec<(AnswerHit, f32, usize, usize)> =
+        Vec::with_capacity(tfidf_index.documents.len());
     for (document_index, document_entry) in tfidf_index.documents.iter().enumerate() {
         let document_norm = tfidf_index.document_l2_norms[document_index];
         if document_norm == 0.0 {
             continue;
         }
 
-        // Dot product only over query terms present in the document
+        // Dot product (and matched query weight sum) only over query terms present in the
+        // document
         let mut dot_product_sum: f32 = 0.0;
-        for (term_text, query_weight) in &query_weighted_map {
-            if let Some(document_tf) = document_entry.term_frequencies.get(term_text) {
-                let idf_value = *tfidf_index
-                    .inverse_document_frequency
-                    .get(term_text)
-                    .unwrap_or(&0.0);
-                if idf_value != 0.0 {
-                    let document_weight = (*document_tf) * idf_value;
-                    dot_product_sum += query_weight * document_weight;
-                }
+        let mut matched_query_weight_sum: f32 = 0.0;
+        for (term_text, query_weight) in &ordered_query_terms {
+            if let Some(document_weight) = document_entry.weighted_terms.get(*term_text)
+                && *document_weight != 0.0
+            {
+                dot_product_sum += *query_weight * document_weight;
+                matched_query_weight_sum += *query_weight;
             }
         }
 
@@ -179,7 +532,14 @@ pub fn score_tfidf(
             continue;
         }
 
-        let cosine_similarity = dot_product_sum / (query_l2_norm * document_norm);
+        let mut cosine_similarity = if idf_sum_scoring {
+            matched_query_weight_sum / query_weight_total
+        } else {
+            dot_product_sum / (query_l2_norm * document_norm)
+        } + recency_boost * document_entry.recency;
+        if parser_config.deterministic {
+            cosine_similarity = round_score(cosine_similarity);
+        }
 
         // Tie-breakers need overlap count and question length
         let document_token_set = document_entry
@@ -187,9 +547,7 @@ pub fn score_tfidf(
             .keys()
             .cloned()
             .collect::<HashSet<String>>();
-        let overlap_count = document_token_set
-            .intersection(&query_token_set)
-            .count();
+        let overlap_count = document_token_set.intersection(&query_token_set).count();
 
         let answer_hit = make_hit(
             document_entry.guid.clone(),
@@ -206,17 +564,583 @@ pub fn score_tfidf(
         ));
     }
 
-    // 4) Sort with tie-breakers: higher similarity, then higher overlap, then shorter question, then lexicographic GUID
+    // 4) Sort with tie-breakers: higher similarity, then higher overlap, then question length per `tie_break`, then deck path per `tie_break_secondary`, then lexicographic GUID
     scored_hits.sort_by(|left, right| {
-        right.1.partial_cmp(&left.1).unwrap_or(std::cmp::Ordering::Equal)
+        right
+            .1
+            .partial_cmp(&left.1)
+            .unwrap_or(std::cmp::Ordering::Equal)
             .then_with(|| right.2.cmp(&left.2)) // greater overlap wins
-            .then_with(|| left.3.cmp(&right.3)) // shorter question wins
+            .then_with(|| compare_question_length(left.3, right.3, parser_config.tie_break))
+            .then_with(|| compare_secondary(&left.0, &right.0, parser_config.tie_break_secondary))
             .then_with(|| left.0.guid.cmp(&right.0.guid))
     });
 
     scored_hits
         .into_iter()
+        .filter(|tuple| tuple.0.score >= min_score)
         .take(top_k)
         .map(|tuple| tuple.0)
         .collect()
 }
+
+/// Explain one document's score: each matched query term with its document `tf * idf` weight,
+/// in term order. Empty if `guid` is not in the index or no query term matched.
+pub fn explain_tfidf(
+    query_token_vector: &[String],
+    tfidf_index: &TfidfIndex,
+    guid: &str,
+    parser_config: &ParserConfig,
+) -> Vec<(String, f32)> {
+    let Some(document_entry) = tfidf_index
+        .documents
+        .iter()
+        .find(|entry| entry.guid == guid)
+    else {
+        return Vec::new();
+    };
+    let query_weighted_map = weight_query_tokens(query_token_vector, tfidf_index, parser_config);
+
+    let mut term_weights: Vec<(String, f32)> = Vec::new();
+    for (term_text, _) in ordered_terms(&query_weighted_map) {
+        if let Some(document_weight) = document_entry.weighted_terms.get(term_text) {
+            term_weights.push((term_text.clone(), *document_weight));
+        }
+    }
+    term_weights
+}
+
+/// Cosine numerator (query · document) and denominator (‖query‖ × ‖document‖) for one document.
+/// `None` if `guid` is not in the index.
+pub fn cosine_components(
+    query_token_vector: &[String],
+    tfidf_index: &TfidfIndex,
+    guid: &str,
+    parser_config: &ParserConfig,
+) -> Option<(f32, f32)> {
+    let document_index = tfidf_index
+        .documents
+        .iter()
+        .position(|entry| entry.guid == guid)?;
+    let document_entry = &tfidf_index.documents[document_index];
+
+    let query_weighted_map = weight_query_tokens(query_token_vector, tfidf_index, parser_config);
+    let ordered_query_terms = ordered_terms(&query_weighted_map);
+
+    let mut dot_product_sum: f32 = 0.0;
+    for (term_text, query_weight) in &ordered_query_terms {
+        if let Some(document_weight) = document_entry.weighted_terms.get(*term_text) {
+            dot_product_sum += *query_weight * document_weight;
+        }
+    }
+    let denominator =
+        weighted_l2_norm(&ordered_query_terms) * tfidf_index.document_l2_norms[document_index];
+    Some((dot_product_sum, denominator))
+}
+
+/// Convert query tokens into TF–IDF weights (TF × IDF, with the same TF scaling as documents).
+pub(crate) fn weight_query_tokens(
+    query_token_vector: &[String],
+    tfidf_index: &TfidfIndex,
+    parser_config: &ParserConfig,
+) -> HashMap<String, f32> {
+    let mut query_term_counts: HashMap<String, usize> = HashMap::new();
+    for token_text in query_token_vector.iter() {
+        *query_term_counts.entry(token_text.clone()).or_insert(0) += 1;
+    }
+
+    let mut query_weighted_map: HashMap<String, f32> = HashMap::new();
+    for (term_text, count_value) in query_term_counts {
+        let idf_value = *tfidf_index
+            .inverse_document_frequency
+            .get(&term_text)
+            .unwrap_or(&0.0);
+        if idf_value == 0.0 {
+            continue; // term unseen in the corpus → contributes nothing
+        }
+        let stopword_factor = if tfidf_index.stopword_terms.contains(&term_text) {
+            parser_config.stopword_weight
+        } else {
+            1.0
+        };
+        let term_frequency = term_frequency_weight(
+            count_value as f32 * stopword_factor,
+            parser_config.sublinear_tf,
+        );
+        query_weighted_map.insert(term_text, term_frequency * idf_value);
+    }
+    query_weighted_map
+}
+
+/// L2 norm of the weighted query vector, summed in the given term order.
+fn weighted_l2_norm(ordered_query_terms: &[(&String, &f32)]) -> f32 {
+    let mut query_squared_sum: f32 = 0.0;
+    for (_, weighted_value) in ordered_query_terms {
+        query_squared_sum += **weighted_value * **weighted_value;
+    }
+    query_squared_sum.sqrt()
+}
+
+/// Return map entries in sorted term order, so float sums are accumulated identically on
+/// every run and every build (HashMap iteration order is randomised per map).
+pub(crate) fn ordered_terms<V>(term_map: &HashMap<String, V>) -> Vec<(&String, &V)> {
+    let mut entries: Vec<(&String, &V)> = term_map.iter().collect();
+    entries.sort_by(|left, right| left.0.cmp(right.0));
+    entries
+}
+
+/// Round a similarity to 6 decimal places (the display precision).
+pub(crate) fn round_score(score_value: f32) -> f32 {
+    (score_value * 1_000_000.0).round() / 1_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SearchField;
+    use crate::data_model::test_card;
+
+    #[test]
+    fn test_idf_smoothing_flag_changes_idf() {
+        let cards = vec![
+            test_card("g1", "stack frame"),
+            test_card("g2", "heap frame"),
+            test_card("g3", "queue frame"),
+        ];
+        let stopword_set = HashSet::new();
+        let idf_of = |idf_smoothing: bool, term_text: &str| {
+            let parser_config = ParserConfig {
+                idf_smoothing,
+                ..Default::default()
+            };
+            build_tfidf_index(&cards, &stopword_set, &parser_config).inverse_document_frequency
+                [term_text]
+        };
+
+        // "stack" has df = 1 in a 3-document corpus.
+        assert!((idf_of(true, "stack") - ((4.0f32 / 2.0).ln() + 1.0)).abs() < 1e-6);
+        assert!((idf_of(false, "stack") - 3.0f32.ln()).abs() < 1e-6);
+        // A term in every document: smoothed stays positive, unsmoothed is exactly 0.
+        assert!((idf_of(true, "frame") - 1.0).abs() < 1e-6);
+        assert_eq!(idf_of(false, "frame"), 0.0);
+    }
+
+    #[test]
+    fn test_max_df_ratio_prunes_term_in_every_document() {
+        let cards = vec![
+            test_card("g1", "stack frame pointer"),
+            test_card("g2", "heap frame"),
+            test_card("g3", "queue frame"),
+        ];
+        let stopword_set = HashSet::new();
+        let pruning_config = ParserConfig {
+            max_df_ratio: 0.9,
+            ..Default::default()
+        };
+        let full_index = build_tfidf_index(&cards, &stopword_set, &ParserConfig::default());
+        let pruned_index = build_tfidf_index(&cards, &stopword_set, &pruning_config);
+
+        assert_eq!(
+            (
+                full_index.unpruned_vocabulary_size,
+                full_index.vocabulary_size
+            ),
+            (5, 5)
+        );
+        assert_eq!(
+            (
+                pruned_index.unpruned_vocabulary_size,
+                pruned_index.vocabulary_size
+            ),
+            (5, 4)
+        );
+        assert!(
+            !pruned_index
+                .inverse_document_frequency
+                .contains_key("frame")
+        );
+        assert!(
+            !pruned_index.documents[1]
+                .weighted_terms
+                .contains_key("frame")
+        );
+        assert!(
+            score_tfidf(
+                "frame",
+                &pruned_index,
+                &stopword_set,
+                &pruning_config,
+                3,
+                0.0
+            )
+            .is_empty()
+        );
+        // Only the kept term contributes, so "heap" alone is a perfect match for g2.
+        let heap_hits = score_tfidf(
+            "heap frame",
+            &pruned_index,
+            &stopword_set,
+            &pruning_config,
+            3,
+            0.0,
+        );
+        assert_eq!(heap_hits[0].guid, "g2");
+        assert!((heap_hits[0].score - 1.0).abs() < 1e-6);
+
+        let min_df_config = ParserConfig {
+            min_df: 2,
+            ..Default::default()
+        };
+        let rare_pruned_index = build_tfidf_index(&cards, &stopword_set, &min_df_config);
+        assert_eq!(rare_pruned_index.vocabulary_size, 1);
+    }
+
+    #[test]
+    fn test_explanation_reproduces_score() {
+        let cards = vec![
+            test_card("g1", "binary search over sorted arrays"),
+            test_card("g2", "linear search over lists"),
+        ];
+        let stopword_set = HashSet::new();
+        let parser_config = ParserConfig::default();
+        let tfidf_index = build_tfidf_index(&cards, &stopword_set, &parser_config);
+
+        let answer_hits = score_tfidf(
+            "binary search",
+            &tfidf_index,
+            &stopword_set,
+            &parser_config,
+            1,
+            0.0,
+        );
+        let query_tokens = tokenise("binary search", &stopword_set, &parser_config);
+        let term_weights = explain_tfidf(&query_tokens, &tfidf_index, "g1", &parser_config);
+        let matched_terms: Vec<&str> = term_weights.iter().map(|(term, _)| term.as_str()).collect();
+        assert_eq!(matched_terms, vec!["binary", "search"]);
+
+        let (numerator, denominator) =
+            cosine_components(&query_tokens, &tfidf_index, "g1", &parser_config)
+                .expect("g1 indexed");
+        assert!((numerator / denominator - answer_hits[0].score).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_sublinear_tf_reduces_repeated_term_dominance() {
+        let cards = vec![
+            test_card("repeated", "cache cache cache cache cache cache memory"),
+            test_card("balanced", "cache memory"),
+            test_card("other", "disk storage"),
+        ];
+        let stopword_set = HashSet::new();
+        let raw_config = ParserConfig::default();
+        let sublinear_config = ParserConfig {
+            sublinear_tf: true,
+            ..ParserConfig::default()
+        };
+        let memory_score = |parser_config: &ParserConfig, guid: &str| {
+            let tfidf_index = build_tfidf_index(&cards, &stopword_set, parser_config);
+            score_tfidf("memory", &tfidf_index, &stopword_set, parser_config, 3, 0.0)
+                .into_iter()
+                .find(|hit| hit.guid == guid)
+                .map(|hit| hit.score)
+                .expect("memory matches")
+        };
+
+        // "cache" swamps the repeated card's vector under raw counts; 1 + ln(6) tames it.
+        let raw_score = memory_score(&raw_config, "repeated");
+        let sublinear_score = memory_score(&sublinear_config, "repeated");
+        assert!(sublinear_score > raw_score);
+        // Unrepeated terms are unaffected: 1 + ln(1) = 1.
+        assert_eq!(
+            memory_score(&raw_config, "balanced"),
+            memory_score(&sublinear_config, "balanced")
+        );
+    }
+
+    #[test]
+    fn test_query_boost_short_scores_short_queries_by_idf_sum() {
+        let cards = vec![
+            test_card("a_repeated", "binary binary binary tree"),
+            test_card("b_short", "binary heap"),
+            test_card("c_other", "disk storage"),
+        ];
+        let stopword_set = HashSet::new();
+        let cosine_config = ParserConfig::default();
+        let idf_sum_config = ParserConfig {
+            query_boost_short: 2,
+            ..ParserConfig::default()
+        };
+        let ranking = |parser_config: &ParserConfig, query_text: &str| {
+            let tfidf_index = build_tfidf_index(&cards, &stopword_set, parser_config);
+            score_tfidf(
+                query_text,
+                &tfidf_index,
+                &stopword_set,
+                parser_config,
+                3,
+                0.0,
+            )
+        };
+
+        // Cosine favours the vector dominated by the repeated term.
+        let cosine_hits = ranking(&cosine_config, "binary");
+        assert_eq!(cosine_hits[0].guid, "a_repeated");
+        assert!(cosine_hits[0].score > cosine_hits[1].score);
+
+        // IDF sum scores both matches alike, so the shorter question wins the tie-break; each
+        // matches the whole query weight, so both score 1.
+        let idf_sum_hits = ranking(&idf_sum_config, "binary");
+        assert_eq!(idf_sum_hits[0].guid, "b_short");
+        assert_eq!(idf_sum_hits[0].score, 1.0);
+        assert_eq!(idf_sum_hits[1].score, 1.0);
+
+        // With two query terms, matching only the rarer one scores its share of the IDF mass.
+        let two_term_config = ParserConfig {
+            query_boost_short: 3,
+            ..ParserConfig::default()
+        };
+        let tfidf_index = build_tfidf_index(&cards, &stopword_set, &two_term_config);
+        let (binary_idf, disk_idf) = (
+            tfidf_index.inverse_document_frequency["binary"],
+            tfidf_index.inverse_document_frequency["disk"],
+        );
+        let two_term_hits = ranking(&two_term_config, "binary disk");
+        assert_eq!(two_term_hits[0].guid, "c_other");
+        assert!((two_term_hits[0].score - disk_idf / (binary_idf + disk_idf)).abs() < 1e-6);
+
+        // Queries at or above the threshold keep cosine scoring.
+        let long_query = "binary tree heap";
+        let long_cosine: Vec<(String, f32)> = ranking(&cosine_config, long_query)
+            .into_iter()
+            .map(|hit| (hit.guid, hit.score))
+            .collect();
+        let long_boosted: Vec<(String, f32)> = ranking(&idf_sum_config, long_query)
+            .into_iter()
+            .map(|hit| (hit.guid, hit.score))
+            .collect();
+        assert_eq!(long_cosine, long_boosted);
+    }
+
+    #[test]
+    fn test_field_weights_blend_question_and_answer_counts() {
+        let cards = vec![Card::new(
+            "g1",
+            "what is a cache",
+            "a small fast cache",
+            vec!["Deck".to_string()],
+        )];
+        let stopword_set = HashSet::new();
+        let parser_config = ParserConfig {
+            search_field: SearchField::Both,
+            question_weight: 2.0,
+            answer_weight: 0.5,
+            ..ParserConfig::default()
+        };
+        let tfidf_index = build_tfidf_index(&cards, &stopword_set, &parser_config);
+        let term_frequencies = &tfidf_index.documents[0].term_frequencies;
+
+        // Once in each field: 2.0 (question) + 0.5 (answer).
+        assert_eq!(term_frequencies["cache"], 2.5);
+        assert_eq!(term_frequencies["what"], 2.0);
+        assert_eq!(term_frequencies["fast"], 0.5);
+        assert_eq!(tfidf_index.documents[0].token_count, 6);
+    }
+
+    #[test]
+    fn test_precomputed_weights_rank_like_per_query_idf_lookups() {
+        let cards = vec![
+            test_card("g1", "binary search over sorted arrays"),
+            test_card("g2", "linear search over lists"),
+            test_card("g3", "binary trees and search trees"),
+            test_card("g4", "hash tables give constant time search"),
+            test_card("g5", "sorted arrays allow binary search"),
+        ];
+        let stopword_set = HashSet::new();
+        let parser_config = ParserConfig::default();
+        let tfidf_index = build_tfidf_index(&cards, &stopword_set, &parser_config);
+
+        // The pre-refactor inner loop: document weight = tf * idf looked up per query term.
+        let reference_score = |query_text: &str, document_index: usize| -> f32 {
+            let query_token_vector = tokenise(query_text, &stopword_set, &parser_config);
+            let query_weighted_map =
+                weight_query_tokens(&query_token_vector, &tfidf_index, &parser_config);
+            let ordered_query_terms = ordered_terms(&query_weighted_map);
+            let document_entry = &tfidf_index.documents[document_index];
+            let mut dot_product_sum: f32 = 0.0;
+            let mut document_squared_sum: f32 = 0.0;
+            for (term_text, term_frequency) in ordered_terms(&document_entry.term_frequencies) {
+                let weighted_value =
+                    term_frequency * tfidf_index.inverse_document_frequency[term_text];
+                document_squared_sum += weighted_value * weighted_value;
+            }
+            for (term_text, query_weight) in &ordered_query_terms {
+                if let Some(document_tf) = document_entry.term_frequencies.get(*term_text) {
+                    let idf_value = tfidf_index.inverse_document_frequency[*term_text];
+                    dot_product_sum += *query_weight * (*document_tf * idf_value);
+                }
+            }
+            dot_product_sum / (weighted_l2_norm(&ordered_query_terms) * document_squared_sum.sqrt())
+        };
+
+        for query_text in [
+            "binary search",
+            "sorted arrays",
+            "search trees",
+            "constant time",
+        ] {
+            let answer_hits = score_tfidf(
+                query_text,
+                &tfidf_index,
+                &stopword_set,
+                &parser_config,
+                5,
+                0.0,
+            );
+            let mut reference_ranking: Vec<(String, f32)> = (0..cards.len())
+                .map(|document_index| {
+                    let guid = cards[document_index].guid.clone();
+                    (guid, reference_score(query_text, document_index))
+                })
+                .filter(|(_, score_value)| *score_value > 0.0)
+                .collect();
+            reference_ranking.sort_by(|left, right| {
+                right
+                    .1
+                    .partial_cmp(&left.1)
+                    .unwrap()
+                    .then_with(|| left.0.cmp(&right.0))
+            });
+            let reference_scores: Vec<f32> = reference_ranking
+                .iter()
+                .map(|(_, score_value)| *score_value)
+                .collect();
+            let hit_scores: Vec<f32> = answer_hits.iter().map(|hit| hit.score).collect();
+            assert_eq!(hit_scores, reference_scores, "{query_text}");
+            for answer_hit in &answer_hits {
+                let document_index = cards
+                    .iter()
+                    .position(|card| card.guid == answer_hit.guid)
+                    .unwrap();
+                assert_eq!(
+                    answer_hit.score,
+                    reference_score(query_text, document_index)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_add_documents_matches_full_rebuild() {
+        let cards = vec![
+            test_card("g1", "binary search over sorted arrays"),
+            test_card("g2", "linear search over lists"),
+            test_card("g3", "binary trees and search trees"),
+            test_card("g4", "hash tables give constant time search"),
+        ];
+        let stopword_set = HashSet::new();
+        let parser_config = ParserConfig::default();
+
+        let mut incremental_index = build_tfidf_index(&cards[..2], &stopword_set, &parser_config);
+        incremental_index.add_documents(&cards[2..], &stopword_set, &parser_config);
+        let rebuilt_index = build_tfidf_index(&cards, &stopword_set, &parser_config);
+
+        assert_eq!(incremental_index.document_count, 4);
+        assert_eq!(
+            incremental_index.vocabulary_size,
+            rebuilt_index.vocabulary_size
+        );
+        for query_text in [
+            "binary search",
+            "search trees",
+            "constant time",
+            "sorted lists",
+        ] {
+            let ranking = |tfidf_index: &TfidfIndex| -> Vec<(String, f32)> {
+                score_tfidf(
+                    query_text,
+                    tfidf_index,
+                    &stopword_set,
+                    &parser_config,
+                    4,
+                    0.0,
+                )
+                .into_iter()
+                .map(|hit| (hit.guid, hit.score))
+                .collect()
+            };
+            assert_eq!(
+                ranking(&incremental_index),
+                ranking(&rebuilt_index),
+                "{query_text}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_rare_synonym_does_not_outrank_common_query_word() {
+        // "func" is in almost every card, so its IDF is far below the rare synonym's.
+        let mut cards = vec![
+            test_card("exact", "func call"),
+            test_card("synonym", "procedure call"),
+        ];
+        for filler_index in 0..8 {
+            cards.push(test_card(&format!("filler{filler_index}"), "func"));
+        }
+        let stopword_set = HashSet::new();
+        let parser_config = ParserConfig::default();
+        let tfidf_index = build_tfidf_index(&cards, &stopword_set, &parser_config);
+        assert!(
+            tfidf_index.inverse_document_frequency["procedure"]
+                > 2.0 * tfidf_index.inverse_document_frequency["func"]
+        );
+
+        let answer_hits = score_tfidf_expanded_tokens(
+            &["func".to_string()],
+            &["procedure".to_string()],
+            &tfidf_index,
+            &parser_config,
+            10,
+            0.0,
+        );
+        let rank_of = |guid: &str| answer_hits.iter().position(|hit| hit.guid == guid);
+        assert!(rank_of("exact") < rank_of("synonym"), "{answer_hits:?}");
+    }
+
+    #[test]
+    fn test_zero_documents_and_empty_queries_return_no_hits() {
+        let stopword_set = HashSet::from(["what".to_string(), "is".to_string()]);
+        for idf_smoothing in [true, false] {
+            let parser_config = ParserConfig {
+                idf_smoothing,
+                ..ParserConfig::default()
+            };
+            let empty_index = build_tfidf_index(&[], &stopword_set, &parser_config);
+            assert_eq!(
+                (empty_index.document_count, empty_index.vocabulary_size),
+                (0, 0)
+            );
+            assert!(empty_index.inverse_document_frequency.is_empty());
+            assert!(empty_index.document_l2_norms.is_empty());
+
+            let tfidf_index = build_tfidf_index(
+                &[test_card("g1", "binary search")],
+                &stopword_set,
+                &parser_config,
+            );
+            for (query_text, index) in [
+                ("binary search", &empty_index),
+                ("", &empty_index),
+                ("", &tfidf_index),
+                ("what is", &tfidf_index),
+            ] {
+                let answer_hits =
+                    score_tfidf(query_text, index, &stopword_set, &parser_config, 5, 0.0);
+                assert!(answer_hits.is_empty(), "{query_text:?}");
+            }
+            assert!(
+                cosine_components(&["binary".to_string()], &empty_index, "g1", &parser_config)
+                    .is_none()
+            );
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use rulebot_rust::ChatbotEngine;
+use rulebot_rust::data_model::{AnswerHit, DeckPath};
+use rulebot_rust::topics::{list_available_topics, resolve_topic_string};
+
+use crate::cli::format_hits_as_json;
+
+const WORKER_THREAD_COUNT: usize = 4;
+
+/// Body of `POST /query`; omitted fields fall back to the server defaults.
+#[derive(Debug, Deserialize)]
+struct QueryRequest {
+    query: String,
+    algo: Option<String>,
+    topic: Option<String>,
+    k: Option<usize>,
+}
+
+/// Read-only state shared by every worker thread.
+struct ServerState {
+    engine: ChatbotEngine,
+    /// Scorer used when a request omits `algo`.
+    default_scorer_name: String,
+    known_topics: Vec<DeckPath>,
+    include_subtree: bool,
+}
+
+/// Serve the engine over HTTP on `address` until SIGINT:
+/// - `POST /query` takes `{query, algo, topic, k}` and returns the `--format json` array
+/// - `GET /topics` lists every deck path
+/// - `GET /healthz` returns `{"status":"ok"}`
+///
+/// The engine (indexed for every scorer over all cards) is built once by the caller and
+/// shared behind an `Arc`. Topics filter the full-corpus ranking, so scores match an
+/// unscoped query rather than one whose index was built over the topic alone.
+pub fn serve(
+    address: &str,
+    engine: ChatbotEngine,
+    default_scorer_name: String,
+    include_subtree: bool,
+) -> Result<()> {
+    let server = Arc::new(
+        Server::http(address).map_err(|error| anyhow!("Failed to bind {address}: {error}"))?,
+    );
+    let shutdown_requested = Arc::new(AtomicBool::new(false));
+    {
+        let server = Arc::clone(&server);
+        let shutdown_requested = Arc::clone(&shutdown_requested);
+        ctrlc::set_handler(move || {
+            shutdown_requested.store(true, Ordering::SeqCst);
+            // Each call wakes one blocked worker.
+            for _ in 0..WORKER_THREAD_COUNT {
+                server.unblock();
+            }
+        })
+        .with_context(|| "Failed to install SIGINT handler")?;
+    }
+
+    let server_state = Arc::new(ServerState {
+        known_topics: list_available_topics(engine.cards()),
+        engine,
+        default_scorer_name,
+        include_subtree,
+    });
+    eprintln!("Serving on http://{address} (Ctrl+C to stop).");
+
+    let worker_handles: Vec<thread::JoinHandle<()>> = (0..WORKER_THREAD_COUNT)
+        .map(|_| {
+            let server = Arc::clone(&server);
+            let shutdown_requested = Arc::clone(&shutdown_requested);
+            let server_state = Arc::clone(&server_state);
+            thread::spawn(move || {
+                loop {
+                    match server.recv() {
+                        Ok(request) => handle_request(request, &server_state),
+                        Err(_) if shutdown_requested.load(Ordering::SeqCst) => break,
+                        Err(error) => eprintln!("Error: {error}"),
+                    }
+                }
+            })
+        })
+        .collect();
+    for worker_handle in worker_handles {
+        let _ = worker_handle.join();
+    }
+    eprintln!("Server stopped.");
+    Ok(())
+}
+
+/// Route one request and send its response; send failures only mean the client went away.
+fn handle_request(mut request: Request, server_state: &ServerState) {
+    let (status_code, body_text) = match (request.method(), request.url()) {
+        (Method::Get, "/healthz") => (200, r#"{"status":"ok"}"#.to_string()),
+        (Method::Get, "/topics") => topics_response(server_state),
+        (Method::Post, "/query") => {
+            let mut request_body = String::new();
+            match request.as_reader().read_to_string(&mut request_body) {
+                Ok(_) => match answer_query(&request_body, server_state) {
+                    Ok(json_text) => (200, json_text),
+                    Err(error) => (400, error_body(&error.to_string())),
+                },
+                Err(error) => (400, error_body(&format!("Unreadable body: {error}"))),
+            }
+        }
+        _ => (404, error_body("Not found")),
+    };
+    let content_type =
+        Header::from_bytes("Content-Type", "application/json").expect("static header is valid");
+    let response = Response::from_string(body_text)
+        .with_status_code(status_code)
+        .with_header(content_type);
+    let _ = request.respond(response);
+}
+
+fn topics_response(server_state: &ServerState) -> (u16, String) {
+    let topic_separator = &server_state.engine.parser_config().topic_separator;
+    let topic_names: Vec<String> = server_state
+        .known_topics
+        .iter()
+        .map(|deck_path| deck_path.join(topic_separator))
+        .collect();
+    match serde_json::to_string(&topic_names) {
+        Ok(json_text) => (200, json_text),
+        Err(error) => (500, error_body(&error.to_string())),
+    }
+}
+
+/// Run a `POST /query` body against the shared engine, returning the JSON result array.
+fn answer_query(request_body: &str, server_state: &ServerState) -> Result<String> {
+    let query_request: QueryRequest = serde_json::from_str(request_body)
+        .with_context(|| "Body must be {query, algo, topic, k}")?;
+    let engine = &server_state.engine;
+    let scorer_name = match query_request.algo.as_deref() {
+        Some(algorithm_name) if engine.scorer_names().contains(&algorithm_name) => algorithm_name,
+        Some(algorithm_name) => return Err(anyhow!("Unknown algo: {algorithm_name}")),
+        None => server_state.default_scorer_name.as_str(),
+    };
+    let top_k = query_request.k.unwrap_or(1);
+
+    let answer_hits: Vec<AnswerHit> = match query_request.topic.as_deref() {
+        Some(topic_text) => {
+            let resolved_topic = resolve_topic_string(
+                topic_text,
+                &engine.parser_config().topic_separator,
+                &server_state.known_topics,
+            )?;
+            engine
+                .query(&query_request.query, scorer_name, engine.cards().len())
+                .into_iter()
+                .filter(|answer_hit| {
+                    if server_state.include_subtree {
+                        answer_hit.deck_path.starts_with(&resolved_topic)
+                    } else {
+                        answer_hit.deck_path == resolved_topic
+                    }
+                })
+                .take(top_k)
+                .collect()
+        }
+        None => engine.query(&query_request.query, scorer_name, top_k),
+    };
+    format_hits_as_json(&answer_hits, engine.guid_index())
+}
+
+fn error_body(message: &str) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use rulebot_rust::config::ParserConfig;
+    use rulebot_rust::data_model::Card;
+    use rulebot_rust::scoring::ScorerRegistry;
+
+    use super::*;
+
+    fn card(guid: &str, question_text: &str, deck_path: &[&str]) -> Card {
+        Card::new(
+            guid,
+            question_text,
+            &format!("answer {guid}"),
+            deck_path.iter().map(|part| part.to_string()).collect(),
+        )
+    }
+
+    fn server_state() -> ServerState {
+        let cards = vec![
+            card("g1", "what is recursion", &["Algorithms", "Basics"]),
+            card("g2", "what is recursion depth", &["Systems"]),
+        ];
+        let engine = ChatbotEngine::from_cards(
+            cards,
+            ParserConfig::default(),
+            HashSet::new(),
+            ScorerRegistry::builtin().scorers(),
+        );
+        ServerState {
+            known_topics: list_available_topics(engine.cards()),
+            engine,
+            default_scorer_name: "tfidf".to_string(),
+            include_subtree: true,
+        }
+    }
+
+    #[test]
+    fn test_answer_query_filters_by_topic_and_rejects_bad_input() {
+        let server_state = server_state();
+
+        let all_hits: serde_json::Value = serde_json::from_str(
+            &answer_query(r#"{"query": "recursion", "k": 5}"#, &server_state).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(all_hits.as_array().unwrap().len(), 2);
+
+        let topic_hits: serde_json::Value = serde_json::from_str(
+            &answer_query(
+                r#"{"query": "recursion", "algo": "keyword", "topic": "Systems", "k": 5}"#,
+                &server_state,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(topic_hits.as_array().unwrap().len(), 1);
+        assert_eq!(topic_hits[0]["guid"], "g2");
+        assert_eq!(topic_hits[0]["rank"], 1);
+
+        assert!(answer_query(r#"{"query": "x", "algo": "nope"}"#, &server_state).is_err());
+        assert!(answer_query(r#"{"k": 1}"#, &server_state).is_err());
+        assert!(answer_query(r#"{"query": "x", "topic": "Missing"}"#, &server_state).is_err());
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::HashSet;
+
+use crate::tokenise::stem_token;
+
+/// Longest entity body (between `&` and `;`) treated as one unit, e.g. `#x1F600`.
+const MAX_ENTITY_LENGTH: usize = 10;
+
+/// Byte length of the HTML entity starting at `byte_index` (`&`, up to `MAX_ENTITY_LENGTH`
+/// alphanumerics or `#`, then `;`), or `None` if there is none.
+fn entity_length_at(text: &str, byte_index: usize) -> Option<usize> {
+    let entity_body = text[byte_index..].strip_prefix('&')?;
+    let body_length = entity_body.find(|body_character: char| {
+        !(body_character.is_ascii_alphanumeric() || body_character == '#')
+    })?;
+    let is_entity = (1..=MAX_ENTITY_LENGTH).contains(&body_length)
+        && entity_body[body_length..].starts_with(';');
+    is_entity.then_some(body_length + 2)
+}
+
+/// Split `text` into display units: an HTML entity (`&lt;`, `&#39;`) is one unit, every other
+/// character is its own, so cutting between units never breaks an entity or a UTF-8 sequence.
+fn display_units(text: &str) -> Vec<&str> {
+    let mut units: Vec<&str> = Vec::with_capacity(text.len());
+    let mut byte_index = 0;
+    while let Some(character) = text[byte_index..].chars().next() {
+        let unit_length = entity_length_at(text, byte_index).unwrap_or(character.len_utf8());
+        units.push(&text[byte_index..byte_index + unit_length]);
+        byte_index += unit_length;
+    }
+    units
+}
+
+/// Unit ranges `[start, end)` of the words in `units` whose lowercase form (or its stem) is one
+/// of `matched_tokens`. Words are runs of alphanumeric characters; entities end a word.
+fn matched_word_spans(units: &[&str], matched_tokens: &HashSet<String>) -> Vec<(usize, usize)> {
+    let is_word_unit = |unit: &str| {
+        let mut unit_characters = unit.chars();
+        matches!(
+            (unit_characters.next(), unit_characters.next()),
+            (Some(character), None) if character.is_alphanumeric()
+        )
+    };
+    let mut word_spans: Vec<(usize, usize)> = Vec::new();
+    let mut unit_index = 0;
+    while unit_index < units.len() {
+        if !is_word_unit(units[unit_index]) {
+            unit_index += 1;
+            continue;
+        }
+        let word_start = unit_index;
+        while unit_index < units.len() && is_word_unit(units[unit_index]) {
+            unit_index += 1;
+        }
+        let word_text = units[word_start..unit_index].concat();
+        let lowercase_word = word_text.to_lowercase();
+        if matched_tokens.contains(&lowercase_word)
+            || matched_tokens.contains(&word_text)
+            || matched_tokens.contains(&stem_token(&lowercase_word))
+        {
+            word_spans.push((word_start, unit_index));
+        }
+    }
+    word_spans
+}
+
+/// Excerpt of about `window_chars` characters of `answer_display` (an entity counts as one)
+/// centred on the first window holding the most words from `matched_tokens`, with `…` marking
+/// trimmed text at either end. Without a match the excerpt starts at the beginning; answers
+/// that already fit are returned whole.
+pub fn extract_snippet(
+    answer_display: &str,
+    matched_tokens: &HashSet<String>,
+    window_chars: usize,
+) -> String {
+    let units = display_units(answer_display);
+    if units.len() <= window_chars || window_chars == 0 {
+        return answer_display.to_string();
+    }
+
+    // Windows starting at a match cover every achievable set of matches; keep the first best.
+    let word_spans = matched_word_spans(&units, matched_tokens);
+    let mut best_span: Option<(usize, usize, usize)> = None;
+    for (first_position, (window_start, _)) in word_spans.iter().enumerate() {
+        let contained_spans: Vec<&(usize, usize)> = word_spans[first_position..]
+            .iter()
+            .take_while(|(_, word_end)| *word_end <= window_start + window_chars)
+            .collect();
+        let covered_end = contained_spans
+            .last()
+            .map_or(*window_start, |(_, word_end)| *word_end);
+        if best_span.is_none_or(|(best_count, _, _)| contained_spans.len() > best_count) {
+            best_span = Some((contained_spans.len(), *window_start, covered_end));
+        }
+    }
+
+    let snippet_start = match best_span {
+        Some((_, covered_start, covered_end)) => {
+            let covered_centre = (covered_start + covered_end) / 2;
+            covered_centre
+                .saturating_sub(window_chars / 2)
+                .min(units.len() - window_chars)
+        }
+        None => 0,
+    };
+    let snippet_end = snippet_start + window_chars;
+
+    let mut snippet_text = String::new();
+    if snippet_start > 0 {
+        snippet_text.push('…');
+    }
+    snippet_text.push_str(units[snippet_start..snippet_end].concat().trim());
+    if snippet_end < units.len() {
+        snippet_text.push('…');
+    }
+    snippet_text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token_set(tokens: &[&str]) -> HashSet<String> {
+        tokens
+            .iter()
+            .map(|token_text| token_text.to_string())
+            .collect()
+    }
+
+    fn filler(word_count: usize) -> String {
+        vec!["lorem"; word_count].join(" ")
+    }
+
+    #[test]
+    fn test_snippet_follows_match_at_start_middle_and_end() {
+        let matched_tokens = token_set(&["cache"]);
+
+        let start_answer = format!("Cache lines hold data. {}", filler(40));
+        let start_snippet = extract_snippet(&start_answer, &matched_tokens, 60);
+        assert!(start_snippet.starts_with("Cache lines"));
+        assert!(start_snippet.ends_with('…'));
+
+        let middle_answer = format!("{} the cache hit rate {}", filler(40), filler(40));
+        let middle_snippet = extract_snippet(&middle_answer, &matched_tokens, 60);
+        assert!(middle_snippet.starts_with('…') && middle_snippet.ends_with('…'));
+        assert!(middle_snippet.contains("the cache hit rate"));
+        assert!(middle_snippet.chars().count() <= 62);
+
+        let end_answer = format!("{} ends with a cache", filler(40));
+        let end_snippet = extract_snippet(&end_answer, &matched_tokens, 60);
+        assert!(end_snippet.starts_with('…'));
+        assert!(end_snippet.ends_with("ends with a cache"));
+    }
+
+    #[test]
+    fn test_snippet_prefers_densest_window_and_keeps_entities_whole() {
+        let matched_tokens = token_set(&["tree", "binary"]);
+        let answer_display = format!(
+            "A tree {} then a binary tree &lt;node&gt; é {}",
+            filler(30),
+            filler(30)
+        );
+        let snippet_text = extract_snippet(&answer_display, &matched_tokens, 40);
+        assert!(snippet_text.contains("binary tree"), "{snippet_text}");
+
+        // Every window cut lands between units, never inside `&lt;` or `&gt;`.
+        for window_chars in 20..60 {
+            let snippet_text = extract_snippet(&answer_display, &matched_tokens, window_chars);
+            assert_eq!(
+                snippet_text.matches('&').count(),
+                snippet_text.matches(';').count(),
+                "{snippet_text}"
+            );
+        }
+    }
+}
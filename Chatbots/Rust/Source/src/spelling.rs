@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+
+/// Optimal-string-alignment Damerau–Levenshtein distance: insertions, deletions,
+/// substitutions and adjacent transpositions each cost 1.
+pub fn damerau_levenshtein(left_text: &str, right_text: &str) -> usize {
+    let left_chars: Vec<char> = left_text.chars().collect();
+    let right_chars: Vec<char> = right_text.chars().collect();
+    let column_count = right_chars.len() + 1;
+
+    // Three rolling rows: two back (for transpositions), previous, and current.
+    let mut two_rows_back: Vec<usize> = vec![0; column_count];
+    let mut previous_row: Vec<usize> = (0..column_count).collect();
+    let mut current_row: Vec<usize> = vec![0; column_count];
+
+    for left_index in 1..=left_chars.len() {
+        current_row[0] = left_index;
+        for right_index in 1..column_count {
+            let substitution_cost =
+                usize::from(left_chars[left_index - 1] != right_chars[right_index - 1]);
+            let mut best_cost = (previous_row[right_index] + 1)
+                .min(current_row[right_index - 1] + 1)
+                .min(previous_row[right_index - 1] + substitution_cost);
+            if left_index > 1
+                && right_index > 1
+                && left_chars[left_index - 1] == right_chars[right_index - 2]
+                && left_chars[left_index - 2] == right_chars[right_index - 1]
+            {
+                best_cost = best_cost.min(two_rows_back[right_index - 2] + 1);
+            }
+            current_row[right_index] = best_cost;
+        }
+        std::mem::swap(&mut two_rows_back, &mut previous_row);
+        std::mem::swap(&mut previous_row, &mut current_row);
+    }
+    previous_row[column_count - 1]
+}
+
+/// Vocabulary terms within `max_edit_distance` of `token`, best first: nearer terms, then
+/// higher document frequency, then lexicographic order.
+pub fn suggest_corrections(
+    token: &str,
+    vocabulary: &HashMap<String, usize>,
+    max_edit_distance: usize,
+) -> Vec<String> {
+    let token_length = token.chars().count();
+    let mut candidates: Vec<(&String, usize, usize)> = vocabulary
+        .iter()
+        // Lengths further apart than the limit cannot be within it.
+        .filter(|(term_text, _)| {
+            term_text.chars().count().abs_diff(token_length) <= max_edit_distance
+        })
+        .filter_map(|(term_text, document_frequency)| {
+            let edit_distance = damerau_levenshtein(token, term_text);
+            (edit_distance <= max_edit_distance).then_some((
+                term_text,
+                edit_distance,
+                *document_frequency,
+            ))
+        })
+        .collect();
+    candidates.sort_by(|left, right| {
+        left.1
+            .cmp(&right.1)
+            .then_with(|| right.2.cmp(&left.2))
+            .then_with(|| left.0.cmp(right.0))
+    });
+    candidates
+        .into_iter()
+        .map(|(term_text, _, _)| term_text.clone())
+        .collect()
+}
+
+/// Replace each token missing from `vocabulary` with its best suggestion, if any.
+/// Tokens containing digits are left alone. Returns the corrected tokens and the
+/// `(original, replacement)` substitutions made, in query order.
+pub fn correct_tokens(
+    tokens: &[String],
+    vocabulary: &HashMap<String, usize>,
+    max_edit_distance: usize,
+) -> (Vec<String>, Vec<(String, String)>) {
+    let mut substitutions: Vec<(String, String)> = Vec::new();
+    let corrected_tokens = tokens
+        .iter()
+        .map(|token_text| {
+            if vocabulary.contains_key(token_text)
+                || token_text
+                    .chars()
+                    .any(|character| character.is_ascii_digit())
+            {
+                return token_text.clone();
+            }
+            let best_suggestion = suggest_corrections(token_text, vocabulary, max_edit_distance)
+                .into_iter()
+                .next();
+            match best_suggestion {
+                Some(replacement) => {
+                    substitutions.push((token_text.clone(), replacement.clone()));
+                    replacement
+                }
+                None => token_text.clone(),
+            }
+        })
+        .collect();
+    (corrected_tokens, substitutions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vocabulary(entries: &[(&str, usize)]) -> HashMap<String, usize> {
+        entries
+            .iter()
+            .map(|(term_text, document_frequency)| (term_text.to_string(), *document_frequency))
+            .collect()
+    }
+
+    #[test]
+    fn test_single_substitution() {
+        let vocabulary = vocabulary(&[("database", 4), ("databases", 1)]);
+        assert_eq!(damerau_levenshtein("databaze", "database"), 1);
+        assert_eq!(
+            suggest_corrections("databaze", &vocabulary, 2)[0],
+            "database"
+        );
+    }
+
+    #[test]
+    fn test_transposition_counts_as_one_edit() {
+        let vocabulary = vocabulary(&[("recursion", 2), ("recursive", 5)]);
+        assert_eq!(damerau_levenshtein("recursoin", "recursion"), 1);
+        assert_eq!(
+            suggest_corrections("recursoin", &vocabulary, 2),
+            vec!["recursion".to_string()]
+        );
+
+        let query_tokens = vec!["recursoin".to_string(), "42".to_string()];
+        let (corrected_tokens, substitutions) = correct_tokens(&query_tokens, &vocabulary, 2);
+        assert_eq!(
+            corrected_tokens,
+            vec!["recursion".to_string(), "42".to_string()]
+        );
+        assert_eq!(
+            substitutions,
+            vec![("recursoin".to_string(), "recursion".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_ties_prefer_higher_document_frequency() {
+        let vocabulary = vocabulary(&[("cat", 1), ("car", 9)]);
+        assert_eq!(
+            suggest_corrections("caw", &vocabulary, 1),
+            vec!["car", "cat"]
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use std::fmt::Write;
 use std::time::{Duration, Instant};
 
 /// Convert a `Duration` into milliseconds as `f64`.
@@ -6,7 +7,7 @@ pub fn duration_to_milliseconds(duration: Duration) -> f64 {
 }
 
 /// A simple stopwatch for measuring elapsed wall-clock time in milliseconds.
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Stopwatch {
     start_instant: Option<Instant>,
 }
@@ -15,7 +16,9 @@ pub struct Stopwatch {
 impl Stopwatch {
     /// Create a new, not-yet-started stopwatch.
     pub fn new() -> Self {
-        Self { start_instant: None }
+        Self {
+            start_instant: None,
+        }
     }
 
     /// Start or restart the stopwatch.
@@ -53,3 +56,177 @@ pub fn measure_closure_milliseconds<T, F: FnOnce() -> T>(closure: F) -> (T, f64)
     let elapsed_milliseconds = duration_to_milliseconds(start_instant.elapsed());
     (result_value, elapsed_milliseconds)
 }
+
+/// Summary statistics for one stage's recorded durations, in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StageStatistics {
+    pub count: usize,
+    pub min: f64,
+    pub median: f64,
+    pub p95: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+/// Collects per-query durations by stage name (e.g. `"wall"`, `"rank"`) across a batch.
+#[derive(Debug, Default)]
+pub struct TimingAggregator {
+    /// Stage names in first-recorded order, with their samples.
+    stage_samples: Vec<(String, Vec<f64>)>,
+}
+
+impl TimingAggregator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add one duration sample for `stage`.
+    pub fn record(&mut self, stage: &str, milliseconds: f64) {
+        match self
+            .stage_samples
+            .iter_mut()
+            .find(|(stage_name, _)| stage_name == stage)
+        {
+            Some((_, samples)) => samples.push(milliseconds),
+            None => self
+                .stage_samples
+                .push((stage.to_string(), vec![milliseconds])),
+        }
+    }
+
+    /// Statistics for `stage`, or `None` if nothing was recorded for it.
+    /// The median averages the two middle samples for even counts; p95 is the nearest-rank
+    /// percentile (the smallest sample with at least 95% of samples at or below it).
+    pub fn statistics(&self, stage: &str) -> Option<StageStatistics> {
+        let (_, samples) = self
+            .stage_samples
+            .iter()
+            .find(|(stage_name, _)| stage_name == stage)?;
+        let mut sorted_samples = samples.clone();
+        sorted_samples.sort_by(f64::total_cmp);
+        let count = sorted_samples.len();
+        let median = if count % 2 == 0 {
+            (sorted_samples[count / 2 - 1] + sorted_samples[count / 2]) / 2.0
+        } else {
+            sorted_samples[count / 2]
+        };
+        let p95_rank = ((count as f64) * 0.95).ceil() as usize;
+        Some(StageStatistics {
+            count,
+            min: sorted_samples[0],
+            median,
+            p95: sorted_samples[p95_rank.max(1) - 1],
+            max: sorted_samples[count - 1],
+            mean: sorted_samples.iter().sum::<f64>() / count as f64,
+        })
+    }
+
+    /// One line per stage: `stage: n=… min=… median=… p95=… max=… mean=… ms`.
+    pub fn summary(&self) -> String {
+        let mut summary_text = String::new();
+        for (stage_name, _) in &self.stage_samples {
+            let Some(stage_statistics) = self.statistics(stage_name) else {
+                continue;
+            };
+            let _ = writeln!(
+                summary_text,
+                "{stage_name}: n={} min={:.3} median={:.3} p95={:.3} max={:.3} mean={:.3} ms",
+                stage_statistics.count,
+                stage_statistics.min,
+                stage_statistics.median,
+                stage_statistics.p95,
+                stage_statistics.max,
+                stage_statistics.mean,
+            );
+        }
+        summary_text
+    }
+}
+
+/// Spread of repeated timings of the same operation, in milliseconds (for `bench`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RepeatStatistics {
+    pub count: usize,
+    pub mean: f64,
+    /// Sample standard deviation (divides by `count - 1`); 0 for a single sample.
+    pub stddev: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl RepeatStatistics {
+    /// Statistics over `samples`, or `None` if there are none.
+    pub fn from_samples(samples: &[f64]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let count = samples.len();
+        let mean = samples.iter().sum::<f64>() / count as f64;
+        let squared_deviation_sum: f64 = samples.iter().map(|sample| (sample - mean).powi(2)).sum();
+        let stddev = if count > 1 {
+            (squared_deviation_sum / (count - 1) as f64).sqrt()
+        } else {
+            0.0
+        };
+        Some(Self {
+            count,
+            mean,
+            stddev,
+            min: samples.iter().copied().fold(f64::INFINITY, f64::min),
+            max: samples.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timing_aggregator_percentiles() {
+        let mut timing_aggregator = TimingAggregator::new();
+        // 1..=20 shuffled: median (10 + 11) / 2, p95 is the 19th smallest.
+        for milliseconds in [
+            7, 3, 20, 1, 15, 11, 2, 19, 8, 10, 4, 18, 5, 16, 9, 12, 6, 17, 13, 14,
+        ] {
+            timing_aggregator.record("wall", milliseconds as f64);
+        }
+        timing_aggregator.record("rank", 2.0);
+
+        let wall_statistics = timing_aggregator.statistics("wall").expect("recorded");
+        assert_eq!(wall_statistics.count, 20);
+        assert_eq!(wall_statistics.median, 10.5);
+        assert_eq!(wall_statistics.p95, 19.0);
+        assert_eq!((wall_statistics.min, wall_statistics.max), (1.0, 20.0));
+        assert_eq!(wall_statistics.mean, 10.5);
+
+        let rank_statistics = timing_aggregator.statistics("rank").expect("recorded");
+        assert_eq!((rank_statistics.median, rank_statistics.p95), (2.0, 2.0));
+        assert!(timing_aggregator.statistics("format").is_none());
+
+        let summary_text = timing_aggregator.summary();
+        let summary_lines: Vec<&str> = summary_text.lines().collect();
+        assert_eq!(summary_lines.len(), 2);
+        assert!(summary_lines[0].starts_with("wall: n=20 min=1.000 median=10.500 p95=19.000"));
+    }
+
+    #[test]
+    fn test_repeat_statistics_sample_stddev() {
+        let repeat_statistics =
+            RepeatStatistics::from_samples(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0])
+                .expect("samples");
+        assert_eq!(repeat_statistics.count, 8);
+        assert_eq!(repeat_statistics.mean, 5.0);
+        // Squared deviations sum to 32; 32 / 7 is the sample variance.
+        assert!((repeat_statistics.stddev - (32.0_f64 / 7.0).sqrt()).abs() < 1e-12);
+        assert_eq!((repeat_statistics.min, repeat_statistics.max), (2.0, 9.0));
+
+        assert_eq!(
+            RepeatStatistics::from_samples(&[3.0])
+                .expect("one sample")
+                .stddev,
+            0.0
+        );
+        assert!(RepeatStatistics::from_samples(&[]).is_none());
+    }
+}
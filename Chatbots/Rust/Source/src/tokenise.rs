@@ -1,56 +1,321 @@
 use std::collections::{HashSet, hash_map::RandomState};
+use std::sync::Mutex;
+
+use regex::{Regex, RegexBuilder};
 
 use crate::config::ParserConfig;
+use crate::normalise::apply_unicode_normalisation;
 
 /// Determine whether a token consists only of digits.
 fn token_is_numeric(token_text: &str) -> bool {
-    token_text.chars().all(|character| character.is_ascii_digit())
+    token_text
+        .chars()
+        .all(|character| character.is_ascii_digit())
+}
+
+/// `preserve_patterns` and whether they were compiled case-insensitively.
+type PreservePatternKey = (Vec<String>, bool);
+
+/// The most recently compiled `preserve_patterns` alternation, reused while its key is unchanged.
+static PRESERVE_PATTERN_CACHE: Mutex<Option<(PreservePatternKey, Option<Regex>)>> =
+    Mutex::new(None);
+
+/// Compile `preserve_patterns` into one alternation (cached), case-insensitive when
+/// `ignore_case` is set. Card text is already lowercased by `normalise_for_matching` while
+/// queries are not, so with `lowercase` on a pattern like `C\+\+` must match "c++" as well.
+/// `None` if the list is empty or invalid; `load_parser_config` rejects invalid patterns up front.
+fn preserve_pattern_regex(preserve_patterns: &[String], ignore_case: bool) -> Option<Regex> {
+    if preserve_patterns.is_empty() {
+        return None;
+    }
+    let mut cache_guard = PRESERVE_PATTERN_CACHE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(((cached_patterns, cached_ignore_case), cached_regex)) = cache_guard.as_ref()
+        && cached_patterns.as_slice() == preserve_patterns
+        && *cached_ignore_case == ignore_case
+    {
+        return cached_regex.clone();
+    }
+    let alternation = preserve_patterns
+        .iter()
+        .map(|pattern_text| format!("(?:{pattern_text})"))
+        .collect::<Vec<String>>()
+        .join("|");
+    let compiled_regex = RegexBuilder::new(&alternation)
+        .case_insensitive(ignore_case)
+        .build()
+        .ok();
+    *cache_guard = Some((
+        (preserve_patterns.to_vec(), ignore_case),
+        compiled_regex.clone(),
+    ));
+    compiled_regex
+}
+
+/// Split `text_segment` on non-alphanumeric characters other than `join_char_set`, appending
+/// tokens (lowercased when `lowercase` is set). A join character only counts inside a token, so
+/// "don't" stays whole while a leading or trailing one ("'quoted'") is dropped.
+fn split_on_non_alphanumeric(
+    text_segment: &str,
+    lowercase: bool,
+    join_char_set: &HashSet<char>,
+    tokens: &mut Vec<String>,
+) {
+    let mut current_token = String::new();
+    let mut push_token = |current_token: &mut String| {
+        let trimmed_length = current_token
+            .trim_end_matches(|character| join_char_set.contains(&character))
+            .len();
+        current_token.truncate(trimmed_length);
+        if !current_token.is_empty() {
+            tokens.push(std::mem::take(current_token));
+        }
+    };
+
+    // Build tokens using Unicode-aware classification.
+    for character in text_segment.chars() {
+        if character.is_alphanumeric() {
+            current_token.push(if lowercase {
+                character.to_ascii_lowercase()
+            } else {
+                character
+            });
+        } else if join_char_set.contains(&character) && !current_token.is_empty() {
+            current_token.push(character);
+        } else {
+            push_token(&mut current_token);
+        }
+    }
+    push_token(&mut current_token);
 }
 
 /// Split on non-alphanumeric characters, keep only tokens that pass length rules,
-/// and remove stopwords if configured. Returns tokens in the original order.
+/// remove stopwords if configured (unless `stopword_weight` keeps them), then stem if configured. Returns tokens in the original order.
+/// Substrings matching `preserve_patterns` are kept whole (lowercased) as single tokens.
+/// With `lowercase` off, tokens keep their original case.
+/// Uses the question `min_token_length`; see `tokenise_with_min_length` for answer text.
 pub fn tokenise(
     input_text: &str,
     stopword_set: &HashSet<String>,
     parser_config: &ParserConfig,
 ) -> Vec<String> {
-    let mut tokens: Vec<String> = Vec::new();
-    let mut current_token = String::new();
+    tokenise_with_min_length(
+        input_text,
+        stopword_set,
+        parser_config,
+        parser_config.min_token_length.question(),
+    )
+}
 
-    // Build tokens using Unicode-aware classification.
-    for character in input_text.chars() {
-        if character.is_alphanumeric() {
-            current_token.push(character.to_ascii_lowercase());
-        } else {
-            if !current_token.is_empty() {
-                tokens.push(current_token.clone());
-                current_token.clear();
+/// As `tokenise`, dropping tokens shorter than `min_token_length` instead of the configured
+/// question minimum (e.g. the answer minimum when indexing answers).
+pub fn tokenise_with_min_length(
+    input_text: &str,
+    stopword_set: &HashSet<String>,
+    parser_config: &ParserConfig,
+    min_token_length: usize,
+) -> Vec<String> {
+    split_raw_tokens(input_text, parser_config)
+        .into_iter()
+        .filter_map(|token_text| {
+            classify_token(token_text, stopword_set, parser_config, min_token_length)
+                .into_emitted_token()
+        })
+        .collect()
+}
+
+/// What `tokenise` did with one raw token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenFate {
+    /// Emitted as the contained token (the stem, when `stem` is on).
+    Kept(String),
+    /// Shorter than `min_token_length` but kept because it is all digits.
+    KeptNumeric(String),
+    /// A stopword kept at `stopword_weight` instead of being removed.
+    KeptStopword(String),
+    /// Shorter than `min_token_length`.
+    DroppedTooShort,
+    /// In the stopword list, with `remove_stopwords` on.
+    DroppedStopword,
+    /// All digits, with `keep_digits` off.
+    DroppedNumeric,
+}
+
+impl TokenFate {
+    /// The token `tokenise` emits, or `None` if it was dropped.
+    pub fn into_emitted_token(self) -> Option<String> {
+        match self {
+            TokenFate::Kept(token_text)
+            | TokenFate::KeptNumeric(token_text)
+            | TokenFate::KeptStopword(token_text) => Some(token_text),
+            TokenFate::DroppedTooShort | TokenFate::DroppedStopword | TokenFate::DroppedNumeric => {
+                None
             }
         }
     }
-    if !current_token.is_empty() {
-        tokens.push(current_token.clone());
-        current_token.clear();
+
+    /// Short description for debug output (e.g. `"dropped: stopword"`).
+    pub fn label(&self) -> &'static str {
+        match self {
+            TokenFate::Kept(_) => "kept",
+            TokenFate::KeptNumeric(_) => "kept: numeric",
+            TokenFate::KeptStopword(_) => "kept: weighted stopword",
+            TokenFate::DroppedTooShort => "dropped: too short",
+            TokenFate::DroppedStopword => "dropped: stopword",
+            TokenFate::DroppedNumeric => "dropped: numeric",
+        }
     }
+}
 
-    // Apply length and stopword rules
-    let mut filtered_tokens: Vec<String> = Vec::with_capacity(tokens.len());
-    for token_text in tokens.into_iter() {
-        let token_length = token_text.chars().count();
-        let is_numeric = token_is_numeric(&token_text);
-        let meets_length_rule =
-            token_length >= parser_config.min_token_length || is_numeric;
+/// As `tokenise`, but return every raw token (after splitting and lowercasing) with its fate,
+/// including the ones that were dropped. For debugging why a query does not match.
+pub fn tokenise_with_trace(
+    input_text: &str,
+    stopword_set: &HashSet<String>,
+    parser_config: &ParserConfig,
+) -> Vec<(String, TokenFate)> {
+    split_raw_tokens(input_text, parser_config)
+        .into_iter()
+        .map(|token_text| {
+            let token_fate = classify_token(
+                token_text.clone(),
+                stopword_set,
+                parser_config,
+                parser_config.min_token_length.question(),
+            );
+            (token_text, token_fate)
+        })
+        .collect()
+}
 
-        if !meets_length_rule {
-            continue;
-        }
-        if parser_config.remove_stopwords && stopword_set.contains(&token_text) {
-            continue;
+/// Split into raw tokens (lowercase unless `lowercase` is off), keeping `preserve_patterns`
+/// matches whole and `join_chars` inside tokens.
+fn split_raw_tokens(input_text: &str, parser_config: &ParserConfig) -> Vec<String> {
+    // Queries reach here without `normalise_for_matching`, so NFKC is applied here too.
+    let normalised_input = apply_unicode_normalisation(input_text, parser_config);
+    let input_text = normalised_input.as_ref();
+    let lowercase = parser_config.lowercase;
+    let join_char_set: HashSet<char> = parser_config.join_chars.iter().copied().collect();
+    let mut tokens: Vec<String> = Vec::new();
+
+    match preserve_pattern_regex(&parser_config.preserve_patterns, lowercase) {
+        Some(preserve_regex) => {
+            let mut segment_start = 0;
+            for preserved_match in preserve_regex.find_iter(input_text) {
+                if preserved_match.is_empty() {
+                    continue;
+                }
+                let preceding_segment = &input_text[segment_start..preserved_match.start()];
+                split_on_non_alphanumeric(
+                    preceding_segment,
+                    lowercase,
+                    &join_char_set,
+                    &mut tokens,
+                );
+                tokens.push(if lowercase {
+                    preserved_match.as_str().to_lowercase()
+                } else {
+                    preserved_match.as_str().to_string()
+                });
+                segment_start = preserved_match.end();
+            }
+            split_on_non_alphanumeric(
+                &input_text[segment_start..],
+                lowercase,
+                &join_char_set,
+                &mut tokens,
+            );
         }
-        filtered_tokens.push(token_text);
+        None => split_on_non_alphanumeric(input_text, lowercase, &join_char_set, &mut tokens),
+    }
+    tokens
+}
+
+/// Apply the digit, length (`min_token_length`) and stopword rules to one raw token, stemming it
+/// if it survives.
+/// Without `keep_digits`, all-digit tokens are dropped and digits are stripped from mixed
+/// tokens before the length check ("ipv4" → "ipv").
+fn classify_token(
+    token_text: String,
+    stopword_set: &HashSet<String>,
+    parser_config: &ParserConfig,
+    min_token_length: usize,
+) -> TokenFate {
+    let token_text = if parser_config.keep_digits {
+        token_text
+    } else if token_is_numeric(&token_text) {
+        return TokenFate::DroppedNumeric;
+    } else {
+        token_text
+            .chars()
+            .filter(|character| !character.is_ascii_digit())
+            .collect()
+    };
+    let token_length = token_text.chars().count();
+    let is_numeric = token_is_numeric(&token_text);
+    let is_short = token_length < min_token_length;
+
+    if is_short && !is_numeric {
+        return TokenFate::DroppedTooShort;
+    }
+    let is_stopword = parser_config.remove_stopwords && stopword_set.contains(&token_text);
+    if is_stopword && !keeps_weighted_stopwords(parser_config) {
+        return TokenFate::DroppedStopword;
+    }
+    let emitted_token = if parser_config.stem {
+        stem_token(&token_text)
+    } else {
+        token_text
+    };
+    if is_stopword {
+        TokenFate::KeptStopword(emitted_token)
+    } else if is_short {
+        TokenFate::KeptNumeric(emitted_token)
+    } else {
+        TokenFate::Kept(emitted_token)
+    }
+}
+
+/// Whether stopwords are kept at `stopword_weight` rather than removed: stopword removal is on
+/// and the weight is non-zero.
+pub fn keeps_weighted_stopwords(parser_config: &ParserConfig) -> bool {
+    parser_config.remove_stopwords && parser_config.stopword_weight != 0.0
+}
+
+/// The stopwords as `tokenise` emits them when it keeps them (stemmed when `stem` is on, so
+/// "was" appears as "wa"). Empty unless `keeps_weighted_stopwords`.
+pub fn weighted_stopword_terms(
+    stopword_set: &HashSet<String>,
+    parser_config: &ParserConfig,
+) -> HashSet<String> {
+    if !keeps_weighted_stopwords(parser_config) {
+        return HashSet::new();
     }
+    stopword_set
+        .iter()
+        .map(|stopword_text| {
+            if parser_config.stem {
+                stem_token(stopword_text)
+            } else {
+                stopword_text.clone()
+            }
+        })
+        .collect()
+}
 
-    filtered_tokens
+/// Scoring weight of one emitted token: `stopword_weight` for a kept stopword (one of
+/// `weighted_stopword_terms`), otherwise 1.
+pub fn token_weight(
+    token_text: &str,
+    weighted_stopword_terms: &HashSet<String>,
+    parser_config: &ParserConfig,
+) -> f32 {
+    if weighted_stopword_terms.contains(token_text) {
+        parser_config.stopword_weight
+    } else {
+        1.0
+    }
 }
 
 /// As above, but return a set of unique tokens (order not preserved).
@@ -60,5 +325,431 @@ pub fn tokenise_to_set(
     parser_config: &ParserConfig,
 ) -> HashSet<String> {
     let sequence_tokens = tokenise(input_text, stopword_set, parser_config);
-    sequence_tokens.into_iter().collect::<HashSet<String, RandomState>>()
+    sequence_tokens
+        .into_iter()
+        .collect::<HashSet<String, RandomState>>()
+}
+
+/// Expand a token into its character n-grams, padded with `#` at both ends so prefixes and
+/// suffixes get their own grams ("cat" → "#ca", "cat", "at#" for `ngram_size` 3).
+/// A padded token shorter than `ngram_size` yields itself as the only gram.
+pub fn character_ngrams(token_text: &str, ngram_size: usize) -> HashSet<String> {
+    let ngram_size = ngram_size.max(1);
+    let padded_characters: Vec<char> = std::iter::once('#')
+        .chain(token_text.chars())
+        .chain(std::iter::once('#'))
+        .collect();
+    if padded_characters.len() <= ngram_size {
+        return HashSet::from([padded_characters.into_iter().collect()]);
+    }
+    padded_characters
+        .windows(ngram_size)
+        .map(|window| window.iter().collect())
+        .collect()
+}
+
+/// Reduce a lowercase token to its Porter stem (Porter, 1980).
+/// Tokens that are not pure ASCII letters, or are two characters or shorter, are returned unchanged.
+pub fn stem_token(token_text: &str) -> String {
+    if token_text.len() <= 2 || !token_text.bytes().all(|byte| byte.is_ascii_lowercase()) {
+        return token_text.to_string();
+    }
+    let mut word: Vec<u8> = token_text.as_bytes().to_vec();
+    porter_step_1a(&mut word);
+    porter_step_1b(&mut word);
+    porter_step_1c(&mut word);
+    porter_step_2(&mut word);
+    porter_step_3(&mut word);
+    porter_step_4(&mut word);
+    porter_step_5(&mut word);
+    String::from_utf8(word).expect("ASCII input stays ASCII")
+}
+
+/// True if `word[index]` is a consonant in Porter's sense ('y' after a consonant is a vowel).
+fn is_consonant(word: &[u8], index: usize) -> bool {
+    match word[index] {
+        b'a' | b'e' | b'i' | b'o' | b'u' => false,
+        b'y' => index == 0 || !is_consonant(word, index - 1),
+        _ => true,
+    }
+}
+
+/// Porter's measure m: the number of vowel-consonant sequences in `stem`.
+fn measure(stem: &[u8]) -> usize {
+    let mut measure_value = 0;
+    let mut previous_was_vowel = false;
+    for index in 0..stem.len() {
+        let consonant = is_consonant(stem, index);
+        if consonant && previous_was_vowel {
+            measure_value += 1;
+        }
+        previous_was_vowel = !consonant;
+    }
+    measure_value
+}
+
+fn contains_vowel(stem: &[u8]) -> bool {
+    (0..stem.len()).any(|index| !is_consonant(stem, index))
+}
+
+fn ends_double_consonant(stem: &[u8]) -> bool {
+    let length = stem.len();
+    length >= 2 && stem[length - 1] == stem[length - 2] && is_consonant(stem, length - 1)
+}
+
+/// True if `stem` ends consonant-vowel-consonant and the final consonant is not w, x, or y.
+fn ends_cvc(stem: &[u8]) -> bool {
+    let length = stem.len();
+    length >= 3
+        && is_consonant(stem, length - 3)
+        && !is_consonant(stem, length - 2)
+        && is_consonant(stem, length - 1)
+        && !matches!(stem[length - 1], b'w' | b'x' | b'y')
+}
+
+/// Replace the first matching suffix from `rules` when the remaining stem's measure exceeds `minimum_measure`.
+/// Only the first matching suffix is considered, even if its condition fails.
+fn replace_suffix(word: &mut Vec<u8>, rules: &[(&str, &str)], minimum_measure: usize) {
+    for (suffix, replacement) in rules {
+        if word.ends_with(suffix.as_bytes()) {
+            let stem_length = word.len() - suffix.len();
+            if measure(&word[..stem_length]) > minimum_measure {
+                word.truncate(stem_length);
+                word.extend_from_slice(replacement.as_bytes());
+            }
+            return;
+        }
+    }
+}
+
+fn porter_step_1a(word: &mut Vec<u8>) {
+    if word.ends_with(b"sses") || word.ends_with(b"ies") {
+        word.truncate(word.len() - 2);
+    } else if word.ends_with(b"s") && !word.ends_with(b"ss") {
+        word.truncate(word.len() - 1);
+    }
+}
+
+fn porter_step_1b(word: &mut Vec<u8>) {
+    if word.ends_with(b"eed") {
+        if measure(&word[..word.len() - 3]) > 0 {
+            word.truncate(word.len() - 1);
+        }
+        return;
+    }
+    let suffix_length = if word.ends_with(b"ed") {
+        2
+    } else if word.ends_with(b"ing") {
+        3
+    } else {
+        return;
+    };
+    if !contains_vowel(&word[..word.len() - suffix_length]) {
+        return;
+    }
+    word.truncate(word.len() - suffix_length);
+
+    if word.ends_with(b"at") || word.ends_with(b"bl") || word.ends_with(b"iz") {
+        word.push(b'e');
+    } else if ends_double_consonant(word) && !matches!(word[word.len() - 1], b'l' | b's' | b'z') {
+        word.truncate(word.len() - 1);
+    } else if measure(word) == 1 && ends_cvc(word) {
+        word.push(b'e');
+    }
+}
+
+fn porter_step_1c(word: &mut [u8]) {
+    let length = word.len();
+    if word.ends_with(b"y") && contains_vowel(&word[..length - 1]) {
+        word[length - 1] = b'i';
+    }
+}
+
+fn porter_step_2(word: &mut Vec<u8>) {
+    const RULES: &[(&str, &str)] = &[
+        ("ational", "ate"),
+        ("tional", "tion"),
+        ("enci", "ence"),
+        ("anci", "ance"),
+        ("izer", "ize"),
+        ("abli", "able"),
+        ("alli", "al"),
+        ("entli", "ent"),
+        ("eli", "e"),
+        ("ousli", "ous"),
+        ("ization", "ize"),
+        ("ation", "ate"),
+        ("ator", "ate"),
+        ("alism", "al"),
+        ("iveness", "ive"),
+        ("fulness", "ful"),
+        ("ousness", "ous"),
+        ("aliti", "al"),
+        ("iviti", "ive"),
+        ("biliti", "ble"),
+    ];
+    replace_suffix(word, RULES, 0);
+}
+
+fn porter_step_3(word: &mut Vec<u8>) {
+    const RULES: &[(&str, &str)] = &[
+        ("icate", "ic"),
+        ("ative", ""),
+        ("alize", "al"),
+        ("iciti", "ic"),
+        ("ical", "ic"),
+        ("ful", ""),
+        ("ness", ""),
+    ];
+    replace_suffix(word, RULES, 0);
+}
+
+fn porter_step_4(word: &mut Vec<u8>) {
+    // "ion" is only removed after 's' or 't', so it is handled separately.
+    if word.ends_with(b"ion") {
+        let stem_length = word.len() - 3;
+        if stem_length > 0
+            && matches!(word[stem_length - 1], b's' | b't')
+            && measure(&word[..stem_length]) > 1
+        {
+            word.truncate(stem_length);
+        }
+        return;
+    }
+    const RULES: &[(&str, &str)] = &[
+        ("al", ""),
+        ("ance", ""),
+        ("ence", ""),
+        ("er", ""),
+        ("ic", ""),
+        ("able", ""),
+        ("ible", ""),
+        ("ant", ""),
+        ("ement", ""),
+        ("ment", ""),
+        ("ent", ""),
+        ("ou", ""),
+        ("ism", ""),
+        ("ate", ""),
+        ("iti", ""),
+        ("ous", ""),
+        ("ive", ""),
+        ("ize", ""),
+    ];
+    replace_suffix(word, RULES, 1);
+}
+
+fn porter_step_5(word: &mut Vec<u8>) {
+    if word.ends_with(b"e") {
+        let stem = &word[..word.len() - 1];
+        let stem_measure = measure(stem);
+        if stem_measure > 1 || (stem_measure == 1 && !ends_cvc(stem)) {
+            word.truncate(word.len() - 1);
+        }
+    }
+    if word.ends_with(b"ll") && measure(word) > 1 {
+        word.truncate(word.len() - 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MinTokenLength;
+
+    #[test]
+    fn test_preserve_patterns_keep_tokens_whole() {
+        let stopword_set = HashSet::new();
+        let default_tokens = tokenise("A 32-bit register", &stopword_set, &ParserConfig::default());
+        assert_eq!(default_tokens, vec!["32", "bit", "register"]);
+
+        let parser_config = ParserConfig {
+            preserve_patterns: vec![r"\d+-bit".to_string(), r"O\(n\^2\)".to_string()],
+            ..ParserConfig::default()
+        };
+        let preserved_tokens = tokenise(
+            "A 32-bit CPU sorts in O(n^2) time",
+            &stopword_set,
+            &parser_config,
+        );
+        assert_eq!(
+            preserved_tokens,
+            vec!["32-bit", "cpu", "sorts", "in", "o(n^2)", "time"]
+        );
+    }
+
+    #[test]
+    fn test_preserve_patterns_match_card_text_and_query_alike() {
+        let stopword_set = HashSet::new();
+        let parser_config = ParserConfig {
+            preserve_patterns: vec![r"C\+\+".to_string()],
+            ..ParserConfig::default()
+        };
+        // Card text is lowercased before tokenising; the query arrives as typed.
+        let card_tokens = tokenise(
+            &crate::normalise::normalise_for_matching("Why use C++?", &parser_config),
+            &stopword_set,
+            &parser_config,
+        );
+        let query_tokens = tokenise("C++ templates", &stopword_set, &parser_config);
+        assert_eq!(card_tokens, vec!["why", "use", "c++"]);
+        assert_eq!(query_tokens, vec!["c++", "templates"]);
+    }
+
+    #[test]
+    fn test_lowercase_off_keeps_case_distinct_tokens() {
+        let stopword_set = HashSet::new();
+        let default_tokens = tokenise("IT it", &stopword_set, &ParserConfig::default());
+        assert_eq!(default_tokens, vec!["it", "it"]);
+
+        let parser_config = ParserConfig {
+            lowercase: false,
+            ..ParserConfig::default()
+        };
+        assert_eq!(
+            tokenise("IT it", &stopword_set, &parser_config),
+            vec!["IT", "it"]
+        );
+
+        // Stopwords are matched verbatim too.
+        let stopword_set = HashSet::from(["it".to_string()]);
+        let parser_config = ParserConfig {
+            remove_stopwords: true,
+            ..parser_config
+        };
+        assert_eq!(tokenise("IT it", &stopword_set, &parser_config), vec!["IT"]);
+    }
+
+    #[test]
+    fn test_join_chars_keep_apostrophes_and_hyphens_inside_tokens() {
+        let stopword_set = HashSet::new();
+        let input_text = "Don't use 'well-known' -flags";
+        // The "t" of "don't" falls below `min_token_length`.
+        assert_eq!(
+            tokenise(input_text, &stopword_set, &ParserConfig::default()),
+            vec!["don", "use", "well", "known", "flags"]
+        );
+
+        let parser_config = ParserConfig {
+            join_chars: vec!['\'', '-'],
+            ..ParserConfig::default()
+        };
+        // Only joins inside a token; leading and trailing quotes and hyphens still split.
+        assert_eq!(
+            tokenise(input_text, &stopword_set, &parser_config),
+            vec!["don't", "use", "well-known", "flags"]
+        );
+    }
+
+    #[test]
+    fn test_character_ngrams_padded() {
+        let expected: HashSet<String> = ["#ca", "cat", "at#"]
+            .iter()
+            .map(|gram| gram.to_string())
+            .collect();
+        assert_eq!(character_ngrams("cat", 3), expected);
+        assert_eq!(character_ngrams("a", 3), HashSet::from(["#a#".to_string()]));
+    }
+
+    #[test]
+    fn test_stem_token_porter_cases() {
+        let cases = [
+            ("caresses", "caress"),
+            ("ponies", "poni"),
+            ("caress", "caress"),
+            ("cats", "cat"),
+            ("agreed", "agre"),
+            ("feed", "feed"),
+            ("running", "run"),
+            ("hopping", "hop"),
+            ("filing", "file"),
+            ("happy", "happi"),
+            ("relational", "relat"),
+            ("conditional", "condit"),
+            ("generalization", "gener"),
+            ("adoption", "adopt"),
+            ("controll", "control"),
+        ];
+        for (input_token, expected_stem) in cases {
+            assert_eq!(
+                stem_token(input_token),
+                expected_stem,
+                "stemming {input_token}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_stopwords_removed_before_stemming() {
+        let stopword_set: HashSet<String> = ["was".to_string()].into_iter().collect();
+        let parser_config = ParserConfig {
+            stem: true,
+            ..Default::default()
+        };
+        // "was" would stem to "wa" and slip past the stopword list if stemming ran first.
+        assert_eq!(
+            tokenise("Was running", &stopword_set, &parser_config),
+            vec!["run".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_tokenise_with_trace_reports_drop_reasons() {
+        let stopword_set: HashSet<String> =
+            ["what".to_string(), "is".to_string()].into_iter().collect();
+        let parser_config = ParserConfig {
+            min_token_length: MinTokenLength::Uniform(3),
+            remove_stopwords: true,
+            ..Default::default()
+        };
+        let token_trace = tokenise_with_trace("What is a 64 bit OS", &stopword_set, &parser_config);
+        assert_eq!(
+            token_trace,
+            vec![
+                ("what".to_string(), TokenFate::DroppedStopword),
+                // Length is checked first, so a short stopword is reported as too short.
+                ("is".to_string(), TokenFate::DroppedTooShort),
+                ("a".to_string(), TokenFate::DroppedTooShort),
+                ("64".to_string(), TokenFate::KeptNumeric("64".to_string())),
+                ("bit".to_string(), TokenFate::Kept("bit".to_string())),
+                ("os".to_string(), TokenFate::DroppedTooShort),
+            ]
+        );
+        // The trace's kept tokens are exactly what `tokenise` returns.
+        let emitted_tokens: Vec<String> = token_trace
+            .into_iter()
+            .filter_map(|(_, token_fate)| token_fate.into_emitted_token())
+            .collect();
+        assert_eq!(
+            emitted_tokens,
+            tokenise("What is a 64 bit OS", &stopword_set, &parser_config)
+        );
+    }
+
+    #[test]
+    fn test_keep_digits_off_drops_numbers_and_strips_mixed_tokens() {
+        let stopword_set = HashSet::new();
+        let input_text = "ipv4 in 2024 uses x86 and mp3";
+        let keeping_config = ParserConfig {
+            min_token_length: MinTokenLength::Uniform(3),
+            ..Default::default()
+        };
+        assert_eq!(
+            tokenise(input_text, &stopword_set, &keeping_config),
+            vec!["ipv4", "2024", "uses", "x86", "and", "mp3"]
+        );
+
+        let stripping_config = ParserConfig {
+            keep_digits: false,
+            ..keeping_config
+        };
+        // "x86" → "x" and "mp3" → "mp" fall below the minimum length once stripped.
+        assert_eq!(
+            tokenise(input_text, &stopword_set, &stripping_config),
+            vec!["ipv", "uses", "and"]
+        );
+        let token_trace = tokenise_with_trace("2024 x86", &stopword_set, &stripping_config);
+        assert_eq!(token_trace[0].1, TokenFate::DroppedNumeric);
+        assert_eq!(token_trace[1].1, TokenFate::DroppedTooShort);
+    }
 }
@@ -1,35 +1,108 @@
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
+use crate::config::ParserConfig;
 use crate::data_model::{Card, DeckPath};
+use crate::error::ChatbotError;
+use crate::tokenise::tokenise;
+
+/// Synthetic topic holding cards whose deck path is empty, so they stay selectable by `--topic`.
+pub const UNCATEGORISED_TOPIC: &str = "<uncategorised>";
+
+/// Corpus-wide totals reported by `--count-only`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorpusStats {
+    pub card_count: usize,
+    pub topic_count: usize,
+    pub max_deck_depth: usize,
+    pub average_question_tokens: f32,
+}
+
+/// The topic a card is filed under: its deck path, or `UNCATEGORISED_TOPIC` when that is empty.
+pub fn card_topic_path(card: &Card) -> DeckPath {
+    if card.deck_path.is_empty() {
+        vec![UNCATEGORISED_TOPIC.to_string()]
+    } else {
+        card.deck_path.clone()
+    }
+}
+
+/// The first `depth` segments of `deck_path`; a `depth` of 0 keeps the whole path.
+pub fn truncate_deck_path(deck_path: &DeckPath, depth: usize) -> DeckPath {
+    if depth == 0 {
+        return deck_path.clone();
+    }
+    deck_path.iter().take(depth).cloned().collect()
+}
 
 /// Build an index from full deck paths to the cards that live at that path.
+/// Cards with an empty deck path are keyed under `UNCATEGORISED_TOPIC`.
 pub fn build_topic_index(cards: &[Card]) -> HashMap<DeckPath, Vec<Card>> {
+    build_topic_index_with_depth(cards, 0)
+}
+
+/// As `build_topic_index`, with deck paths cut to `topic_depth` segments (0 = full paths), so
+/// each key holds the cards that live at or below that path.
+pub fn build_topic_index_with_depth(
+    cards: &[Card],
+    topic_depth: usize,
+) -> HashMap<DeckPath, Vec<Card>> {
     let mut index: HashMap<DeckPath, Vec<Card>> = HashMap::new();
     for card in cards.iter().cloned() {
-        index.entry(card.deck_path.clone()).or_default().push(card);
+        let topic_path = truncate_deck_path(&card_topic_path(&card), topic_depth);
+        index.entry(topic_path).or_default().push(card);
     }
     index
 }
 
-/// Return a sorted list of all unique topic paths present across cards.
+/// Return a sorted list of all unique topic paths present across cards, including
+/// `UNCATEGORISED_TOPIC` when any card has an empty deck path.
 pub fn list_available_topics(cards: &[Card]) -> Vec<DeckPath> {
     let mut set: HashSet<DeckPath> = HashSet::new();
     for card in cards {
-        if !card.deck_path.is_empty() {
-            set.insert(card.deck_path.clone());
-        }
+        set.insert(card_topic_path(card));
     }
     let mut list: Vec<DeckPath> = set.into_iter().collect();
     list.sort();
     list
 }
 
-/// Convert a topic string into a DeckPath using the provided separator
+/// Card and distinct topic counts, the deepest deck path, and the mean question token count
+/// (each question is tokenised once). An empty corpus averages 0 tokens.
+pub fn corpus_stats(
+    cards: &[Card],
+    stopword_set: &HashSet<String>,
+    parser_config: &ParserConfig,
+) -> CorpusStats {
+    let total_question_tokens: usize = cards
+        .iter()
+        .map(|card| tokenise(&card.question_text, stopword_set, parser_config).len())
+        .sum();
+    let average_question_tokens = if cards.is_empty() {
+        0.0
+    } else {
+        total_question_tokens as f32 / cards.len() as f32
+    };
+    CorpusStats {
+        card_count: cards.len(),
+        topic_count: list_available_topics(cards).len(),
+        max_deck_depth: cards
+            .iter()
+            .map(|card| card.deck_path.len())
+            .max()
+            .unwrap_or(0),
+        average_question_tokens,
+    }
+}
+
+/// Convert a topic string into a DeckPath using the provided separator.
+/// An exact match wins; otherwise segments are compared ignoring case, and a single known topic
+/// matching that way is returned in its stored case. Several case-insensitive matches are an
+/// `UnknownTopic` error listing them.
 pub fn resolve_topic_string(
     topic_text: &str,
     topic_separator: &str,
     known_topics: &[DeckPath],
-) -> Result<DeckPath, String> {
+) -> Result<DeckPath, ChatbotError> {
     let deck_path: DeckPath = if topic_text.trim().is_empty() {
         Vec::new()
     } else {
@@ -40,44 +113,78 @@ pub fn resolve_topic_string(
     };
 
     if deck_path.is_empty() {
-        return Err("Topic cannot be empty; provide a valid deck path.".to_string());
+        return Err(ChatbotError::EmptyTopic);
     }
 
     if known_topics.iter().any(|path| path == &deck_path) {
-        Ok(deck_path)
-    } else {
-        let mut by_string: BTreeMap<String, ()> = BTreeMap::new();
-        for path in known_topics {
-            by_string.insert(path.join(topic_separator), ());
+        return Ok(deck_path);
+    }
+
+    let wanted = deck_path.join(topic_separator);
+    let case_insensitive_matches: Vec<&DeckPath> = known_topics
+        .iter()
+        .filter(|path| paths_equal_ignoring_case(path, &deck_path))
+        .collect();
+    match case_insensitive_matches.as_slice() {
+        [] => {}
+        [single_match] => return Ok((*single_match).clone()),
+        ambiguous_matches => {
+            return Err(ChatbotError::UnknownTopic {
+                topic: wanted,
+                suggestions: ambiguous_matches
+                    .iter()
+                    .map(|path| path.join(topic_separator))
+                    .collect(),
+            });
         }
+    }
 
-        let wanted = deck_path.join(topic_separator);
-        let mut suggestions: Vec<String> = by_string
-            .keys()
-            .filter(|candidate| candidate.starts_with(&deck_path[0]))
-            .take(5)
-            .cloned()
-            .collect();
+    let mut by_string: BTreeMap<String, ()> = BTreeMap::new();
+    for path in known_topics {
+        by_string.insert(path.join(topic_separator), ());
+    }
 
-        if suggestions.is_empty() {
-            suggestions = by_string.keys().take(5).cloned().collect();
-        }
+    let mut suggestions: Vec<String> = by_string
+        .keys()
+        .filter(|candidate| candidate.starts_with(&deck_path[0]))
+        .take(5)
+        .cloned()
+        .collect();
 
-        let hint = if suggestions.is_empty() {
-            String::from("No topics available.")
-        } else {
-            format!("Did you mean one of:\n- {}", suggestions.join("\n- "))
-        };
-        Err(format!("Unknown topic: \"{}\"\n{}", wanted, hint))
+    if suggestions.is_empty() {
+        suggestions = by_string.keys().take(5).cloned().collect();
     }
+
+    Err(ChatbotError::UnknownTopic {
+        topic: wanted,
+        suggestions,
+    })
+}
+
+/// True if both paths have the same segments, ignoring case.
+fn paths_equal_ignoring_case(left_path: &DeckPath, right_path: &DeckPath) -> bool {
+    left_path.len() == right_path.len()
+        && left_path
+            .iter()
+            .zip(right_path)
+            .all(|(left_segment, right_segment)| {
+                left_segment.to_lowercase() == right_segment.to_lowercase()
+            })
 }
 
 /// Gather candidate cards
+/// An empty `root_topic` selects the uncategorised cards, matching how `build_topic_index` keys them.
 pub fn collect_subtree_candidates(
     topic_index: &HashMap<DeckPath, Vec<Card>>,
     root_topic: &DeckPath,
     include_subtree: bool,
 ) -> Vec<Card> {
+    let uncategorised_path = vec![UNCATEGORISED_TOPIC.to_string()];
+    let root_topic = if root_topic.is_empty() {
+        &uncategorised_path
+    } else {
+        root_topic
+    };
     if !include_subtree {
         return topic_index
             .get(root_topic)
@@ -94,8 +201,27 @@ pub fn collect_subtree_candidates(
     results
 }
 
+/// Cards whose deck path starts with the `prefix` segments (each compared whole), without
+/// requiring `prefix` itself to be a known topic.
+pub fn collect_prefix_candidates(cards: &[Card], prefix: &DeckPath) -> Vec<Card> {
+    cards
+        .iter()
+        .filter(|card| path_starts_with(&card_topic_path(card), prefix))
+        .cloned()
+        .collect()
+}
+
+/// Sorted, distinct first segments of every deck path.
+pub fn list_top_level_topics(cards: &[Card]) -> Vec<String> {
+    let top_level_set: BTreeSet<&String> = cards
+        .iter()
+        .filter_map(|card| card.deck_path.first())
+        .collect();
+    top_level_set.into_iter().cloned().collect()
+}
+
 /// True if `path` has `prefix` as its leading segments.
-fn path_starts_with(path: &DeckPath, prefix: &DeckPath) -> bool {
+pub fn path_starts_with(path: &DeckPath, prefix: &DeckPath) -> bool {
     if prefix.len() > path.len() {
         return false;
     }
@@ -112,12 +238,94 @@ mod tests {
     use super::*;
 
     fn card_with_path(segments: &[&str]) -> Card {
-        Card {
-            guid: "g".to_string(),
-            question_text: "q".to_string(),
-            answer_raw: "a".to_string(),
-            deck_path: segments.iter().map(|s| s.to_string()).collect(),
-        }
+        Card::new(
+            "g",
+            "q",
+            "a",
+            segments.iter().map(|s| s.to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn test_corpus_stats_counts_cards_topics_depth_and_tokens() {
+        let mut cards = vec![
+            card_with_path(&["A", "B"]),
+            card_with_path(&["A", "B", "C"]),
+            card_with_path(&["A", "B", "C"]),
+            card_with_path(&["X"]),
+        ];
+        cards[0].question_text = "binary search tree".to_string();
+        cards[1].question_text = "merge sort".to_string();
+        cards[2].question_text = "the heap".to_string();
+        cards[3].question_text = "graph".to_string();
+        let stopword_set: HashSet<String> = ["the".to_string()].into_iter().collect();
+
+        let stats = corpus_stats(&cards, &stopword_set, &ParserConfig::default());
+        assert_eq!(stats.card_count, 4);
+        assert_eq!(stats.topic_count, 3);
+        assert_eq!(stats.max_deck_depth, 3);
+        assert!((stats.average_question_tokens - 7.0 / 4.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_uncategorised_cards_are_listed_and_selectable() {
+        let cards = vec![
+            card_with_path(&["A", "B"]),
+            card_with_path(&[]),
+            card_with_path(&[]),
+        ];
+        let known_topics = list_available_topics(&cards);
+        assert_eq!(
+            known_topics,
+            vec![
+                vec![UNCATEGORISED_TOPIC.to_string()],
+                vec!["A".to_string(), "B".to_string()],
+            ]
+        );
+
+        let index = build_topic_index(&cards);
+        let uncategorised_topic =
+            resolve_topic_string(UNCATEGORISED_TOPIC, "::", &known_topics).unwrap();
+        assert_eq!(
+            collect_subtree_candidates(&index, &uncategorised_topic, false).len(),
+            2
+        );
+        assert_eq!(
+            collect_subtree_candidates(&index, &Vec::new(), true).len(),
+            2
+        );
+        let categorised_topic = resolve_topic_string("A::B", "::", &known_topics).unwrap();
+        assert_eq!(
+            collect_subtree_candidates(&index, &categorised_topic, true).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_topic_depth_rolls_level_three_paths_into_level_two() {
+        let cards = vec![
+            card_with_path(&["A", "B", "C"]),
+            card_with_path(&["A", "B", "D"]),
+            card_with_path(&["A", "E"]),
+        ];
+        assert_eq!(truncate_deck_path(&cards[0].deck_path, 2), vec!["A", "B"]);
+        assert_eq!(
+            truncate_deck_path(&cards[0].deck_path, 0),
+            cards[0].deck_path
+        );
+
+        let full_index = build_topic_index(&cards);
+        assert_eq!(full_index.len(), 3);
+        let rolled_up_index = build_topic_index_with_depth(&cards, 2);
+        assert_eq!(rolled_up_index.len(), 2);
+        assert_eq!(
+            rolled_up_index[&vec!["A".to_string(), "B".to_string()]].len(),
+            2
+        );
+        assert_eq!(
+            rolled_up_index[&vec!["A".to_string(), "E".to_string()]].len(),
+            1
+        );
     }
 
     #[test]
@@ -137,6 +345,29 @@ mod tests {
         assert_eq!(sub.len(), 3);
     }
 
+    #[test]
+    fn test_collect_prefix_candidates_spans_subtopics() {
+        let cards = vec![
+            card_with_path(&["Course", "Unit 03", "Sorting"]),
+            card_with_path(&["Course", "Unit 03", "Searching"]),
+            card_with_path(&["Course", "Unit 03"]),
+            card_with_path(&["Course", "Unit 04"]),
+            card_with_path(&["Other"]),
+        ];
+
+        // "Course::Unit 03" need not be an exact deck path for the prefix to match.
+        let unit_prefix: DeckPath = vec!["Course".into(), "Unit 03".into()];
+        assert_eq!(collect_prefix_candidates(&cards, &unit_prefix).len(), 3);
+        // Segments match whole, so "Unit 0" is not a prefix of "Unit 03".
+        let partial_segment: DeckPath = vec!["Course".into(), "Unit 0".into()];
+        assert!(collect_prefix_candidates(&cards, &partial_segment).is_empty());
+
+        assert_eq!(
+            list_top_level_topics(&cards),
+            vec!["Course".to_string(), "Other".to_string()]
+        );
+    }
+
     #[test]
     fn test_resolve_topic_string() {
         let known = vec![
@@ -144,8 +375,46 @@ mod tests {
             vec!["A".into(), "D".into()],
             vec!["X".into()],
         ];
-        let resolved =
-            resolve_topic_string("A::B", "::", &known).expect("should resolve");
-        assert_eq!(resolved, vec!["A".into(), "B".into()]);
+        let resolved = resolve_topic_string("A::B", "::", &known).expect("should resolve");
+        assert_eq!(resolved, vec!["A".to_string(), "B".to_string()]);
+
+        match resolve_topic_string("A::Z", "::", &known) {
+            Err(ChatbotError::UnknownTopic { topic, suggestions }) => {
+                assert_eq!(topic, "A::Z");
+                assert_eq!(suggestions, vec!["A::B".to_string(), "A::D".to_string()]);
+            }
+            other => panic!("expected UnknownTopic, got {other:?}"),
+        }
+        assert!(matches!(
+            resolve_topic_string("  ", "::", &known),
+            Err(ChatbotError::EmptyTopic)
+        ));
+    }
+
+    #[test]
+    fn test_resolve_topic_string_ignoring_case() {
+        let known = vec![
+            vec!["Launch into Computing".into(), "Unit 01".into()],
+            vec!["Launch into Computing".into(), "Unit 02".into()],
+            vec!["Mixed".into(), "Case".into()],
+            vec!["MIXED".into(), "case".into()],
+        ];
+        let resolved = resolve_topic_string("launch into computing::unit 01", "::", &known)
+            .expect("unique case-insensitive match");
+        assert_eq!(
+            resolved,
+            vec!["Launch into Computing".to_string(), "Unit 01".to_string()]
+        );
+
+        match resolve_topic_string("mixed::CASE", "::", &known) {
+            Err(ChatbotError::UnknownTopic { topic, suggestions }) => {
+                assert_eq!(topic, "mixed::CASE");
+                assert_eq!(
+                    suggestions,
+                    vec!["Mixed::Case".to_string(), "MIXED::case".to_string()]
+                );
+            }
+            other => panic!("expected ambiguous UnknownTopic, got {other:?}"),
+        }
     }
 }
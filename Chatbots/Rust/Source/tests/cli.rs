@@ -0,0 +1,422 @@
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// The binary reads `Data/` relative to the working directory, so run it from the repository root.
+fn repository_root() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../..")
+}
+
+/// Run the CLI with `arguments` (plus a scratch invalid-record log) and return its output.
+fn run_cli(arguments: &[&str]) -> Output {
+    let invalid_log_path = std::env::temp_dir().join(format!(
+        "rulebot_cli_test_invalid_{}.log",
+        std::process::id()
+    ));
+    Command::new(env!("CARGO_BIN_EXE_rulebot-rust"))
+        .current_dir(repository_root())
+        .args(arguments)
+        .arg("--invalid-log")
+        .arg(&invalid_log_path)
+        .output()
+        .expect("binary runs")
+}
+
+#[test]
+fn test_algo_all_prints_a_section_per_algorithm() {
+    let log_path = std::env::temp_dir().join(format!(
+        "rulebot_cli_test_algo_all_{}.jsonl",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&log_path);
+    let log_path_text = log_path.display().to_string();
+
+    let output = run_cli(&[
+        "--algo",
+        "all",
+        "--query",
+        "What is big data?",
+        "--k",
+        "2",
+        "--log",
+        &log_path_text,
+    ]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let stdout_text = String::from_utf8_lossy(&output.stdout);
+    let keyword_position = stdout_text.find("== keyword ==").expect("keyword section");
+    let tfidf_position = stdout_text.find("== tfidf ==").expect("tfidf section");
+    assert!(keyword_position < tfidf_position);
+    assert!(stdout_text[keyword_position..tfidf_position].contains("1. GUID="));
+    assert!(stdout_text[tfidf_position..].contains("1. GUID="));
+
+    // One benchmark record per algorithm, labelled with that algorithm.
+    let log_text = std::fs::read_to_string(&log_path).expect("log written");
+    let logged_algorithms: Vec<String> = log_text
+        .lines()
+        .map(|line_text| {
+            let record: serde_json::Value = serde_json::from_str(line_text).expect("JSON line");
+            record["algo"].as_str().expect("algo field").to_string()
+        })
+        .collect();
+    assert_eq!(&logged_algorithms[..2], ["keyword", "tfidf"]);
+    let _ = std::fs::remove_file(&log_path);
+}
+
+#[test]
+fn test_deterministic_output_is_byte_identical() {
+    // Every run is a fresh process, so HashMap seeds differ between them.
+    let arguments = [
+        "--algo",
+        "all",
+        "--query",
+        "binary search over a sorted array",
+        "--k",
+        "5",
+        "--show-cards",
+        "--deterministic",
+    ];
+    let first_output = run_cli(&arguments);
+    assert!(
+        first_output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&first_output.stderr)
+    );
+    assert!(String::from_utf8_lossy(&first_output.stdout).contains("1. GUID="));
+    for _ in 0..2 {
+        assert_eq!(run_cli(&arguments).stdout, first_output.stdout);
+    }
+}
+
+#[test]
+fn test_bench_reports_statistics_over_repeated_runs() {
+    let output = run_cli(&[
+        "bench",
+        "--algo",
+        "keyword",
+        "--query",
+        "What is big data?",
+        "--repeat",
+        "10",
+        "--warmup",
+        "2",
+    ]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let stdout_text = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout_text.contains("Bench: algo=keyword"));
+    assert!(stdout_text.contains("warmup=2 repeat=10"));
+    let statistics_line = stdout_text
+        .lines()
+        .find(|line_text| line_text.starts_with("rank: "))
+        .expect("rank statistics line");
+    assert!(statistics_line.starts_with("rank: n=10 mean="));
+    for field_name in ["stddev=", "min=", "max="] {
+        assert!(statistics_line.contains(field_name), "{statistics_line}");
+    }
+}
+
+#[test]
+fn test_output_file_receives_results_instead_of_stdout() {
+    let output_directory =
+        std::env::temp_dir().join(format!("rulebot_cli_test_output_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&output_directory);
+    let queries_path = std::env::temp_dir().join(format!(
+        "rulebot_cli_test_output_queries_{}.txt",
+        std::process::id()
+    ));
+    std::fs::write(&queries_path, "What is big data?\nWhat is a logic gate?\n")
+        .expect("write queries");
+    // The parent directory does not exist yet; the CLI creates it.
+    let output_path = output_directory.join("nested").join("results.jsonl");
+
+    let output = run_cli(&[
+        "--algo",
+        "tfidf",
+        "--queries-file",
+        &queries_path.display().to_string(),
+        "--k",
+        "2",
+        "--format",
+        "json",
+        "--output-file",
+        &output_path.display().to_string(),
+    ]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).trim().is_empty());
+
+    // One JSON array per query, appended in order.
+    let output_text = std::fs::read_to_string(&output_path).expect("output file written");
+    let result_lines: Vec<&str> = output_text.lines().collect();
+    assert_eq!(result_lines.len(), 2);
+    for line_text in result_lines {
+        let hits: serde_json::Value = serde_json::from_str(line_text).expect("JSON array");
+        assert_eq!(hits.as_array().expect("array").len(), 2);
+        assert_eq!(hits[0]["rank"], 1);
+    }
+    let _ = std::fs::remove_dir_all(&output_directory);
+    let _ = std::fs::remove_file(&queries_path);
+}
+
+#[test]
+fn test_config_flag_loads_an_alternative_parser_config() {
+    let config_path = std::env::temp_dir().join(format!(
+        "rulebot_cli_test_config_{}.json",
+        std::process::id()
+    ));
+    let shipped_config =
+        std::fs::read_to_string(repository_root().join("Data/Configs/Parser.json"))
+            .expect("shipped config");
+    let mut config_json: serde_json::Value =
+        serde_json::from_str(&shipped_config).expect("shipped config is JSON");
+    config_json["tokenisation"]["min_token_length"] = serde_json::json!(5);
+    std::fs::write(&config_path, config_json.to_string()).expect("write config");
+    let config_path_text = config_path.display().to_string();
+
+    let default_output = run_cli(&["--tokenise", "big data pipeline"]);
+    assert!(default_output.status.success());
+    assert!(
+        String::from_utf8_lossy(&default_output.stdout)
+            .contains(r#"Tokens: ["big", "data", "pipeline"]"#)
+    );
+
+    let longer_minimum_output = run_cli(&[
+        "--tokenise",
+        "big data pipeline",
+        "--config",
+        &config_path_text,
+    ]);
+    assert!(longer_minimum_output.status.success());
+    assert!(
+        String::from_utf8_lossy(&longer_minimum_output.stdout).contains(r#"Tokens: ["pipeline"]"#)
+    );
+
+    // A missing config names the path that was tried.
+    let missing_path_text = format!("{config_path_text}.missing");
+    let missing_output = run_cli(&["--tokenise", "big", "--config", &missing_path_text]);
+    assert!(!missing_output.status.success());
+    assert!(String::from_utf8_lossy(&missing_output.stderr).contains(&missing_path_text));
+    let _ = std::fs::remove_file(&config_path);
+}
+
+#[test]
+fn test_max_invalid_ratio_fails_a_mostly_invalid_deck() {
+    let deck_path = std::env::temp_dir().join(format!(
+        "rulebot_cli_test_invalid_ratio_{}.txt",
+        std::process::id()
+    ));
+    std::fs::write(
+        &deck_path,
+        "g1\tBasic\tDeck\tWhat is a bit?\tA binary digit.\n\
+g2\tBasic\tDeck\tWhat is a byte?\n\
+g3\tBasic\tDeck\tWhat is a gate?\tA logic element.\n\
+\tBasic\tDeck\tNo GUID?\tDropped.\n",
+    )
+    .expect("write deck");
+    let deck_path_text = deck_path.display().to_string();
+    let query_arguments = [
+        "--algo",
+        "keyword",
+        "--query",
+        "bit",
+        "--data",
+        &deck_path_text,
+    ];
+
+    let failing_output = run_cli(&[&query_arguments[..], &["--max-invalid-ratio", "0.3"]].concat());
+    assert!(!failing_output.status.success());
+    let stderr_text = String::from_utf8_lossy(&failing_output.stderr);
+    assert!(
+        stderr_text.contains("2 of 4 records are invalid (ratio 0.500)"),
+        "{stderr_text}"
+    );
+
+    let default_output = run_cli(&query_arguments);
+    assert!(default_output.status.success());
+
+    // --validate still prints its summary before failing on the ratio.
+    let validate_output = run_cli(&[
+        "--validate",
+        "--data",
+        &deck_path_text,
+        "--max-invalid-ratio",
+        "0.3",
+    ]);
+    assert!(!validate_output.status.success());
+    assert!(String::from_utf8_lossy(&validate_output.stdout).contains("Invalid records: 2"));
+    assert!(String::from_utf8_lossy(&validate_output.stderr).contains("ratio 0.500"));
+
+    let bench_output = run_cli(&[
+        "bench",
+        "--algo",
+        "keyword",
+        "--query",
+        "bit",
+        "--data",
+        &deck_path_text,
+        "--max-invalid-ratio",
+        "0.3",
+    ]);
+    assert!(!bench_output.status.success());
+    assert!(String::from_utf8_lossy(&bench_output.stderr).contains("ratio 0.500"));
+
+    for out_of_range in ["NaN", "1.5", "-0.1"] {
+        let ratio_argument = format!("--max-invalid-ratio={out_of_range}");
+        let rejected_output = run_cli(&[&query_arguments[..], &[ratio_argument.as_str()]].concat());
+        assert!(!rejected_output.status.success(), "{out_of_range}");
+        assert!(String::from_utf8_lossy(&rejected_output.stderr).contains("--max-invalid-ratio"));
+    }
+    let _ = std::fs::remove_file(&deck_path);
+}
+
+#[test]
+fn test_similar_to_writes_to_output_file() {
+    let deck_path = std::env::temp_dir().join(format!(
+        "rulebot_cli_test_similar_deck_{}.txt",
+        std::process::id()
+    ));
+    std::fs::write(
+        &deck_path,
+        "g1\tBasic\tDeck\tWhat is a logic gate?\tA switching element.\n\
+g2\tBasic\tDeck\tWhat is an AND logic gate?\tBoth inputs high.\n\
+g3\tBasic\tDeck\tWhat is a byte?\tEight bits.\n",
+    )
+    .expect("write deck");
+    let output_path = std::env::temp_dir().join(format!(
+        "rulebot_cli_test_similar_output_{}.txt",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&output_path);
+
+    let output = run_cli(&[
+        "--algo",
+        "tfidf",
+        "--similar-to",
+        "g1",
+        "--show-cards",
+        "--data",
+        &deck_path.display().to_string(),
+        "--output-file",
+        &output_path.display().to_string(),
+    ]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("GUID=g2"));
+    let output_text = std::fs::read_to_string(&output_path).expect("output file written");
+    assert!(output_text.starts_with("1. GUID=g2"), "{output_text}");
+    assert!(output_text.contains("-> g2  score="), "{output_text}");
+
+    let explain_output = run_cli(&["--algo", "tfidf", "--similar-to", "g1", "--explain"]);
+    assert!(!explain_output.status.success());
+    let _ = std::fs::remove_file(&deck_path);
+    let _ = std::fs::remove_file(&output_path);
+}
+
+#[test]
+fn test_min_score_reports_hits_below_threshold() {
+    let output = run_cli(&[
+        "--algo",
+        "tfidf",
+        "--query",
+        "What is big data?",
+        "--min-score",
+        "2",
+    ]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("No results above threshold."));
+
+    let unmatched_output = run_cli(&["--algo", "tfidf", "--query", "zzqx", "--min-score", "2"]);
+    assert!(unmatched_output.status.success());
+    assert!(!String::from_utf8_lossy(&unmatched_output.stdout).contains("above threshold"));
+}
+
+#[test]
+fn test_eval_and_query_agree_with_min_overlap() {
+    // Matching questions against answers: g2's question shares only "stack" with its answer.
+    let deck_path = std::env::temp_dir().join(format!(
+        "rulebot_cli_test_min_overlap_deck_{}.txt",
+        std::process::id()
+    ));
+    std::fs::write(
+        &deck_path,
+        "g1\tBasic\tDeck\tbinary heap\tA binary tree kept in heap order.\n\
+g2\tBasic\tDeck\tstack frame\tOne stack entry per call.\n",
+    )
+    .expect("write deck");
+    let config_path = std::env::temp_dir().join(format!(
+        "rulebot_cli_test_min_overlap_config_{}.json",
+        std::process::id()
+    ));
+    let shipped_config =
+        std::fs::read_to_string(repository_root().join("Data/Configs/Parser.json"))
+            .expect("shipped config");
+    let mut config_json: serde_json::Value =
+        serde_json::from_str(&shipped_config).expect("shipped config is JSON");
+    config_json["tokenisation"]["search_field"] = serde_json::json!("answer");
+    std::fs::write(&config_path, config_json.to_string()).expect("write config");
+    let deck_path_text = deck_path.display().to_string();
+    let config_path_text = config_path.display().to_string();
+    let shared_arguments = [
+        "--algo",
+        "keyword",
+        "--k",
+        "1",
+        "--data",
+        &deck_path_text,
+        "--config",
+        &config_path_text,
+    ];
+
+    for (min_overlap, expected_recall, query_hits_g2) in
+        [("1", "recall@1: 2/2", true), ("2", "recall@1: 1/2", false)]
+    {
+        let eval_output = run_cli(
+            &[
+                &shared_arguments[..],
+                &["--eval", "--min-overlap", min_overlap],
+            ]
+            .concat(),
+        );
+        assert!(
+            eval_output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&eval_output.stderr)
+        );
+        let eval_text = String::from_utf8_lossy(&eval_output.stdout);
+        assert!(eval_text.contains(expected_recall), "{eval_text}");
+
+        let query_output = run_cli(
+            &[
+                &shared_arguments[..],
+                &["--query", "stack frame", "--min-overlap", min_overlap],
+            ]
+            .concat(),
+        );
+        assert!(query_output.status.success());
+        assert_eq!(
+            String::from_utf8_lossy(&query_output.stdout).contains("1. GUID=g2"),
+            query_hits_g2,
+            "--min-overlap {min_overlap}"
+        );
+    }
+    let _ = std::fs::remove_file(&deck_path);
+    let _ = std::fs::remove_file(&config_path);
+}
@@ -0,0 +1,137 @@
+use std::collections::HashSet;
+
+use rulebot_rust::ChatbotEngine;
+use rulebot_rust::config::ParserConfig;
+use rulebot_rust::data_model::Card;
+use rulebot_rust::scoring::ScorerRegistry;
+
+const QUERY_RUNS: usize = 50;
+
+fn card(guid: &str, question_text: &str) -> Card {
+    Card::new(
+        guid,
+        question_text,
+        &format!("answer for {guid}"),
+        vec!["Deck".to_string()],
+    )
+}
+
+/// Many near-identical questions so every scorer has to fall back on its tie-breakers.
+fn tie_heavy_cards() -> Vec<Card> {
+    let mut cards = Vec::new();
+    for card_number in 0..20 {
+        cards.push(card(
+            &format!("g{card_number:02}"),
+            "binary search over sorted arrays and lists of values",
+        ));
+        cards.push(card(
+            &format!("h{card_number:02}"),
+            "lists of values and sorted arrays over binary search",
+        ));
+        cards.push(card(
+            &format!("k{card_number:02}"),
+            "search trees and sorted values",
+        ));
+    }
+    cards
+}
+
+fn ranked_guids(engine: &ChatbotEngine, scorer_name: &str) -> Vec<String> {
+    engine
+        .query("sorted binary search values lists arrays", scorer_name, 30)
+        .into_iter()
+        .map(|answer_hit| answer_hit.guid)
+        .collect()
+}
+
+#[test]
+fn test_repeated_queries_return_identical_order() {
+    let parser_config = ParserConfig {
+        remove_stopwords: false,
+        ..ParserConfig::default()
+    };
+    let engine = ChatbotEngine::from_cards(
+        tie_heavy_cards(),
+        parser_config,
+        HashSet::new(),
+        ScorerRegistry::builtin().scorers(),
+    );
+
+    for scorer_name in engine.scorer_names() {
+        let first_order = ranked_guids(&engine, scorer_name);
+        assert_eq!(first_order.len(), 30, "{scorer_name}");
+        for _ in 1..QUERY_RUNS {
+            assert_eq!(
+                ranked_guids(&engine, scorer_name),
+                first_order,
+                "{scorer_name}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_rebuilt_indices_return_identical_order() {
+    let build_engine = || {
+        ChatbotEngine::from_cards(
+            tie_heavy_cards(),
+            ParserConfig {
+                remove_stopwords: false,
+                ..ParserConfig::default()
+            },
+            HashSet::new(),
+            ScorerRegistry::builtin().scorers(),
+        )
+    };
+    let reference_engine = build_engine();
+
+    // Every build hashes with fresh random keys, so HashMap iteration order differs each time.
+    for _ in 0..QUERY_RUNS {
+        let rebuilt_engine = build_engine();
+        for scorer_name in ["keyword", "tfidf", "jaccard", "dice"] {
+            assert_eq!(
+                ranked_guids(&rebuilt_engine, scorer_name),
+                ranked_guids(&reference_engine, scorer_name),
+                "{scorer_name}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_rebuilt_keyword_index_with_stopword_weight_returns_identical_scores() {
+    // Fractional stopword weights make the float sum depend on the order tokens are added.
+    let stopword_set: HashSet<String> = ["of", "and", "over"]
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+    let build_engine = || {
+        ChatbotEngine::from_cards(
+            tie_heavy_cards(),
+            ParserConfig {
+                stopword_weight: 0.1,
+                ..ParserConfig::default()
+            },
+            stopword_set.clone(),
+            ScorerRegistry::builtin().scorers(),
+        )
+    };
+    let ranked_hits = |engine: &ChatbotEngine| -> Vec<(String, f32)> {
+        engine
+            .query(
+                "lists of values and arrays over binary search",
+                "keyword",
+                30,
+            )
+            .into_iter()
+            .map(|answer_hit| (answer_hit.guid, answer_hit.score))
+            .collect()
+    };
+    let reference_hits = ranked_hits(&build_engine());
+    assert_eq!(reference_hits.len(), 30);
+
+    // Every rebuild and query hashes with fresh keys, so an unsorted sum would drift between runs.
+    for _ in 0..QUERY_RUNS {
+        assert_eq!(ranked_hits(&build_engine()), reference_hits);
+    }
+}